@@ -1,22 +1,37 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
 use uuid::Uuid;
 
+use crate::core::indexing::index_bundle::IndexBundle;
+use crate::core::indexing::index_insert::DocumentInsertReport;
 use crate::core::indexing::index_metadata::IndexMetadata;
+use crate::core::indexing::index_scan::{ScanCursor, ScanOptions, ScanPage};
 use tuo_shared::types::return_type::TuoResult;
 
-use crate::core::messaging::content::{TextEmbedded, TextInput, TextSourceType};
-use crate::core::source::node::Node;
+use crate::core::messaging::content::{
+    QueryEmbeddingCache, TextEmbedded, TextEmbeddingOptions, TextInput, TextSourceType,
+};
+use crate::core::source::document_tree::{DocumentTree, DocumentTreeOptions};
+use crate::core::source::node::{Node, NodeRelationTrait};
 use crate::core::source::sources::{
-    SourceData, SourceInputData, SourceType, SourceTypeTrait, SourcesId,
+    SourceData, SourceDataLookup, SourceInputData, SourceType, SourceTypeTrait, SourcesId,
 };
-use crate::embedding::embedder::EmbedderTrait;
+use crate::embedding::embedder::{EmbedResultStats, EmbedderTrait};
 use crate::extraction::reader::UniFolderReaderTrait;
 use crate::model::model_metadata::EmbeddingModelMetadata;
 use crate::parsing::document_parser::ParsedDocument;
-use crate::retrieval::search_result::SimilarResult;
+use crate::retrieval::hybrid::{fuse_rankings, HybridSearchOptions};
+use crate::retrieval::keyword::{keyword_matches, Bm25Options, KeywordMatch};
+use crate::retrieval::search_result::{HybridScores, SimilarResult};
 use crate::storage::store_metadata::StoreMetadata;
+use crate::storage::store_models::RegisteredModel;
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_utils::datetime::timestamp::now;
+use tuo_utils::hash::hash_str::hash_str;
 
 /// # IndexTrait
 ///
@@ -37,6 +52,9 @@ use crate::storage::store_metadata::StoreMetadata;
 ///
 /// See [StoreTrait](crate::core::storage::store::StoreTrait) for the database structure.
 ///
+/// Since indices of a store share the same tables, every document, section, node and text embedding carries the `index_id` of its index.
+/// Implementations must scope every read, write, search and count to their own `index_id`.
+///
 ///
 /// ## Database-agnostic
 ///
@@ -58,26 +76,105 @@ pub trait IndexTrait: Sync + Send {
     /// - `filters` for filtering the search results according type (Document, Section, Node) or parents e.g. `section_id` or `document_id`.
     type QueryOptions;
 
-    type InsertOptions: Send + Sync;
+    type InsertOptions: Send + Sync + Default;
 
     type TableType;
 
+    /// Add parsed documents with their sections and nodes, returning the changes to the documents of the index.
+    ///
+    /// The [content hash](crate::core::source::document::Document::content_hash) of the documents is set, so that the documents can be [upserted](crate::core::indexing::index_insert::DocumentInsertOptions::upsert) when their sources are indexed again.
     async fn add_document(
         &self,
         data: Vec<ParsedDocument>,
         opt: Self::InsertOptions,
-    ) -> TuoResult<()>;
+    ) -> TuoResult<DocumentInsertReport>;
     async fn add_source_data(
         &self,
         source_data: SourceData,
         opt: Self::InsertOptions,
     ) -> TuoResult<()>;
     async fn add_text_embeddings(&self, text: &Vec<TextEmbedded>) -> TuoResult<()>;
+    /// Find the text embeddings of the index by the hashes of their texts, their embedding model and their text source type.
+    async fn find_text_embeddings(
+        &self,
+        hashes: &[String],
+        embedding_model: &str,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Vec<TextEmbedded>>;
+    /// Find a text embedding of the index by the hash of its text, its embedding model and its text source type.
+    async fn find_text_embedded(
+        &self,
+        hash: &str,
+        embedding_model: &str,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Option<TextEmbedded>> {
+        let text_embedded = self
+            .find_text_embeddings(&[hash.to_string()], embedding_model, text_source_type)
+            .await?;
+        Ok(text_embedded.into_iter().next())
+    }
+    /// Set the `used_at` of text embeddings of the index to now.
+    async fn touch_text_embeddings(&self, ids: &[Uuid]) -> TuoResult<()>;
+    /// Embed the text of a query, reusing the persisted embedding of the same text and embedding model if the cache allows it.
+    ///
+    /// A reused embedding has its `used_at` updated, so that the embeddings of stale queries can be told apart.
+    async fn embed_query(
+        &self,
+        text: &TextInput,
+        cache: &QueryEmbeddingCache,
+    ) -> TuoResult<TextEmbedded> {
+        let embedder = self.get_index_embedder().await?;
+        if *cache != QueryEmbeddingCache::Disabled {
+            let cached = self
+                .find_text_embedded(
+                    &hash_str(&text.text),
+                    &embedder.get_model_name(),
+                    &text.source_type,
+                )
+                .await?;
+            if let Some(mut cached) = cached {
+                self.touch_text_embeddings(&[cached.id]).await?;
+                cached.used_at = now();
+                return Ok(cached);
+            }
+        }
+        let embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
+        let embedded_text = embedder.embed_input(text, &embedding_opt).await?;
+        if *cache == QueryEmbeddingCache::ReadWrite {
+            self.add_text_embeddings(&vec![embedded_text.clone()])
+                .await?;
+        }
+        Ok(embedded_text)
+    }
+    /// Embed the text of a query with a model, the model of the store if `model_id` is `None`.
+    ///
+    /// Queries embedded by a [registered model](IndexTrait::get_registered_model) are not cached, the cache only holding embeddings of the model of the store.
+    async fn embed_query_with_model(
+        &self,
+        text: &TextInput,
+        model_id: Option<Uuid>,
+        cache: &QueryEmbeddingCache,
+    ) -> TuoResult<TextEmbedded> {
+        match model_id.filter(|model_id| *model_id != self.get_model().id) {
+            Some(model_id) => {
+                let embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
+                self.get_registered_model(model_id)?
+                    .embedder
+                    .embed_input(text, &embedding_opt)
+                    .await
+            }
+            None => self.embed_query(text, cache).await,
+        }
+    }
     async fn delete(&self, source_ids: &Vec<Uuid>, source_type: &SourceType) -> TuoResult<()>;
+    /// Delete documents with their sections and nodes, and the text embeddings of their content and summaries.
+    ///
+    /// Content embeddings still used by other nodes of the index are kept.
+    async fn delete_documents(&self, document_ids: &[Uuid]) -> TuoResult<()>;
     async fn update(&self, sources: SourceData, opt: Self::InsertOptions) -> TuoResult<()> {
         let ids = sources.get_ids();
         let source_type = sources.source_type();
-        let result = self.delete(&ids, &source_type).await?;
+        self.delete(&ids, &source_type).await?;
         self.add_source_data(sources, opt).await?;
         Ok(())
     }
@@ -94,7 +191,6 @@ pub trait IndexTrait: Sync + Send {
     /// [text source type]: crate::core::messaging::content::TextSourceType
     /// [source type]: crate::core::source::sources::SourceType
     /// [distance]: crate::core::messaging::content::TextEmbedded
-    
     async fn similar_embedded_text(
         &self,
         text_input: &TextInput,
@@ -105,12 +201,133 @@ pub trait IndexTrait: Sync + Send {
     /// Search for similar documents/sections/nodes in the index.
     ///
     /// This calls the upstream `similar_embedded_text` method and then fetches the source data from the index based on the returned ids.
+    ///
+    /// By default only [similar nodes](IndexTrait::similar_nodes) are searched, other source types are an error.
     async fn similar_sources(
         &self,
         text: &TextInput,
         source_type: &SourceType,
         opts: Self::QueryOptions,
-    ) -> TuoResult<SourceData>;
+    ) -> TuoResult<SourceData>
+    where
+        Self::QueryOptions: Send,
+    {
+        match source_type {
+            SourceType::Node => self.similar_nodes(text, opts).await,
+            _ => Err(TuoPartsError::IndexError(format!(
+                "The index {} cannot search similar {:?} records",
+                self.get_index_name(),
+                source_type
+            )).into()),
+        }
+    }
+
+    /// Search the nodes whose content embeddings are the most similar to the text, in the order of their distance.
+    async fn similar_nodes(&self, text: &TextInput, opts: Self::QueryOptions) -> TuoResult<SourceData>
+    where
+        Self::QueryOptions: Send,
+    {
+        let node_ids: Vec<Uuid> = self
+            .similar_embedded_text(text, &TextSourceType::NodeContent, opts)
+            .await?
+            .into_iter()
+            .filter_map(|text_embedded| text_embedded.data.source_id)
+            .collect();
+        self.get_source_data_by_ids(&SourceType::Node, node_ids, true)
+            .await
+    }
+
+    /// Search the nodes of the index by the keywords of their content, ranked by [BM25](crate::retrieval::keyword::bm25_rank).
+    ///
+    /// Returns at most `top_k` nodes sharing at least one term with the query.
+    async fn keyword_search(
+        &self,
+        text: &TextInput,
+        top_k: usize,
+        opts: &Bm25Options,
+    ) -> TuoResult<Vec<KeywordMatch<Node>>>;
+
+    /// Fetch the nodes of the `top_k` best [ranked](crate::retrieval::keyword::bm25_rank) node ids, in the order of the ranking.
+    async fn fetch_keyword_matches(
+        &self,
+        mut ranked: Vec<(Uuid, f32)>,
+        top_k: usize,
+    ) -> TuoResult<Vec<KeywordMatch<Node>>> {
+        ranked.truncate(top_k);
+        let nodes = self
+            .get_source_data_by_ids(
+                &SourceType::Node,
+                ranked.iter().map(|(id, _)| *id).collect(),
+                false,
+            )
+            .await?
+            .get_node()
+            .unwrap_or_default();
+        Ok(keyword_matches(ranked, nodes))
+    }
+
+    /// Search nodes by fusing a vector search on their content embeddings with a [keyword search](IndexTrait::keyword_search).
+    ///
+    /// The query options set the number of vector matches. Results carry the scores of both searches in [hybrid_scores](SimilarResult::hybrid_scores).
+    async fn hybrid_search(
+        &self,
+        text: &TextInput,
+        hybrid_opts: &HybridSearchOptions,
+        opts: Self::QueryOptions,
+    ) -> TuoResult<Vec<SimilarResult<Node>>>
+    where
+        Self::QueryOptions: Send,
+    {
+        let vector_matches: Vec<(Uuid, f32)> = self
+            .similar_embedded_text(text, &TextSourceType::NodeContent, opts)
+            .await?
+            .into_iter()
+            .filter_map(|result| Some((result.data.source_id?, result.distance)))
+            .collect();
+        let keyword_matches = self
+            .keyword_search(text, hybrid_opts.keyword_candidates, &hybrid_opts.bm25)
+            .await?;
+        let fused = fuse_rankings(
+            &vector_matches,
+            &keyword_matches
+                .iter()
+                .map(|keyword_match| (keyword_match.data_id, keyword_match.score))
+                .collect::<Vec<_>>(),
+            &hybrid_opts.fusion,
+        );
+        let fused: Vec<(Uuid, HybridScores)> =
+            fused.into_iter().take(hybrid_opts.top_k).collect();
+
+        // nodes that only matched the vector search are fetched
+        let mut nodes: HashMap<Uuid, Node> = keyword_matches
+            .into_iter()
+            .map(|keyword_match| (keyword_match.data_id, keyword_match.data))
+            .collect();
+        let missing_ids: Vec<Uuid> = fused
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| !nodes.contains_key(id))
+            .collect();
+        if !missing_ids.is_empty() {
+            let fetched = self
+                .get_source_data_by_ids(&SourceType::Node, missing_ids, false)
+                .await?
+                .get_node()
+                .unwrap_or_default();
+            nodes.extend(fetched.into_iter().map(|node| (node.id, node)));
+        }
+        Ok(fused
+            .into_iter()
+            .filter_map(|(id, scores)| {
+                nodes.remove(&id).map(|node| SimilarResult {
+                    distance: 1.0 - scores.fused,
+                    data: node,
+                    data_id: id,
+                    hybrid_scores: Some(scores),
+                })
+            })
+            .collect())
+    }
     async fn get_index_embedder(&self) -> TuoResult<Arc<Box<dyn EmbedderTrait>>>;
 
     fn get_index_metadata(&self) -> IndexMetadata;
@@ -129,22 +346,350 @@ pub trait IndexTrait: Sync + Send {
         id: Uuid,
     ) -> TuoResult<SourceData>;
 
+    /// Get the records with the given ids, skipping the ids not found in the index.
+    ///
+    /// With `preserve_order`, the records follow the order of the ids.
     async fn get_source_data_by_ids(
         &self,
         source_type: &SourceType,
         ids: Vec<Uuid>,
         preserve_order: bool,
     ) -> TuoResult<SourceData>;
+    /// Get the records with the given ids, see [get_source_data_by_ids](IndexTrait::get_source_data_by_ids), reporting the ids not found in the index.
+    async fn lookup_source_data_by_ids(
+        &self,
+        source_type: &SourceType,
+        ids: Vec<Uuid>,
+        preserve_order: bool,
+    ) -> TuoResult<SourceDataLookup> {
+        let source_data = self
+            .get_source_data_by_ids(source_type, ids.clone(), preserve_order)
+            .await?;
+        let found_ids: HashSet<Uuid> = source_data.get_ids().into_iter().collect();
+        let missing_ids = ids
+            .into_iter()
+            .filter(|id| !found_ids.contains(id))
+            .collect();
+        Ok(SourceDataLookup {
+            source_data,
+            missing_ids,
+        })
+    }
+    /// Get a record by id with its relations: the content embeddings of a node.
     async fn get_source_data_with_relations_by_id(
         &self,
         source_type: &SourceType,
         id: Uuid,
+    ) -> TuoResult<SourceData> {
+        let data = self.get_source_data_by_id(source_type, id).await?;
+        match data {
+            SourceData::Node(mut nodes) => {
+                for node in nodes.iter_mut() {
+                    let Some(text_embedded_id) = node.content_embeddings_id else {
+                        continue;
+                    };
+                    let text_embedded = self
+                        .get_source_data_by_id(&SourceType::TextEmbedded, text_embedded_id)
+                        .await?
+                        .get_text_embedded()
+                        .unwrap_or_default();
+                    if let Some(first_text_embedded) = text_embedded.first() {
+                        node.merge_embedded_text(first_text_embedded);
+                    }
+                }
+                Ok(SourceData::Node(nodes))
+            }
+            _ => Ok(data),
+        }
+    }
+    /// Get all the records of the given type in the index.
+    async fn get_all_source_data(&self, source_type: &SourceType) -> TuoResult<SourceData>;
+
+    /// Get the sections or nodes of the documents, in no particular order.
+    async fn get_source_data_by_document_ids(
+        &self,
+        source_type: &SourceType,
+        document_ids: &[Uuid],
     ) -> TuoResult<SourceData>;
 
+    /// Get the documents with their sections and nodes in the order of the documents, see [DocumentTree].
+    ///
+    /// Trees follow the order of the ids, skipping the ids not found in the index.
+    async fn get_document_trees(
+        &self,
+        document_ids: &[Uuid],
+        opts: &DocumentTreeOptions,
+    ) -> TuoResult<Vec<DocumentTree>> {
+        if document_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut documents = self
+            .get_source_data_by_ids(&SourceType::Document, document_ids.to_vec(), true)
+            .await?
+            .get_document()
+            .unwrap_or_default();
+        let mut sections = self
+            .get_source_data_by_document_ids(&SourceType::Section, document_ids)
+            .await?
+            .get_section()
+            .unwrap_or_default();
+        let mut nodes = self
+            .get_source_data_by_document_ids(&SourceType::Node, document_ids)
+            .await?
+            .get_node()
+            .unwrap_or_default();
+
+        let mut text_embedded_ids: HashSet<Uuid> = HashSet::new();
+        if opts.with_embeddings {
+            text_embedded_ids
+                .extend(nodes.iter().filter_map(|node| node.content_embeddings_id));
+        }
+        if opts.with_summaries {
+            text_embedded_ids
+                .extend(documents.iter().filter_map(|document| document.summary_text_id));
+            text_embedded_ids
+                .extend(sections.iter().filter_map(|section| section.summary_text_id));
+        }
+        if !text_embedded_ids.is_empty() {
+            let texts_embedded: HashMap<Uuid, TextEmbedded> = self
+                .get_source_data_by_ids(
+                    &SourceType::TextEmbedded,
+                    text_embedded_ids.into_iter().collect(),
+                    false,
+                )
+                .await?
+                .get_text_embedded()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|text_embedded| (text_embedded.id, text_embedded))
+                .collect();
+            let text_embedded = |id: Option<Uuid>| id.and_then(|id| texts_embedded.get(&id));
+            if opts.with_embeddings {
+                for node in nodes.iter_mut() {
+                    if let Some(content_embeddings) = text_embedded(node.content_embeddings_id) {
+                        node.merge_embedded_text(content_embeddings);
+                    }
+                }
+            }
+            if opts.with_summaries {
+                for document in documents.iter_mut() {
+                    document.summary = text_embedded(document.summary_text_id).cloned();
+                }
+                for section in sections.iter_mut() {
+                    section.summary = text_embedded(section.summary_text_id).cloned();
+                }
+            }
+        }
+        Ok(DocumentTree::assemble(documents, sections, nodes))
+    }
+
+    /// Get a document with its sections and nodes, see [get_document_trees](IndexTrait::get_document_trees).
+    async fn get_document_tree(
+        &self,
+        document_id: Uuid,
+        opts: &DocumentTreeOptions,
+    ) -> TuoResult<Option<DocumentTree>> {
+        Ok(self.get_document_trees(&[document_id], opts).await?.pop())
+    }
+
+    /// Get the next `page_size` records of the given type in the index after the cursor, in the order of their [scan keys](ScanCursor).
+    async fn scan_page(
+        &self,
+        source_type: &SourceType,
+        page_size: usize,
+        cursor: Option<&ScanCursor>,
+    ) -> TuoResult<ScanPage>;
+
+    /// Stream the records of the given type in the index, one page at a time, see [ScanOptions].
+    ///
+    /// Only the current page is held in memory. The stream ends after the last page, or after the first error;
+    /// the cursor of the last page received resumes the scan.
+    fn scan<'a>(
+        &'a self,
+        source_type: &'a SourceType,
+        opts: ScanOptions,
+    ) -> BoxStream<'a, TuoResult<ScanPage>> {
+        let page_size = opts.page_size.max(1);
+        // the state is the cursor of the next page, `None` once the last page was returned
+        futures::stream::try_unfold(Some(opts.cursor), move |cursor| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let page = self
+                .scan_page(source_type, page_size, cursor.as_ref())
+                .await?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+            let next_cursor = (page.len() == page_size).then(|| page.cursor.clone());
+            Ok(Some((page, next_cursor)))
+        })
+        .boxed()
+    }
+
+    /// Export the records of the index with its metadata and model, see [IndexBundle].
+    async fn export_bundle(&self) -> TuoResult<IndexBundle> {
+        Ok(IndexBundle::builder()
+            .index_metadata(self.get_index_metadata())
+            .model(self.get_model())
+            .documents(
+                self.get_all_source_data(&SourceType::Document)
+                    .await?
+                    .get_document()
+                    .unwrap_or_default(),
+            )
+            .sections(
+                self.get_all_source_data(&SourceType::Section)
+                    .await?
+                    .get_section()
+                    .unwrap_or_default(),
+            )
+            .nodes(
+                self.get_all_source_data(&SourceType::Node)
+                    .await?
+                    .get_node()
+                    .unwrap_or_default(),
+            )
+            .texts_embedded(
+                self.get_all_source_data(&SourceType::TextEmbedded)
+                    .await?
+                    .get_text_embedded()
+                    .unwrap_or_default(),
+            )
+            .build())
+    }
+
     async fn open_source_table(&self, source_type: &SourceType) -> TuoResult<Self::TableType>;
+    /// Get the nodes of this index that have no content embeddings yet.
     async fn get_unembedded_nodes(&self) -> TuoResult<Vec<Node>>;
 
-    async fn embed_nodes(&self, doc_ids: Vec<Uuid>) -> TuoResult<()>;
+    /// Embed the unembedded nodes of this index.
+    ///
+    /// Only the nodes with the given ids are embedded; if `node_ids` is empty, all unembedded nodes of the index are embedded.
+    ///
+    /// Nodes whose content was already embedded by the model of the index reuse that embedding, see [embed_nodes_reusing](EmbedderTrait::embed_nodes_reusing).
+    async fn embed_nodes(&self, node_ids: Vec<Uuid>) -> TuoResult<EmbedResultStats> {
+        let node_ids: HashSet<Uuid> = node_ids.into_iter().collect();
+        let unembedded_nodes: Vec<Node> = self
+            .get_unembedded_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node_ids.is_empty() || node_ids.contains(&node.id))
+            .collect();
+        if unembedded_nodes.is_empty() {
+            return Ok(EmbedResultStats::default());
+        }
+        let embedder = self.get_index_embedder().await?;
+        // reuse the embeddings of the contents embedded before
+        let hashes: Vec<String> = unembedded_nodes
+            .iter()
+            .map(|node| hash_str(&node.content))
+            .collect();
+        let reusable = self
+            .find_text_embeddings(
+                &hashes,
+                &embedder.get_model_name(),
+                &TextSourceType::NodeContent,
+            )
+            .await?;
+        let text_embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
+        let (embedded_nodes, stats) = embedder
+            .embed_nodes_reusing(unembedded_nodes, &reusable, &text_embedding_opt)
+            .await?;
+        // persist the new node embeddings first, once per content
+        let mut persisted_ids: HashSet<Uuid> = reusable
+            .iter()
+            .map(|text_embedded| text_embedded.id)
+            .collect();
+        let node_embeddings: Vec<TextEmbedded> = embedded_nodes
+            .iter()
+            .filter_map(|node| node.content_embeddings.clone())
+            .filter(|text_embedded| persisted_ids.insert(text_embedded.id))
+            .collect();
+        if !node_embeddings.is_empty() {
+            self.add_source_data(SourceData::TextEmbedded(node_embeddings), Default::default())
+                .await?;
+        }
+        // update the nodes with the embeddings
+        self.update(SourceData::Node(embedded_nodes), Default::default())
+            .await?;
+        Ok(stats)
+    }
+
+    // --- Registered models ---
+
+    /// Get a model [registered](crate::storage::store::StoreTrait::register_embedding_model) on the store before the index was opened, with its embedder.
+    fn get_registered_model(&self, model_id: Uuid) -> TuoResult<RegisteredModel>;
+
+    /// Add node content embeddings of a registered model to the table of the model, assigning them to this index.
+    async fn add_model_embeddings(
+        &self,
+        model_id: Uuid,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()>;
+
+    /// Find the node content embeddings of a registered model in this index by the hashes of their texts.
+    async fn find_model_embeddings(
+        &self,
+        model_id: Uuid,
+        hashes: &[String],
+    ) -> TuoResult<Vec<TextEmbedded>>;
+
+    /// Get the nodes of this index that have no content embeddings by the model yet.
+    ///
+    /// For the model of the store, these are the [unembedded nodes](IndexTrait::get_unembedded_nodes); for a registered model, the nodes without a text embedding in the table of the model.
+    async fn get_unembedded_nodes_of_model(&self, model_id: Uuid) -> TuoResult<Vec<Node>>;
+
+    /// Embed the nodes of this index that are not embedded by the model yet, see [embed_nodes](IndexTrait::embed_nodes).
+    ///
+    /// The contents embedded by a registered model are only written to the table of the model, one text embedding per node, and nodes of the same content reuse the embedding.
+    /// The nodes themselves keep referencing their content embeddings by the model of the store.
+    async fn embed_nodes_with_model(
+        &self,
+        model_id: Uuid,
+        node_ids: Vec<Uuid>,
+    ) -> TuoResult<EmbedResultStats> {
+        if model_id == self.get_model().id {
+            return self.embed_nodes(node_ids).await;
+        }
+        let registered_model = self.get_registered_model(model_id)?;
+        let node_ids: HashSet<Uuid> = node_ids.into_iter().collect();
+        let unembedded_nodes: Vec<Node> = self
+            .get_unembedded_nodes_of_model(model_id)
+            .await?
+            .into_iter()
+            .filter(|node| node_ids.is_empty() || node_ids.contains(&node.id))
+            .collect();
+        if unembedded_nodes.is_empty() {
+            return Ok(EmbedResultStats::default());
+        }
+        let hashes: Vec<String> = unembedded_nodes
+            .iter()
+            .map(|node| hash_str(&node.content))
+            .collect();
+        let reusable = self.find_model_embeddings(model_id, &hashes).await?;
+        let text_embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
+        let (embedded_nodes, stats) = registered_model
+            .embedder
+            .embed_nodes_reusing(unembedded_nodes, &reusable, &text_embedding_opt)
+            .await?;
+        // a reused embedding is copied for each node, so that every node embedded by the model has its own row
+        let texts_embedded: Vec<TextEmbedded> = embedded_nodes
+            .into_iter()
+            .map(|node| {
+                let mut text_embedded = node
+                    .content_embeddings
+                    .expect("Node embeddings should be present after embedding");
+                if text_embedded.source_id != Some(node.id) {
+                    text_embedded.id = Uuid::new_v4();
+                    text_embedded.source_id = Some(node.id);
+                }
+                text_embedded
+            })
+            .collect();
+        self.add_model_embeddings(model_id, texts_embedded).await?;
+        Ok(stats)
+    }
 
     // async fn from_folder(&mut self, folder: &str) -> TuoResult<Box<dyn IndexTrait<SearchOptions=Self::SearchOptions, InputDataEntryType=Self::InputDataEntryType>>> {
     //     match reader {
@@ -0,0 +1,40 @@
+use typed_builder::TypedBuilder;
+
+use crate::core::indexing::index_metadata::IndexMetadata;
+use crate::core::messaging::content::TextEmbedded;
+use crate::core::source::document::Document;
+use crate::core::source::node::Node;
+use crate::core::source::section::Section;
+use crate::core::source::sources::SourceData;
+use crate::model::model_metadata::EmbeddingModelMetadata;
+
+/// All the records of an index with the metadata needed to recreate it, in another store or another version of tuo.
+///
+/// Exported by [export_bundle](crate::core::indexing::index::IndexTrait::export_bundle) and imported by [index_import](crate::storage::store::StoreTrait::index_import).
+/// Records keep their ids, so the relations between them and to their text embeddings are preserved.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct IndexBundle {
+    pub index_metadata: IndexMetadata,
+    /// The model that embedded the text embeddings of the index.
+    pub model: EmbeddingModelMetadata,
+    #[builder(default)]
+    pub documents: Vec<Document>,
+    #[builder(default)]
+    pub sections: Vec<Section>,
+    #[builder(default)]
+    pub nodes: Vec<Node>,
+    #[builder(default)]
+    pub texts_embedded: Vec<TextEmbedded>,
+}
+
+impl IndexBundle {
+    /// The records of the bundle, in the order they are added to an index: nodes and sections before their documents.
+    pub fn to_source_data(&self) -> Vec<SourceData> {
+        vec![
+            SourceData::TextEmbedded(self.texts_embedded.clone()),
+            SourceData::Node(self.nodes.clone()),
+            SourceData::Section(self.sections.clone()),
+            SourceData::Document(self.documents.clone()),
+        ]
+    }
+}
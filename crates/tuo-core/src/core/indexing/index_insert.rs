@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::core::indexing::index::IndexTrait;
+use crate::core::source::document::Document;
+use crate::core::source::sources::SourceType;
+use crate::parsing::document_parser::ParsedDocument;
+
+/// Options of [adding documents](crate::core::indexing::index::IndexTrait::add_document) to an index.
+///
+/// By default documents are always added, even if the index already has documents of the same sources.
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct DocumentInsertOptions {
+    /// Match the documents with the documents of the index by their [source_uri](Document::source_uri), comparing their [content hash](Document::content_hash).
+    ///
+    /// Documents of unchanged sources are skipped, documents of changed sources replace the previous ones, along with their sections, nodes and text embeddings.
+    /// If several documents have the same source, the last one is added.
+    #[builder(default)]
+    pub upsert: bool,
+    /// Remove the documents of the index whose source uri starts with this prefix, e.g. the folder indexed again, and which are not among the documents added.
+    ///
+    /// An empty prefix removes all the documents of the index that are not added again.
+    #[builder(default)]
+    pub remove_missing_under: Option<String>,
+}
+
+impl DocumentInsertOptions {
+    /// Whether the documents already in the index are needed to add documents.
+    pub fn compares_documents(&self) -> bool {
+        self.upsert || self.remove_missing_under.is_some()
+    }
+}
+
+/// Changes to the documents of an index made by [adding documents](crate::core::indexing::index::IndexTrait::add_document), by source uri.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentInsertReport {
+    /// Sources without documents in the index, whose documents were added.
+    pub added: Vec<String>,
+    /// Changed sources, whose documents replaced the previous ones.
+    pub updated: Vec<String>,
+    /// Unchanged sources, whose documents were skipped.
+    pub unchanged: Vec<String>,
+    /// Missing sources, whose documents were removed.
+    pub removed: Vec<String>,
+}
+
+/// The writes of [adding documents](crate::core::indexing::index::IndexTrait::add_document) to an index, given the documents already in the index.
+#[derive(Default)]
+pub struct DocumentInsertPlan {
+    /// Documents to add, with their content hash.
+    pub documents: Vec<ParsedDocument>,
+    /// Documents to remove, with their sections, nodes and text embeddings.
+    pub removed_document_ids: Vec<Uuid>,
+    pub report: DocumentInsertReport,
+}
+
+impl DocumentInsertPlan {
+    /// Plan the writes, see [DocumentInsertOptions].
+    ///
+    /// `existing` are the documents of the index, only needed if the options [compare documents](DocumentInsertOptions::compares_documents).
+    pub fn new(
+        data: Vec<ParsedDocument>,
+        existing: Vec<Document>,
+        opts: &DocumentInsertOptions,
+    ) -> Self {
+        let mut existing_by_uri: HashMap<String, Vec<Document>> = HashMap::new();
+        for document in existing {
+            existing_by_uri
+                .entry(document.source_uri.clone())
+                .or_default()
+                .push(document);
+        }
+        let data = match opts.upsert {
+            true => last_by_source_uri(data),
+            false => data,
+        };
+
+        let mut plan = DocumentInsertPlan::default();
+        for mut parsed in data {
+            let content_hash = parsed.content_hash();
+            parsed.document.content_hash = Some(content_hash.clone());
+            let source_uri = parsed.document.source_uri.clone();
+            let previous = existing_by_uri.remove(&source_uri).unwrap_or_default();
+            if !opts.upsert {
+                plan.report.added.push(source_uri);
+                plan.documents.push(parsed);
+                continue;
+            }
+            match previous
+                .iter()
+                .position(|document| document.content_hash.as_ref() == Some(&content_hash))
+            {
+                Some(position) => {
+                    // duplicates of the unchanged document, e.g. added before upserts, are removed
+                    plan.removed_document_ids.extend(
+                        previous
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| *index != position)
+                            .map(|(_, document)| document.id),
+                    );
+                    plan.report.unchanged.push(source_uri);
+                }
+                None => {
+                    match previous.is_empty() {
+                        true => plan.report.added.push(source_uri),
+                        false => plan.report.updated.push(source_uri),
+                    }
+                    plan.removed_document_ids
+                        .extend(previous.iter().map(|document| document.id));
+                    plan.documents.push(parsed);
+                }
+            }
+        }
+
+        if let Some(prefix) = &opts.remove_missing_under {
+            let mut missing: Vec<(String, Vec<Document>)> = existing_by_uri
+                .into_iter()
+                .filter(|(source_uri, _)| source_uri.starts_with(prefix.as_str()))
+                .collect();
+            missing.sort_by(|a, b| a.0.cmp(&b.0));
+            for (source_uri, documents) in missing {
+                plan.removed_document_ids
+                    .extend(documents.iter().map(|document| document.id));
+                plan.report.removed.push(source_uri);
+            }
+        }
+        plan
+    }
+}
+
+/// Add parsed documents to an index one document at a time, see [DocumentInsertPlan].
+///
+/// The nodes, sections and document of each document are written with [add_source_data](IndexTrait::add_source_data), then the documents replaced or missing are deleted.
+pub async fn insert_documents<I: IndexTrait + ?Sized>(
+    index: &I,
+    data: Vec<ParsedDocument>,
+    opts: &DocumentInsertOptions,
+) -> TuoResult<DocumentInsertReport> {
+    let existing = match opts.compares_documents() {
+        true => index
+            .get_all_source_data(&SourceType::Document)
+            .await?
+            .get_document()
+            .unwrap_or_default(),
+        false => vec![],
+    };
+    let plan = DocumentInsertPlan::new(data, existing, opts);
+    for doc in plan.documents {
+        for source_data in [
+            doc.to_source_nodes(),
+            doc.to_source_sections(),
+            doc.to_source_document(),
+        ] {
+            index
+                .add_source_data(source_data, Default::default())
+                .await?;
+        }
+    }
+    index.delete_documents(&plan.removed_document_ids).await?;
+    Ok(plan.report)
+}
+
+/// The last document of each source, in the order of the documents.
+fn last_by_source_uri(data: Vec<ParsedDocument>) -> Vec<ParsedDocument> {
+    let mut seen = HashSet::new();
+    let mut data: Vec<ParsedDocument> = data
+        .into_iter()
+        .rev()
+        .filter(|parsed| seen.insert(parsed.document.source_uri.clone()))
+        .collect();
+    data.reverse();
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(source_uri: &str, content_hash: Option<&str>) -> Document {
+        Document::builder()
+            .name(source_uri.to_string())
+            .index_id(Uuid::nil())
+            .source_uri(source_uri.to_string())
+            .content_hash(content_hash.map(str::to_string))
+            .build()
+    }
+
+    fn parsed(source_uri: &str, content_hash: &str) -> ParsedDocument {
+        ParsedDocument {
+            document: document(source_uri, Some(content_hash)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_document_insert_plan() {
+        let unchanged = document("a", Some("1"));
+        let duplicate = document("a", None);
+        let legacy = document("b", None);
+        let missing = document("dir/c", Some("3"));
+        let kept = document("d", Some("4"));
+        let existing = vec![
+            unchanged.clone(),
+            duplicate.clone(),
+            legacy.clone(),
+            missing.clone(),
+            kept.clone(),
+        ];
+        let data = vec![
+            parsed("a", "1"),
+            parsed("b", "2"),
+            parsed("e", "0"),
+            parsed("e", "5"),
+        ];
+
+        // without upsert, every document is added
+        let plan = DocumentInsertPlan::new(
+            data.iter()
+                .map(|parsed| ParsedDocument {
+                    document: parsed.document.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+            existing.clone(),
+            &DocumentInsertOptions::default(),
+        );
+        assert_eq!(plan.documents.len(), 4);
+        assert!(plan.removed_document_ids.is_empty());
+
+        let opts = DocumentInsertOptions::builder()
+            .upsert(true)
+            .remove_missing_under(Some("dir/".to_string()))
+            .build();
+        let plan = DocumentInsertPlan::new(data, existing, &opts);
+        assert_eq!(plan.report.unchanged, vec!["a"]);
+        // documents added before their content hash was recorded are replaced
+        assert_eq!(plan.report.updated, vec!["b"]);
+        assert_eq!(plan.report.added, vec!["e"]);
+        assert_eq!(plan.report.removed, vec!["dir/c"]);
+        let content_hashes: Vec<Option<String>> = plan
+            .documents
+            .iter()
+            .map(|parsed| parsed.document.content_hash.clone())
+            .collect();
+        assert_eq!(
+            content_hashes,
+            vec![Some("2".to_string()), Some("5".to_string())]
+        );
+        assert_eq!(
+            plan.removed_document_ids,
+            vec![duplicate.id, legacy.id, missing.id]
+        );
+    }
+}
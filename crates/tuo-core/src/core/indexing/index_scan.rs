@@ -0,0 +1,81 @@
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::core::source::sources::SourceData;
+
+/// Position of a [scan](crate::core::indexing::index::IndexTrait::scan) after a record, from which the scan can be resumed.
+///
+/// Records are scanned in the order of their scan key: nodes by document, then by their [index](crate::core::source::node::Node::index) in the document, other records by id.
+/// The id breaks ties, so that the order is total and a resumed scan neither repeats nor skips the records that existed when it started.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScanCursor {
+    /// Document of the node, `None` for other records.
+    pub document_id: Option<Uuid>,
+    /// Index of the node in its document, `None` for other records.
+    pub index: Option<i32>,
+    pub id: Uuid,
+}
+
+impl ScanCursor {
+    /// The scan keys of the records, in the order of the records.
+    pub fn of(source_data: &SourceData) -> Vec<ScanCursor> {
+        match source_data {
+            SourceData::Node(nodes) => nodes
+                .iter()
+                .map(|node| ScanCursor {
+                    document_id: Some(node.document_id),
+                    index: Some(node.index),
+                    id: node.id,
+                })
+                .collect(),
+            _ => source_data
+                .get_ids()
+                .into_iter()
+                .map(|id| ScanCursor {
+                    document_id: None,
+                    index: None,
+                    id,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Options of a [scan](crate::core::indexing::index::IndexTrait::scan) of the records of an index.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ScanOptions {
+    /// Maximum number of records of a page.
+    #[builder(default = 1000)]
+    pub page_size: usize,
+    /// Resume the scan after this cursor, e.g. the cursor of the last page of an interrupted scan.
+    #[builder(default)]
+    pub cursor: Option<ScanCursor>,
+}
+
+/// A page of a [scan](crate::core::indexing::index::IndexTrait::scan), in the order of the [scan keys](ScanCursor).
+#[derive(Debug)]
+pub struct ScanPage {
+    pub source_data: SourceData,
+    /// Cursor after the last record of the page, from which the scan continues with the next page.
+    ///
+    /// `None` if the page is empty.
+    pub cursor: Option<ScanCursor>,
+}
+
+impl ScanPage {
+    pub fn new(source_data: SourceData) -> Self {
+        let cursor = ScanCursor::of(&source_data).pop();
+        Self {
+            source_data,
+            cursor,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.source_data.get_ids().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cursor.is_none()
+    }
+}
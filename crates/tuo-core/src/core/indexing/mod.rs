@@ -1,2 +1,5 @@
 pub mod index;
+pub mod index_bundle;
+pub mod index_insert;
 pub mod index_metadata;
+pub mod index_scan;
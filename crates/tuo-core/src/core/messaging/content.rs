@@ -20,6 +20,20 @@ pub enum TextSourceType {
     NodeContent,
 }
 
+/// How an index caches the embeddings of user queries, so that repeated queries are not embedded again.
+///
+/// Cached embeddings are looked up by the hash of the query text and the embedding model.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum QueryEmbeddingCache {
+    /// Reuse cached embeddings, and persist the embeddings of new queries.
+    #[default]
+    ReadWrite,
+    /// Reuse cached embeddings, without persisting the embeddings of new queries.
+    ReadOnly,
+    /// Always embed queries, without persisting them.
+    Disabled,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct TextInput {
     pub text: String,
@@ -86,6 +100,10 @@ pub struct TextEmbedded {
     pub used_at: TuoDateTime,
     pub source_type: TextSourceType,
     pub source_id: Option<Uuid>,
+    /// The index the text belongs to.
+    ///
+    /// Set by the index when the text is persisted, so that text embeddings from different indices sharing the same table do not leak into each other.
+    pub index_id: Option<Uuid>,
 }
 
 impl TextEmbedded {
@@ -108,6 +126,7 @@ impl TextEmbedded {
             used_at: now(),
             source_type,
             source_id,
+            index_id: None,
         }
     }
 
@@ -108,6 +108,11 @@ pub struct Document {
     /// If it is a file, it is the file path.
     /// If it is a url, it is the url.
     pub source_uri: String,
+    /// Hash of the content of the source, telling whether the source changed when it is indexed again.
+    ///
+    /// Readers may set it, e.g. from the bytes of a file, otherwise it is computed from the parsed content, see [ParsedDocument::content_hash](crate::parsing::document_parser::ParsedDocument::content_hash).
+    #[builder(default = None)]
+    pub content_hash: Option<String>,
 
     /// The summary of the source
     #[builder(default = None, setter(skip))]
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::core::source::document::Document;
+use crate::core::source::node::Node;
+use crate::core::source::section::Section;
+
+/// A document with its sections and their nodes, in the order of the document.
+///
+/// Loaded by [get_document_trees](crate::core::indexing::index::IndexTrait::get_document_trees) to reconstruct the structure of the source.
+#[derive(Debug, Clone)]
+pub struct DocumentTree {
+    pub document: Document,
+    /// Sections by [section_order](Section::section_order).
+    pub sections: Vec<SectionTree>,
+}
+
+/// A section of a [DocumentTree] with its nodes.
+#[derive(Debug, Clone)]
+pub struct SectionTree {
+    pub section: Section,
+    /// Nodes by [index](Node::index) in the document.
+    pub nodes: Vec<Node>,
+}
+
+/// Relations loaded along with a [DocumentTree].
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct DocumentTreeOptions {
+    /// Load the [content embeddings](Node::content_embeddings) of the nodes.
+    #[builder(default)]
+    pub with_embeddings: bool,
+    /// Load the [summary](Document::summary) of the document and of its sections.
+    #[builder(default)]
+    pub with_summaries: bool,
+}
+
+impl DocumentTree {
+    /// Group the sections and nodes under their documents, in the order of the documents.
+    ///
+    /// Sections and nodes of other documents, and nodes of other sections, are left out.
+    pub fn assemble(
+        documents: Vec<Document>,
+        sections: Vec<Section>,
+        nodes: Vec<Node>,
+    ) -> Vec<DocumentTree> {
+        let mut nodes_by_section: HashMap<Uuid, Vec<Node>> = HashMap::new();
+        for node in nodes {
+            nodes_by_section
+                .entry(node.section_id)
+                .or_default()
+                .push(node);
+        }
+        let mut sections_by_document: HashMap<Uuid, Vec<SectionTree>> = HashMap::new();
+        for section in sections {
+            let mut nodes = nodes_by_section.remove(&section.id).unwrap_or_default();
+            nodes.retain(|node| node.document_id == section.document_id);
+            nodes.sort_by_key(|node| (node.index, node.id));
+            sections_by_document
+                .entry(section.document_id)
+                .or_default()
+                .push(SectionTree { section, nodes });
+        }
+        documents
+            .into_iter()
+            .map(|document| {
+                let mut sections = sections_by_document
+                    .remove(&document.id)
+                    .unwrap_or_default();
+                sections.sort_by_key(|tree| (tree.section.section_order, tree.section.id));
+                DocumentTree { document, sections }
+            })
+            .collect()
+    }
+
+    /// All the nodes of the document, in the order of the document.
+    pub fn nodes(&self) -> Vec<&Node> {
+        self.sections
+            .iter()
+            .flat_map(|section| section.nodes.iter())
+            .collect()
+    }
+}
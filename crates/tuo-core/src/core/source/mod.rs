@@ -7,6 +7,7 @@
 //! - A section is a collection of nodes.
 //! - A node has content.
 pub mod document;
+pub mod document_tree;
 pub mod section;
 pub mod node;
 pub mod sources;
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use strum::{AsRefStr, EnumString};
 use uuid::Uuid;
 
@@ -23,7 +25,7 @@ pub trait SourceTypeTrait {
     fn source_type(&self) -> SourceType;
 }
 
-#[derive(Debug, Clone, EnumString, AsRefStr)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EnumString, AsRefStr)]
 pub enum SourceType {
     StoreMetadata,
     ModelMetadata,
@@ -34,6 +36,19 @@ pub enum SourceType {
     Node,
 }
 
+impl SourceType {
+    /// Whether the records of this type belong to a single index, i.e. carry an `index_id`.
+    pub fn is_index_scoped(&self) -> bool {
+        matches!(
+            self,
+            SourceType::TextEmbedded
+                | SourceType::Document
+                | SourceType::Section
+                | SourceType::Node
+        )
+    }
+}
+
 impl SourceTableName for SourceType {
     fn table_name(&self) -> String {
         match self {
@@ -73,6 +88,96 @@ impl SourceData {
             SourceData::Node(data) => data.iter().map(|x| x.id).collect(),
         }
     }
+
+    /// Assign the index id to all index-scoped records, i.e. text embeddings, documents, sections and nodes.
+    ///
+    /// Store, model and index metadata are not scoped to an index and are left untouched.
+    pub fn assign_index_id(&mut self, index_id: Uuid) {
+        match self {
+            SourceData::TextEmbedded(data) => {
+                data.iter_mut().for_each(|x| x.index_id = Some(index_id))
+            }
+            SourceData::Document(data) => data.iter_mut().for_each(|x| x.index_id = index_id),
+            SourceData::Section(data) => data.iter_mut().for_each(|x| x.index_id = index_id),
+            SourceData::Node(data) => data.iter_mut().for_each(|x| x.index_id = index_id),
+            SourceData::StoreMetadata(_)
+            | SourceData::ModelMetadata(_)
+            | SourceData::IndexMetadata(_) => {}
+        }
+    }
+
+    /// Append the records of another source data of the same type.
+    ///
+    /// Panics if the source data are of different types.
+    pub fn extend(&mut self, other: SourceData) {
+        match (self, other) {
+            (SourceData::StoreMetadata(data), SourceData::StoreMetadata(other)) => {
+                data.extend(other)
+            }
+            (SourceData::ModelMetadata(data), SourceData::ModelMetadata(other)) => {
+                data.extend(other)
+            }
+            (SourceData::IndexMetadata(data), SourceData::IndexMetadata(other)) => {
+                data.extend(other)
+            }
+            (SourceData::TextEmbedded(data), SourceData::TextEmbedded(other)) => {
+                data.extend(other)
+            }
+            (SourceData::Document(data), SourceData::Document(other)) => data.extend(other),
+            (SourceData::Section(data), SourceData::Section(other)) => data.extend(other),
+            (SourceData::Node(data), SourceData::Node(other)) => data.extend(other),
+            (data, other) => panic!(
+                "Cannot extend {} records with {} records",
+                data.source_type().as_ref(),
+                other.source_type().as_ref()
+            ),
+        }
+    }
+
+    /// Reorder the records to follow the order of the ids.
+    ///
+    /// Records whose id is not in `ids` are put last, keeping their relative order.
+    pub fn order_by_ids(self, ids: &[Uuid]) -> SourceData {
+        let positions: HashMap<&Uuid, usize> = ids
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(position, id)| (id, position))
+            .collect();
+        fn sort<T>(
+            mut data: Vec<T>,
+            positions: &HashMap<&Uuid, usize>,
+            id: fn(&T) -> Uuid,
+        ) -> Vec<T> {
+            data.sort_by_key(|x| positions.get(&id(x)).copied().unwrap_or(usize::MAX));
+            data
+        }
+        match self {
+            SourceData::StoreMetadata(data) => {
+                SourceData::StoreMetadata(sort(data, &positions, |x| x.id))
+            }
+            SourceData::ModelMetadata(data) => {
+                SourceData::ModelMetadata(sort(data, &positions, |x| x.id))
+            }
+            SourceData::IndexMetadata(data) => {
+                SourceData::IndexMetadata(sort(data, &positions, |x| x.id))
+            }
+            SourceData::TextEmbedded(data) => {
+                SourceData::TextEmbedded(sort(data, &positions, |x| x.id))
+            }
+            SourceData::Document(data) => SourceData::Document(sort(data, &positions, |x| x.id)),
+            SourceData::Section(data) => SourceData::Section(sort(data, &positions, |x| x.id)),
+            SourceData::Node(data) => SourceData::Node(sort(data, &positions, |x| x.id)),
+        }
+    }
+}
+
+/// Records looked up by their ids, along with the ids without a record.
+#[derive(Debug)]
+pub struct SourceDataLookup {
+    pub source_data: SourceData,
+    /// Ids not found, in the order they were requested.
+    pub missing_ids: Vec<Uuid>,
 }
 
 // First, declare the macro.
@@ -136,7 +241,6 @@ pub enum SourceInputData<SOURCE> {
     Node(Vec<SOURCE>),
 }
 
-
 impl<SOURCE> SourceInputData<SOURCE> {
     pub fn from_data(data: Vec<SOURCE>, source_type: &SourceType) -> Self {
         match source_type {
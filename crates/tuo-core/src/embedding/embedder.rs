@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 
 use tuo_shared::types::return_type::TuoResult;
@@ -7,9 +9,14 @@ use crate::core::source::node::{Node, NodeRelationTrait};
 use crate::embedding::embeddings::Embeddings;
 use crate::model::model::ModelTrait;
 use crate::model::model_metadata::EmbeddingModelMetadata;
+use tuo_utils::hash::hash_str::hash_str;
 
-#[derive(Debug)]
-pub struct EmbedResultStats {}
+/// Counts of the node contents embedded by the embedder and of those reusing an existing embedding of the same text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmbedResultStats {
+    pub embedded: usize,
+    pub reused: usize,
+}
 
 #[async_trait]
 pub trait EmbedderTrait: Sync + Send + ModelTrait {
@@ -23,18 +30,52 @@ pub trait EmbedderTrait: Sync + Send + ModelTrait {
         let result = input.to_embedded(embeddings, opt);
         Ok(result)
     }
+    /// Embed the contents of the nodes, embedding each distinct content once.
+    ///
+    /// See [embed_nodes_reusing](EmbedderTrait::embed_nodes_reusing).
     async fn embed_nodes(
         &self,
         nodes: Vec<Node>,
         opt: &TextEmbeddingOptions,
     ) -> TuoResult<Vec<Node>> {
+        let (nodes, _) = self.embed_nodes_reusing(nodes, &[], opt).await?;
+        Ok(nodes)
+    }
+    /// Embed the contents of the nodes, reusing the embeddings of the same text by the same model.
+    ///
+    /// Embeddings are looked up by the hash of the text among the `reusable` embeddings, then among those computed for previous nodes.
+    /// Nodes with the same content thus share one text embedding, whose `source_id` is the first of them.
+    async fn embed_nodes_reusing(
+        &self,
+        nodes: Vec<Node>,
+        reusable: &[TextEmbedded],
+        opt: &TextEmbeddingOptions,
+    ) -> TuoResult<(Vec<Node>, EmbedResultStats)> {
+        let model_name = self.get_model_name();
+        let mut embeddings: HashMap<String, TextEmbedded> = reusable
+            .iter()
+            .filter(|text_embedded| text_embedded.embedding_model == model_name)
+            .map(|text_embedded| (text_embedded.hash.clone(), text_embedded.clone()))
+            .collect();
+        let mut stats = EmbedResultStats::default();
         let mut result: Vec<Node> = Vec::new();
         for mut node in nodes.into_iter() {
-            let input = TextInput::from_node_text(node.content.as_str(), node.id);
-            let text_embedded = self.embed_input(&input, &opt).await?;
-            node.merge_embedded_text(&text_embedded);
+            let hash = hash_str(&node.content);
+            match embeddings.get(&hash) {
+                Some(text_embedded) => {
+                    node.merge_embedded_text(text_embedded);
+                    stats.reused += 1;
+                }
+                None => {
+                    let input = TextInput::from_node_text(node.content.as_str(), node.id);
+                    let text_embedded = self.embed_input(&input, opt).await?;
+                    node.merge_embedded_text(&text_embedded);
+                    embeddings.insert(hash, text_embedded);
+                    stats.embedded += 1;
+                }
+            }
             result.push(node)
         }
-        Ok(result)
+        Ok((result, stats))
     }
 }
@@ -6,6 +6,7 @@ use crate::core::source::document::Document;
 use crate::core::source::node::Node;
 use crate::core::source::section::Section;
 use crate::core::source::sources::SourceData;
+use tuo_utils::hash::hash_str::hash_str;
 
 
 #[derive(Default)]
@@ -29,6 +30,22 @@ impl ParsedDocument {
     pub fn to_source_nodes(&self) -> SourceData {
         SourceData::Node(self.nodes.clone())
     }
+    /// The [content hash](Document::content_hash) of the document, or the hash of its section names and node contents if the reader did not set it.
+    pub fn content_hash(&self) -> String {
+        if let Some(content_hash) = &self.document.content_hash {
+            return content_hash.clone();
+        }
+        let mut sections: Vec<&Section> = self.sections.iter().collect();
+        sections.sort_by_key(|section| section.section_order);
+        let mut nodes: Vec<&Node> = self.nodes.iter().collect();
+        nodes.sort_by_key(|node| node.index);
+        let content: Vec<&str> = sections
+            .iter()
+            .map(|section| section.name.as_str())
+            .chain(nodes.iter().map(|node| node.content.as_str()))
+            .collect();
+        hash_str(&content.join("\0"))
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::retrieval::keyword::Bm25Options;
+use crate::retrieval::search_result::HybridScores;
+
+/// How a [hybrid search](crate::core::indexing::index::IndexTrait::hybrid_search) fuses the vector and keyword rankings.
+#[derive(Debug, Clone)]
+pub enum HybridFusion {
+    /// Weighted sum of the vector similarity, `1 - distance`, and the keyword score divided by the best keyword score.
+    ///
+    /// The keyword weight is `1 - vector_weight`.
+    Weighted { vector_weight: f32 },
+    /// Reciprocal rank fusion, summing `1 / (k + rank)` over the rankings a result appears in.
+    ///
+    /// Only ranks matter, so it needs no tuning across queries whose scores are not comparable.
+    ReciprocalRank { k: f32 },
+}
+
+impl Default for HybridFusion {
+    fn default() -> Self {
+        HybridFusion::ReciprocalRank { k: 60.0 }
+    }
+}
+
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct HybridSearchOptions {
+    /// Number of fused results to return.
+    #[builder(default = 10)]
+    pub top_k: usize,
+    /// Number of keyword matches to fuse.
+    ///
+    /// The number of vector matches is set by the query options of the index.
+    #[builder(default = 50)]
+    pub keyword_candidates: usize,
+    #[builder(default)]
+    pub fusion: HybridFusion,
+    #[builder(default)]
+    pub bm25: Bm25Options,
+}
+
+/// Fuse the vector matches, ranked by ascending distance, with the keyword matches, ranked by descending score.
+///
+/// Returns the ids of all matches with their scores, by descending fused score.
+pub fn fuse_rankings(
+    vector_matches: &[(Uuid, f32)],
+    keyword_matches: &[(Uuid, f32)],
+    fusion: &HybridFusion,
+) -> Vec<(Uuid, HybridScores)> {
+    let mut scores: HashMap<Uuid, HybridScores> = HashMap::new();
+    let max_keyword_score = keyword_matches
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(0.0f32, f32::max);
+    for (rank, (id, distance)) in vector_matches.iter().enumerate() {
+        let entry = scores.entry(*id).or_default();
+        entry.vector_distance = Some(*distance);
+        entry.fused += match fusion {
+            HybridFusion::Weighted { vector_weight } => vector_weight * (1.0 - distance).max(0.0),
+            HybridFusion::ReciprocalRank { k } => 1.0 / (k + rank as f32 + 1.0),
+        };
+    }
+    for (rank, (id, score)) in keyword_matches.iter().enumerate() {
+        let entry = scores.entry(*id).or_default();
+        entry.keyword_score = Some(*score);
+        entry.fused += match fusion {
+            HybridFusion::Weighted { vector_weight } if max_keyword_score > 0.0 => {
+                (1.0 - vector_weight) * score / max_keyword_score
+            }
+            HybridFusion::Weighted { .. } => 0.0,
+            HybridFusion::ReciprocalRank { k } => 1.0 / (k + rank as f32 + 1.0),
+        };
+    }
+    let mut fused: Vec<(Uuid, HybridScores)> = scores.into_iter().collect();
+    fused.sort_by(|a, b| b.1.fused.total_cmp(&a.1.fused));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuse_rankings() {
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let vector_matches = vec![(ids[0], 0.1), (ids[1], 0.2)];
+        let keyword_matches = vec![(ids[1], 4.0), (ids[2], 2.0)];
+
+        // appearing in both rankings wins with reciprocal rank fusion
+        let fused = fuse_rankings(&vector_matches, &keyword_matches, &HybridFusion::default());
+        assert_eq!(fused.len(), 3);
+        assert_eq!(fused[0].0, ids[1]);
+        assert_eq!(fused[0].1.vector_distance, Some(0.2));
+        assert_eq!(fused[0].1.keyword_score, Some(4.0));
+        assert_eq!(fused[1].0, ids[0]);
+        assert_eq!(fused[1].1.keyword_score, None);
+
+        // only the vector similarity counts with a full vector weight
+        let fused = fuse_rankings(
+            &vector_matches,
+            &keyword_matches,
+            &HybridFusion::Weighted { vector_weight: 1.0 },
+        );
+        assert_eq!(fused[0].0, ids[0]);
+        assert!((fused[0].1.fused - 0.9).abs() < 1e-6);
+        assert_eq!(fused[2].0, ids[2]);
+        assert_eq!(fused[2].1.fused, 0.0);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::core::source::node::Node;
+
+/// A node matching the keywords of a query, with its BM25 score, the higher the more relevant.
+#[derive(Debug, Clone)]
+pub struct KeywordMatch<Data> {
+    pub score: f32,
+    pub data: Data,
+    pub data_id: Uuid,
+}
+
+/// Parameters of the BM25 ranking function.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Bm25Options {
+    /// Saturation of the term frequency.
+    #[builder(default = 1.2)]
+    pub k1: f32,
+    /// Normalisation of the term frequency by the length of the text, from 0 (none) to 1 (full).
+    #[builder(default = 0.75)]
+    pub b: f32,
+}
+
+impl Default for Bm25Options {
+    fn default() -> Self {
+        Bm25Options::builder().build()
+    }
+}
+
+/// Whether the character belongs to a script written without spaces between words: Chinese, Japanese and Korean.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // hiragana, katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{AC00}'..='\u{D7AF}' // hangul syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{20000}'..='\u{2A6DF}' // CJK extension B
+    )
+}
+
+/// Split a text into lowercase terms for keyword search.
+///
+/// Words of alphabetic scripts are split on non-alphanumeric characters.
+/// Since CJK text has no word delimiters, each run of CJK characters yields its characters and its overlapping character bigrams, e.g. `中文例子` yields `中`, `文`, `例`, `子`, `中文`, `文例`, `例子`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut terms = vec![];
+    let mut word = String::new();
+    let mut cjk_run: Vec<char> = vec![];
+    let flush_cjk = |cjk_run: &mut Vec<char>, terms: &mut Vec<String>| {
+        terms.extend(cjk_run.iter().map(|c| c.to_string()));
+        terms.extend(
+            cjk_run
+                .windows(2)
+                .map(|pair| pair.iter().collect::<String>()),
+        );
+        cjk_run.clear();
+    };
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                terms.push(std::mem::take(&mut word));
+            }
+            cjk_run.push(c);
+            continue;
+        }
+        flush_cjk(&mut cjk_run, &mut terms);
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        } else if !word.is_empty() {
+            terms.push(std::mem::take(&mut word));
+        }
+    }
+    flush_cjk(&mut cjk_run, &mut terms);
+    if !word.is_empty() {
+        terms.push(word);
+    }
+    terms
+}
+
+/// Term statistics of a corpus of texts for ranking queries with BM25, computed once for all queries of the same texts.
+///
+/// Terms are indexed with the texts containing them, so that ranking a query only visits the texts sharing a term with it.
+#[derive(Debug, Clone, Default)]
+pub struct Bm25Corpus {
+    ids: Vec<Uuid>,
+    /// Number of terms of each text.
+    lengths: Vec<usize>,
+    total_length: usize,
+    /// The texts containing each term, by position in `ids`, with the frequency of the term in the text.
+    postings: HashMap<String, Vec<(usize, usize)>>,
+}
+
+impl Bm25Corpus {
+    pub fn new<'a>(texts: impl IntoIterator<Item = (Uuid, &'a str)>) -> Self {
+        let mut corpus = Bm25Corpus::default();
+        for (position, (id, text)) in texts.into_iter().enumerate() {
+            let terms = tokenize(text);
+            let mut frequencies: HashMap<String, usize> = HashMap::new();
+            for term in terms.iter() {
+                *frequencies.entry(term.clone()).or_insert(0) += 1;
+            }
+            for (term, frequency) in frequencies {
+                corpus
+                    .postings
+                    .entry(term)
+                    .or_default()
+                    .push((position, frequency));
+            }
+            corpus.ids.push(id);
+            corpus.lengths.push(terms.len());
+            corpus.total_length += terms.len();
+        }
+        corpus
+    }
+
+    /// Number of texts of the corpus.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Rank the texts against a query.
+    ///
+    /// Returns the ids of the texts sharing at least one term with the query, by descending score.
+    pub fn rank(&self, query: &str, opts: &Bm25Options) -> Vec<(Uuid, f32)> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let mut query_terms = tokenize(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        let total = self.len() as f32;
+        let average_length = self.total_length as f32 / total;
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        for postings in query_terms
+            .iter()
+            .filter_map(|term| self.postings.get(term.as_str()))
+        {
+            let document_frequency = postings.len() as f32;
+            let idf = (1.0 + (total - document_frequency + 0.5) / (document_frequency + 0.5)).ln();
+            for &(position, frequency) in postings {
+                let frequency = frequency as f32;
+                let length_norm =
+                    1.0 - opts.b + opts.b * self.lengths[position] as f32 / average_length.max(1.0);
+                *scores.entry(position).or_insert(0.0) +=
+                    idf * frequency * (opts.k1 + 1.0) / (frequency + opts.k1 * length_norm);
+            }
+        }
+        let mut ranked: Vec<(usize, f32)> = scores
+            .into_iter()
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        // ties keep the order of the texts
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+            .into_iter()
+            .map(|(position, score)| (self.ids[position], score))
+            .collect()
+    }
+}
+
+/// Rank texts against a query with BM25, see [Bm25Corpus] to rank several queries against the same texts.
+///
+/// Returns the ids of the texts sharing at least one term with the query, by descending score.
+pub fn bm25_rank<'a>(
+    query: &str,
+    texts: impl IntoIterator<Item = (Uuid, &'a str)>,
+    opts: &Bm25Options,
+) -> Vec<(Uuid, f32)> {
+    Bm25Corpus::new(texts).rank(query, opts)
+}
+
+/// Search nodes by the keywords of their content, returning the `top_k` best matches.
+pub fn keyword_search_nodes(
+    query: &str,
+    nodes: Vec<Node>,
+    top_k: usize,
+    opts: &Bm25Options,
+) -> Vec<KeywordMatch<Node>> {
+    let mut ranked = bm25_rank(
+        query,
+        nodes.iter().map(|node| (node.id, node.content.as_str())),
+        opts,
+    );
+    ranked.truncate(top_k);
+    keyword_matches(ranked, nodes)
+}
+
+/// Pair the ranked ids with their nodes, in the order of the ranking.
+///
+/// Ids without a node are skipped.
+pub fn keyword_matches(ranked: Vec<(Uuid, f32)>, nodes: Vec<Node>) -> Vec<KeywordMatch<Node>> {
+    let mut nodes: HashMap<Uuid, Node> = nodes.into_iter().map(|node| (node.id, node)).collect();
+    ranked
+        .into_iter()
+        .filter_map(|(id, score)| {
+            nodes.remove(&id).map(|node| KeywordMatch {
+                score,
+                data: node,
+                data_id: id,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_mixed_text() {
+        assert_eq!(
+            tokenize("Rust's 中文例子, v2!"),
+            vec!["rust", "s", "中", "文", "例", "子", "中文", "文例", "例子", "v2"]
+        );
+        assert!(tokenize(" ,.").is_empty());
+    }
+
+    #[test]
+    fn test_bm25_rank() {
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let texts = [
+            "apple pie with apple sauce",
+            "apple tart",
+            "中文例子",
+            "pear and plum",
+        ];
+        let opts = Bm25Options::default();
+        let ranked = bm25_rank("apple", ids.iter().cloned().zip(texts), &opts);
+        assert_eq!(
+            ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![ids[0], ids[1]]
+        );
+        let ranked = bm25_rank("例子", ids.iter().cloned().zip(texts), &opts);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, ids[2]);
+        assert!(bm25_rank("banana", ids.iter().cloned().zip(texts), &opts).is_empty());
+
+        // the statistics of a corpus are reused across queries
+        let corpus = Bm25Corpus::new(ids.iter().cloned().zip(texts));
+        assert_eq!(corpus.len(), 4);
+        assert_eq!(
+            corpus.rank("apple", &opts),
+            bm25_rank("apple", ids.iter().cloned().zip(texts), &opts)
+        );
+        assert_eq!(corpus.rank("plum pie", &opts).len(), 2);
+    }
+}
@@ -2,3 +2,6 @@ pub mod retriever;
 pub mod router;
 pub mod post_processor;
 pub mod search_result;
+pub mod similarity;
+pub mod keyword;
+pub mod hybrid;
@@ -1,7 +1,23 @@
 use uuid::Uuid;
 
 pub struct SimilarResult<Data> {
+    /// Distance to the query, the lower the more similar.
+    ///
+    /// For a [hybrid search](crate::core::indexing::index::IndexTrait::hybrid_search), `1 - fused score`.
     pub distance: f32,
     pub data: Data,
     pub data_id: Uuid,
-}
\ No newline at end of file
+    /// Scores of the rankings fused by a hybrid search, `None` for a vector search.
+    pub hybrid_scores: Option<HybridScores>,
+}
+
+/// Scores of a [hybrid search](crate::core::indexing::index::IndexTrait::hybrid_search) result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HybridScores {
+    /// The fused score, the higher the more relevant.
+    pub fused: f32,
+    /// Distance of the vector match, `None` if the result only matched keywords.
+    pub vector_distance: Option<f32>,
+    /// BM25 score of the keyword match, `None` if the result only matched the vector search.
+    pub keyword_score: Option<f32>,
+}
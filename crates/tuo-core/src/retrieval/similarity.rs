@@ -0,0 +1,15 @@
+/// Cosine distance between two vectors, i.e. `1 - cosine similarity`, ranging from 0 (same direction) to 2 (opposite).
+///
+/// This is the metric used by vector stores for similarity search. A zero vector has no direction, and its distance to any vector is 1.
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let (dot, norm_a, norm_b) = a
+        .iter()
+        .zip(b.iter())
+        .fold((0.0f32, 0.0f32, 0.0f32), |(dot, norm_a, norm_b), (x, y)| {
+            (dot + x * y, norm_a + x * x, norm_b + y * y)
+        });
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())
+}
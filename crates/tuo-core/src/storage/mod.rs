@@ -1,3 +1,6 @@
 pub mod store;
+pub mod store_health;
 pub mod store_metadata;
+pub mod store_migration;
+pub mod store_models;
 pub mod stored_prompt;
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use async_trait::async_trait;
+use typed_builder::TypedBuilder;
 use uuid::Uuid;
 use tuo_shared::consts::defaults::{D_TABLE_NAME_DOCUMENTS, D_TABLE_NAME_INDEX_METADATA, D_TABLE_NAME_MODELS_METADATA, D_TABLE_NAME_NODES, D_TABLE_NAME_SECTIONS, D_TABLE_NAME_STORE_METADATA, D_TABLE_NAME_TEXT_EMBEDDED};
 use tuo_shared::errors::parts::TuoPartsError;
@@ -7,6 +9,7 @@ use tuo_shared::errors::parts::TuoPartsError;
 use tuo_shared::types::return_type::TuoResult;
 
 use crate::core::indexing::index::IndexTrait;
+use crate::core::indexing::index_bundle::IndexBundle;
 use crate::core::indexing::index_metadata::IndexMetadata;
 use crate::core::source::document::Document;
 use crate::core::source::node::Node;
@@ -14,7 +17,10 @@ use crate::core::source::section::Section;
 use crate::core::source::sources::{SourceData, SourcesId, SourceType};
 use crate::embedding::embedder::EmbedderTrait;
 use crate::model::model_metadata::EmbeddingModelMetadata;
+use crate::storage::store_health::StoreHealthReport;
+use crate::storage::store_migration::{ModelMigrationOptions, ModelMigrationReport};
 use crate::storage::store_metadata::StoreMetadata;
+use crate::storage::store_models::RegisteredModel;
 
 pub struct StoreInput {}
 
@@ -22,6 +28,31 @@ pub struct StoreIndexInfo {}
 
 pub struct PersistResult {}
 
+/// Result of [removing an index](StoreTrait::index_remove) from a store.
+#[derive(Debug, Default)]
+pub struct IndexRemoveResult {
+    pub index_id: Uuid,
+    /// Number of rows removed from each table, keyed by the source type of the table.
+    pub removed: HashMap<SourceType, usize>,
+}
+
+impl IndexRemoveResult {
+    /// Number of rows removed for the source type.
+    pub fn removed_count(&self, source_type: &SourceType) -> usize {
+        self.removed.get(source_type).copied().unwrap_or(0)
+    }
+}
+
+/// Options of [opening](StoreTrait::open_with_options) a store.
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct StoreOpenOptions {
+    /// Open the store with an embedder of another model than the model of the store, e.g. to read its records before migrating it.
+    ///
+    /// The store keeps its model, so searches embedding their queries with the embedder do not compare with the embeddings of the store.
+    #[builder(default)]
+    pub allow_model_mismatch: bool,
+}
+
 /// ## Store
 ///
 /// A store is the largest unit of data organization.
@@ -71,7 +102,9 @@ pub struct PersistResult {}
 ///
 /// #### 7. model table
 ///
-/// Contains a single row of **embedding** [ModelMetadata].
+/// Contains the row of the **embedding** [ModelMetadata] of the store, and the rows of the [registered models](StoreTrait::register_embedding_model).
+///
+/// The text embeddings of each registered model are kept in a table of their own, see [RegisteredModel].
 ///
 /// ### Index
 ///
@@ -108,7 +141,18 @@ pub trait StoreTrait {
     /// 2. Create the essential tables/collections if they don't exist.
     async fn create(store_name: &str, store_folder: &str, embedder: Box<dyn EmbedderTrait>) -> TuoResult<Self> where Self: Sized;
     
-    async fn open(uri: &str, embedder: Box<dyn EmbedderTrait>) -> TuoResult<Self> where Self: Sized;
+    /// Open the store at the uri, failing if the embedder is not of the model of the store
+    ///
+    /// See [open_with_options](StoreTrait::open_with_options).
+    async fn open(uri: &str, embedder: Box<dyn EmbedderTrait>) -> TuoResult<Self> where Self: Sized {
+        Self::open_with_options(uri, embedder, &StoreOpenOptions::default()).await
+    }
+
+    /// Open the store at the uri
+    ///
+    /// The model of the embedder is compared with the model recorded for the store, see [check_model](StoreMetadata::check_model).
+    /// Stores created before their model was recorded take the model of the embedder.
+    async fn open_with_options(uri: &str, embedder: Box<dyn EmbedderTrait>, opts: &StoreOpenOptions) -> TuoResult<Self> where Self: Sized;
 
     // --- Accessors ---
 
@@ -120,7 +164,8 @@ pub trait StoreTrait {
     ///
     /// Each store (usually a database) uses a single dimension/model for all its indices. This is for the sake of uniformity.
     ///
-    /// To switch models, use *reset* method, which will reset the store.
+    /// To switch models, use [migrate_model](StoreTrait::migrate_model), which re-embeds the store with the new model.
+    /// To search with other models as well, use [register_embedding_model](StoreTrait::register_embedding_model).
     fn get_store_model_dimensions(&self) -> i32;
 
     /// Get the uri of the store
@@ -149,8 +194,71 @@ pub trait StoreTrait {
     async fn index_count_records(&self, index_name: &str, source_type: &SourceType) -> TuoResult<usize>;
     
 
-    async fn index_remove(&self, index_id: Uuid) -> TuoResult<()>;
+    /// Remove an index and all its records
+    ///
+    /// Deletes the index metadata along with every document, section, node and text embedding belonging to the index.
+    /// Other indices in the store are left untouched.
+    async fn index_remove(&self, index_id: Uuid) -> TuoResult<IndexRemoveResult>;
+
+    /// Import an index exported with [export_bundle](IndexTrait::export_bundle) under a new name
+    ///
+    /// The records keep their ids and are assigned to the new index. The bundle must have been embedded with a model of the same dimension as the store's.
+    async fn index_import(&self, bundle: IndexBundle, name: &str) -> TuoResult<Self::IndexType> where Self: Sync {
+        let dimension = self.get_store_model_dimensions();
+        if bundle.model.dimensions != dimension {
+            return Err(TuoPartsError::StoreError(format!(
+                "Cannot import index {} embedded with {} of dimension {} into a store of dimension {}",
+                bundle.index_metadata.name, bundle.model.name, bundle.model.dimensions, dimension
+            )).into());
+        }
+        if self.index_exists(name).await? {
+            return Err(TuoPartsError::StoreError(format!("Index {} already exists", name)).into());
+        }
+        let index = self.index_create(name).await?;
+        for source_data in bundle.to_source_data() {
+            if source_data.get_ids().is_empty() {
+                continue;
+            }
+            index.add_source_data(source_data, Default::default()).await?;
+        }
+        Ok(index)
+    }
+
+    /// Check health
+    ///
+    /// Validates the tables and their columns against the store schema, including the dimension of the vector columns, the store metadata against the embedder, and looks for orphaned sections, nodes and text embeddings, including the text embeddings of the registered models.
+    ///
+    /// With `repair`, missing tables are recreated and orphans are removed; the fixed issues are listed in [StoreHealthReport::repaired].
+    async fn check_health(&self, repair: bool) -> TuoResult<StoreHealthReport>;
+
+    /// Migrate the store to another embedding model
+    ///
+    /// Re-embeds the content of all nodes, and the summaries whose text is saved, with the new embedder, in batches, into staging tables.
+    /// Once every batch is done, the text embedding and node tables are replaced by the staging tables, and the store metadata is switched to the new model.
+    /// Until then, the store and its indices keep reading the data of the previous model.
+    ///
+    /// An interrupted migration is resumed by calling it again with the same model; the batches already done are kept.
+    ///
+    /// User query embeddings are a cache of the previous model and are dropped.
+    /// The text embeddings of the [registered models](StoreTrait::register_embedding_model) are kept, and the store cannot be migrated to one of them.
+    async fn migrate_model(&mut self, embedder: Box<dyn EmbedderTrait>, opts: ModelMigrationOptions) -> TuoResult<ModelMigrationReport>;
+
+    // --- Embedding models ---
+
+    /// Register an embedding model besides the model of the store, e.g. a cheap local model next to a hosted one
+    ///
+    /// The model is recorded along with an empty table for its text embeddings, see [RegisteredModel].
+    /// A model registered again, e.g. after the store is reopened, keeps the id it was recorded with, and its text embeddings.
+    ///
+    /// Indices opened afterwards can [embed their nodes](IndexTrait::embed_nodes_with_model) with the model, and search with it by the `model_id` of their search options.
+    /// The model of the store cannot be registered.
+    async fn register_embedding_model(&mut self, embedder: Box<dyn EmbedderTrait>) -> TuoResult<EmbeddingModelMetadata>;
+
+    /// The embedding models recorded for the store, the model of the store first, then the registered models.
+    ///
+    /// Models registered before the store was opened are listed, even if they are not registered again.
+    async fn list_embedding_models(&self) -> TuoResult<Vec<EmbeddingModelMetadata>>;
 
-     /// Check health
-    async fn check_health(&self) -> TuoResult<StoreMetadata>;
+    /// The models registered since the store was opened, with their embedders.
+    fn get_registered_models(&self) -> Vec<RegisteredModel>;
 }
@@ -0,0 +1,62 @@
+use uuid::Uuid;
+
+/// A problem found by [check_health](crate::storage::store::StoreTrait::check_health).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreHealthIssue {
+    /// A table of the store schema does not exist.
+    MissingTable { table: String },
+    /// A table exists but lacks a column of the store schema.
+    MissingColumn { table: String, column: String },
+    /// The vector column of a table has a different dimension than the embedder of the store.
+    DimensionMismatch {
+        table: String,
+        column: String,
+        expected: i32,
+        actual: i32,
+    },
+    /// The store metadata table does not contain exactly one row for the store.
+    StoreMetadataCount { count: usize },
+    /// The model recorded for the store is not the model of the embedder.
+    ModelMismatch {
+        store_model: String,
+        store_dimensions: i32,
+        embedder_model: String,
+        embedder_dimensions: i32,
+    },
+    /// Sections whose document does not exist.
+    OrphanSections { section_ids: Vec<Uuid> },
+    /// Nodes whose document does not exist.
+    OrphanNodes { node_ids: Vec<Uuid> },
+    /// Text embeddings whose source (node, section or document) does not exist.
+    ///
+    /// User queries have no source and are never orphans.
+    OrphanTextEmbedded { text_embedded_ids: Vec<Uuid> },
+    /// Text embeddings of a registered model whose node does not exist.
+    OrphanModelEmbeddings {
+        model_id: Uuid,
+        text_embedded_ids: Vec<Uuid>,
+    },
+}
+
+/// Report of [checking the health](crate::storage::store::StoreTrait::check_health) of a store.
+#[derive(Debug, Default)]
+pub struct StoreHealthReport {
+    /// All issues found, including the repaired ones.
+    pub issues: Vec<StoreHealthIssue>,
+    /// Issues fixed in repair mode.
+    pub repaired: Vec<StoreHealthIssue>,
+}
+
+impl StoreHealthReport {
+    /// Issues that are still present in the store.
+    pub fn unresolved(&self) -> Vec<&StoreHealthIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| !self.repaired.contains(issue))
+            .collect()
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.unresolved().is_empty()
+    }
+}
@@ -4,6 +4,9 @@ use field_types::FieldName;
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
 use crate::model::model_metadata::EmbeddingModelMetadata;
 use crate::types::date_time::TuoDateTime;
 use tuo_utils::datetime::timestamp::now;
@@ -22,4 +25,20 @@ pub struct StoreMetadata {
 
 }
 
-
+impl StoreMetadata {
+    /// Check that the model of the embedder is the model of the store, by name and dimensions.
+    ///
+    /// Stores without model accept any embedder.
+    pub fn check_model(&self, embedder_model: &EmbeddingModelMetadata) -> TuoResult<()> {
+        let Some(model) = &self.model else {
+            return Ok(());
+        };
+        if model.name != embedder_model.name || model.dimensions != embedder_model.dimensions {
+            return Err(TuoPartsError::StoreError(format!(
+                "Store {} uses model {} of dimension {}, but the embedder is of model {} of dimension {}. Migrate the store to the new model, or allow the model mismatch to only read it",
+                self.name, model.name, model.dimensions, embedder_model.name, embedder_model.dimensions
+            )).into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::core::messaging::content::{TextEmbedded, TextEmbeddingOptions, TextSourceType};
+use crate::core::source::node::Node;
+use crate::embedding::embedder::EmbedderTrait;
+use crate::model::model_metadata::EmbeddingModelMetadata;
+
+/// Options of [migrating a store](crate::storage::store::StoreTrait::migrate_model) to another embedding model.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ModelMigrationOptions {
+    /// Number of nodes or texts embedded and persisted at a time.
+    #[builder(default = 64)]
+    pub batch_size: usize,
+    /// Stop after this many batches without committing, to spread a large migration over several calls.
+    ///
+    /// Calling the migration again with the same model resumes where it stopped.
+    #[builder(default)]
+    pub max_batches: Option<usize>,
+}
+
+/// Report of [migrating a store](crate::storage::store::StoreTrait::migrate_model) to another embedding model.
+#[derive(Debug)]
+pub struct ModelMigrationReport {
+    /// The model of the store before the migration.
+    pub previous_model: Option<EmbeddingModelMetadata>,
+    /// The model the store is migrated to.
+    pub model: EmbeddingModelMetadata,
+    /// Nodes whose content was embedded by this call.
+    pub nodes_embedded: usize,
+    /// Nodes migrated by a previous, interrupted call.
+    pub nodes_resumed: usize,
+    /// Nodes and texts staged by a previous call that were deleted or changed in the store since, and are migrated again if still present.
+    pub records_unstaged: usize,
+    /// Summaries embedded by this call.
+    pub texts_embedded: usize,
+    /// Text embeddings that cannot be carried over: user queries, and summaries whose text was not saved.
+    pub texts_dropped: usize,
+    /// Whether the store was switched to the new model.
+    ///
+    /// `false` if the migration stopped after [max_batches](ModelMigrationOptions::max_batches); the store then still uses the previous model.
+    pub committed: bool,
+}
+
+/// Embed a batch of nodes with the new model.
+///
+/// Returns the nodes, pointing to their new content embeddings, along with the embeddings, shared by the nodes of the same content.
+/// Nodes that were not embedded with the previous model are returned as is.
+pub async fn migrate_nodes(
+    embedder: &dyn EmbedderTrait,
+    nodes: Vec<Node>,
+) -> TuoResult<(Vec<Node>, Vec<TextEmbedded>)> {
+    let (embedded, unembedded): (Vec<Node>, Vec<Node>) = nodes
+        .into_iter()
+        .partition(|node| node.content_embeddings_id.is_some());
+    let opt = TextEmbeddingOptions::builder().save_text(true).build();
+    let mut nodes = embedder.embed_nodes(embedded, &opt).await?;
+    let mut text_ids = HashSet::new();
+    let texts_embedded = nodes
+        .iter_mut()
+        .filter_map(|node| {
+            let mut text_embedded = node
+                .content_embeddings
+                .take()
+                .expect("Node embeddings should be present after embedding");
+            // nodes with the same content share their embedding
+            text_ids.insert(text_embedded.id).then(|| {
+                text_embedded.index_id = Some(node.index_id);
+                text_embedded
+            })
+        })
+        .collect();
+    nodes.extend(unembedded);
+    Ok((nodes, texts_embedded))
+}
+
+/// Whether the text embedding is carried over by a migration, i.e. re-embedded from its saved text.
+///
+/// Node contents are migrated along with their nodes, see [migrate_nodes].
+pub fn is_migrated_text(text_embedded: &TextEmbedded) -> bool {
+    match text_embedded.source_type {
+        TextSourceType::UserQuery | TextSourceType::NodeContent => false,
+        _ => text_embedded.text.is_some(),
+    }
+}
+
+/// Embed the saved text of a batch of [migrated texts](is_migrated_text) with the new model, keeping their ids so that sources still refer to them.
+pub async fn migrate_texts(
+    embedder: &dyn EmbedderTrait,
+    texts_embedded: Vec<TextEmbedded>,
+) -> TuoResult<Vec<TextEmbedded>> {
+    let mut result = Vec::with_capacity(texts_embedded.len());
+    for mut text_embedded in texts_embedded {
+        let text = text_embedded.text.clone().unwrap_or_default();
+        let embeddings = embedder.embed_string(text.as_str()).await?;
+        text_embedded.embedding_model = embeddings.model;
+        text_embedded.embeddings = embeddings.vector;
+        text_embedded.embedded_at = embeddings.embedded_at;
+        result.push(text_embedded);
+    }
+    Ok(result)
+}
+
+/// Whether a node staged by a previous call still matches the node of the store, i.e. whether the node was not changed since.
+///
+/// The content embeddings are not compared, the staged node points to its new content embeddings.
+pub fn is_staged_node_current(staged: &Node, live: &Node) -> bool {
+    staged.index_id == live.index_id
+        && staged.document_id == live.document_id
+        && staged.section_id == live.section_id
+        && staged.content == live.content
+        && staged.content_type.as_ref() == live.content_type.as_ref()
+        && staged.content_embeddings_id.is_some() == live.content_embeddings_id.is_some()
+        && staged.tokens == live.tokens
+        && staged.index == live.index
+        && staged.start_char_index == live.start_char_index
+        && staged.end_char_index == live.end_char_index
+}
+
+/// Staged records found to no longer match the store, unstaged before they are staged again, see [run_model_migration].
+#[derive(Default)]
+struct StaleRecords {
+    node_ids: Vec<Uuid>,
+    text_ids: Vec<Uuid>,
+}
+
+impl StaleRecords {
+    async fn unstage(
+        &mut self,
+        staging: &impl ModelMigrationStaging,
+        report: &mut ModelMigrationReport,
+    ) -> TuoResult<()> {
+        if self.node_ids.is_empty() && self.text_ids.is_empty() {
+            return Ok(());
+        }
+        staging.unstage(&self.node_ids, &self.text_ids).await?;
+        report.records_unstaged += self.node_ids.len() + self.text_ids.len();
+        self.node_ids.clear();
+        self.text_ids.clear();
+        Ok(())
+    }
+}
+
+/// Staging area of a store for [migrating](crate::storage::store::StoreTrait::migrate_model) to another embedding model, e.g. a copy of the text embedding and node tables.
+///
+/// Staged records must survive an interrupted migration, so that it can be resumed.
+#[async_trait]
+pub trait ModelMigrationStaging: Sync {
+    /// The staged nodes, without their content embeddings.
+    async fn staged_nodes(&self) -> TuoResult<Vec<Node>>;
+    /// The staged text embeddings, node contents included.
+    async fn staged_texts(&self) -> TuoResult<Vec<TextEmbedded>>;
+    /// Remove staged nodes and text embeddings, along with the staged content embeddings no staged node points to anymore.
+    async fn unstage(&self, node_ids: &[Uuid], text_ids: &[Uuid]) -> TuoResult<()>;
+    /// Stage nodes along with their new content embeddings.
+    ///
+    /// Content embeddings staged for the nodes by an interrupted batch are replaced.
+    async fn stage_nodes(
+        &self,
+        nodes: Vec<Node>,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()>;
+    async fn stage_texts(&self, texts_embedded: Vec<TextEmbedded>) -> TuoResult<()>;
+    /// Replace the text embeddings and nodes of the store by the staged ones, and clear the staging area.
+    async fn commit(&self) -> TuoResult<()>;
+}
+
+/// Migrate the nodes and text embeddings of a store into the staging area, in batches, and commit.
+///
+/// The nodes and text embeddings of the store are read one page at a time, so that only the staged records and a batch are held at once.
+/// Records that are already staged are skipped, unless they were deleted or changed in the store since they were staged: those are unstaged, and migrated again if still present.
+/// The store is responsible for switching its metadata to the new model once committed.
+pub async fn run_model_migration(
+    staging: &impl ModelMigrationStaging,
+    embedder: &dyn EmbedderTrait,
+    mut nodes: BoxStream<'_, TuoResult<Vec<Node>>>,
+    mut texts_embedded: BoxStream<'_, TuoResult<Vec<TextEmbedded>>>,
+    opts: &ModelMigrationOptions,
+) -> TuoResult<ModelMigrationReport> {
+    let mut report = ModelMigrationReport {
+        previous_model: None,
+        model: embedder.get_model_metadata(),
+        nodes_embedded: 0,
+        nodes_resumed: 0,
+        records_unstaged: 0,
+        texts_embedded: 0,
+        texts_dropped: 0,
+        committed: false,
+    };
+    let batch_size = opts.batch_size.max(1);
+    let mut batches = 0;
+    let is_stopped = |batches: usize| {
+        opts.max_batches
+            .is_some_and(|max_batches| batches >= max_batches)
+    };
+
+    // the store may have changed since a previous call, staged records no longer matching it are migrated again
+    let mut staged_nodes: HashMap<Uuid, Node> = staging
+        .staged_nodes()
+        .await?
+        .into_iter()
+        .map(|staged| (staged.id, staged))
+        .collect();
+    let mut staged_text_hashes: HashMap<Uuid, String> = staging
+        .staged_texts()
+        .await?
+        .into_iter()
+        .filter(|staged| !matches!(staged.source_type, TextSourceType::NodeContent))
+        .map(|staged| (staged.id, staged.hash))
+        .collect();
+    let mut stale = StaleRecords::default();
+
+    let mut pending_nodes: Vec<Node> = vec![];
+    let mut read_all = false;
+    while !read_all {
+        match nodes.try_next().await? {
+            Some(page) => {
+                for node in page {
+                    match staged_nodes.remove(&node.id) {
+                        Some(staged) if is_staged_node_current(&staged, &node) => {
+                            report.nodes_resumed += 1
+                        }
+                        Some(_) => {
+                            stale.node_ids.push(node.id);
+                            pending_nodes.push(node);
+                        }
+                        None => pending_nodes.push(node),
+                    }
+                }
+            }
+            // the staged nodes left were deleted from the store
+            None => {
+                read_all = true;
+                stale
+                    .node_ids
+                    .extend(staged_nodes.drain().map(|(id, _)| id));
+            }
+        }
+        while pending_nodes.len() >= batch_size || (read_all && !pending_nodes.is_empty()) {
+            stale.unstage(staging, &mut report).await?;
+            if is_stopped(batches) {
+                return Ok(report);
+            }
+            let batch: Vec<Node> = pending_nodes
+                .drain(..batch_size.min(pending_nodes.len()))
+                .collect();
+            let embedded = batch
+                .iter()
+                .filter(|node| node.content_embeddings_id.is_some())
+                .count();
+            let (nodes, texts_embedded) = migrate_nodes(embedder, batch).await?;
+            report.nodes_embedded += embedded;
+            staging.stage_nodes(nodes, texts_embedded).await?;
+            batches += 1;
+        }
+    }
+
+    let mut pending_texts: Vec<TextEmbedded> = vec![];
+    let mut read_all = false;
+    while !read_all {
+        match texts_embedded.try_next().await? {
+            Some(page) => {
+                for text_embedded in page {
+                    if matches!(text_embedded.source_type, TextSourceType::NodeContent) {
+                        continue;
+                    }
+                    let staged_hash = staged_text_hashes.remove(&text_embedded.id);
+                    if !is_migrated_text(&text_embedded) {
+                        report.texts_dropped += 1;
+                        stale.text_ids.extend(staged_hash.map(|_| text_embedded.id));
+                        continue;
+                    }
+                    match staged_hash {
+                        Some(hash) if hash == text_embedded.hash => {}
+                        Some(_) => {
+                            stale.text_ids.push(text_embedded.id);
+                            pending_texts.push(text_embedded);
+                        }
+                        None => pending_texts.push(text_embedded),
+                    }
+                }
+            }
+            // the staged texts left were deleted from the store
+            None => {
+                read_all = true;
+                stale
+                    .text_ids
+                    .extend(staged_text_hashes.drain().map(|(id, _)| id));
+            }
+        }
+        while pending_texts.len() >= batch_size || (read_all && !pending_texts.is_empty()) {
+            stale.unstage(staging, &mut report).await?;
+            if is_stopped(batches) {
+                return Ok(report);
+            }
+            let batch: Vec<TextEmbedded> = pending_texts
+                .drain(..batch_size.min(pending_texts.len()))
+                .collect();
+            let texts_embedded = migrate_texts(embedder, batch).await?;
+            report.texts_embedded += texts_embedded.len();
+            staging.stage_texts(texts_embedded).await?;
+            batches += 1;
+        }
+    }
+
+    stale.unstage(staging, &mut report).await?;
+    staging.commit().await?;
+    report.committed = true;
+    Ok(report)
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use tuo_shared::consts::defaults::D_TABLE_NAME_TEXT_EMBEDDED;
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::embedding::embedder::EmbedderTrait;
+use crate::model::model_metadata::EmbeddingModelMetadata;
+
+/// An embedding model [registered](crate::storage::store::StoreTrait::register_embedding_model) on a store besides the model of the store, with its embedder.
+///
+/// A registered model only embeds the contents of nodes, into a table of its own, see [model_embeddings_table_name].
+/// Each node embedded by the model has one text embedding there, whose `source_id` is the node, so that the nodes embedded by each model are told apart.
+#[derive(Clone)]
+pub struct RegisteredModel {
+    pub model: EmbeddingModelMetadata,
+    pub embedder: Arc<Box<dyn EmbedderTrait>>,
+}
+
+impl RegisteredModel {
+    pub fn new(embedder: Box<dyn EmbedderTrait>) -> Self {
+        Self {
+            model: embedder.get_model_metadata(),
+            embedder: Arc::new(embedder),
+        }
+    }
+}
+
+/// Get one of the models registered on the store of an index, see [get_registered_model](crate::core::indexing::index::IndexTrait::get_registered_model).
+pub fn find_registered_model(
+    registered_models: &HashMap<Uuid, RegisteredModel>,
+    model_id: Uuid,
+    index_name: &str,
+) -> TuoResult<RegisteredModel> {
+    Ok(registered_models
+        .get(&model_id)
+        .cloned()
+        .ok_or(TuoPartsError::IndexError(format!(
+            "Model {} is not registered on the store of the index {}",
+            model_id, index_name
+        )))?)
+}
+
+/// Name of the table of the text embeddings of a registered model, keyed by the id of the model.
+pub fn model_embeddings_table_name(model_id: Uuid) -> String {
+    format!("{}_{}", D_TABLE_NAME_TEXT_EMBEDDED, model_id.simple())
+}
+
+/// The id of the registered model whose text embeddings the table holds, `None` for other tables.
+pub fn model_embeddings_table_model_id(table_name: &str) -> Option<Uuid> {
+    let model_id = table_name
+        .strip_prefix(D_TABLE_NAME_TEXT_EMBEDDED)?
+        .strip_prefix('_')?;
+    Uuid::try_parse(model_id)
+        .ok()
+        .filter(|id| id.simple().to_string() == model_id)
+}
+
+/// The model to register on a store, taking the id of the recorded model of the same name and dimensions, so that its text embeddings are kept.
+///
+/// Returns whether the model still has to be recorded. The model of the store cannot be registered.
+pub fn model_to_register(
+    store_model: &EmbeddingModelMetadata,
+    recorded: &[EmbeddingModelMetadata],
+    model: EmbeddingModelMetadata,
+) -> TuoResult<(EmbeddingModelMetadata, bool)> {
+    let same_model = |other: &EmbeddingModelMetadata| {
+        other.name == model.name && other.dimensions == model.dimensions
+    };
+    if same_model(store_model) {
+        return Err(TuoPartsError::StoreError(format!(
+            "Model {} of dimension {} is the model of the store",
+            model.name, model.dimensions
+        ))
+        .into());
+    }
+    Ok(
+        match recorded
+            .iter()
+            .find(|recorded| recorded.id != store_model.id && same_model(recorded))
+        {
+            Some(recorded) => (
+                EmbeddingModelMetadata {
+                    id: recorded.id,
+                    ..model
+                },
+                false,
+            ),
+            None => (model, true),
+        },
+    )
+}
+
+/// Fail if a store is [migrated](crate::storage::store::StoreTrait::migrate_model) to one of its registered models, recorded with a table of text embeddings of its own.
+///
+/// The tables of the registered models are kept by a migration: they refer to the nodes by their ids, which the migration keeps.
+pub fn check_migration_model(
+    store_model: &EmbeddingModelMetadata,
+    recorded: &[EmbeddingModelMetadata],
+    model: &EmbeddingModelMetadata,
+) -> TuoResult<()> {
+    match recorded.iter().find(|recorded| {
+        recorded.id != store_model.id
+            && recorded.name == model.name
+            && recorded.dimensions == model.dimensions
+    }) {
+        Some(_) => Err(TuoPartsError::StoreError(format!(
+            "Model {} of dimension {} is registered on the store, the store cannot be migrated to it",
+            model.name, model.dimensions
+        ))
+        .into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_embeddings_table_name() {
+        let model_id = Uuid::new_v4();
+        let table_name = model_embeddings_table_name(model_id);
+        assert_eq!(model_embeddings_table_model_id(&table_name), Some(model_id));
+        assert_eq!(
+            model_embeddings_table_model_id(D_TABLE_NAME_TEXT_EMBEDDED),
+            None
+        );
+        assert_eq!(
+            model_embeddings_table_model_id(&format!("{}_migration", D_TABLE_NAME_TEXT_EMBEDDED)),
+            None
+        );
+        assert_eq!(
+            model_embeddings_table_model_id(&format!(
+                "{}_{}",
+                D_TABLE_NAME_TEXT_EMBEDDED, model_id
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_migration_model() {
+        let model = |name: &str, dimensions: i32| {
+            EmbeddingModelMetadata::builder()
+                .name(name.to_string())
+                .author("tuo".to_string())
+                .url("".to_string())
+                .dimensions(dimensions)
+                .max_input(512)
+                .pricing_per_1k_tokens(0.0)
+                .build()
+        };
+        let store_model = model("hosted", 8);
+        let recorded = vec![store_model.clone(), model("local", 8)];
+        assert!(check_migration_model(&store_model, &recorded, &model("local", 8)).is_err());
+        assert!(check_migration_model(&store_model, &recorded, &model("local", 16)).is_ok());
+        assert!(check_migration_model(&store_model, &recorded, &model("hosted", 8)).is_ok());
+    }
+}
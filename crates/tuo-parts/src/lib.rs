@@ -6,3 +6,6 @@ pub mod query;
 pub mod models;
 pub mod messaging;
 pub mod parsers;
+
+#[cfg(test)]
+mod testing;
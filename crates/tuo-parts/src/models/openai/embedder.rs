@@ -4,7 +4,6 @@ use async_trait::async_trait;
 use tracing::debug;
 use async_openai::types::CreateEmbeddingRequestArgs;
 use tuo_core::core::messaging::content::{TextEmbedded, TextEmbeddingOptions, TextInput};
-use tuo_core::core::source::node::{Node, NodeRelationTrait};
 use tuo_core::embedding::embedder::EmbedderTrait;
 use tuo_core::embedding::embeddings::Embeddings;
 use tuo_core::model::model::ModelTrait;
@@ -81,22 +80,4 @@ impl EmbedderTrait for OpenAIEmbedder {
         let result = input.to_embedded(embeddings, opt);
         Ok(result)
     }
-
-    /// Embeds a list of nodes
-    ///
-    /// Embed result is merged into nodes via [merge_embedded_text](Node::merge_embedded_text)
-    async fn embed_nodes(
-        &self,
-        nodes: Vec<Node>,
-        opt: &TextEmbeddingOptions,
-    ) -> TuoResult<Vec<Node>> {
-        let mut result: Vec<Node> = Vec::new();
-        for mut node in nodes.into_iter() {
-            let input = TextInput::from_node_text(node.content.as_str(), node.id);
-            let text_embedded = self.embed_input(&input, &opt).await?;
-            node.merge_embedded_text(&text_embedded);
-            result.push(node)
-        }
-        Ok(result)
-    }
 }
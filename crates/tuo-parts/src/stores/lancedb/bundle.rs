@@ -0,0 +1,482 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{make_array, Array, ArrayRef, RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use serde_json::{json, Value};
+
+use tuo_core::core::indexing::index_bundle::IndexBundle;
+use tuo_core::core::source::sources::{
+    SourceData, SourceInputData, SourceTableName, SourceType, SourceTypeTrait,
+};
+use tuo_core::types::date_time::TuoDateTime;
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::{now, utc_from_epoch};
+
+use crate::stores::lancedb::schema::{
+    compare_table_schema, convert_record_batch_to_sources, convert_sources_to_table_data,
+    get_all_schema,
+};
+use crate::stores::lancedb::schema_migration::{
+    migrate_record_batch, schema_migrations, LANCEDB_SCHEMA_VERSION,
+};
+use crate::stores::lancedb::vector_storage::LanceDbVectorStorage;
+
+/// Name of the format in the manifest of index bundles.
+pub const INDEX_BUNDLE_FORMAT: &str = "tuo-index-bundle";
+/// Version of the bundle layout, bumped when the manifest or the files of a bundle change.
+pub const INDEX_BUNDLE_FORMAT_VERSION: i32 = 1;
+/// File of the manifest in the bundle folder, written last.
+pub const INDEX_BUNDLE_MANIFEST: &str = "manifest.json";
+
+/// Manifest of an index bundle written by [write_index_bundle].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexBundleManifest {
+    pub format_version: i32,
+    /// Version of tuo that wrote the bundle.
+    pub tuo_version: String,
+    /// Version of the LanceDB schemas the record files follow.
+    pub schema_version: i32,
+    pub index_name: String,
+    pub embedding_model: String,
+    pub dimension: i32,
+    pub exported_at: TuoDateTime,
+    pub files: Vec<IndexBundleFile>,
+}
+
+/// A Parquet file of an index bundle, holding the records of one source type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexBundleFile {
+    pub source_type: SourceType,
+    /// Path relative to the bundle folder.
+    pub path: String,
+    pub records: usize,
+}
+
+impl IndexBundleManifest {
+    fn to_json(&self) -> Value {
+        json!({
+            "format": INDEX_BUNDLE_FORMAT,
+            "format_version": self.format_version,
+            "tuo_version": self.tuo_version,
+            "schema_version": self.schema_version,
+            "index_name": self.index_name,
+            "embedding_model": self.embedding_model,
+            "dimension": self.dimension,
+            "exported_at": self.exported_at.timestamp(),
+            "files": self.files.iter().map(|file| json!({
+                "source_type": file.source_type.as_ref(),
+                "path": file.path,
+                "records": file.records,
+            })).collect::<Vec<Value>>(),
+        })
+    }
+
+    fn from_json(value: &Value) -> TuoResult<Self> {
+        let invalid = |field: &str| {
+            TuoPartsError::StoreError(format!("Invalid index bundle manifest: {}", field))
+        };
+        let str_field = |value: &Value, field: &str| {
+            value[field]
+                .as_str()
+                .map(|value| value.to_string())
+                .ok_or(invalid(field))
+        };
+        let int_field = |value: &Value, field: &str| value[field].as_i64().ok_or(invalid(field));
+
+        if value["format"].as_str() != Some(INDEX_BUNDLE_FORMAT) {
+            return Err(invalid("format").into());
+        }
+        let files = value["files"]
+            .as_array()
+            .ok_or(invalid("files"))?
+            .iter()
+            .map(|file| {
+                Ok(IndexBundleFile {
+                    source_type: str_field(file, "source_type")?
+                        .parse()
+                        .map_err(|_| invalid("source_type"))?,
+                    path: str_field(file, "path")?,
+                    records: int_field(file, "records")? as usize,
+                })
+            })
+            .collect::<TuoResult<Vec<IndexBundleFile>>>()?;
+        Ok(IndexBundleManifest {
+            format_version: int_field(value, "format_version")? as i32,
+            tuo_version: str_field(value, "tuo_version")?,
+            schema_version: int_field(value, "schema_version")? as i32,
+            index_name: str_field(value, "index_name")?,
+            embedding_model: str_field(value, "embedding_model")?,
+            dimension: int_field(value, "dimension")? as i32,
+            exported_at: utc_from_epoch(int_field(value, "exported_at")?),
+            files,
+        })
+    }
+}
+
+/// Write an index bundle to a folder, as one Parquet file per source type and a JSON manifest.
+///
+/// The files follow the LanceDB schemas, whatever the store the bundle was exported from, with full precision vectors.
+/// The manifest is written last, so a folder without one is an incomplete bundle.
+pub fn write_index_bundle(bundle: &IndexBundle, folder: &str) -> TuoResult<IndexBundleManifest> {
+    std::fs::create_dir_all(folder).map_err(|err| {
+        TuoPartsError::StoreError(format!("Error creating bundle folder {}: {}", folder, err))
+    })?;
+    let dimension = bundle.model.dimensions;
+    let mut files = vec![];
+    for source_data in [
+        SourceData::IndexMetadata(vec![bundle.index_metadata.clone()]),
+        SourceData::ModelMetadata(vec![bundle.model.clone()]),
+    ]
+    .into_iter()
+    .chain(bundle.to_source_data())
+    {
+        let file = IndexBundleFile {
+            source_type: source_data.source_type(),
+            path: format!("{}.parquet", source_data.source_type().table_name()),
+            records: source_data.get_ids().len(),
+        };
+        write_parquet(
+            &Path::new(folder).join(&file.path),
+            convert_sources_to_table_data(source_data, dimension, LanceDbVectorStorage::Float32),
+        )?;
+        files.push(file);
+    }
+
+    let manifest = IndexBundleManifest {
+        format_version: INDEX_BUNDLE_FORMAT_VERSION,
+        tuo_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: LANCEDB_SCHEMA_VERSION,
+        index_name: bundle.index_metadata.name.clone(),
+        embedding_model: bundle.model.name.clone(),
+        dimension,
+        // the manifest keeps seconds, like the dates of the records
+        exported_at: utc_from_epoch(now().timestamp()),
+        files,
+    };
+    let content = serde_json::to_string_pretty(&manifest.to_json())
+        .map_err(|err| TuoPartsError::StoreError(format!("Error writing manifest: {}", err)))?;
+    std::fs::write(Path::new(folder).join(INDEX_BUNDLE_MANIFEST), content).map_err(|err| {
+        TuoPartsError::StoreError(format!("Error writing manifest to {}: {}", folder, err))
+    })?;
+    Ok(manifest)
+}
+
+/// Read the manifest of an index bundle.
+pub fn read_index_bundle_manifest(folder: &str) -> TuoResult<IndexBundleManifest> {
+    let content =
+        std::fs::read_to_string(Path::new(folder).join(INDEX_BUNDLE_MANIFEST)).map_err(|err| {
+            TuoPartsError::StoreError(format!("Error reading manifest of {}: {}", folder, err))
+        })?;
+    let value: Value = serde_json::from_str(&content)
+        .map_err(|err| TuoPartsError::StoreError(format!("Error parsing manifest: {}", err)))?;
+    IndexBundleManifest::from_json(&value)
+}
+
+/// Read an index bundle written by [write_index_bundle], to import it with [index_import](tuo_core::storage::store::StoreTrait::index_import).
+///
+/// Bundles of older schemas are upgraded to the current schemas. Bundles of newer formats or schemas are rejected, as well as files missing columns of the current schemas.
+pub fn read_index_bundle(folder: &str) -> TuoResult<IndexBundle> {
+    let manifest = read_index_bundle_manifest(folder)?;
+    if manifest.format_version > INDEX_BUNDLE_FORMAT_VERSION
+        || manifest.schema_version > LANCEDB_SCHEMA_VERSION
+    {
+        return Err(TuoPartsError::StoreError(format!(
+            "Index bundle {} was written by a newer version of tuo ({})",
+            folder, manifest.tuo_version
+        ))
+        .into());
+    }
+    let schemas = get_all_schema(manifest.dimension, LanceDbVectorStorage::Float32);
+    let mut bundle_data = vec![];
+    for file in &manifest.files {
+        let expected_schema =
+            schemas
+                .get(&file.source_type.table_name())
+                .ok_or(TuoPartsError::StoreError(format!(
+                    "Unexpected {} file in index bundle",
+                    file.source_type.as_ref()
+                )))?;
+        let record_batch = read_parquet(
+            &Path::new(folder).join(&file.path),
+            expected_schema,
+            |batch| {
+                migrate_record_batch(
+                    &schema_migrations(),
+                    &file.source_type.table_name(),
+                    manifest.schema_version,
+                    batch,
+                )
+            },
+        )?;
+        let source_data = convert_record_batch_to_sources(
+            SourceInputData::from_data(record_batch, &file.source_type),
+            manifest.dimension,
+        )?;
+        if source_data.get_ids().len() != file.records {
+            return Err(TuoPartsError::StoreError(format!(
+                "Index bundle file {} has {} records instead of {}",
+                file.path,
+                source_data.get_ids().len(),
+                file.records
+            ))
+            .into());
+        }
+        bundle_data.push(source_data);
+    }
+
+    let mut bundle_parts = (None, None, vec![], vec![], vec![], vec![]);
+    for source_data in bundle_data {
+        match source_data {
+            SourceData::IndexMetadata(mut index_metadata) => bundle_parts.0 = index_metadata.pop(),
+            SourceData::ModelMetadata(mut model) => bundle_parts.1 = model.pop(),
+            SourceData::Document(documents) => bundle_parts.2 = documents,
+            SourceData::Section(sections) => bundle_parts.3 = sections,
+            SourceData::Node(nodes) => bundle_parts.4 = nodes,
+            SourceData::TextEmbedded(texts_embedded) => bundle_parts.5 = texts_embedded,
+            SourceData::StoreMetadata(_) => {}
+        }
+    }
+    let (Some(index_metadata), Some(model), documents, sections, nodes, texts_embedded) =
+        bundle_parts
+    else {
+        return Err(TuoPartsError::StoreError(format!(
+            "Index bundle {} has no index or model metadata",
+            folder
+        ))
+        .into());
+    };
+    Ok(IndexBundle::builder()
+        .index_metadata(index_metadata)
+        .model(model)
+        .documents(documents)
+        .sections(sections)
+        .nodes(nodes)
+        .texts_embedded(texts_embedded)
+        .build())
+}
+
+fn write_parquet(
+    path: &Path,
+    reader: Box<dyn RecordBatchReader<Item = Result<RecordBatch, ArrowError>> + Send>,
+) -> TuoResult<()> {
+    let parquet_error = |err: &dyn std::fmt::Display| {
+        TuoPartsError::StoreError(format!("Error writing {}: {}", path.display(), err))
+    };
+    let schema = dates_as_integers(&reader.schema());
+    let file = File::create(path).map_err(|err| parquet_error(&err))?;
+    let mut writer =
+        ArrowWriter::try_new(file, schema.clone(), None).map_err(|err| parquet_error(&err))?;
+    for batch in reader {
+        let batch = batch.map_err(|err| parquet_error(&err))?;
+        let batch = RecordBatch::try_new(schema.clone(), cast_columns(&batch, &schema)?)
+            .map_err(|err| parquet_error(&err))?;
+        writer.write(&batch).map_err(|err| parquet_error(&err))?;
+    }
+    writer.close().map_err(|err| parquet_error(&err))?;
+    Ok(())
+}
+
+/// Read the record batches of a parquet file, upgraded by `migrate` to the expected schema.
+fn read_parquet(
+    path: &Path,
+    expected_schema: &Arc<Schema>,
+    migrate: impl Fn(RecordBatch) -> TuoResult<RecordBatch>,
+) -> TuoResult<Vec<RecordBatch>> {
+    let parquet_error = |err: &dyn std::fmt::Display| {
+        TuoPartsError::StoreError(format!("Error reading {}: {}", path.display(), err))
+    };
+    let file = File::open(path).map_err(|err| parquet_error(&err))?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .and_then(|builder| builder.build())
+        .map_err(|err| parquet_error(&err))?;
+    let migrated_schema = migrate(RecordBatch::new_empty(reader.schema()))?.schema();
+    let (missing_columns, dimension_mismatches) =
+        compare_table_schema(expected_schema, &migrated_schema);
+    if !missing_columns.is_empty() || !dimension_mismatches.is_empty() {
+        return Err(TuoPartsError::StoreError(format!(
+            "Index bundle file {} does not match the schema: missing columns {:?}, dimension mismatches {:?}",
+            path.display(),
+            missing_columns,
+            dimension_mismatches
+        )).into());
+    }
+    let mut batches = vec![];
+    for batch in reader {
+        let batch = batch.map_err(|err| parquet_error(&err))?;
+        let schema = Arc::new(Schema::new(
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(
+                    |field| match expected_schema.field_with_name(field.name()) {
+                        Ok(expected) if expected.data_type() == &DataType::Date64 => {
+                            field.as_ref().clone().with_data_type(DataType::Date64)
+                        }
+                        _ => field.as_ref().clone(),
+                    },
+                )
+                .collect::<Vec<Field>>(),
+        ));
+        batches.push(migrate(
+            RecordBatch::try_new(schema.clone(), cast_columns(&batch, &schema)?)
+                .map_err(|err| parquet_error(&err))?,
+        )?);
+    }
+    Ok(batches)
+}
+
+/// The schema with its Date64 columns as Int64.
+///
+/// Dates are stored as seconds since the epoch, while Parquet truncates Date64 columns to days, so the raw values are written instead.
+fn dates_as_integers(schema: &Schema) -> Arc<Schema> {
+    Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|field| match field.data_type() {
+                DataType::Date64 => field.as_ref().clone().with_data_type(DataType::Int64),
+                _ => field.as_ref().clone(),
+            })
+            .collect::<Vec<Field>>(),
+    ))
+}
+
+/// Reinterpret the columns of the batch with the types of the schema, for types of the same layout, i.e. Date64 and Int64.
+fn cast_columns(batch: &RecordBatch, schema: &Schema) -> TuoResult<Vec<ArrayRef>> {
+    batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(
+            |(column, field)| match column.data_type() == field.data_type() {
+                true => Ok(column.clone()),
+                false => Ok(make_array(
+                    column
+                        .to_data()
+                        .into_builder()
+                        .data_type(field.data_type().clone())
+                        .build()
+                        .map_err(|err| TuoPartsError::StoreError(err.to_string()))?,
+                )),
+            },
+        )
+        .collect()
+}
+
+#[cfg(all(test, feature = "db_sqlite"))]
+mod tests {
+    use test_log::test;
+
+    use tuo_core::core::indexing::index::IndexTrait;
+    use tuo_core::core::messaging::content::{TextInput, TextSourceType};
+    use tuo_core::storage::store::StoreTrait;
+    use tuo_utils::testing::get_random_test_temp_folder;
+
+    use crate::stores::lancedb::store::LanceDb;
+    use crate::stores::sqlite::store::SqliteStore;
+    use crate::testing::{parsed_document, CharHashEmbedder};
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_index_bundle_round_trip() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        index
+            .add_document(
+                vec![parsed_document(index_id, "doc", &["apple", "pear"])],
+                None,
+            )
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .similar_embedded_text(
+                &TextInput::from_user_str("apple"),
+                &TextSourceType::NodeContent,
+                None,
+            )
+            .await
+            .unwrap();
+        let bundle = index.export_bundle().await.unwrap();
+        assert_eq!(bundle.nodes.len(), 2);
+        assert_eq!(bundle.texts_embedded.len(), 3);
+
+        let bundle_folder = format!("{}/bundle", get_random_test_temp_folder());
+        let manifest = write_index_bundle(&bundle, &bundle_folder).unwrap();
+        assert_eq!(
+            read_index_bundle_manifest(&bundle_folder).unwrap(),
+            manifest
+        );
+        let read_bundle = read_index_bundle(&bundle_folder).unwrap();
+        assert_eq!(read_bundle.index_metadata.id, index_id);
+        assert_eq!(read_bundle.model.name, bundle.model.name);
+        let mut texts_embedded = bundle.texts_embedded.clone();
+        let mut read_texts_embedded = read_bundle.texts_embedded.clone();
+        texts_embedded.sort_by_key(|text| text.id);
+        read_texts_embedded.sort_by_key(|text| text.id);
+        for (text, read_text) in texts_embedded.iter().zip(read_texts_embedded.iter()) {
+            assert_eq!(read_text.text, text.text);
+            assert_eq!(read_text.embeddings, text.embeddings);
+            assert_eq!(read_text.embedded_at, text.embedded_at);
+        }
+
+        // import into a store of another backend with the same dimension
+        let other_store = SqliteStore::create(
+            "other_store",
+            get_random_test_temp_folder().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let imported = other_store
+            .index_import(read_bundle.clone(), "imported")
+            .await
+            .unwrap();
+        for source_type in [
+            SourceType::Document,
+            SourceType::Section,
+            SourceType::Node,
+            SourceType::TextEmbedded,
+        ] {
+            assert_eq!(
+                imported.count_records(&source_type).await.unwrap(),
+                index.count_records(&source_type).await.unwrap()
+            );
+        }
+        let results = imported
+            .similar_embedded_text(
+                &TextInput::from_user_str("pear"),
+                &TextSourceType::NodeContent,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.text.as_deref(), Some("pear"));
+        assert!(other_store
+            .index_import(read_bundle.clone(), "imported")
+            .await
+            .is_err());
+
+        let mut other_dimension = read_bundle;
+        other_dimension.model.dimensions = 32;
+        assert!(other_store
+            .index_import(other_dimension, "other_dimension")
+            .await
+            .is_err());
+    }
+}
@@ -1,37 +1,62 @@
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
 
-use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_array::{Array, FixedSizeListArray, RecordBatch, RecordBatchReader, StringArray};
 use async_trait::async_trait;
 use futures::TryStreamExt;
+use lance::dataset::scanner::ColumnOrdering;
+use lance::dataset::Dataset;
 use lancedb::connection::Connection;
 use lancedb::index::MetricType;
 use lancedb::{connect, Table};
-use tracing::{debug, info};
+use tracing::{debug, error, warn};
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use tuo_core::core::indexing::index::IndexTrait;
+use tuo_core::core::indexing::index_insert::{
+    DocumentInsertOptions, DocumentInsertPlan, DocumentInsertReport,
+};
 use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::indexing::index_scan::{ScanCursor, ScanPage};
 use tuo_core::core::messaging::content::{
-    TextEmbedded, TextEmbeddingOptions, TextInput, TextSourceType,
+    QueryEmbeddingCache, TextEmbedded, TextEmbeddedFieldName, TextInput, TextSourceType,
 };
-use tuo_core::core::source::node::{Node, NodeRelationTrait};
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::{Node, NodeFieldName};
+use tuo_core::core::source::section::SectionFieldName;
 use tuo_core::core::source::sources::{
     SourceData, SourceInputData, SourceTableName, SourceType, SourceTypeTrait, SourcesId,
 };
 use tuo_core::embedding::embedder::EmbedderTrait;
 use tuo_core::model::model_metadata::EmbeddingModelMetadata;
 use tuo_core::parsing::document_parser::ParsedDocument;
+use tuo_core::retrieval::keyword::{Bm25Corpus, Bm25Options, KeywordMatch};
 use tuo_core::retrieval::search_result::SimilarResult;
+use tuo_core::retrieval::similarity::cosine_distance;
 use tuo_core::storage::store_metadata::StoreMetadata;
-use tuo_shared::consts::defaults::D_TABLE_NAME_TEXT_EMBEDDED;
+use tuo_core::storage::store_models::{
+    find_registered_model, model_embeddings_table_model_id, model_embeddings_table_name,
+    RegisteredModel,
+};
+use tuo_shared::consts::defaults::{D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_TEXT_EMBEDDED};
 use tuo_shared::errors::parts::TuoPartsError;
 use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::now;
 
 use crate::stores::lancedb::schema::{
-    convert_record_batch_to_sources, convert_record_batch_to_text_embedded_search_result,
-    convert_sources_to_table_data,
+    convert_record_batch_to_sources, convert_record_batch_to_string_rows,
+    convert_record_batch_to_text_embedded_search_result, convert_sources_to_table_data,
+    document_id_column, id_column,
 };
+use crate::stores::lancedb::search_filter::LanceDbSearchFilter;
+use crate::stores::lancedb::snapshot::{restore_table_version, table_uri, SnapshotFolder};
+use crate::stores::lancedb::sql_constructor::{Predicate, SqlValue};
+use crate::stores::lancedb::vector_index::{load_vector_index, LanceDbVectorIndexInfo};
+use crate::stores::lancedb::vector_storage::{decode_vector, LanceDbVectorStorage};
+
+/// Maximum number of ids looked up by one query, keeping the filters of large lookups to a reasonable size.
+pub const SOURCE_IDS_CHUNK_SIZE: usize = 1000;
 
 #[derive(TypedBuilder)]
 pub struct LanceDbIndex {
@@ -41,6 +66,23 @@ pub struct LanceDbIndex {
     /// The embedder to use for the index
     pub embedder: Option<Arc<Box<dyn EmbedderTrait>>>,
     pub model: EmbeddingModelMetadata,
+    /// Reject writes, e.g. for an index opened at a [snapshot](crate::stores::lancedb::store::LanceDb::snapshot_index_open).
+    ///
+    /// Searches of a read-only index do not touch the text embeddings they use, nor cache the embeddings of queries.
+    #[builder(default)]
+    pub read_only: bool,
+    /// The models registered on the store when the index was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+    /// Encoding of the embedding vectors of the store.
+    #[builder(default)]
+    pub vector_storage: LanceDbVectorStorage,
+    /// The copy of the snapshot the index was [opened](crate::stores::lancedb::store::LanceDb::snapshot_index_open) from, removed once the index is dropped.
+    #[builder(default)]
+    pub(crate) snapshot_folder: Option<Arc<SnapshotFolder>>,
+    /// Term statistics of the node contents for keyword search, with the version of the node table they were computed at.
+    #[builder(default)]
+    keyword_corpus: Mutex<Option<(u64, Arc<Bm25Corpus>)>>,
 }
 
 #[async_trait]
@@ -48,26 +90,65 @@ impl IndexTrait for LanceDbIndex {
     type StoreDataType = RecordBatch;
     type QueryOptions = Option<LanceDbIndexSearchOptions>;
 
-    type InsertOptions = Option<()>;
+    type InsertOptions = Option<DocumentInsertOptions>;
     type TableType = Table;
 
     async fn add_document(
         &self,
         data: Vec<ParsedDocument>,
         opt: Self::InsertOptions,
-    ) -> TuoResult<()> {
-        for doc in data {
-            // add nodes first
-            let nodes = doc.to_source_nodes();
-            self.add_to_table(nodes).await?;
-            // add sections
-            let sections = doc.to_source_sections();
-            self.add_to_table(sections).await?;
-            // add docs
-            let parsed_doc = doc.to_source_document();
-            self.add_to_table(parsed_doc).await?;
+    ) -> TuoResult<DocumentInsertReport> {
+        let opt = opt.unwrap_or_default();
+        let existing = match opt.compares_documents() {
+            true => self
+                .get_all_source_data(&SourceType::Document)
+                .await?
+                .get_document()
+                .unwrap_or_default(),
+            false => vec![],
+        };
+        let plan = DocumentInsertPlan::new(data, existing, &opt);
+
+        // one write per table for the whole batch, the documents last,
+        // so that a document is only added once all its nodes and sections are
+        let mut nodes = vec![];
+        let mut sections = vec![];
+        let mut documents = vec![];
+        for doc in plan.documents {
+            nodes.extend(doc.nodes);
+            sections.extend(doc.sections);
+            documents.push(doc.document);
         }
-        Ok(())
+        let mut written: Vec<(SourceType, u64)> = vec![];
+        for source_data in [
+            SourceData::Node(nodes),
+            SourceData::Section(sections),
+            SourceData::Document(documents),
+        ] {
+            if source_data.get_ids().is_empty() {
+                continue;
+            }
+            // a failed write may still have been committed, so it is rolled back as well
+            let source_type = source_data.source_type();
+            let version = match self.open_source_table(&source_type).await {
+                Ok(table) => table_version(&table).await,
+                Err(err) => Err(err),
+            };
+            let result = match version {
+                Ok(version) => {
+                    written.push((source_type, version));
+                    self.add_to_table(source_data).await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                self.rollback_writes(&written).await;
+                return Err(err);
+            }
+        }
+        // the previous documents of changed sources are only removed once their replacements are added
+        self.delete_documents(&plan.removed_document_ids).await?;
+        Ok(plan.report)
     }
 
     async fn add_source_data(
@@ -75,11 +156,7 @@ impl IndexTrait for LanceDbIndex {
         source_data: SourceData,
         opt: Self::InsertOptions,
     ) -> TuoResult<()> {
-        let table_ref = self.open_source_table(&source_data.source_type()).await?;
-        let dimension = self.get_dimension();
-        let insert_data = convert_sources_to_table_data(source_data, dimension);
-        table_ref.add(insert_data).execute().await?;
-        Ok(())
+        self.add_to_table(source_data).await
     }
 
     async fn add_text_embeddings(&self, text_embedded: &Vec<TextEmbedded>) -> TuoResult<()> {
@@ -88,28 +165,173 @@ impl IndexTrait for LanceDbIndex {
         Ok(())
     }
 
+    async fn find_text_embeddings(
+        &self,
+        hashes: &[String],
+        embedding_model: &str,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        let source_type = SourceType::TextEmbedded;
+        let table = self.open_source_table(&source_type).await?;
+        let predicate = Predicate::is_in(TextEmbeddedFieldName::Hash, hashes)
+            .and(Predicate::eq(
+                TextEmbeddedFieldName::EmbeddingModel,
+                embedding_model,
+            ))
+            .and(Predicate::eq(
+                TextEmbeddedFieldName::SourceType,
+                text_source_type.as_ref(),
+            ));
+        let record_batch = table
+            .query()
+            .filter(self.scoped_filter(&source_type, predicate).to_string())
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError("Error collecting text embeddings".to_string())
+            })?;
+        let load_data = SourceInputData::from_data(record_batch, &source_type);
+        Ok(
+            convert_record_batch_to_sources(load_data, self.get_dimension())?
+                .get_text_embedded()
+                .unwrap_or_default(),
+        )
+    }
+
+    async fn touch_text_embeddings(&self, ids: &[Uuid]) -> TuoResult<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        let source_type = SourceType::TextEmbedded;
+        let table = self.open_source_table(&source_type).await?;
+        let native_table = table.as_native().ok_or(TuoPartsError::IndexError(format!(
+            "Table {} is not a local table",
+            table.name()
+        )))?;
+        let predicate = self
+            .scoped_filter(
+                &source_type,
+                Predicate::is_in(TextEmbeddedFieldName::Id, ids),
+            )
+            .to_string();
+        let used_at = SqlValue::from(now()).to_string();
+        native_table
+            .update(
+                Some(predicate.as_str()),
+                vec![(TextEmbeddedFieldName::UsedAt.name(), used_at.as_str())],
+            )
+            .await?;
+        Ok(())
+    }
+
     async fn delete(&self, source_ids: &Vec<Uuid>, source_type: &SourceType) -> TuoResult<()> {
+        self.check_writable()?;
         let conn = self.connect().await?;
         let table = conn.open_table(source_type.table_name()).execute().await?;
 
-        let predicate = format!(
-            "id IN ({})",
-            source_ids
-                .iter()
-                .map(|id| format!("'{}'", id))
-                .collect::<Vec<String>>()
-                .join(",")
-        );
+        let predicate = self
+            .scoped_filter(
+                source_type,
+                Predicate::is_in(id_column(source_type), source_ids),
+            )
+            .to_string();
         table.delete(predicate.as_str()).await?;
         Ok(())
     }
 
-    async fn update(&self, sources: SourceData, opt: Self::InsertOptions) -> TuoResult<()> {
-        let ids = sources.get_ids();
-        let source_type = sources.source_type();
-        let result = self.delete(&ids, &source_type).await?;
-        self.add_source_data(sources, opt).await?;
-        Ok(())
+    async fn delete_documents(&self, document_ids: &[Uuid]) -> TuoResult<()> {
+        if document_ids.is_empty() {
+            return Ok(());
+        }
+        self.check_writable()?;
+        let section_ids = self
+            .select_ids_in(
+                &SourceType::Section,
+                SectionFieldName::Id.name(),
+                SectionFieldName::DocumentId.name(),
+                document_ids,
+            )
+            .await?;
+        let node_ids = self
+            .select_ids_in(
+                &SourceType::Node,
+                NodeFieldName::Id.name(),
+                NodeFieldName::DocumentId.name(),
+                document_ids,
+            )
+            .await?;
+        let source_ids: Vec<Uuid> = document_ids
+            .iter()
+            .chain(section_ids.iter())
+            .chain(node_ids.iter())
+            .cloned()
+            .collect();
+        // the summaries and content embeddings of the records, and the content embeddings their nodes reuse
+        let mut text_embedded_ids: HashSet<Uuid> = self
+            .select_ids_in(
+                &SourceType::TextEmbedded,
+                TextEmbeddedFieldName::Id.name(),
+                TextEmbeddedFieldName::SourceId.name(),
+                &source_ids,
+            )
+            .await?
+            .into_iter()
+            .collect();
+        text_embedded_ids.extend(
+            self.select_ids_in(
+                &SourceType::Node,
+                NodeFieldName::ContentEmbeddingsId.name(),
+                NodeFieldName::DocumentId.name(),
+                document_ids,
+            )
+            .await?,
+        );
+
+        // in the reverse order of additions, so that an interrupted deletion only leaves orphans
+        for (source_type, column) in [
+            (SourceType::Node, NodeFieldName::DocumentId.name()),
+            (SourceType::Section, SectionFieldName::DocumentId.name()),
+            (SourceType::Document, DocumentFieldName::Id.name()),
+        ] {
+            self.delete_in(&source_type, column, document_ids).await?;
+        }
+        // the content embeddings of the registered models, including those not registered on this store instance
+        let connection = self.connect().await?;
+        for table_name in connection.table_names().execute().await? {
+            if model_embeddings_table_model_id(&table_name).is_some() {
+                let table = connection.open_table(table_name).execute().await?;
+                self.delete_table_in(
+                    &table,
+                    &SourceType::TextEmbedded,
+                    TextEmbeddedFieldName::SourceId.name(),
+                    &node_ids,
+                )
+                .await?;
+            }
+        }
+        let text_embedded_ids: Vec<Uuid> = text_embedded_ids.into_iter().collect();
+        let used_ids: HashSet<Uuid> = self
+            .select_ids_in(
+                &SourceType::Node,
+                NodeFieldName::ContentEmbeddingsId.name(),
+                NodeFieldName::ContentEmbeddingsId.name(),
+                &text_embedded_ids,
+            )
+            .await?
+            .into_iter()
+            .collect();
+        let unused_ids: Vec<Uuid> = text_embedded_ids
+            .into_iter()
+            .filter(|id| !used_ids.contains(id))
+            .collect();
+        self.delete_in(
+            &SourceType::TextEmbedded,
+            TextEmbeddedFieldName::Id.name(),
+            &unused_ids,
+        )
+        .await
     }
 
     async fn similar_embedded_text(
@@ -118,31 +340,17 @@ impl IndexTrait for LanceDbIndex {
         text_source_type: &TextSourceType,
         opts: Self::QueryOptions,
     ) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
-        let embedder = self.get_index_embedder().await?;
-        let embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
-        let embedded_text = embedder.embed_input(text, &embedding_opt).await?;
-        // persist text embedded for caching
-        self.add_text_embeddings(&vec![embedded_text.clone()])
-            .await?;
         let opts = opts.unwrap_or(LanceDbIndexSearchOptions::builder().build());
-        let top_k = opts.top_k;
-        let source_type = SourceType::TextEmbedded;
-        let table = self.open_source_table(&source_type).await?;
-        let record_batch = table
-            .search(&embedded_text.embeddings)
-            .prefilter(true)
-            .filter(format!("source_type = '{}'", text_source_type.as_ref()))
-            .metric_type(MetricType::Cosine)
-            .limit(top_k)
-            .execute_stream()
-            .await?
-            .try_collect::<Vec<_>>()
-            .await
-            .expect("Error collecting search results");
-        let result = SourceInputData::from_data(record_batch, &source_type);
-        let data =
-            convert_record_batch_to_text_embedded_search_result(result, self.get_dimension());
-        Ok(data)
+        let embedded_text = self
+            .embed_query_with_model(text, opts.model_id, &self.query_cache(&opts))
+            .await?;
+        self.search_text_embedded(
+            &embedded_text.embeddings,
+            text_source_type,
+            opts.top_k,
+            &opts,
+        )
+        .await
     }
 
     async fn similar_sources(
@@ -151,44 +359,29 @@ impl IndexTrait for LanceDbIndex {
         source_type: &SourceType,
         opts: Self::QueryOptions,
     ) -> TuoResult<SourceData> {
-        // continue to search
-
-        // unwrap or error
-        let source_data = match source_type {
-            SourceType::Node => {
-                // when user ask for similarity search on node, match the node content type among text embedded records.
-                let text_embedded = self
-                    .similar_embedded_text(text, &TextSourceType::NodeContent, opts)
-                    .await?;
-
-                for text_embedded in text_embedded.iter() {
-                    debug!(
-                        "For query text '{:?}': similar embedded text and distance: {:?} {:?}",
-                        text.text, text_embedded.distance, text_embedded.data.text
-                    );
-                }
-
-                // node ids
-                let node_ids: Vec<Uuid> = text_embedded
-                    .iter()
-                    .map(|text_embedded| {
-                        text_embedded
-                            .data
-                            .source_id
-                            .expect("Node id should be present")
-                    })
-                    .collect();
-
-                info!("Node ids: {:?}", node_ids);
-                self.get_source_data_by_ids(source_type, node_ids, true)
-                    .await?
-            }
-            _ => {
-                unimplemented!()
+        match source_type {
+            SourceType::Node => self.similar_nodes(text, opts).await,
+            SourceType::Document | SourceType::Section => {
+                let opts = opts.unwrap_or(LanceDbIndexSearchOptions::builder().build());
+                self.similar_summarized_sources(text, source_type, &opts)
+                    .await
             }
-        };
+            _ => Err(TuoPartsError::IndexError(format!(
+                "The index {} cannot search similar {:?} records",
+                self.index_name, source_type
+            ))
+            .into()),
+        }
+    }
 
-        Ok(source_data)
+    async fn keyword_search(
+        &self,
+        text: &TextInput,
+        top_k: usize,
+        opts: &Bm25Options,
+    ) -> TuoResult<Vec<KeywordMatch<Node>>> {
+        let ranked = self.keyword_corpus().await?.rank(&text.text, opts);
+        self.fetch_keyword_matches(ranked, top_k).await
     }
 
     async fn get_index_embedder(&self) -> TuoResult<Arc<Box<dyn EmbedderTrait>>> {
@@ -211,7 +404,12 @@ impl IndexTrait for LanceDbIndex {
 
     async fn count_records(&self, source_type: &SourceType) -> TuoResult<usize> {
         let table = self.open_source_table(source_type).await?;
-        Ok(table.count_rows(None).await?)
+        Ok(table
+            .count_rows(
+                self.index_filter(source_type)
+                    .map(|filter| filter.to_string()),
+            )
+            .await?)
     }
 
     async fn get_source_data_by_id(
@@ -220,104 +418,144 @@ impl IndexTrait for LanceDbIndex {
         id: Uuid,
     ) -> TuoResult<SourceData> {
         let table = self.open_source_table(source_type).await?;
+        let filter = self.scoped_filter(source_type, Predicate::eq(id_column(source_type), id));
         let record = table
             .query()
-            .filter(format!("id = '{}'", id.to_string()))
+            .filter(filter.to_string())
             .limit(1)
             .execute_stream()
             .await?
             .try_collect::<Vec<_>>()
             .await
-            .expect("Error collecting search results");
+            .map_err(|_| {
+                TuoPartsError::IndexError(format!(
+                    "Error collecting {} results",
+                    source_type.table_name()
+                ))
+            })?;
         let load_data = SourceInputData::from_data(record, &source_type);
 
-        let converted_data = convert_record_batch_to_sources(load_data, self.get_dimension());
+        let converted_data = convert_record_batch_to_sources(load_data, self.get_dimension())?;
 
         Ok(converted_data)
     }
 
     /// Get source data by ids
     ///
-    /// __Note that the order of the ids in the result is not guaranteed to be the same as the input ids.__
-    ///
-    /// So if you need to preserve order, you should use `get_source_data_by_id` method.
+    /// Ids are looked up in chunks of [SOURCE_IDS_CHUNK_SIZE] with one query each. With `preserve_order`, the records are then reordered to follow the ids.
+    /// Ids not found in the index are skipped and logged, see [lookup_source_data_by_ids](IndexTrait::lookup_source_data_by_ids) to get them.
     async fn get_source_data_by_ids(
         &self,
         source_type: &SourceType,
         ids: Vec<Uuid>,
         preserve_order: bool,
     ) -> TuoResult<SourceData> {
-        let table = self.open_source_table(source_type).await?;
+        let converted_data = self
+            .get_source_data_in(source_type, id_column(source_type), &ids)
+            .await?;
+        let found_ids: HashSet<Uuid> = converted_data.get_ids().into_iter().collect();
+        let missing_ids: Vec<&Uuid> = ids.iter().filter(|id| !found_ids.contains(id)).collect();
+        if !missing_ids.is_empty() {
+            warn!(
+                "{} ids not found in {}: {:?}",
+                missing_ids.len(),
+                source_type.table_name(),
+                missing_ids
+            );
+        }
+        Ok(match preserve_order {
+            true => converted_data.order_by_ids(&ids),
+            false => converted_data,
+        })
+    }
 
-        let record = match preserve_order {
-            true => {
-                let mut all_results: Vec<RecordBatch> = Vec::new();
-                for id in ids {
-                    info!("Collecting id: {:?}", id);
-                    let record = table
-                        .query()
-                        .filter(format!("id = '{}'", id.to_string()))
-                        .limit(1)
-                        .execute_stream()
-                        .await?
-                        .try_collect::<Vec<_>>()
-                        .await
-                        .expect("Error collecting search results");
-                    all_results.extend(record);
-                }
-                all_results
-            }
-            false => table
-                .query()
-                .filter(format!(
-                    "id IN ({})",
-                    ids.iter()
-                        .map(|id| format!("'{}'", id))
-                        .collect::<Vec<String>>()
-                        .join(",")
+    async fn get_all_source_data(&self, source_type: &SourceType) -> TuoResult<SourceData> {
+        let table = self.open_source_table(source_type).await?;
+        let mut query = table.query();
+        if let Some(filter) = self.index_filter(source_type) {
+            query = query.filter(filter.to_string());
+        }
+        let record_batch = query
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError(format!(
+                    "Error collecting {} results",
+                    source_type.table_name()
                 ))
-                .execute_stream()
-                .await?
-                .try_collect::<Vec<_>>()
-                .await
-                .expect("Error collecting search results"),
-        };
-        let load_data = SourceInputData::from_data(record, &source_type);
-        let converted_data = convert_record_batch_to_sources(load_data, self.get_dimension());
-        info!("Converted data: {:?}", converted_data);
-
-        Ok(converted_data)
+            })?;
+        let load_data = SourceInputData::from_data(record_batch, source_type);
+        convert_record_batch_to_sources(load_data, self.get_dimension())
     }
 
-    async fn get_source_data_with_relations_by_id(
+    async fn get_source_data_by_document_ids(
         &self,
         source_type: &SourceType,
-        id: Uuid,
+        document_ids: &[Uuid],
     ) -> TuoResult<SourceData> {
-        let data = self.get_source_data_by_id(source_type, id).await?;
-        match data {
-            SourceData::Node(mut nodes) => {
-                for node in nodes.iter_mut() {
-                    let text_embedded_id = node.content_embeddings_id;
-                    if text_embedded_id.is_none() {
-                        continue;
-                    }
-                    let text_embedded_id = text_embedded_id.unwrap();
-                    let text_embedded = self
-                        .get_source_data_by_id(&SourceType::TextEmbedded, text_embedded_id)
-                        .await?
-                        .get_text_embedded();
-                    if text_embedded.is_none() {
-                        continue;
-                    }
-                    let text_embedded = text_embedded.unwrap();
-                    let first_text_embedded = text_embedded.first().unwrap();
-                    node.merge_embedded_text(first_text_embedded);
-                }
-                Ok(SourceData::Node(nodes))
+        let column = document_id_column(source_type).ok_or(TuoPartsError::IndexError(format!(
+            "Records of {} do not belong to documents",
+            source_type.table_name()
+        )))?;
+        self.get_source_data_in(source_type, column, document_ids)
+            .await
+    }
+
+    /// The cursor is pushed down as a filter, and the records after it are sorted by their scan key in the query, which only reads the other columns for the records of the page.
+    ///
+    /// Lance tables are not stored in the order of the scan keys, so each page still reads the scan keys of all the records after the cursor:
+    /// a full scan of `n` records reads about `n² / (2 * page_size)` keys.
+    async fn scan_page(
+        &self,
+        source_type: &SourceType,
+        page_size: usize,
+        cursor: Option<&ScanCursor>,
+    ) -> TuoResult<ScanPage> {
+        let table_name = source_type.table_name();
+        let dataset = Dataset::open(&table_uri(&self.get_store_metadata().uri, &table_name))
+            .await
+            .map_err(|err| {
+                TuoPartsError::IndexError(format!("Cannot open table {}: {}", table_name, err))
+            })?;
+        let filter = match cursor {
+            Some(cursor) => {
+                Some(self.scoped_filter(source_type, after_scan_cursor(source_type, cursor)))
             }
-            _ => Ok(data),
+            None => self.index_filter(source_type),
+        };
+        let scan_error = |err: lance::Error| {
+            TuoPartsError::IndexError(format!("Cannot scan table {}: {}", table_name, err))
+        };
+        let mut scanner = dataset.scan();
+        if let Some(filter) = filter {
+            scanner
+                .filter(filter.to_string().as_str())
+                .map_err(scan_error)?;
         }
+        scanner
+            .order_by(Some(
+                scan_key_columns(source_type)
+                    .into_iter()
+                    .map(|column| ColumnOrdering::asc_nulls_first(column.to_string()))
+                    .collect(),
+            ))
+            .map_err(scan_error)?
+            .limit(Some(page_size as i64), None)
+            .map_err(scan_error)?;
+        let record_batch = scanner
+            .try_into_stream()
+            .await
+            .map_err(scan_error)?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(scan_error)?;
+        let source_data = convert_record_batch_to_sources(
+            SourceInputData::from_data(record_batch, source_type),
+            self.get_dimension(),
+        )?;
+        Ok(ScanPage::new(source_data))
     }
 
     async fn open_source_table(&self, source_type: &SourceType) -> TuoResult<Self::TableType> {
@@ -329,45 +567,128 @@ impl IndexTrait for LanceDbIndex {
 
     async fn get_unembedded_nodes(&self) -> TuoResult<Vec<Node>> {
         let table = self.open_source_table(&SourceType::Node).await?;
+        let filter = self.scoped_filter(
+            &SourceType::Node,
+            Predicate::is_null(NodeFieldName::ContentEmbeddingsId),
+        );
         let nodes = table
             .query()
-            .filter("content_embeddings_id IS NULL")
+            .filter(filter.to_string())
             .execute_stream()
             .await?
             .try_collect::<Vec<_>>()
             .await
-            .unwrap();
+            .map_err(|_| {
+                TuoPartsError::IndexError("Error collecting unembedded nodes".to_string())
+            })?;
         let converted = SourceInputData::from_data(nodes, &SourceType::Node);
-        let nodes = convert_record_batch_to_sources(converted, self.get_dimension())
+        let nodes = convert_record_batch_to_sources(converted, self.get_dimension())?
             .get_node()
-            .unwrap();
+            .unwrap_or_default();
         Ok(nodes)
     }
-    async fn embed_nodes(&self, doc_ids: Vec<Uuid>) -> TuoResult<()> {
-        let unembbedded_nodes = self.get_unembedded_nodes().await?;
-        let embedder = self.get_index_embedder().await?;
-        // Do not duplicate node text to text embeddings to save space
-        let text_embedding_opt = TextEmbeddingOptions::builder().save_text(true).build();
-        let embedded_nodes = embedder
-            .embed_nodes(unembbedded_nodes, &text_embedding_opt)
+    fn get_registered_model(&self, model_id: Uuid) -> TuoResult<RegisteredModel> {
+        find_registered_model(&self.registered_models, model_id, &self.index_name)
+    }
+
+    async fn add_model_embeddings(
+        &self,
+        model_id: Uuid,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        self.check_writable()?;
+        let (table, dimension) = self.open_model_table(model_id).await?;
+        let mut source_data = SourceData::TextEmbedded(texts_embedded);
+        source_data.assign_index_id(self.index_metadata.id);
+        table
+            .add(convert_sources_to_table_data(
+                source_data,
+                dimension,
+                self.vector_storage,
+            ))
+            .execute()
             .await?;
-        // persist the node embeddings first
-        let node_embeddings: Vec<TextEmbedded> = embedded_nodes
-            .iter()
-            .map(|node| {
-                node.content_embeddings
-                    .clone()
-                    .expect("Node embeddings should be present after embedding")
-            })
-            .collect();
-        let source_text_embedded = SourceData::TextEmbedded(node_embeddings);
-        self.add_source_data(source_text_embedded, None).await?;
-        // update the nodes with the embeddings
-        let source_data = SourceData::Node(embedded_nodes);
-        self.update(source_data, None).await?;
         Ok(())
     }
 
+    async fn find_model_embeddings(
+        &self,
+        model_id: Uuid,
+        hashes: &[String],
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        let source_type = SourceType::TextEmbedded;
+        let (table, dimension) = self.open_model_table(model_id).await?;
+        let record_batch = table
+            .query()
+            .filter(
+                self.scoped_filter(
+                    &source_type,
+                    Predicate::is_in(TextEmbeddedFieldName::Hash, hashes),
+                )
+                .to_string(),
+            )
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError("Error collecting text embeddings".to_string())
+            })?;
+        let load_data = SourceInputData::from_data(record_batch, &source_type);
+        Ok(convert_record_batch_to_sources(load_data, dimension)?
+            .get_text_embedded()
+            .unwrap_or_default())
+    }
+
+    async fn get_unembedded_nodes_of_model(&self, model_id: Uuid) -> TuoResult<Vec<Node>> {
+        if model_id == self.model.id {
+            return self.get_unembedded_nodes().await;
+        }
+        let (table, _) = self.open_model_table(model_id).await?;
+        let source_type = SourceType::Node;
+        let mut query = self
+            .open_source_table(&source_type)
+            .await?
+            .query()
+            .select(&[NodeFieldName::Id.name()]);
+        if let Some(filter) = self.index_filter(&source_type) {
+            query = query.filter(filter.to_string());
+        }
+        // the ids of the nodes are read batch by batch and looked up in the table of the model by chunks, so that only the unembedded nodes are loaded
+        let mut batches = query.execute_stream().await?;
+        let mut unembedded_ids = vec![];
+        while let Some(batch) = batches.try_next().await.map_err(|_| {
+            TuoPartsError::IndexError(format!(
+                "Error collecting {} results",
+                source_type.table_name()
+            ))
+        })? {
+            let node_ids: Vec<Uuid> =
+                convert_record_batch_to_string_rows(vec![batch], &[NodeFieldName::Id.name()])
+                    .into_iter()
+                    .filter_map(|row| Uuid::try_parse(row[0].as_deref()?).ok())
+                    .collect();
+            for chunk in node_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+                let embedded_ids: HashSet<Uuid> = self
+                    .select_table_ids(
+                        &table,
+                        &SourceType::TextEmbedded,
+                        TextEmbeddedFieldName::SourceId.name(),
+                        Predicate::is_in(TextEmbeddedFieldName::SourceId, chunk),
+                    )
+                    .await?
+                    .into_iter()
+                    .collect();
+                unembedded_ids.extend(chunk.iter().filter(|id| !embedded_ids.contains(id)));
+            }
+        }
+        Ok(self
+            .get_source_data_in(&source_type, NodeFieldName::Id.name(), &unembedded_ids)
+            .await?
+            .get_node()
+            .unwrap_or_default())
+    }
+
     fn get_dimension(&self) -> i32 {
         self.model.dimensions
     }
@@ -381,21 +702,659 @@ impl IndexTrait for LanceDbIndex {
     }
 }
 
+/// Columns of the [scan key](ScanCursor) of the records of the source type.
+fn scan_key_columns(source_type: &SourceType) -> Vec<&'static str> {
+    match source_type {
+        SourceType::Node => vec![
+            NodeFieldName::DocumentId.name(),
+            NodeFieldName::Index.name(),
+            NodeFieldName::Id.name(),
+        ],
+        _ => vec![id_column(source_type)],
+    }
+}
+
+/// Filter matching the records whose scan key is after the cursor.
+fn after_scan_cursor(source_type: &SourceType, cursor: &ScanCursor) -> Predicate {
+    let after_id = Predicate::gt(id_column(source_type), cursor.id);
+    match (cursor.document_id, cursor.index) {
+        (Some(document_id), Some(index)) => Predicate::gt(NodeFieldName::DocumentId, document_id)
+            .or(Predicate::eq(NodeFieldName::DocumentId, document_id).and(
+                Predicate::gt(NodeFieldName::Index, index).or(Predicate::eq(
+                    NodeFieldName::Index,
+                    index,
+                )
+                .and(after_id)),
+            )),
+        _ => after_id,
+    }
+}
+
 #[derive(TypedBuilder)]
 pub struct LanceDbIndexSearchOptions {
     #[builder(default = 10)]
     pub top_k: usize,
+    /// Number of partitions of the [vector index](crate::stores::lancedb::store::LanceDb::build_vector_index) to search, trading speed for recall.
+    ///
+    /// Ignored by the flat search used when the store has no vector index.
+    #[builder(default)]
+    pub nprobes: Option<usize>,
+    /// Re-rank `top_k * refine_factor` candidates of the vector index by their exact distance.
+    ///
+    /// Ignored by the flat search used when the store has no vector index.
+    #[builder(default)]
+    pub refine_factor: Option<u32>,
+    /// Re-score `top_k * rescore_factor` candidates by the distance to the query of their texts, embedded again at full precision.
+    ///
+    /// Only used by stores of [int8 or binary vectors](LanceDbVectorStorage), whose vectors are scanned and ranked by their distance to the query compressed the same way.
+    /// Each search then embeds up to `top_k * rescore_factor` texts with the embedder of the model searched, as the stores keep no full precision vectors.
+    /// Queries are embedded without the query cache, whose embeddings are compressed as well.
+    #[builder(default)]
+    pub rescore_factor: Option<usize>,
+    /// Restrict the search to part of the index, e.g. one document or one folder.
+    #[builder(default)]
+    pub filter: LanceDbSearchFilter,
+    /// Number of nodes most similar to the query whose distances rank the documents or sections without a summary.
+    ///
+    /// At least `top_k` nodes are searched.
+    #[builder(default = 50)]
+    pub node_candidates: usize,
+    #[builder(default)]
+    pub query_cache: QueryEmbeddingCache,
+    /// Search the node content embeddings of a [registered model](IndexTrait::get_registered_model), instead of the text embeddings of the model of the store.
+    ///
+    /// Summaries are only embedded by the model of the store, so documents and sections are then ranked by their nodes.
+    #[builder(default)]
+    pub model_id: Option<Uuid>,
 }
 
 impl LanceDbIndex {
-    async fn add_to_table(&self, source_data: SourceData) -> TuoResult<()> {
+    /// The vector index of the text embeddings shared by all indices of the store, `None` if searches use a flat scan.
+    pub async fn vector_index(&self) -> TuoResult<Option<LanceDbVectorIndexInfo>> {
+        let table = self.open_source_table(&SourceType::TextEmbedded).await?;
+        load_vector_index(&table).await
+    }
+
+    fn check_writable(&self) -> TuoResult<()> {
+        if self.read_only {
+            return Err(TuoPartsError::IndexError(format!(
+                "Index {} is read-only",
+                self.index_name
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The query cache of a search, which does not persist the embeddings of new queries if the index is read-only.
+    fn query_cache(&self, opts: &LanceDbIndexSearchOptions) -> QueryEmbeddingCache {
+        if opts.rescore_factor.is_some() && !self.vector_storage.is_searchable() {
+            return QueryEmbeddingCache::Disabled;
+        }
+        match opts.query_cache {
+            QueryEmbeddingCache::ReadWrite if self.read_only => QueryEmbeddingCache::ReadOnly,
+            _ => opts.query_cache.clone(),
+        }
+    }
+
+    /// Restore the tables written by a failed batch to their versions before the batch, most recent first.
+    ///
+    /// The tables are restored rather than the records deleted, so that readers never see the nodes of a document missing from its table.
+    /// Writes of other processes to the same tables during the batch are rolled back as well.
+    /// A process interrupted before the rollback can leave nodes without their document,
+    /// which [check_health](crate::stores::lancedb::store::LanceDb::check_health) repairs as orphans.
+    async fn rollback_writes(&self, written: &[(SourceType, u64)]) {
+        let uri = &self.get_store_metadata().uri;
+        for (source_type, version) in written.iter().rev() {
+            if let Err(err) = restore_table_version(uri, &source_type.table_name(), *version).await
+            {
+                error!(
+                    "Error restoring version {} of {}: {:?}",
+                    version,
+                    source_type.table_name(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Add records to their table, assigning them to this index.
+    async fn add_to_table(&self, mut source_data: SourceData) -> TuoResult<()> {
+        self.check_writable()?;
+        source_data.assign_index_id(self.index_metadata.id);
         let table_ref = self.open_source_table(&source_data.source_type()).await?;
         let dimension = self.get_dimension();
-        let insert_data = convert_sources_to_table_data(source_data, dimension);
+        let insert_data =
+            convert_sources_to_table_data(source_data, dimension, self.vector_storage);
         table_ref.add(insert_data).execute().await?;
         Ok(())
     }
 
+    /// Search the `top_k` text embeddings of the given type closest to the embeddings of a query.
+    async fn search_text_embedded(
+        &self,
+        embeddings: &[f32],
+        text_source_type: &TextSourceType,
+        top_k: usize,
+        opts: &LanceDbIndexSearchOptions,
+    ) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
+        let source_type = SourceType::TextEmbedded;
+        let mut predicate =
+            Predicate::eq(TextEmbeddedFieldName::SourceType, text_source_type.as_ref())
+                .and(opts.filter.text_embedded_predicate());
+        if let Some(source_ids) = self
+            .filtered_source_ids(&opts.filter, text_source_type)
+            .await?
+        {
+            if source_ids.is_empty() {
+                return Ok(vec![]);
+            }
+            predicate = predicate.and(Predicate::is_in(
+                TextEmbeddedFieldName::SourceId,
+                source_ids,
+            ));
+        }
+        let (table, dimension) = match opts.model_id.filter(|model_id| *model_id != self.model.id) {
+            Some(model_id) => self.open_model_table(model_id).await?,
+            None => (
+                self.open_source_table(&source_type).await?,
+                self.get_dimension(),
+            ),
+        };
+        let filter = self.scoped_filter(&source_type, predicate).to_string();
+        if !self.vector_storage.is_searchable() {
+            return self
+                .scan_text_embedded(&table, embeddings, filter, top_k, dimension, opts)
+                .await;
+        }
+        let mut query = table
+            .search(embeddings)
+            .prefilter(true)
+            .filter(filter)
+            .metric_type(MetricType::Cosine)
+            .limit(top_k);
+        // fall back to a flat search when the store has no vector index
+        match load_vector_index(&table).await? {
+            Some(_) => {
+                if let Some(nprobes) = opts.nprobes {
+                    query = query.nprobes(nprobes);
+                }
+                if let Some(refine_factor) = opts.refine_factor {
+                    query = query.refine_factor(refine_factor);
+                }
+            }
+            None => query = query.use_index(false),
+        }
+        let record_batch = query
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError("Error collecting search results".to_string())
+            })?;
+        let result = SourceInputData::from_data(record_batch, &source_type);
+        let data = convert_record_batch_to_text_embedded_search_result(result, dimension)?;
+        Ok(data)
+    }
+
+    /// Search the text embeddings of a table whose vectors LanceDB cannot search, comparing them with the query compressed the same way.
+    ///
+    /// Only the ids and compressed vectors of the matching rows are scanned, keeping the closest ones, whose rows are loaded afterwards.
+    /// With a [rescore_factor](LanceDbIndexSearchOptions::rescore_factor), the closest `top_k * rescore_factor` rows are ranked again by the distance to the query of their texts embedded again at full precision.
+    async fn scan_text_embedded(
+        &self,
+        table: &Table,
+        embeddings: &[f32],
+        filter: String,
+        top_k: usize,
+        dimension: i32,
+        opts: &LanceDbIndexSearchOptions,
+    ) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
+        let compressed_query = self.vector_storage.compress(embeddings)?;
+        let candidates_count = top_k * opts.rescore_factor.unwrap_or(1).max(1);
+        let by_distance = |a: &(Uuid, f32), b: &(Uuid, f32)| a.1.total_cmp(&b.1);
+        let mut candidates: Vec<(Uuid, f32)> = vec![];
+        let mut stream = table
+            .query()
+            .select(&[TextEmbeddedFieldName::Id.name(), D_TABLE_COLUMN_NAME_VECTOR])
+            .filter(filter)
+            .execute_stream()
+            .await?;
+        while let Some(batch) = stream.try_next().await.map_err(|_| {
+            TuoPartsError::IndexError("Error collecting scanned vectors".to_string())
+        })? {
+            let ids = batch
+                .column_by_name(TextEmbeddedFieldName::Id.name())
+                .and_then(|array| array.as_any().downcast_ref::<StringArray>())
+                .ok_or(TuoPartsError::IndexError(
+                    "Scanned vectors without an id column".to_string(),
+                ))?;
+            let vectors = batch
+                .column_by_name(D_TABLE_COLUMN_NAME_VECTOR)
+                .and_then(|array| array.as_any().downcast_ref::<FixedSizeListArray>())
+                .ok_or(TuoPartsError::IndexError(
+                    "Scanned vectors without a vector column".to_string(),
+                ))?;
+            for row in 0..batch.num_rows() {
+                let id = Uuid::try_parse(ids.value(row)).map_err(|_| {
+                    TuoPartsError::IndexError(format!(
+                        "Invalid text embedding id {}",
+                        ids.value(row)
+                    ))
+                })?;
+                let vector = decode_vector(&vectors.value(row), dimension)?;
+                candidates.push((id, cosine_distance(&compressed_query, &vector)));
+            }
+            // only the closest candidates are kept as the batches are read
+            if candidates.len() > candidates_count {
+                candidates.select_nth_unstable_by(candidates_count, by_distance);
+                candidates.truncate(candidates_count);
+            }
+        }
+        if candidates.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let distances: HashMap<Uuid, f32> = candidates.iter().cloned().collect();
+        let record_batch = table
+            .query()
+            .filter(
+                Predicate::is_in(TextEmbeddedFieldName::Id, distances.keys().cloned()).to_string(),
+            )
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError("Error collecting search results".to_string())
+            })?;
+        let mut results = convert_record_batch_to_text_embedded_search_result(
+            SourceInputData::from_data(record_batch, &SourceType::TextEmbedded),
+            dimension,
+        )?;
+        let rescoring_embedder = match opts.rescore_factor {
+            Some(_) => Some(
+                match opts.model_id.filter(|model_id| *model_id != self.model.id) {
+                    Some(model_id) => self.get_registered_model(model_id)?.embedder,
+                    None => self.get_index_embedder().await?,
+                },
+            ),
+            None => None,
+        };
+        for result in results.iter_mut() {
+            result.distance = distances[&result.data_id];
+            // texts saved without their text keep the distance of their compressed vector
+            if let (Some(embedder), Some(text)) = (&rescoring_embedder, &result.data.text) {
+                result.data.embeddings = embedder.embed_string(text).await?.vector;
+                result.distance = cosine_distance(embeddings, &result.data.embeddings);
+            }
+        }
+        results.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
+    /// Documents or sections similar to the text, by ascending distance.
+    ///
+    /// Sources with a summary are ranked by the distance of their summary embedding.
+    /// The others are ranked by the distance of their closest node among the
+    /// [node_candidates](LanceDbIndexSearchOptions::node_candidates) nodes most similar to the text.
+    async fn similar_summarized_sources(
+        &self,
+        text: &TextInput,
+        source_type: &SourceType,
+        opts: &LanceDbIndexSearchOptions,
+    ) -> TuoResult<SourceData> {
+        let summary_source_type = match source_type {
+            SourceType::Document => TextSourceType::SummaryDocument,
+            SourceType::Section => TextSourceType::SummarySection,
+            _ => {
+                return Err(TuoPartsError::IndexError(format!(
+                    "Similarity search by summary is not supported for {}",
+                    source_type.table_name()
+                )))?
+            }
+        };
+        let embedded_text = self
+            .embed_query_with_model(text, opts.model_id, &self.query_cache(opts))
+            .await?;
+
+        // distances of the summaries, none for a registered model
+        let mut distances: HashMap<Uuid, f32> = HashMap::new();
+        for result in self
+            .search_text_embedded(
+                &embedded_text.embeddings,
+                &summary_source_type,
+                opts.top_k,
+                opts,
+            )
+            .await?
+        {
+            if let Some(source_id) = result.data.source_id {
+                distances.entry(source_id).or_insert(result.distance);
+            }
+        }
+
+        // distances of the closest nodes
+        let node_distances: HashMap<Uuid, f32> = self
+            .search_text_embedded(
+                &embedded_text.embeddings,
+                &TextSourceType::NodeContent,
+                opts.node_candidates.max(opts.top_k),
+                opts,
+            )
+            .await?
+            .into_iter()
+            .filter_map(|result| Some((result.data.source_id?, result.distance)))
+            .collect();
+        let mut node_scores: HashMap<Uuid, f32> = HashMap::new();
+        if !node_distances.is_empty() {
+            let nodes = self
+                .get_source_data_by_ids(
+                    &SourceType::Node,
+                    node_distances.keys().cloned().collect(),
+                    false,
+                )
+                .await?
+                .get_node()
+                .unwrap_or_default();
+            for node in nodes {
+                let source_id = match source_type {
+                    SourceType::Document => node.document_id,
+                    _ => node.section_id,
+                };
+                let distance = node_distances[&node.id];
+                let score = node_scores.entry(source_id).or_insert(distance);
+                *score = score.min(distance);
+            }
+        }
+
+        let candidate_ids: HashSet<Uuid> = distances
+            .keys()
+            .chain(node_scores.keys())
+            .cloned()
+            .collect();
+        if candidate_ids.is_empty() {
+            return Ok(match source_type {
+                SourceType::Document => SourceData::Document(vec![]),
+                _ => SourceData::Section(vec![]),
+            });
+        }
+        let sources = self
+            .get_source_data_by_ids(source_type, candidate_ids.into_iter().collect(), false)
+            .await?;
+        // sources with a summary are only ranked by it, unless the summaries were not searched
+        let registered_model = opts
+            .model_id
+            .is_some_and(|model_id| model_id != self.model.id);
+        let summarized: HashSet<Uuid> = match &sources {
+            _ if registered_model => HashSet::new(),
+            SourceData::Document(documents) => documents
+                .iter()
+                .filter(|document| document.summary_text_id.is_some())
+                .map(|document| document.id)
+                .collect(),
+            SourceData::Section(sections) => sections
+                .iter()
+                .filter(|section| section.summary_text_id.is_some())
+                .map(|section| section.id)
+                .collect(),
+            _ => HashSet::new(),
+        };
+        for (source_id, distance) in node_scores {
+            if !summarized.contains(&source_id) {
+                distances.entry(source_id).or_insert(distance);
+            }
+        }
+        let mut ranked: Vec<(Uuid, f32)> = distances.into_iter().collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+        ranked.truncate(opts.top_k);
+        let ranked_ids: Vec<Uuid> = ranked.into_iter().map(|(id, _)| id).collect();
+        debug!("Similar {} ids: {:?}", source_type.table_name(), ranked_ids);
+
+        let sources = match sources {
+            SourceData::Document(documents) => SourceData::Document(
+                documents
+                    .into_iter()
+                    .filter(|document| ranked_ids.contains(&document.id))
+                    .collect(),
+            ),
+            SourceData::Section(sections) => SourceData::Section(
+                sections
+                    .into_iter()
+                    .filter(|section| ranked_ids.contains(&section.id))
+                    .collect(),
+            ),
+            sources => sources,
+        };
+        Ok(sources.order_by_ids(&ranked_ids))
+    }
+
+    /// Ids of the sources of the text embeddings of the given type containing a node matching the filter.
+    ///
+    /// `None` if the filter does not restrict nodes, or the text embeddings have no source.
+    async fn filtered_source_ids(
+        &self,
+        filter: &LanceDbSearchFilter,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Option<Vec<Uuid>>> {
+        let source_column = match text_source_type {
+            TextSourceType::UserQuery => return Ok(None),
+            TextSourceType::NodeContent | TextSourceType::SummaryNode => NodeFieldName::Id,
+            TextSourceType::SummarySection => NodeFieldName::SectionId,
+            TextSourceType::SummaryDocument => NodeFieldName::DocumentId,
+        };
+        if !filter.filters_nodes() {
+            return Ok(None);
+        }
+        let mut predicate = filter.node_predicate();
+        if let Some(document_predicate) = filter.document_predicate() {
+            let document_ids = self
+                .select_ids(
+                    &SourceType::Document,
+                    DocumentFieldName::Id.name(),
+                    document_predicate,
+                )
+                .await?;
+            predicate = predicate.and(Predicate::is_in(NodeFieldName::DocumentId, document_ids));
+        }
+        let mut source_ids = self
+            .select_ids(&SourceType::Node, source_column.name(), predicate)
+            .await?;
+        source_ids.sort();
+        source_ids.dedup();
+        Ok(Some(source_ids))
+    }
+
+    /// The table of the node content embeddings of a registered model, with the dimension of the model.
+    async fn open_model_table(&self, model_id: Uuid) -> TuoResult<(Table, i32)> {
+        let registered_model = self.get_registered_model(model_id)?;
+        let table = self
+            .connect()
+            .await?
+            .open_table(model_embeddings_table_name(model_id))
+            .execute()
+            .await?;
+        Ok((table, registered_model.model.dimensions))
+    }
+
+    /// Select the uuid column of the records of this index matching the predicate.
+    async fn select_ids(
+        &self,
+        source_type: &SourceType,
+        column: &str,
+        predicate: Predicate,
+    ) -> TuoResult<Vec<Uuid>> {
+        let table = self.open_source_table(source_type).await?;
+        self.select_table_ids(&table, source_type, column, predicate)
+            .await
+    }
+
+    /// Select the uuid column of the records of this index in a table holding records of the source type, see [select_ids](LanceDbIndex::select_ids).
+    async fn select_table_ids(
+        &self,
+        table: &Table,
+        source_type: &SourceType,
+        column: &str,
+        predicate: Predicate,
+    ) -> TuoResult<Vec<Uuid>> {
+        let record_batch = table
+            .query()
+            .select(&[column])
+            .filter(self.scoped_filter(source_type, predicate).to_string())
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::IndexError(format!(
+                    "Error collecting {} results",
+                    source_type.table_name()
+                ))
+            })?;
+        Ok(convert_record_batch_to_string_rows(record_batch, &[column])
+            .into_iter()
+            .filter_map(|row| Uuid::try_parse(row[0].as_deref()?).ok())
+            .collect())
+    }
+
+    /// Get the records of this index whose column is one of the ids, in chunks of [SOURCE_IDS_CHUNK_SIZE] ids.
+    async fn get_source_data_in(
+        &self,
+        source_type: &SourceType,
+        column: &'static str,
+        ids: &[Uuid],
+    ) -> TuoResult<SourceData> {
+        let table = self.open_source_table(source_type).await?;
+        let mut record: Vec<RecordBatch> = Vec::new();
+        for chunk in ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            let batches = table
+                .query()
+                .filter(
+                    self.scoped_filter(source_type, Predicate::is_in(column, chunk))
+                        .to_string(),
+                )
+                .execute_stream()
+                .await?
+                .try_collect::<Vec<_>>()
+                .await
+                .map_err(|_| {
+                    TuoPartsError::IndexError(format!(
+                        "Error collecting {} results",
+                        source_type.table_name()
+                    ))
+                })?;
+            record.extend(batches);
+        }
+        let load_data = SourceInputData::from_data(record, source_type);
+        convert_record_batch_to_sources(load_data, self.get_dimension())
+    }
+
+    /// Select the uuid column of the records of this index whose `by_column` is one of the ids, in chunks of [SOURCE_IDS_CHUNK_SIZE] ids.
+    async fn select_ids_in(
+        &self,
+        source_type: &SourceType,
+        column: &'static str,
+        by_column: &'static str,
+        ids: &[Uuid],
+    ) -> TuoResult<Vec<Uuid>> {
+        let mut selected_ids = vec![];
+        for ids in ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            selected_ids.extend(
+                self.select_ids(source_type, column, Predicate::is_in(by_column, ids))
+                    .await?,
+            );
+        }
+        Ok(selected_ids)
+    }
+
+    /// Delete the records of this index whose column is one of the ids, in chunks of [SOURCE_IDS_CHUNK_SIZE] ids.
+    async fn delete_in(
+        &self,
+        source_type: &SourceType,
+        column: &'static str,
+        ids: &[Uuid],
+    ) -> TuoResult<()> {
+        let table = self.open_source_table(source_type).await?;
+        self.delete_table_in(&table, source_type, column, ids).await
+    }
+
+    /// Delete the records of this index in a table holding records of the source type, see [delete_in](LanceDbIndex::delete_in).
+    async fn delete_table_in(
+        &self,
+        table: &Table,
+        source_type: &SourceType,
+        column: &'static str,
+        ids: &[Uuid],
+    ) -> TuoResult<()> {
+        for ids in ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            let predicate = self
+                .scoped_filter(source_type, Predicate::is_in(column, ids))
+                .to_string();
+            table.delete(predicate.as_str()).await?;
+        }
+        Ok(())
+    }
+
+    /// Term statistics of the node contents of this index, computed again only once the node table changed.
+    async fn keyword_corpus(&self) -> TuoResult<Arc<Bm25Corpus>> {
+        let table = self.open_source_table(&SourceType::Node).await?;
+        let version = table_version(&table).await?;
+        if let Some((cached_version, corpus)) = self
+            .keyword_corpus
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            if *cached_version == version {
+                return Ok(corpus.clone());
+            }
+        }
+
+        // the contents only
+        let columns = [NodeFieldName::Id.name(), NodeFieldName::Content.name()];
+        let mut query = table.query().select(&columns);
+        if let Some(filter) = self.index_filter(&SourceType::Node) {
+            query = query.filter(filter.to_string());
+        }
+        let record_batch = query
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| TuoPartsError::IndexError("Error collecting node contents".to_string()))?;
+        let contents = convert_record_batch_to_string_rows(record_batch, &columns);
+        let corpus = Arc::new(Bm25Corpus::new(contents.iter().filter_map(|row| {
+            Some((
+                Uuid::try_parse(row[0].as_deref()?).ok()?,
+                row[1].as_deref()?,
+            ))
+        })));
+        *self
+            .keyword_corpus
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some((version, corpus.clone()));
+        Ok(corpus)
+    }
+
+    /// Filter matching the records of this index, if the source type is index-scoped.
+    ///
+    /// All indices share the same document, section, node and text embedding tables, so every query on them must be scoped by `index_id`.
+    fn index_filter(&self, source_type: &SourceType) -> Option<Predicate> {
+        source_type
+            .is_index_scoped()
+            .then(|| Predicate::eq(NodeFieldName::IndexId, self.index_metadata.id))
+    }
+
+    /// Restrict the filter to the records of this index, see [index_filter](LanceDbIndex::index_filter).
+    fn scoped_filter(&self, source_type: &SourceType, filter: Predicate) -> Predicate {
+        match self.index_filter(source_type) {
+            Some(index_filter) => index_filter.and(filter),
+            None => filter,
+        }
+    }
+
     async fn connect(&self) -> TuoResult<Connection> {
         let conn = connect(self.get_store_metadata().uri.as_str())
             .execute()
@@ -403,3 +1362,16 @@ impl LanceDbIndex {
         Ok(conn)
     }
 }
+
+/// The latest version of a table.
+async fn table_version(table: &Table) -> TuoResult<u64> {
+    let version = table
+        .as_native()
+        .ok_or(TuoPartsError::IndexError(format!(
+            "Table {} is not a local table",
+            table.name()
+        )))?
+        .version()
+        .await?;
+    Ok(version)
+}
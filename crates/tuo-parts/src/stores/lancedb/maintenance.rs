@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use lance::dataset::optimize::CompactionOptions;
+use lance::dataset::Dataset;
+use lancedb::table::OptimizeAction;
+use lancedb::Table;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::now;
+
+use crate::stores::lancedb::snapshot::table_uri;
+
+/// Options of [collecting the garbage](crate::stores::lancedb::store::LanceDb::collect_garbage) of a [LanceDb](crate::stores::lancedb::store::LanceDb) store.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct LanceDbGcOptions {
+    /// Remove the text embeddings that no node, section or document references, and the text embeddings of the registered models whose node is gone.
+    #[builder(default = true)]
+    pub remove_unreferenced: bool,
+    /// Remove the embeddings of user queries not used for this long, `None` keeps them all.
+    #[builder(default)]
+    pub user_query_max_age: Option<Duration>,
+    /// Merge the small fragments left by appends and deletes into larger ones.
+    #[builder(default = true)]
+    pub compact: bool,
+    /// Remove the table versions older than this, except the versions of [snapshots](crate::stores::lancedb::store::LanceDb::snapshot_create).
+    ///
+    /// Removed records only free disk space once the versions that still hold them are removed. `None` keeps all versions.
+    #[builder(default = Some(Duration::from_secs(7 * 24 * 60 * 60)))]
+    pub prune_versions_older_than: Option<Duration>,
+    /// Report what would be removed, without removing anything.
+    #[builder(default)]
+    pub dry_run: bool,
+}
+
+/// Report of [collecting the garbage](crate::stores::lancedb::store::LanceDb::collect_garbage) of a [LanceDb](crate::stores::lancedb::store::LanceDb) store.
+#[derive(Debug, Clone, Default)]
+pub struct LanceDbGcReport {
+    /// Text embeddings that no node, section or document references.
+    pub unreferenced_text_embedded_ids: Vec<Uuid>,
+    /// Text embeddings of the registered models whose node is gone, by model.
+    pub unreferenced_model_embedding_ids: HashMap<Uuid, Vec<Uuid>>,
+    /// Embeddings of user queries not used since the maximum age.
+    pub expired_user_query_ids: Vec<Uuid>,
+    /// Fragments merged by the compaction, by table.
+    pub fragments_removed: HashMap<String, usize>,
+    /// Fragments written by the compaction, by table.
+    pub fragments_added: HashMap<String, usize>,
+    /// Table versions removed, by table.
+    pub versions_removed: HashMap<String, u64>,
+    /// Size of the store folder before the collection.
+    pub bytes_before: u64,
+    /// Size of the store folder after the collection.
+    pub bytes_after: u64,
+}
+
+impl LanceDbGcReport {
+    /// Disk space freed by the collection.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Merge the small fragments of the table, returning the number of fragments removed and added.
+pub(crate) async fn compact_table(table: &Table) -> TuoResult<(usize, usize)> {
+    let stats = table
+        .optimize(OptimizeAction::Compact {
+            options: CompactionOptions::default(),
+            remap_options: None,
+        })
+        .await?;
+    Ok(stats
+        .compaction
+        .map(|metrics| (metrics.fragments_removed, metrics.fragments_added))
+        .unwrap_or_default())
+}
+
+/// Remove the versions of the table older than `older_than`, keeping the given versions.
+///
+/// Versions are removed by age, so the versions to keep also keep all the versions after them.
+pub(crate) async fn prune_table(
+    store_uri: &str,
+    table: &Table,
+    older_than: Duration,
+    keep_versions: &[u64],
+) -> TuoResult<u64> {
+    let mut older_than = chrono::Duration::from_std(older_than).map_err(|err| {
+        TuoPartsError::StoreError(format!("Invalid version age {:?}: {}", older_than, err))
+    })?;
+    if !keep_versions.is_empty() {
+        let dataset = Dataset::open(&table_uri(store_uri, table.name()))
+            .await
+            .map_err(|err| {
+                TuoPartsError::StoreError(format!("Cannot open table {}: {}", table.name(), err))
+            })?;
+        let versions = dataset.versions().await.map_err(|err| {
+            TuoPartsError::StoreError(format!(
+                "Cannot list versions of table {}: {}",
+                table.name(),
+                err
+            ))
+        })?;
+        if let Some(oldest_kept) = versions
+            .iter()
+            .filter(|version| keep_versions.contains(&version.version))
+            .map(|version| version.timestamp)
+            .min()
+        {
+            // versions older than the cutoff are removed, so it is moved before the oldest version to keep
+            older_than = older_than.max(now() - oldest_kept + chrono::Duration::seconds(1));
+        }
+    }
+    let stats = table
+        .optimize(OptimizeAction::Prune {
+            older_than,
+            delete_unverified: None,
+        })
+        .await?;
+    Ok(stats.prune.map(|stats| stats.old_versions).unwrap_or(0))
+}
+
+/// Size of all the files in the folder and its sub-folders.
+pub(crate) fn folder_size(path: &Path) -> TuoResult<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => folder_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
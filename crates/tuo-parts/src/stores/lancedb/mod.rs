@@ -1,4 +1,11 @@
+pub mod bundle;
 pub mod index;
+pub mod maintenance;
 pub mod store;
 mod schema;
-mod sql_constructor;
+pub mod search_filter;
+pub mod snapshot;
+pub mod schema_migration;
+pub mod sql_constructor;
+pub mod vector_index;
+pub mod vector_storage;
@@ -2,10 +2,9 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use arrow_array::types::Float32Type;
 use arrow_array::{
-    Array, ArrayRef, Date64Array, Datum, FixedSizeListArray, Float32Array, Float64Array,
-    Int32Array, RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray,
+    Array, Date64Array, Datum, FixedSizeListArray, Float32Array, Float64Array, Int32Array,
+    RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray,
 };
 use arrow_schema::{ArrowError, DataType, Field, Schema};
 use tracing::{debug, info};
@@ -16,21 +15,51 @@ use tuo_core::core::messaging::content::{TextEmbedded, TextEmbeddedFieldName, Te
 use tuo_core::core::source::document::{DocumentFieldName, DocumentSourceType, DocumentType};
 use tuo_core::core::source::node::{ContentType, NodeFieldName};
 use tuo_core::core::source::section::SectionFieldName;
-use tuo_core::core::source::sources::{SourceData, SourceInputData};
+use tuo_core::core::source::sources::{SourceData, SourceInputData, SourceType};
 use tuo_core::model::model_metadata::{EmbeddingModelMetadata, EmbeddingModelMetadataFieldName};
 use tuo_core::retrieval::search_result::SimilarResult;
 use tuo_core::storage::store_metadata::{StoreMetadata, StoreMetadataFieldName};
 use tuo_shared::consts::defaults::{
-    D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_DOCUMENTS, D_TABLE_NAME_INDEX_METADATA,
-    D_TABLE_NAME_MODELS_METADATA, D_TABLE_NAME_NODES, D_TABLE_NAME_SECTIONS,
-    D_TABLE_NAME_STORE_METADATA, D_TABLE_NAME_TEXT_EMBEDDED,
+    D_TABLE_COLUMN_NAME_SCHEMA_VERSION, D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_DOCUMENTS,
+    D_TABLE_NAME_INDEX_METADATA, D_TABLE_NAME_MODELS_METADATA, D_TABLE_NAME_NODES,
+    D_TABLE_NAME_SECTIONS, D_TABLE_NAME_STORE_METADATA, D_TABLE_NAME_TEXT_EMBEDDED,
 };
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
 use tuo_utils::datetime::timestamp::utc_from_epoch;
 
+use crate::stores::lancedb::schema_migration::LANCEDB_SCHEMA_VERSION;
+use crate::stores::lancedb::vector_storage::{decode_vector, LanceDbVectorStorage};
+
+/// The id column of the table of the source type.
+pub(crate) fn id_column(source_type: &SourceType) -> &'static str {
+    match source_type {
+        SourceType::StoreMetadata => StoreMetadataFieldName::Id.name(),
+        SourceType::ModelMetadata => EmbeddingModelMetadataFieldName::Id.name(),
+        SourceType::IndexMetadata => IndexMetadataFieldName::Id.name(),
+        SourceType::TextEmbedded => TextEmbeddedFieldName::Id.name(),
+        SourceType::Document => DocumentFieldName::Id.name(),
+        SourceType::Section => SectionFieldName::Id.name(),
+        SourceType::Node => NodeFieldName::Id.name(),
+    }
+}
+
+/// The column of the table of the source type holding the id of the document of the records, the id itself for documents.
+///
+/// `None` for the records not belonging to a document.
+pub(crate) fn document_id_column(source_type: &SourceType) -> Option<&'static str> {
+    match source_type {
+        SourceType::Document => Some(DocumentFieldName::Id.name()),
+        SourceType::Section => Some(SectionFieldName::DocumentId.name()),
+        SourceType::Node => Some(NodeFieldName::DocumentId.name()),
+        _ => None,
+    }
+}
+
 pub(crate) fn convert_record_batch_to_text_embedded_search_result(
     input: SourceInputData<RecordBatch>,
     dimension: i32,
-) -> Vec<SimilarResult<TextEmbedded>> {
+) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
     match input {
         SourceInputData::TextEmbedded(text_embedded) => {
             convert_record_batch_to_text_embedded(text_embedded, dimension)
@@ -41,8 +70,8 @@ pub(crate) fn convert_record_batch_to_text_embedded_search_result(
 pub(crate) fn convert_record_batch_to_sources(
     sources: SourceInputData<RecordBatch>,
     dimension: i32,
-) -> SourceData {
-    match sources {
+) -> TuoResult<SourceData> {
+    Ok(match sources {
         SourceInputData::StoreMetadata(store_metadata) => {
             SourceData::StoreMetadata(convert_record_batch_to_store_metadata(store_metadata))
         }
@@ -53,7 +82,7 @@ pub(crate) fn convert_record_batch_to_sources(
             SourceData::IndexMetadata(convert_record_batch_to_index_metadata(index_metadata))
         }
         SourceInputData::TextEmbedded(text_embedded) => SourceData::TextEmbedded(
-            convert_record_batch_to_text_embedded(text_embedded, dimension)
+            convert_record_batch_to_text_embedded(text_embedded, dimension)?
                 .into_iter()
                 .map(|data| data.data)
                 .collect(),
@@ -66,25 +95,99 @@ pub(crate) fn convert_record_batch_to_sources(
         }
         SourceInputData::Node(nodes) => SourceData::Node(convert_record_batch_to_node(nodes)),
         _ => unimplemented!(),
-    }
+    })
 }
 
 pub(crate) fn convert_sources_to_table_data(
     sources: SourceData,
     dimension: i32,
+    vector_storage: LanceDbVectorStorage,
 ) -> Box<dyn RecordBatchReader<Item = Result<RecordBatch, ArrowError>> + Send> {
     match sources {
         SourceData::StoreMetadata(store_metadata) => convert_store_metadata(store_metadata),
         SourceData::ModelMetadata(model_metadata) => convert_model_metadata(model_metadata),
         SourceData::IndexMetadata(index_metadata) => convert_indices_metadata(index_metadata),
-        SourceData::TextEmbedded(text_embedded) => convert_texts_embedded(text_embedded, dimension),
+        SourceData::TextEmbedded(text_embedded) => {
+            convert_texts_embedded(text_embedded, dimension, vector_storage)
+        }
         SourceData::Document(documents) => convert_documents(documents),
         SourceData::Section(sections) => convert_sections(sections),
         SourceData::Node(nodes) => convert_nodes(nodes, dimension),
     }
 }
 
-pub(crate) fn get_all_schema(dimension: i32) -> HashMap<String, Arc<Schema>> {
+/// Convert the string columns of record batches to rows of optional values, in the order of the columns.
+///
+/// Used for projected queries, e.g. reading only the ids of a table.
+pub(crate) fn convert_record_batch_to_string_rows(
+    record_batch: Vec<RecordBatch>,
+    columns: &[&str],
+) -> Vec<Vec<Option<String>>> {
+    record_batch
+        .iter()
+        .flat_map(|batch| {
+            let arrays = columns
+                .iter()
+                .map(|column| {
+                    batch
+                        .column_by_name(column)
+                        .unwrap()
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap()
+                })
+                .collect::<Vec<&StringArray>>();
+            (0..batch.num_rows())
+                .map(|row| {
+                    arrays
+                        .iter()
+                        .map(|array| match array.is_null(row) {
+                            true => None,
+                            false => Some(array.value(row).to_string()),
+                        })
+                        .collect::<Vec<Option<String>>>()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Compare the schema of an existing table against the expected schema.
+///
+/// Returns the expected columns missing from the table, and the vector columns whose dimension differs as `(column, expected, actual)`.
+pub(crate) fn compare_table_schema(
+    expected: &Schema,
+    actual: &Schema,
+) -> (Vec<String>, Vec<(String, i32, i32)>) {
+    let mut missing_columns = vec![];
+    let mut dimension_mismatches = vec![];
+    for field in expected.fields() {
+        match actual.field_with_name(field.name()) {
+            Err(_) => missing_columns.push(field.name().to_string()),
+            Ok(actual_field) => {
+                if let (
+                    DataType::FixedSizeList(_, expected_size),
+                    DataType::FixedSizeList(_, actual_size),
+                ) = (field.data_type(), actual_field.data_type())
+                {
+                    if expected_size != actual_size {
+                        dimension_mismatches.push((
+                            field.name().to_string(),
+                            *expected_size,
+                            *actual_size,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    (missing_columns, dimension_mismatches)
+}
+
+pub(crate) fn get_all_schema(
+    dimension: i32,
+    vector_storage: LanceDbVectorStorage,
+) -> HashMap<String, Arc<Schema>> {
     let mut schema_map = HashMap::new();
 
     schema_map.insert(
@@ -110,7 +213,7 @@ pub(crate) fn get_all_schema(dimension: i32) -> HashMap<String, Arc<Schema>> {
 
     schema_map.insert(
         D_TABLE_NAME_TEXT_EMBEDDED.to_string(),
-        text_embedded_schema(dimension),
+        text_embedded_schema(dimension, vector_storage),
     );
 
     schema_map
@@ -128,6 +231,7 @@ fn store_metadata_schema() -> Arc<Schema> {
         ),
         Field::new(StoreMetadataFieldName::ModelId.name(), DataType::Utf8, true),
         Field::new(StoreMetadataFieldName::Uri.name(), DataType::Utf8, false),
+        Field::new(D_TABLE_COLUMN_NAME_SCHEMA_VERSION, DataType::Int32, true),
     ]))
 }
 
@@ -159,6 +263,10 @@ fn convert_store_metadata(
                 Arc::new(StringArray::from_iter_values(
                     sources.iter().map(|data| data.uri.clone()),
                 )),
+                Arc::new(Int32Array::from(vec![
+                    LANCEDB_SCHEMA_VERSION;
+                    sources.len()
+                ])),
             ],
         )
         .unwrap()]
@@ -588,8 +696,10 @@ fn convert_record_batch_to_model_metadata(
 }
 
 /// Schema for [TextEmbedded](tuo_core::core::messaging::content::TextEmbedded)
+///
+/// The vector column holds the components in the encoding of the [vector storage](LanceDbVectorStorage).
 // Refactor using TextEmbeddedFieldName
-fn text_embedded_schema(dimension: i32) -> Arc<Schema> {
+fn text_embedded_schema(dimension: i32, vector_storage: LanceDbVectorStorage) -> Arc<Schema> {
     Arc::new(Schema::new(vec![
         Field::new(TextEmbeddedFieldName::Id.name(), DataType::Utf8, false),
         Field::new(TextEmbeddedFieldName::Text.name(), DataType::Utf8, true),
@@ -606,10 +716,7 @@ fn text_embedded_schema(dimension: i32) -> Arc<Schema> {
         ),
         Field::new(
             D_TABLE_COLUMN_NAME_VECTOR,
-            DataType::FixedSizeList(
-                Arc::new(Field::new("item", DataType::Float32, true)),
-                dimension,
-            ),
+            vector_storage.vector_type(dimension),
             true,
         ),
         Field::new(
@@ -628,17 +735,19 @@ fn text_embedded_schema(dimension: i32) -> Arc<Schema> {
             false,
         ),
         Field::new(TextEmbeddedFieldName::SourceId.name(), DataType::Utf8, true),
+        Field::new(TextEmbeddedFieldName::IndexId.name(), DataType::Utf8, true),
     ]))
 }
 
 fn convert_texts_embedded(
     embedding_result: Vec<TextEmbedded>,
     dimension: i32,
+    vector_storage: LanceDbVectorStorage,
 ) -> Box<dyn RecordBatchReader<Item = Result<RecordBatch, ArrowError>> + Send> {
     let sources = embedding_result;
     let batches = RecordBatchIterator::new(
         vec![RecordBatch::try_new(
-            text_embedded_schema(dimension),
+            text_embedded_schema(dimension, vector_storage),
             vec![
                 Arc::new(StringArray::from_iter_values(
                     sources.iter().map(|data| data.id.to_string()),
@@ -658,19 +767,10 @@ fn convert_texts_embedded(
                 Arc::new(Date64Array::from_iter_values(
                     sources.iter().map(|(er)| er.created_at.timestamp()),
                 )),
-                Arc::new(
-                    FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
-                        sources.iter().map(|data| {
-                            Some(
-                                data.embeddings
-                                    .iter()
-                                    .map(|v| Some(*v))
-                                    .collect::<Vec<Option<f32>>>(),
-                            )
-                        }),
-                        dimension,
-                    ),
-                ),
+                Arc::new(vector_storage.encode(
+                    sources.iter().map(|data| data.embeddings.as_slice()),
+                    dimension,
+                )),
                 Arc::new(Date64Array::from_iter_values(
                     sources.iter().map(|(er)| er.embedded_at.timestamp()),
                 )),
@@ -686,12 +786,18 @@ fn convert_texts_embedded(
                         .map(|data| data.source_id.map(|id| id.to_string()))
                         .collect::<Vec<Option<String>>>(),
                 )),
+                Arc::new(StringArray::from(
+                    sources
+                        .iter()
+                        .map(|data| data.index_id.map(|id| id.to_string()))
+                        .collect::<Vec<Option<String>>>(),
+                )),
             ],
         )
         .unwrap()]
         .into_iter()
         .map(Ok),
-        text_embedded_schema(dimension),
+        text_embedded_schema(dimension, vector_storage),
     );
     Box::new(batches)
 }
@@ -699,12 +805,12 @@ fn convert_texts_embedded(
 fn convert_record_batch_to_text_embedded(
     record_batch: Vec<RecordBatch>,
     dimension: i32,
-) -> Vec<SimilarResult<TextEmbedded>> {
+) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
     record_batch
         .iter()
         .flat_map(|batch| {
             (0..batch.num_rows())
-                .map(|row| {
+                .map(|row| -> TuoResult<SimilarResult<TextEmbedded>> {
                     let id = batch
                         .column_by_name(TextEmbeddedFieldName::Id.name())
                         .unwrap()
@@ -750,30 +856,19 @@ fn convert_record_batch_to_text_embedded(
                         .downcast_ref::<Date64Array>()
                         .unwrap()
                         .value(row);
+                    // decoded from the encoding of the vector storage
                     let embeddings = match batch
                         .column_by_name(D_TABLE_COLUMN_NAME_VECTOR)
-                        .unwrap()
-                        .as_any()
-                        .downcast_ref::<FixedSizeListArray>()
+                        .and_then(|array| array.as_any().downcast_ref::<FixedSizeListArray>())
+                        .filter(|array| !array.is_null(row))
                     {
-                        Some(array) => {
-                            // use the first array of the vector array
-                            let array: ArrayRef = array.value(row);
-                            // specific to dimension size
-                            let mut embeddings: Vec<f32> = Vec::with_capacity(dimension as usize);
-                            for i in 0..dimension {
-                                let value = array
-                                    .as_any()
-                                    .downcast_ref::<Float32Array>()
-                                    .unwrap()
-                                    .value(i as usize);
-
-                                embeddings.push(value);
-                            }
-                            embeddings
-                        }
+                        Some(array) => decode_vector(&array.value(row), dimension)?,
                         None => {
-                            vec![0.0; dimension as usize]
+                            return Err(TuoPartsError::IndexError(format!(
+                                "Text embedding {} has no vector",
+                                id
+                            ))
+                            .into())
                         }
                     };
                     let embedded_at = batch
@@ -812,6 +907,12 @@ fn convert_record_batch_to_text_embedded(
                         }
                         None => None,
                     };
+                    // missing from the batches of tables not migrated yet, see [schema_migrations](crate::stores::lancedb::schema_migration::schema_migrations)
+                    let index_id = batch
+                        .column_by_name(TextEmbeddedFieldName::IndexId.name())
+                        .and_then(|column| column.as_any().downcast_ref::<StringArray>())
+                        .filter(|array| !array.is_null(row))
+                        .and_then(|array| Uuid::try_parse(array.value(row)).ok());
                     let distance_column = batch.column_by_name("_distance");
 
                     let distance = match distance_column {
@@ -833,15 +934,17 @@ fn convert_record_batch_to_text_embedded(
                         used_at: utc_from_epoch(used_at),
                         source_type: TextSourceType::from_str(source_type).unwrap(),
                         source_id: source_id.map(|id| Uuid::try_parse(id).unwrap()),
+                        index_id,
                     };
                     let search_result = SimilarResult {
                         data_id: text_embedded.id,
                         data: text_embedded,
                         distance: distance.unwrap_or(0.0),
+                        hybrid_scores: None,
                     };
-                    search_result
+                    Ok(search_result)
                 })
-                .collect::<Vec<SimilarResult<TextEmbedded>>>()
+                .collect::<Vec<_>>()
         })
         .collect()
 }
@@ -865,6 +968,7 @@ fn document_schema() -> Arc<Schema> {
             DataType::Utf8,
             true,
         ),
+        Field::new(DocumentFieldName::ContentHash.name(), DataType::Utf8, true),
     ]))
 }
 
@@ -903,7 +1007,19 @@ fn convert_documents(
                 Arc::new(StringArray::from(
                     sources
                         .iter()
-                        .map(|data| data.summary.as_ref().map(|summary| summary.id.to_string()))
+                        .map(|data| {
+                            data.summary
+                                .as_ref()
+                                .map(|summary| summary.id)
+                                .or(data.summary_text_id)
+                                .map(|id| id.to_string())
+                        })
+                        .collect::<Vec<Option<String>>>(),
+                )),
+                Arc::new(StringArray::from(
+                    sources
+                        .iter()
+                        .map(|data| data.content_hash.clone())
                         .collect::<Vec<Option<String>>>(),
                 )),
             ],
@@ -996,6 +1112,11 @@ fn convert_record_batch_to_document(
                         }
                         None => None,
                     };
+                    let content_hash = batch
+                        .column_by_name(DocumentFieldName::ContentHash.name())
+                        .and_then(|array| array.as_any().downcast_ref::<StringArray>())
+                        .filter(|array| !array.is_null(row))
+                        .map(|array| array.value(row).to_string());
 
                     tuo_core::core::source::document::Document {
                         id: Uuid::try_parse(id).unwrap(),
@@ -1005,6 +1126,7 @@ fn convert_record_batch_to_document(
                         raw_content: content.map(|content| content.to_string()),
                         source_type: DocumentSourceType::from_str(source_type).unwrap(),
                         source_uri: source_uri.to_string(),
+                        content_hash,
                         summary: None,
                         summary_text_id: summary_text_id.map(|id| Uuid::try_parse(id).unwrap()),
                     }
@@ -1117,7 +1239,13 @@ fn convert_sections(
                 Arc::new(StringArray::from(
                     sources
                         .iter()
-                        .map(|data| data.summary.as_ref().map(|summary| summary.id.to_string()))
+                        .map(|data| {
+                            data.summary
+                                .as_ref()
+                                .map(|summary| summary.id)
+                                .or(data.summary_text_id)
+                                .map(|id| id.to_string())
+                        })
                         .collect::<Vec<Option<String>>>(),
                 )),
             ],
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{
+    new_null_array, Array, ArrayRef, Int32Array, RecordBatch, RecordBatchIterator, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::TryStreamExt;
+use lancedb::connection::{Connection, CreateTableMode};
+use tracing::{debug, info};
+
+use tuo_core::core::messaging::content::TextEmbeddedFieldName;
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::NodeFieldName;
+use tuo_core::core::source::section::SectionFieldName;
+use tuo_shared::consts::defaults::{
+    D_TABLE_COLUMN_NAME_SCHEMA_VERSION, D_TABLE_NAME_DOCUMENTS, D_TABLE_NAME_NODES,
+    D_TABLE_NAME_SECTIONS, D_TABLE_NAME_STORE_METADATA, D_TABLE_NAME_TEXT_EMBEDDED,
+};
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::stores::lancedb::schema::convert_record_batch_to_string_rows;
+use crate::stores::lancedb::snapshot::remove_snapshots;
+
+/// Version of the table schemas written by this library, recorded in the store metadata table.
+///
+/// Stores created before the version was recorded are at version 0.
+pub const LANCEDB_SCHEMA_VERSION: i32 = 3;
+
+/// A change of the schema of a table.
+///
+/// Changes are skipped when already applied, so that an interrupted migration can be run again.
+pub enum SchemaChange {
+    /// Add a column, filled for existing rows with the default, or nulls if there is none.
+    AddColumn {
+        table: &'static str,
+        field: Field,
+        default: Option<fn(usize) -> ArrayRef>,
+    },
+    /// Rename a column, keeping its values.
+    RenameColumn {
+        table: &'static str,
+        from: &'static str,
+        to: &'static str,
+    },
+    /// Fill the nulls of a string column, e.g. added by an earlier change, with the values of the records of other tables referring to the rows by their `id`.
+    ///
+    /// Rows no record refers to keep their nulls. Only the tables of a store are filled, batches read elsewhere are kept as is, see [migrate_record_batch].
+    FillFromReferences {
+        table: &'static str,
+        column: &'static str,
+        /// The referring tables, each with its column holding the id of the row and its column holding the value.
+        references: Vec<(&'static str, &'static str, &'static str)>,
+    },
+}
+
+impl SchemaChange {
+    fn table(&self) -> &'static str {
+        match self {
+            SchemaChange::AddColumn { table, .. } => table,
+            SchemaChange::RenameColumn { table, .. } => table,
+            SchemaChange::FillFromReferences { table, .. } => table,
+        }
+    }
+
+    fn is_applied(&self, schema: &Schema) -> bool {
+        match self {
+            SchemaChange::AddColumn { field, .. } => schema.field_with_name(field.name()).is_ok(),
+            SchemaChange::RenameColumn { from, .. } => schema.field_with_name(from).is_err(),
+            // filling only the nulls, the change can be applied again
+            SchemaChange::FillFromReferences { .. } => false,
+        }
+    }
+
+    fn apply(&self, batch: RecordBatch) -> TuoResult<RecordBatch> {
+        let schema = batch.schema();
+        let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+        let mut columns = batch.columns().to_vec();
+        match self {
+            SchemaChange::AddColumn { field, default, .. } => {
+                let column = match default {
+                    Some(default) => default(batch.num_rows()),
+                    None => new_null_array(field.data_type(), batch.num_rows()),
+                };
+                fields.push(field.clone());
+                columns.push(column);
+            }
+            SchemaChange::RenameColumn { from, to, .. } => {
+                let (position, field) =
+                    schema
+                        .column_with_name(from)
+                        .ok_or(TuoPartsError::StoreError(format!(
+                            "Column {} does not exist",
+                            from
+                        )))?;
+                fields[position] = field.clone().with_name(*to);
+            }
+            SchemaChange::FillFromReferences { .. } => return Ok(batch),
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).map_err(|err| {
+            TuoPartsError::StoreError(format!("Error migrating table {}: {}", self.table(), err))
+                .into()
+        })
+    }
+}
+
+/// The changes bringing the tables of a store to a schema version.
+pub struct SchemaMigration {
+    /// The version reached once the changes are applied.
+    pub version: i32,
+    pub changes: Vec<SchemaChange>,
+}
+
+/// The registry of schema migrations, in ascending order of version.
+///
+/// Bump [LANCEDB_SCHEMA_VERSION] and register a migration whenever a schema in `schema.rs` changes.
+pub(crate) fn schema_migrations() -> Vec<SchemaMigration> {
+    vec![
+        SchemaMigration {
+            version: 1,
+            changes: vec![SchemaChange::AddColumn {
+                table: D_TABLE_NAME_STORE_METADATA,
+                field: Field::new(D_TABLE_COLUMN_NAME_SCHEMA_VERSION, DataType::Int32, true),
+                default: None,
+            }],
+        },
+        SchemaMigration {
+            version: 2,
+            changes: vec![SchemaChange::AddColumn {
+                table: D_TABLE_NAME_DOCUMENTS,
+                field: Field::new(DocumentFieldName::ContentHash.name(), DataType::Utf8, true),
+                default: None,
+            }],
+        },
+        SchemaMigration {
+            version: 3,
+            changes: vec![
+                SchemaChange::AddColumn {
+                    table: D_TABLE_NAME_TEXT_EMBEDDED,
+                    field: Field::new(TextEmbeddedFieldName::IndexId.name(), DataType::Utf8, true),
+                    default: None,
+                },
+                // text embeddings belong to the index of the sources embedded, user queries stay unscoped
+                SchemaChange::FillFromReferences {
+                    table: D_TABLE_NAME_TEXT_EMBEDDED,
+                    column: TextEmbeddedFieldName::IndexId.name(),
+                    references: vec![
+                        (
+                            D_TABLE_NAME_NODES,
+                            NodeFieldName::ContentEmbeddingsId.name(),
+                            NodeFieldName::IndexId.name(),
+                        ),
+                        (
+                            D_TABLE_NAME_SECTIONS,
+                            SectionFieldName::SummaryTextId.name(),
+                            SectionFieldName::IndexId.name(),
+                        ),
+                        (
+                            D_TABLE_NAME_DOCUMENTS,
+                            DocumentFieldName::SummaryTextId.name(),
+                            DocumentFieldName::IndexId.name(),
+                        ),
+                    ],
+                },
+            ],
+        },
+    ]
+}
+
+/// Upgrade a batch of a table written at an older schema version, e.g. read from an [index bundle](crate::stores::lancedb::bundle).
+pub(crate) fn migrate_record_batch(
+    migrations: &[SchemaMigration],
+    table: &str,
+    version: i32,
+    mut batch: RecordBatch,
+) -> TuoResult<RecordBatch> {
+    for change in migrations
+        .iter()
+        .filter(|migration| migration.version > version)
+        .flat_map(|migration| migration.changes.iter())
+        .filter(|change| change.table() == table)
+    {
+        if !change.is_applied(&batch.schema()) {
+            batch = change.apply(batch)?;
+        }
+    }
+    Ok(batch)
+}
+
+/// Read the schema version recorded in the store metadata table.
+pub(crate) async fn read_schema_version(connection: &Connection) -> TuoResult<i32> {
+    let table = connection
+        .open_table(D_TABLE_NAME_STORE_METADATA)
+        .execute()
+        .await?;
+    if table
+        .schema()
+        .await?
+        .field_with_name(D_TABLE_COLUMN_NAME_SCHEMA_VERSION)
+        .is_err()
+    {
+        return Ok(0);
+    }
+    let batches = table
+        .query()
+        .select(&[D_TABLE_COLUMN_NAME_SCHEMA_VERSION])
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| {
+            TuoPartsError::StoreError("Error collecting store metadata results".to_string())
+        })?;
+    let version = batches
+        .iter()
+        .filter_map(|batch| {
+            batch
+                .column_by_name(D_TABLE_COLUMN_NAME_SCHEMA_VERSION)?
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .and_then(|array| array.iter().flatten().max())
+        })
+        .max();
+    Ok(version.unwrap_or(0))
+}
+
+/// Upgrade the tables of a store to the target schema version, applying the migrations above the version of the store.
+///
+/// Returns the version of the store before the migration.
+/// Fails if the store was written by a newer library, whose schema is unknown.
+pub(crate) async fn migrate_schema(
+    connection: &Connection,
+    migrations: &[SchemaMigration],
+    target_version: i32,
+) -> TuoResult<i32> {
+    let version = read_schema_version(connection).await?;
+    if version > target_version {
+        return Err(TuoPartsError::StoreError(format!(
+            "Store schema version {} is newer than the version {} supported by this library, upgrade the library to open the store",
+            version, target_version
+        ))
+        .into());
+    }
+    if version == target_version {
+        return Ok(version);
+    }
+    for migration in migrations
+        .iter()
+        .filter(|migration| migration.version > version && migration.version <= target_version)
+    {
+        info!("Migrating store schema to version {}", migration.version);
+        for change in migration.changes.iter() {
+            apply_change(connection, change).await?;
+        }
+    }
+    // rewritten tables start a new history, without the versions of the snapshots
+    remove_snapshots(connection).await?;
+    rewrite_table(connection, D_TABLE_NAME_STORE_METADATA, |batch| {
+        let (position, _) = batch
+            .schema()
+            .column_with_name(D_TABLE_COLUMN_NAME_SCHEMA_VERSION)
+            .ok_or(TuoPartsError::StoreError(format!(
+                "Column {} does not exist",
+                D_TABLE_COLUMN_NAME_SCHEMA_VERSION
+            )))?;
+        let mut columns = batch.columns().to_vec();
+        columns[position] = Arc::new(Int32Array::from(vec![target_version; batch.num_rows()]));
+        RecordBatch::try_new(batch.schema(), columns).map_err(|err| {
+            TuoPartsError::StoreError(format!("Error recording schema version: {}", err)).into()
+        })
+    })
+    .await?;
+    Ok(version)
+}
+
+async fn apply_change(connection: &Connection, change: &SchemaChange) -> TuoResult<()> {
+    let table_names = connection.table_names().execute().await?;
+    if !table_names.contains(&change.table().to_string()) {
+        debug!(
+            "Table {} is missing, skipping its migration",
+            change.table()
+        );
+        return Ok(());
+    }
+    let schema = connection
+        .open_table(change.table())
+        .execute()
+        .await?
+        .schema()
+        .await?;
+    if change.is_applied(&schema) {
+        return Ok(());
+    }
+    match change {
+        SchemaChange::FillFromReferences {
+            column, references, ..
+        } => {
+            let values = read_references(connection, &table_names, references).await?;
+            rewrite_table(connection, change.table(), |batch| {
+                fill_column(batch, column, &values)
+            })
+            .await
+        }
+        _ => rewrite_table(connection, change.table(), |batch| change.apply(batch)).await,
+    }
+}
+
+/// Read the values of the referring tables by the id they refer to, see [SchemaChange::FillFromReferences].
+async fn read_references(
+    connection: &Connection,
+    table_names: &[String],
+    references: &[(&'static str, &'static str, &'static str)],
+) -> TuoResult<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    for (table, id_column, value_column) in references {
+        if !table_names.contains(&table.to_string()) {
+            continue;
+        }
+        let batches = connection
+            .open_table(*table)
+            .execute()
+            .await?
+            .query()
+            .select(&[id_column, value_column])
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::StoreError(format!("Error collecting {} results", table))
+            })?;
+        for row in convert_record_batch_to_string_rows(batches, &[id_column, value_column]) {
+            if let [Some(id), Some(value)] = &row[..] {
+                values.entry(id.clone()).or_insert(value.clone());
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Fill the nulls of the string column with the values of the rows by id.
+fn fill_column(
+    batch: RecordBatch,
+    column: &str,
+    values: &HashMap<String, String>,
+) -> TuoResult<RecordBatch> {
+    let string_column = |name: &str| {
+        batch
+            .column_by_name(name)
+            .and_then(|array| array.as_any().downcast_ref::<StringArray>())
+            .ok_or(TuoPartsError::StoreError(format!(
+                "Column {} is not a string column",
+                name
+            )))
+    };
+    let ids = string_column(NodeFieldName::Id.name())?;
+    let filled: StringArray = string_column(column)?
+        .iter()
+        .zip(ids.iter())
+        .map(|(value, id)| value.or_else(|| id.and_then(|id| values.get(id).map(|v| v.as_str()))))
+        .collect();
+    let (position, _) =
+        batch
+            .schema()
+            .column_with_name(column)
+            .ok_or(TuoPartsError::StoreError(format!(
+                "Column {} does not exist",
+                column
+            )))?;
+    let mut columns = batch.columns().to_vec();
+    columns[position] = Arc::new(filled);
+    RecordBatch::try_new(batch.schema(), columns).map_err(|err| {
+        TuoPartsError::StoreError(format!("Error filling column {}: {}", column, err)).into()
+    })
+}
+
+/// Replace a table by a copy with every batch transformed, since LanceDB cannot add columns with defaults or rename columns in place.
+///
+/// The copy is written over the table as a new version, so that the table is never missing. Vector indices of the table are not carried over.
+async fn rewrite_table(
+    connection: &Connection,
+    table: &str,
+    transform: impl Fn(RecordBatch) -> TuoResult<RecordBatch>,
+) -> TuoResult<()> {
+    let lance_table = connection.open_table(table).execute().await?;
+    let empty = RecordBatch::new_empty(lance_table.schema().await?);
+    let schema = transform(empty)?.schema();
+    let batches = lance_table
+        .query()
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| TuoPartsError::StoreError(format!("Error collecting {} results", table)))?
+        .into_iter()
+        .map(&transform)
+        .collect::<TuoResult<Vec<_>>>()?;
+    connection
+        .create_table(
+            table,
+            Box::new(RecordBatchIterator::new(
+                batches.into_iter().map(Ok),
+                schema,
+            )),
+        )
+        .mode(CreateTableMode::Overwrite)
+        .execute()
+        .await?;
+    Ok(())
+}
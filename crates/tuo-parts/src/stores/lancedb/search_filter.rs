@@ -0,0 +1,95 @@
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_core::core::messaging::content::TextEmbeddedFieldName;
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::{ContentType, NodeFieldName};
+use tuo_core::types::date_time::TuoDateTime;
+
+use crate::stores::lancedb::sql_constructor::Predicate;
+
+/// Filters of a similarity search of a [LanceDbIndex](crate::stores::lancedb::index::LanceDbIndex), applied before ranking.
+///
+/// Filters on nodes restrict the text embeddings to the sources containing a matching node:
+/// the nodes themselves for node contents, and their sections or documents for section or document summaries.
+/// User queries have no source, so only the `embedded_at` range applies to them.
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct LanceDbSearchFilter {
+    /// Only nodes of these documents.
+    #[builder(default)]
+    pub document_ids: Option<Vec<Uuid>>,
+    /// Only nodes of these sections.
+    #[builder(default)]
+    pub section_ids: Option<Vec<Uuid>>,
+    /// Only nodes of these content types.
+    #[builder(default)]
+    pub content_types: Option<Vec<ContentType>>,
+    /// Only nodes of at least this many tokens.
+    #[builder(default)]
+    pub min_tokens: Option<i32>,
+    /// Only nodes of at most this many tokens.
+    #[builder(default)]
+    pub max_tokens: Option<i32>,
+    /// Only nodes of documents whose source uri starts with this prefix, e.g. a folder.
+    #[builder(default)]
+    pub source_uri_prefix: Option<String>,
+    /// Only text embeddings embedded at or after this time.
+    #[builder(default)]
+    pub embedded_after: Option<TuoDateTime>,
+    /// Only text embeddings embedded at or before this time.
+    #[builder(default)]
+    pub embedded_before: Option<TuoDateTime>,
+}
+
+impl LanceDbSearchFilter {
+    /// Whether the filter restricts the nodes, and thus the sources of the text embeddings.
+    pub fn filters_nodes(&self) -> bool {
+        self.document_ids.is_some()
+            || self.section_ids.is_some()
+            || self.content_types.is_some()
+            || self.min_tokens.is_some()
+            || self.max_tokens.is_some()
+            || self.source_uri_prefix.is_some()
+    }
+
+    /// Predicate on the nodes table, without the filter on source uris which applies to documents.
+    pub(crate) fn node_predicate(&self) -> Predicate {
+        let mut predicates = vec![];
+        if let Some(document_ids) = &self.document_ids {
+            predicates.push(Predicate::is_in(NodeFieldName::DocumentId, document_ids));
+        }
+        if let Some(section_ids) = &self.section_ids {
+            predicates.push(Predicate::is_in(NodeFieldName::SectionId, section_ids));
+        }
+        if let Some(content_types) = &self.content_types {
+            predicates.push(Predicate::is_in(
+                NodeFieldName::ContentType,
+                content_types
+                    .iter()
+                    .map(|content_type| content_type.as_ref()),
+            ));
+        }
+        predicates.push(Predicate::range(
+            NodeFieldName::Tokens,
+            self.min_tokens,
+            self.max_tokens,
+        ));
+        Predicate::all(predicates)
+    }
+
+    /// Predicate on the documents table matching the source uri prefix.
+    pub(crate) fn document_predicate(&self) -> Option<Predicate> {
+        self.source_uri_prefix
+            .as_ref()
+            .map(|prefix| Predicate::starts_with(DocumentFieldName::SourceUri, prefix))
+    }
+
+    /// Predicate on the text embeddings table for the `embedded_at` range.
+    pub(crate) fn text_embedded_predicate(&self) -> Predicate {
+        Predicate::range(
+            TextEmbeddedFieldName::EmbeddedAt,
+            self.embedded_after,
+            self.embedded_before,
+        )
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow_array::{
+    Array, Date64Array, Int64Array, RecordBatch, RecordBatchIterator, RecordBatchReader,
+    StringArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use futures::{StreamExt, TryStreamExt};
+use lance::dataset::scanner::DatasetRecordBatchStream;
+use lance::dataset::Dataset;
+use lancedb::connection::Connection;
+use tokio::runtime::Handle;
+use tracing::warn;
+use uuid::Uuid;
+
+use tuo_core::types::date_time::TuoDateTime;
+use tuo_shared::consts::defaults::{D_FOLDER_NAME_SNAPSHOTS, D_TABLE_NAME_SNAPSHOTS};
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::utc_from_epoch;
+
+use crate::stores::lancedb::sql_constructor::Predicate;
+
+pub(crate) const SNAPSHOT_COLUMN_ID: &str = "id";
+pub(crate) const SNAPSHOT_COLUMN_NAME: &str = "name";
+pub(crate) const SNAPSHOT_COLUMN_TABLE_NAME: &str = "table_name";
+pub(crate) const SNAPSHOT_COLUMN_VERSION: &str = "version";
+pub(crate) const SNAPSHOT_COLUMN_CREATED_AT: &str = "created_at";
+
+/// A named snapshot of a [LanceDb](crate::stores::lancedb::store::LanceDb) store: the version of each of its tables when it was taken.
+///
+/// Snapshots are kept in their own table, with one row per table of the store, which is not part of the snapshots themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanceDbSnapshot {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: TuoDateTime,
+    /// Version of each table of the store, by table name.
+    pub versions: HashMap<String, u64>,
+}
+
+fn snapshot_schema() -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new(SNAPSHOT_COLUMN_ID, DataType::Utf8, false),
+        Field::new(SNAPSHOT_COLUMN_NAME, DataType::Utf8, false),
+        Field::new(SNAPSHOT_COLUMN_TABLE_NAME, DataType::Utf8, false),
+        Field::new(SNAPSHOT_COLUMN_VERSION, DataType::Int64, false),
+        Field::new(SNAPSHOT_COLUMN_CREATED_AT, DataType::Date64, false),
+    ]))
+}
+
+fn convert_snapshot(snapshot: &LanceDbSnapshot) -> RecordBatch {
+    let mut versions = snapshot.versions.iter().collect::<Vec<_>>();
+    versions.sort();
+    RecordBatch::try_new(
+        snapshot_schema(),
+        vec![
+            Arc::new(StringArray::from_iter_values(
+                versions.iter().map(|_| snapshot.id.to_string()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                versions.iter().map(|_| snapshot.name.clone()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                versions.iter().map(|(table, _)| table.to_string()),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                versions.iter().map(|(_, version)| **version as i64),
+            )),
+            Arc::new(Date64Array::from_iter_values(
+                versions.iter().map(|_| snapshot.created_at.timestamp()),
+            )),
+        ],
+    )
+    .unwrap()
+}
+
+/// Group the rows of the snapshots table by snapshot, in the order they were taken.
+fn convert_record_batch_to_snapshots(record_batch: Vec<RecordBatch>) -> Vec<LanceDbSnapshot> {
+    let mut snapshots: HashMap<Uuid, LanceDbSnapshot> = HashMap::new();
+    for batch in record_batch {
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        let ids = column(SNAPSHOT_COLUMN_ID);
+        let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+        let names = column(SNAPSHOT_COLUMN_NAME);
+        let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+        let tables = column(SNAPSHOT_COLUMN_TABLE_NAME);
+        let tables = tables.as_any().downcast_ref::<StringArray>().unwrap();
+        let versions = column(SNAPSHOT_COLUMN_VERSION);
+        let versions = versions.as_any().downcast_ref::<Int64Array>().unwrap();
+        let created_at = column(SNAPSHOT_COLUMN_CREATED_AT);
+        let created_at = created_at.as_any().downcast_ref::<Date64Array>().unwrap();
+        for row in 0..batch.num_rows() {
+            let id = Uuid::try_parse(ids.value(row)).unwrap();
+            snapshots
+                .entry(id)
+                .or_insert_with(|| LanceDbSnapshot {
+                    id,
+                    name: names.value(row).to_string(),
+                    created_at: utc_from_epoch(created_at.value(row)),
+                    versions: HashMap::new(),
+                })
+                .versions
+                .insert(tables.value(row).to_string(), versions.value(row) as u64);
+        }
+    }
+    let mut snapshots = snapshots.into_values().collect::<Vec<_>>();
+    snapshots.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.name.cmp(&b.name)));
+    snapshots
+}
+
+/// Read the snapshots of the store matching the filter, none if no snapshot was ever taken.
+pub(crate) async fn read_snapshots(
+    connection: &Connection,
+    filter: Option<Predicate>,
+) -> TuoResult<Vec<LanceDbSnapshot>> {
+    let table_names = connection.table_names().execute().await?;
+    if !table_names.contains(&D_TABLE_NAME_SNAPSHOTS.to_string()) {
+        return Ok(vec![]);
+    }
+    let table = connection
+        .open_table(D_TABLE_NAME_SNAPSHOTS)
+        .execute()
+        .await?;
+    let mut query = table.query();
+    if let Some(filter) = filter {
+        query = query.filter(filter.to_string());
+    }
+    let result = query
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| TuoPartsError::StoreError("Error collecting snapshots".to_string()))?;
+    Ok(convert_record_batch_to_snapshots(result))
+}
+
+/// Add a snapshot to the snapshots table, created along with the first snapshot.
+pub(crate) async fn write_snapshot(
+    connection: &Connection,
+    snapshot: &LanceDbSnapshot,
+) -> TuoResult<()> {
+    let table_names = connection.table_names().execute().await?;
+    if !table_names.contains(&D_TABLE_NAME_SNAPSHOTS.to_string()) {
+        connection
+            .create_empty_table(D_TABLE_NAME_SNAPSHOTS, snapshot_schema())
+            .execute()
+            .await?;
+    }
+    connection
+        .open_table(D_TABLE_NAME_SNAPSHOTS)
+        .execute()
+        .await?
+        .add(Box::new(RecordBatchIterator::new(
+            vec![Ok(convert_snapshot(snapshot))],
+            snapshot_schema(),
+        )))
+        .execute()
+        .await?;
+    Ok(())
+}
+
+/// Remove all the snapshots of the store.
+pub(crate) async fn remove_snapshots(connection: &Connection) -> TuoResult<()> {
+    let table_names = connection.table_names().execute().await?;
+    if table_names.contains(&D_TABLE_NAME_SNAPSHOTS.to_string()) {
+        connection.drop_table(D_TABLE_NAME_SNAPSHOTS).await?;
+    }
+    Ok(())
+}
+
+/// Path of the Lance dataset of a table of the store.
+pub(crate) fn table_uri(store_uri: &str, table: &str) -> String {
+    Path::new(store_uri)
+        .join(format!("{}.lance", table))
+        .to_string_lossy()
+        .to_string()
+}
+
+async fn checkout_table(store_uri: &str, table: &str, version: u64) -> TuoResult<Dataset> {
+    Ok(Dataset::checkout(&table_uri(store_uri, table), version)
+        .await
+        .map_err(|err| {
+            TuoPartsError::StoreError(format!(
+                "Cannot checkout version {} of table {}: {}",
+                version, table, err
+            ))
+        })?)
+}
+
+/// Make the version of the table its latest version, as a new version keeping the history of the table.
+pub(crate) async fn restore_table_version(
+    store_uri: &str,
+    table: &str,
+    version: u64,
+) -> TuoResult<()> {
+    let mut dataset = checkout_table(store_uri, table, version).await?;
+    dataset.restore().await.map_err(|err| {
+        TuoPartsError::StoreError(format!(
+            "Cannot restore version {} of table {}: {}",
+            version, table, err
+        ))
+    })?;
+    Ok(())
+}
+
+/// A folder of the temporary directory holding the tables of a snapshot copied for an [opened index](crate::stores::lancedb::store::LanceDb::snapshot_index_open), removed when dropped.
+#[derive(Debug)]
+pub(crate) struct SnapshotFolder {
+    path: PathBuf,
+}
+
+impl SnapshotFolder {
+    /// Create a new folder for a copy of the snapshot, one per copy so that each copy is removed on its own.
+    pub(crate) fn create(store_id: Uuid, snapshot_id: Uuid) -> TuoResult<Self> {
+        let path = std::env::temp_dir()
+            .join(D_FOLDER_NAME_SNAPSHOTS)
+            .join(store_id.to_string())
+            .join(format!("{}_{}", snapshot_id, Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    pub(crate) fn uri(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+impl Drop for SnapshotFolder {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            warn!(
+                "Cannot remove the snapshot copy {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// The batches of a scan, read as a [RecordBatchReader] so that they are written to a table as they are scanned, instead of being collected first.
+///
+/// Lance pulls the batches of the readers it writes on blocking threads, from which the scan is driven on the runtime it was started on.
+pub(crate) struct StreamedBatches {
+    stream: DatasetRecordBatchStream,
+    schema: SchemaRef,
+    runtime: Handle,
+}
+
+impl StreamedBatches {
+    /// Read the batches of the scan, started on the current runtime.
+    pub(crate) fn new(stream: DatasetRecordBatchStream, schema: SchemaRef) -> Self {
+        Self {
+            stream,
+            schema,
+            runtime: Handle::current(),
+        }
+    }
+}
+
+impl Iterator for StreamedBatches {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime
+            .block_on(self.stream.next())
+            .map(|batch| batch.map_err(|err| ArrowError::ExternalError(Box::new(err))))
+    }
+}
+
+impl RecordBatchReader for StreamedBatches {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Copy the records of the table, as they were at the version, to a new table of the target store.
+pub(crate) async fn copy_table_version(
+    store_uri: &str,
+    table: &str,
+    version: u64,
+    target: &Connection,
+) -> TuoResult<()> {
+    let dataset = checkout_table(store_uri, table, version).await?;
+    let schema = Arc::new(Schema::from(dataset.schema()));
+    let batches = dataset
+        .scan()
+        .try_into_stream()
+        .await
+        .map_err(|err| TuoPartsError::StoreError(format!("Error scanning {}: {}", table, err)))?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|err| TuoPartsError::StoreError(format!("Error scanning {}: {}", table, err)))?;
+    let new_table = target
+        .create_empty_table(table, schema.clone())
+        .execute()
+        .await?;
+    if batches.iter().any(|batch| batch.num_rows() > 0) {
+        new_table
+            .add(Box::new(RecordBatchIterator::new(
+                batches.into_iter().map(Ok),
+                schema,
+            )))
+            .execute()
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,472 @@
+use std::fmt::{Display, Formatter};
+
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index_metadata::IndexMetadataFieldName;
+use tuo_core::core::messaging::content::TextEmbeddedFieldName;
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::NodeFieldName;
+use tuo_core::core::source::section::SectionFieldName;
+use tuo_core::model::model_metadata::EmbeddingModelMetadataFieldName;
+use tuo_core::storage::store_metadata::StoreMetadataFieldName;
+use tuo_core::types::date_time::TuoDateTime;
+
+/// A column of a LanceDB table, named after the field of the entity it stores.
+pub trait ColumnName {
+    fn column_name(&self) -> &'static str;
+}
+
+macro_rules! impl_column_name {
+    ($($field_name:ty),*) => {
+        $(
+            impl ColumnName for $field_name {
+                fn column_name(&self) -> &'static str {
+                    self.name()
+                }
+            }
+        )*
+    };
+}
+
+impl_column_name!(
+    NodeFieldName,
+    SectionFieldName,
+    DocumentFieldName,
+    TextEmbeddedFieldName,
+    IndexMetadataFieldName,
+    StoreMetadataFieldName,
+    EmbeddingModelMetadataFieldName
+);
+
+impl ColumnName for &'static str {
+    fn column_name(&self) -> &'static str {
+        self
+    }
+}
+
+/// A literal value of a predicate, rendered as SQL with strings escaped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    /// Stored as a Date64 of seconds since the epoch.
+    DateTime(TuoDateTime),
+}
+
+impl Display for SqlValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SqlValue::String(value) => write!(f, "'{}'", value.replace('\'', "''")),
+            SqlValue::Integer(value) => write!(f, "{}", value),
+            SqlValue::Float(value) => write!(f, "{:?}", value),
+            SqlValue::Boolean(value) => write!(f, "{}", if *value { "TRUE" } else { "FALSE" }),
+            SqlValue::DateTime(value) => write!(f, "arrow_cast({}, 'Date64')", value.timestamp()),
+        }
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::String(value.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::String(value)
+    }
+}
+
+impl From<&String> for SqlValue {
+    fn from(value: &String) -> Self {
+        SqlValue::String(value.clone())
+    }
+}
+
+impl From<Uuid> for SqlValue {
+    fn from(value: Uuid) -> Self {
+        SqlValue::String(value.to_string())
+    }
+}
+
+impl From<&Uuid> for SqlValue {
+    fn from(value: &Uuid) -> Self {
+        SqlValue::String(value.to_string())
+    }
+}
+
+impl From<i32> for SqlValue {
+    fn from(value: i32) -> Self {
+        SqlValue::Integer(value as i64)
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Integer(value)
+    }
+}
+
+impl From<f32> for SqlValue {
+    fn from(value: f32) -> Self {
+        SqlValue::Float(value as f64)
+    }
+}
+
+impl From<f64> for SqlValue {
+    fn from(value: f64) -> Self {
+        SqlValue::Float(value)
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(value: bool) -> Self {
+        SqlValue::Boolean(value)
+    }
+}
+
+impl From<TuoDateTime> for SqlValue {
+    fn from(value: TuoDateTime) -> Self {
+        SqlValue::DateTime(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+        }
+    }
+}
+
+/// A filter of LanceDB queries, updates and deletes, rendered as SQL with [to_string](ToString::to_string).
+///
+/// Columns are named by the `*FieldName` enums of the entities and values are escaped,
+/// so no predicate is built by formatting user input into SQL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// Matches every row.
+    True,
+    /// Matches no row.
+    False,
+    Compare {
+        column: &'static str,
+        operator: Operator,
+        value: SqlValue,
+    },
+    In {
+        column: &'static str,
+        values: Vec<SqlValue>,
+    },
+    IsNull(&'static str),
+    StartsWith {
+        column: &'static str,
+        prefix: String,
+    },
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    fn compare(column: impl ColumnName, operator: Operator, value: impl Into<SqlValue>) -> Self {
+        Predicate::Compare {
+            column: column.column_name(),
+            operator,
+            value: value.into(),
+        }
+    }
+
+    pub fn eq(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Eq, value)
+    }
+
+    pub fn ne(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Ne, value)
+    }
+
+    pub fn gt(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Gt, value)
+    }
+
+    pub fn ge(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Ge, value)
+    }
+
+    pub fn lt(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Lt, value)
+    }
+
+    pub fn le(column: impl ColumnName, value: impl Into<SqlValue>) -> Self {
+        Self::compare(column, Operator::Le, value)
+    }
+
+    /// Matches the rows whose column is one of the values, no row if there are none.
+    pub fn is_in<V: Into<SqlValue>>(
+        column: impl ColumnName,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values: Vec<SqlValue> = values.into_iter().map(Into::into).collect();
+        match values.is_empty() {
+            true => Predicate::False,
+            false => Predicate::In {
+                column: column.column_name(),
+                values,
+            },
+        }
+    }
+
+    /// Matches the rows whose column is within the inclusive bounds, every row if there are none.
+    pub fn range<V: Into<SqlValue>>(
+        column: impl ColumnName,
+        min: Option<V>,
+        max: Option<V>,
+    ) -> Self {
+        let column = column.column_name();
+        Predicate::all(
+            min.map(|min| Predicate::ge(column, min))
+                .into_iter()
+                .chain(max.map(|max| Predicate::le(column, max))),
+        )
+    }
+
+    pub fn is_null(column: impl ColumnName) -> Self {
+        Predicate::IsNull(column.column_name())
+    }
+
+    pub fn is_not_null(column: impl ColumnName) -> Self {
+        !Predicate::is_null(column)
+    }
+
+    pub fn starts_with(column: impl ColumnName, prefix: impl Into<String>) -> Self {
+        Predicate::StartsWith {
+            column: column.column_name(),
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Matches the rows matching every predicate, every row if there are none.
+    pub fn all(predicates: impl IntoIterator<Item = Predicate>) -> Self {
+        predicates.into_iter().fold(Predicate::True, Predicate::and)
+    }
+
+    /// Matches the rows matching any predicate, no row if there are none.
+    pub fn any(predicates: impl IntoIterator<Item = Predicate>) -> Self {
+        predicates.into_iter().fold(Predicate::False, Predicate::or)
+    }
+
+    pub fn and(self, other: Predicate) -> Self {
+        match (self, other) {
+            (Predicate::True, predicate) | (predicate, Predicate::True) => predicate,
+            (Predicate::False, _) | (_, Predicate::False) => Predicate::False,
+            (Predicate::And(mut predicates), Predicate::And(others)) => {
+                predicates.extend(others);
+                Predicate::And(predicates)
+            }
+            (Predicate::And(mut predicates), predicate) => {
+                predicates.push(predicate);
+                Predicate::And(predicates)
+            }
+            (predicate, Predicate::And(mut predicates)) => {
+                predicates.insert(0, predicate);
+                Predicate::And(predicates)
+            }
+            (predicate, other) => Predicate::And(vec![predicate, other]),
+        }
+    }
+
+    pub fn or(self, other: Predicate) -> Self {
+        match (self, other) {
+            (Predicate::False, predicate) | (predicate, Predicate::False) => predicate,
+            (Predicate::True, _) | (_, Predicate::True) => Predicate::True,
+            (Predicate::Or(mut predicates), Predicate::Or(others)) => {
+                predicates.extend(others);
+                Predicate::Or(predicates)
+            }
+            (Predicate::Or(mut predicates), predicate) => {
+                predicates.push(predicate);
+                Predicate::Or(predicates)
+            }
+            (predicate, Predicate::Or(mut predicates)) => {
+                predicates.insert(0, predicate);
+                Predicate::Or(predicates)
+            }
+            (predicate, other) => Predicate::Or(vec![predicate, other]),
+        }
+    }
+
+    /// Render an operand of `AND`, `OR` or `NOT`, in parentheses unless it is a single condition.
+    fn fmt_operand(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Predicate::And(_) | Predicate::Or(_) => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+
+    fn fmt_joined(
+        predicates: &[Predicate],
+        separator: &str,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result {
+        for (i, predicate) in predicates.iter().enumerate() {
+            if i > 0 {
+                write!(f, " {} ", separator)?;
+            }
+            predicate.fmt_operand(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::Not for Predicate {
+    type Output = Predicate;
+
+    fn not(self) -> Self::Output {
+        match self {
+            Predicate::True => Predicate::False,
+            Predicate::False => Predicate::True,
+            Predicate::Not(predicate) => *predicate,
+            predicate => Predicate::Not(Box::new(predicate)),
+        }
+    }
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Predicate::True => write!(f, "TRUE"),
+            Predicate::False => write!(f, "FALSE"),
+            // Dates are stored as seconds since the epoch, hence compared as integers.
+            Predicate::Compare {
+                column,
+                operator,
+                value: SqlValue::DateTime(date_time),
+            } => write!(
+                f,
+                "CAST({} AS BIGINT) {} {}",
+                column,
+                operator.as_sql(),
+                date_time.timestamp()
+            ),
+            Predicate::Compare {
+                column,
+                operator,
+                value,
+            } => write!(f, "{} {} {}", column, operator.as_sql(), value),
+            Predicate::In { column, values } => {
+                write!(f, "{} IN (", column)?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, ")")
+            }
+            Predicate::IsNull(column) => write!(f, "{} IS NULL", column),
+            Predicate::StartsWith { column, prefix } => write!(
+                f,
+                "starts_with({}, {})",
+                column,
+                SqlValue::from(prefix.as_str())
+            ),
+            Predicate::And(predicates) => Predicate::fmt_joined(predicates, "AND", f),
+            Predicate::Or(predicates) => Predicate::fmt_joined(predicates, "OR", f),
+            Predicate::Not(predicate) => match predicate.as_ref() {
+                Predicate::IsNull(column) => write!(f, "{} IS NOT NULL", column),
+                predicate => write!(f, "NOT ({})", predicate),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tuo_utils::datetime::timestamp::utc_from_epoch;
+
+    use super::*;
+
+    #[test]
+    fn test_predicate_escapes_values() {
+        assert_eq!(
+            Predicate::eq(IndexMetadataFieldName::Name, "o'brien").to_string(),
+            "name = 'o''brien'"
+        );
+        assert_eq!(
+            Predicate::starts_with(DocumentFieldName::SourceUri, "it's/").to_string(),
+            "starts_with(source_uri, 'it''s/')"
+        );
+        assert_eq!(
+            Predicate::is_in(NodeFieldName::Id, ["a", "b'c"]).to_string(),
+            "id IN ('a','b''c')"
+        );
+    }
+
+    #[test]
+    fn test_predicate_combinators() {
+        let in_index = Predicate::eq(NodeFieldName::IndexId, "index");
+        assert_eq!(
+            in_index
+                .clone()
+                .and(Predicate::range(NodeFieldName::Tokens, Some(1), Some(10)))
+                .to_string(),
+            "index_id = 'index' AND tokens >= 1 AND tokens <= 10"
+        );
+        assert_eq!(
+            in_index
+                .clone()
+                .and(
+                    Predicate::eq(NodeFieldName::ContentType, "text")
+                        .or(Predicate::is_null(NodeFieldName::ContentType))
+                )
+                .to_string(),
+            "index_id = 'index' AND (content_type = 'text' OR content_type IS NULL)"
+        );
+        assert_eq!(
+            (!Predicate::is_null(StoreMetadataFieldName::Id)).to_string(),
+            "id IS NOT NULL"
+        );
+        assert_eq!(
+            (!in_index.clone().and(Predicate::ne("hash", "h"))).to_string(),
+            "NOT (index_id = 'index' AND hash != 'h')"
+        );
+        assert_eq!(Predicate::all([]), Predicate::True);
+        assert_eq!(Predicate::any([]), Predicate::False);
+        assert_eq!(
+            Predicate::is_in(NodeFieldName::Id, Vec::<Uuid>::new()),
+            Predicate::False
+        );
+        assert_eq!(in_index.clone().and(Predicate::False), Predicate::False);
+        assert_eq!(in_index.clone().or(Predicate::False), in_index);
+    }
+
+    #[test]
+    fn test_predicate_dates() {
+        let date_time = utc_from_epoch(1_700_000_000);
+        assert_eq!(
+            Predicate::ge(TextEmbeddedFieldName::EmbeddedAt, date_time).to_string(),
+            "CAST(embedded_at AS BIGINT) >= 1700000000"
+        );
+        assert_eq!(
+            SqlValue::from(date_time).to_string(),
+            "arrow_cast(1700000000, 'Date64')"
+        );
+    }
+}
@@ -1,38 +1,87 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use arrow_schema::Schema;
 use async_trait::async_trait;
-use futures::TryStreamExt;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
 use lancedb::connect;
-use lancedb::connection::Connection;
+use lancedb::connection::{Connection, CreateTableMode};
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use tuo_core::core::indexing::index::IndexTrait;
-use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::indexing::index_metadata::{IndexMetadata, IndexMetadataFieldName};
+use tuo_core::core::messaging::content::{TextEmbedded, TextEmbeddedFieldName, TextSourceType};
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::{Node, NodeFieldName};
+use tuo_core::core::source::section::SectionFieldName;
 use tuo_core::core::source::sources::{
     SourceData, SourceInputData, SourceTableName, SourceType, SourceTypeTrait,
 };
 use tuo_core::embedding::embedder::EmbedderTrait;
-use tuo_core::model::model_metadata::EmbeddingModelMetadata;
-use tuo_core::storage::store::{StoreIndexInfo, StoreTrait};
-use tuo_core::storage::store_metadata::StoreMetadata;
-use tuo_shared::consts::defaults::{D_TABLE_NAME_INDEX_METADATA, D_TABLE_NAME_STORE_METADATA};
+use tuo_core::model::model_metadata::{EmbeddingModelMetadata, EmbeddingModelMetadataFieldName};
+use tuo_core::storage::store::{IndexRemoveResult, StoreIndexInfo, StoreOpenOptions, StoreTrait};
+use tuo_core::storage::store_health::{StoreHealthIssue, StoreHealthReport};
+use tuo_core::storage::store_metadata::{StoreMetadata, StoreMetadataFieldName};
+use tuo_core::storage::store_migration::{
+    run_model_migration, ModelMigrationOptions, ModelMigrationReport, ModelMigrationStaging,
+};
+use tuo_core::storage::store_models::{
+    check_migration_model, model_embeddings_table_model_id, model_embeddings_table_name,
+    model_to_register, RegisteredModel,
+};
+use tuo_shared::consts::defaults::{
+    D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_INDEX_METADATA, D_TABLE_NAME_MODELS_METADATA,
+    D_TABLE_NAME_STORE_METADATA, D_TABLE_NAME_SUFFIX_MIGRATION, D_TABLE_NAME_TEXT_EMBEDDED,
+};
 use tuo_shared::errors::parts::TuoPartsError;
 use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::{now, utc_from_epoch};
 
-use crate::stores::lancedb::index::LanceDbIndex;
+use crate::stores::lancedb::index::{LanceDbIndex, SOURCE_IDS_CHUNK_SIZE};
+use crate::stores::lancedb::maintenance::{
+    compact_table, folder_size, prune_table, LanceDbGcOptions, LanceDbGcReport,
+};
 use crate::stores::lancedb::schema::{
-    convert_record_batch_to_sources, convert_sources_to_table_data, get_all_schema,
+    compare_table_schema, convert_record_batch_to_sources, convert_record_batch_to_string_rows,
+    convert_sources_to_table_data, get_all_schema, id_column,
 };
+use crate::stores::lancedb::schema_migration::{
+    migrate_schema, schema_migrations, LANCEDB_SCHEMA_VERSION,
+};
+use crate::stores::lancedb::snapshot::{
+    copy_table_version, read_snapshots, remove_snapshots, restore_table_version, write_snapshot,
+    LanceDbSnapshot, SnapshotFolder, StreamedBatches, SNAPSHOT_COLUMN_NAME,
+};
+use crate::stores::lancedb::sql_constructor::Predicate;
+use crate::stores::lancedb::vector_index::{
+    build_vector_index, load_vector_index, LanceDbVectorIndexInfo, LanceDbVectorIndexOptions,
+};
+use crate::stores::lancedb::vector_storage::LanceDbVectorStorage;
 
 #[derive(TypedBuilder)]
 pub struct LanceDb {
     pub store_metadata: StoreMetadata,
     pub embedder: Arc<Box<dyn EmbedderTrait>>,
     pub indices: HashMap<Uuid, IndexMetadata>,
+    /// The models registered since the store was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+    /// Encoding of the embedding vectors, of the store model and of the registered models.
+    #[builder(default)]
+    pub vector_storage: LanceDbVectorStorage,
+}
+
+/// Options of [creating](LanceDb::create_with_options) a [LanceDb] store.
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct LanceDbCreateOptions {
+    /// Persist the embedding vectors compressed, trading recall for size, see [LanceDbVectorStorage].
+    #[builder(default)]
+    pub vector_storage: LanceDbVectorStorage,
 }
 
 impl LanceDb {
@@ -40,16 +89,13 @@ impl LanceDb {
         let db = connect(self.get_store_uri().as_str()).execute().await?;
         Ok(db)
     }
-}
 
-#[async_trait]
-impl StoreTrait for LanceDb {
-    type IndexSchema = Schema;
-    type IndexType = LanceDbIndex;
-    async fn create(
+    /// Create the store, with the [vector storage](LanceDbCreateOptions::vector_storage) of the options.
+    pub async fn create_with_options(
         store_name: &str,
         store_folder: &str,
         embedder: Box<dyn EmbedderTrait>,
+        opts: &LanceDbCreateOptions,
     ) -> TuoResult<Self> {
         let arc_embedder = Arc::new(embedder);
         let model_metadata = arc_embedder.get_model_metadata();
@@ -60,7 +106,7 @@ impl StoreTrait for LanceDb {
 
         let store_metadata = StoreMetadata::builder()
             .name(store_name.to_string())
-            .model(Some(model_metadata))
+            .model(Some(model_metadata.clone()))
             .model_id(Some(model_id))
             .uri(uri_path.to_str().unwrap().to_string())
             .build();
@@ -71,10 +117,11 @@ impl StoreTrait for LanceDb {
             .store_metadata(store_metadata.clone())
             .indices(HashMap::new())
             .embedder(arc_embedder.clone())
+            .vector_storage(opts.vector_storage)
             .build();
         // get default tables schema
         let all_schema: HashMap<String, Arc<Schema>> =
-            get_all_schema(instance.get_store_model_dimensions());
+            get_all_schema(instance.get_store_model_dimensions(), opts.vector_storage);
         // connect to the store
         let conn = instance.connect().await?;
         // create tables
@@ -85,421 +132,4586 @@ impl StoreTrait for LanceDb {
         instance
             .set_store_metadata(store_metadata, instance.get_store_model_dimensions())
             .await?;
+        // insert its model into the D_TABLE_NAME_MODELS_METADATA table
+        write_model_metadata(&conn, &model_metadata, None).await?;
 
         Ok(instance)
     }
 
-    async fn open(uri: &str, embedder: Box<dyn EmbedderTrait>) -> TuoResult<Self>
-    where
-        Self: Sized,
-    {
-        let model = embedder.get_model_metadata();
-        let store_metadata = LanceDb::load_store_metadata(uri, model.dimensions).await?;
-        Ok(LanceDb::builder()
-            .store_metadata(store_metadata)
-            .indices(HashMap::new())
-            .embedder(Arc::new(embedder))
-            .build())
-    }
-
-    fn get_store_metadata(&self) -> StoreMetadata {
-        self.store_metadata.clone()
+    /// Build, or rebuild, the IVF-PQ vector index of the text embeddings shared by all indices of the store.
+    ///
+    /// Text embeddings added afterwards are searched with a flat scan until the index is rebuilt.
+    /// The index is dropped when the store is [migrated to another model](StoreTrait::migrate_model).
+    pub async fn build_vector_index(
+        &self,
+        opts: &LanceDbVectorIndexOptions,
+    ) -> TuoResult<LanceDbVectorIndexInfo> {
+        if !self.vector_storage.is_searchable() {
+            return Err(TuoPartsError::StoreError(format!(
+                "Store {} keeps {:?} vectors, which LanceDB cannot index",
+                self.store_metadata.name, self.vector_storage
+            ))
+            .into());
+        }
+        let table = self
+            .connect()
+            .await?
+            .open_table(D_TABLE_NAME_TEXT_EMBEDDED)
+            .execute()
+            .await?;
+        build_vector_index(&table, opts).await
     }
 
-    fn get_store_model_metadata(&self) -> EmbeddingModelMetadata {
-        self.get_store_metadata()
-            .model
-            .ok_or(TuoPartsError::StoreError(
-                "Store metadata does not have ModelMetadata set.".to_string(),
-            ))
-            .unwrap()
+    /// The vector index of the text embeddings, `None` if searches use a flat scan.
+    pub async fn vector_index(&self) -> TuoResult<Option<LanceDbVectorIndexInfo>> {
+        let table = self
+            .connect()
+            .await?
+            .open_table(D_TABLE_NAME_TEXT_EMBEDDED)
+            .execute()
+            .await?;
+        load_vector_index(&table).await
     }
 
-    fn get_store_model_dimensions(&self) -> i32 {
-        self.get_store_model_metadata().dimensions
+    /// Take a named snapshot of the store, recording the current version of each of its tables, including the tables of the registered models.
+    ///
+    /// The snapshot can later be [opened](LanceDb::snapshot_index_open) or [restored](LanceDb::snapshot_restore).
+    /// Snapshots are removed when the store is [migrated to another model](StoreTrait::migrate_model), which recreates the tables.
+    pub async fn snapshot_create(&self, name: &str) -> TuoResult<LanceDbSnapshot> {
+        let connection = self.connect().await?;
+        let existing =
+            read_snapshots(&connection, Some(Predicate::eq(SNAPSHOT_COLUMN_NAME, name))).await?;
+        if !existing.is_empty() {
+            return Err(
+                TuoPartsError::StoreError(format!("Snapshot {} already exists", name)).into(),
+            );
+        }
+        let mut versions = HashMap::new();
+        let model_table_names: Vec<String> = connection
+            .table_names()
+            .execute()
+            .await?
+            .into_iter()
+            .filter(|table_name| model_embeddings_table_model_id(table_name).is_some())
+            .collect();
+        for table_name in get_all_schema(self.get_store_model_dimensions(), self.vector_storage)
+            .into_keys()
+            .chain(model_table_names)
+        {
+            let table = connection.open_table(&table_name).execute().await?;
+            let native_table = table.as_native().ok_or(TuoPartsError::StoreError(format!(
+                "Table {} is not a local table",
+                table_name
+            )))?;
+            versions.insert(table_name, native_table.version().await?);
+        }
+        let snapshot = LanceDbSnapshot {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            created_at: utc_from_epoch(now().timestamp()),
+            versions,
+        };
+        write_snapshot(&connection, &snapshot).await?;
+        Ok(snapshot)
     }
 
-    fn get_store_uri(&self) -> String {
-        self.get_store_metadata().uri.clone()
+    /// The snapshots of the store, oldest first.
+    pub async fn snapshot_list(&self) -> TuoResult<Vec<LanceDbSnapshot>> {
+        let connection = self.connect().await?;
+        read_snapshots(&connection, None).await
     }
 
-    async fn load_store_metadata(uri: &str, dimension: i32) -> TuoResult<StoreMetadata> {
-        let connection = connect(uri).execute().await?;
-        let store_metadata_table = connection
-            .open_table(D_TABLE_NAME_STORE_METADATA)
-            .execute()
-            .await?;
-        let result = store_metadata_table
-            .query()
-            .limit(1)
-            .execute_stream()
+    async fn snapshot_get(&self, name: &str) -> TuoResult<LanceDbSnapshot> {
+        let connection = self.connect().await?;
+        read_snapshots(&connection, Some(Predicate::eq(SNAPSHOT_COLUMN_NAME, name)))
             .await?
-            .try_collect::<Vec<_>>()
-            .await
-            .map_err(|_| {
-                TuoPartsError::StoreError("Error collecting store metadata results".to_string())
-            })?;
-        let convertion =
-            convert_record_batch_to_sources(SourceInputData::StoreMetadata(result), dimension)
-                .get_store_metadata();
-        Ok(convertion
-            .map(|x| x[0].clone())
-            .ok_or(TuoPartsError::StoreError(
-                "Cannot find store metadata in the store".to_string(),
-            ))?)
+            .into_iter()
+            .next()
+            .ok_or(TuoPartsError::StoreError(format!("Snapshot {} does not exist", name)).into())
     }
 
-    async fn set_store_metadata(
+    /// Open an index as it was when the snapshot was taken, read-only.
+    ///
+    /// The tables are copied at their snapshot version to a folder of the temporary directory, which the index removes when dropped.
+    /// Searches of the copy use a flat scan, as the vector index is not copied.
+    pub async fn snapshot_index_open(
         &self,
-        store_metadata: StoreMetadata,
-        dimension: i32,
-    ) -> TuoResult<()> {
-        let conn = self.connect().await?;
-        let store_metadata_table = conn
-            .open_table(D_TABLE_NAME_STORE_METADATA)
-            .execute()
-            .await?;
-        let store_metadata_data = SourceData::StoreMetadata(vec![store_metadata.clone()]);
-        let converted = convert_sources_to_table_data(store_metadata_data, dimension);
-        store_metadata_table.add(converted).execute().await?;
-        Ok(())
+        snapshot_name: &str,
+        index_name: &str,
+    ) -> TuoResult<LanceDbIndex> {
+        let snapshot = self.snapshot_get(snapshot_name).await?;
+        // removed on error as well, so that an interrupted copy does not remain
+        let folder = SnapshotFolder::create(self.store_metadata.id, snapshot.id)?;
+        let target = connect(folder.uri().as_str()).execute().await?;
+        for (table_name, version) in snapshot.versions.iter() {
+            copy_table_version(&self.get_store_uri(), table_name, *version, &target).await?;
+        }
+        let snapshot_store = LanceDb::builder()
+            .store_metadata(StoreMetadata {
+                uri: folder.uri(),
+                ..self.get_store_metadata()
+            })
+            .indices(HashMap::new())
+            .embedder(self.embedder.clone())
+            .registered_models(self.registered_models.clone())
+            .vector_storage(self.vector_storage)
+            .build();
+        let mut index = snapshot_store.index_open(index_name).await?;
+        index.read_only = true;
+        index.snapshot_folder = Some(Arc::new(folder));
+        Ok(index)
     }
 
-    async fn index_open(&self, index_name: &str) -> TuoResult<Self::IndexType> {
-        // check if index exists
-        let exists = self.index_exists(index_name).await?;
-        if !exists {
+    /// Roll the store back to a snapshot, making the snapshot version of each table its latest version.
+    ///
+    /// The rollback adds new versions, so the snapshots taken after this one remain and can still be restored.
+    /// If the rollback is interrupted, the store is partially rolled back until it is restored again.
+    pub async fn snapshot_restore(&mut self, name: &str) -> TuoResult<()> {
+        let snapshot = self.snapshot_get(name).await?;
+        let uri = self.get_store_uri();
+        let connection = self.connect().await?;
+        let table_names = connection.table_names().execute().await?;
+        if let Some(table_name) = snapshot
+            .versions
+            .keys()
+            .find(|table_name| !table_names.contains(table_name))
+        {
             return Err(TuoPartsError::StoreError(format!(
-                "Index {} does not exist",
-                index_name
-            )))?;
+                "Table {} of snapshot {} does not exist",
+                table_name, name
+            ))
+            .into());
         }
-        let model = self.get_store_model_metadata();
+        for (table_name, version) in snapshot.versions.iter() {
+            restore_table_version(&uri, table_name, *version).await?;
+        }
+        let mut store_metadata =
+            LanceDb::load_store_metadata(&uri, self.get_store_model_dimensions()).await?;
+        store_metadata.model = self.store_metadata.model.clone();
+        self.store_metadata = store_metadata;
+        Ok(())
+    }
 
+    /// Remove the text embeddings that are no longer needed, then compact the tables and remove their old versions, reporting the disk space freed.
+    ///
+    /// The text embedding of a node content is referenced by the nodes using it, the text embeddings of a registered model by their node, other text embeddings by their source.
+    /// New node embeddings are persisted before the nodes reference them, so the collection must not run while documents are embedded.
+    pub async fn collect_garbage(&self, opts: &LanceDbGcOptions) -> TuoResult<LanceDbGcReport> {
+        let uri = self.get_store_uri();
         let connection = self.connect().await?;
-        let indices_table = connection
-            .open_table(D_TABLE_NAME_INDEX_METADATA)
+        let mut report = LanceDbGcReport {
+            bytes_before: folder_size(Path::new(&uri))?,
+            ..Default::default()
+        };
+        let model_ids: Vec<Uuid> = connection
+            .table_names()
             .execute()
-            .await?;
-        let result = indices_table
-            .query()
-            .filter(format!("name = '{}'", index_name))
-            .limit(1)
-            .execute_stream()
             .await?
-            .try_collect::<Vec<_>>()
-            .await
-            .map_err(|_| {
-                TuoPartsError::StoreError("Error collecting indices results".to_string())
-            })?;
-        let source_input_data = SourceInputData::IndexMetadata(result);
-        let source_data =
-            convert_record_batch_to_sources(source_input_data, self.get_store_model_dimensions());
-        let results = source_data.get_index_metadata().unwrap();
-        let index = results.first().ok_or(TuoPartsError::StoreError(
-            "Cannot find index metadata".to_string(),
-        ))?;
-        Ok(LanceDbIndex::builder()
-            .index_name(index_name.to_string())
-            .model(model)
-            .index_metadata(index.clone())
-            .store_metadata(self.get_store_metadata())
-            .embedder(Some(self.embedder.clone()))
-            .build())
+            .iter()
+            .filter_map(|table_name| model_embeddings_table_model_id(table_name))
+            .collect();
+        if opts.remove_unreferenced {
+            report.unreferenced_text_embedded_ids =
+                unreferenced_text_embedded_ids(&connection, D_TABLE_NAME_TEXT_EMBEDDED, false)
+                    .await?;
+            for model_id in model_ids {
+                let ids = unreferenced_text_embedded_ids(
+                    &connection,
+                    &model_embeddings_table_name(model_id),
+                    true,
+                )
+                .await?;
+                if !ids.is_empty() {
+                    report
+                        .unreferenced_model_embedding_ids
+                        .insert(model_id, ids);
+                }
+            }
+        }
+        if let Some(max_age) = opts.user_query_max_age {
+            report.expired_user_query_ids =
+                self.expired_user_query_ids(&connection, max_age).await?;
+        }
+        if opts.dry_run {
+            report.bytes_after = report.bytes_before;
+            return Ok(report);
+        }
+
+        let removals = [
+            (
+                D_TABLE_NAME_TEXT_EMBEDDED.to_string(),
+                &report.unreferenced_text_embedded_ids,
+            ),
+            (
+                D_TABLE_NAME_TEXT_EMBEDDED.to_string(),
+                &report.expired_user_query_ids,
+            ),
+        ]
+        .into_iter()
+        .chain(
+            report
+                .unreferenced_model_embedding_ids
+                .iter()
+                .map(|(model_id, ids)| (model_embeddings_table_name(*model_id), ids)),
+        );
+        for (table_name, ids) in removals {
+            let table = connection.open_table(&table_name).execute().await?;
+            for chunk in ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+                let predicate = Predicate::is_in(TextEmbeddedFieldName::Id, chunk).to_string();
+                table.delete(predicate.as_str()).await?;
+            }
+        }
+
+        // the tables of the registered models are compacted and pruned with the other tables
+        let snapshots = read_snapshots(&connection, None).await?;
+        for table_name in connection.table_names().execute().await? {
+            let table = connection.open_table(&table_name).execute().await?;
+            if opts.compact {
+                let (removed, added) = compact_table(&table).await?;
+                report.fragments_removed.insert(table_name.clone(), removed);
+                report.fragments_added.insert(table_name.clone(), added);
+            }
+            if let Some(older_than) = opts.prune_versions_older_than {
+                let keep_versions = snapshots
+                    .iter()
+                    .filter_map(|snapshot| snapshot.versions.get(&table_name).copied())
+                    .collect::<Vec<_>>();
+                let removed = prune_table(&uri, &table, older_than, &keep_versions).await?;
+                report.versions_removed.insert(table_name, removed);
+            }
+        }
+        report.bytes_after = folder_size(Path::new(&uri))?;
+        Ok(report)
     }
 
-    async fn index_exists(&self, index_name: &str) -> TuoResult<bool> {
-        let connection = self.connect().await?;
-        let indices_table = connection
-            .open_table(D_TABLE_NAME_INDEX_METADATA)
+    /// Embeddings of user queries not used for `max_age`.
+    async fn expired_user_query_ids(
+        &self,
+        connection: &Connection,
+        max_age: Duration,
+    ) -> TuoResult<Vec<Uuid>> {
+        let max_age = chrono::Duration::from_std(max_age).map_err(|err| {
+            TuoPartsError::StoreError(format!("Invalid user query age {:?}: {}", max_age, err))
+        })?;
+        let predicate = Predicate::eq(
+            TextEmbeddedFieldName::SourceType,
+            TextSourceType::UserQuery.as_ref(),
+        )
+        .and(Predicate::lt(
+            TextEmbeddedFieldName::UsedAt,
+            now() - max_age,
+        ));
+        let columns = [TextEmbeddedFieldName::Id.name()];
+        let result = connection
+            .open_table(D_TABLE_NAME_TEXT_EMBEDDED)
             .execute()
-            .await?;
-        let result = indices_table
+            .await?
             .query()
-            .filter(format!("name = '{}'", index_name))
-            .limit(1)
+            .filter(predicate.to_string())
+            .select(&columns)
             .execute_stream()
             .await?
             .try_collect::<Vec<_>>()
             .await
             .map_err(|_| {
-                TuoPartsError::StoreError("Error collecting indices results".to_string())
+                TuoPartsError::StoreError("Error collecting user query results".to_string())
             })?;
-        Ok(result.len() > 0)
+        Ok(convert_record_batch_to_string_rows(result, &columns)
+            .into_iter()
+            .filter_map(|mut row| row.remove(0).and_then(|id| Uuid::try_parse(&id).ok()))
+            .collect())
     }
 
-    async fn list_indices(&self) -> TuoResult<Vec<IndexMetadata>> {
-        let connection = self.connect().await?;
-        let indices_table = connection
-            .open_table(D_TABLE_NAME_INDEX_METADATA)
+    /// Check that the store metadata table holds exactly the row of this store, and that its model is the model of the embedder.
+    ///
+    /// On repair, the row of this store is re-inserted if missing, and rows of other stores are removed.
+    async fn check_store_metadata(
+        &self,
+        connection: &Connection,
+        report: &mut StoreHealthReport,
+        repair: bool,
+    ) -> TuoResult<()> {
+        let embedder_model = self.embedder.get_model_metadata();
+        let store_metadata_table = connection
+            .open_table(D_TABLE_NAME_STORE_METADATA)
             .execute()
             .await?;
-        // select all records
-        let result = indices_table
+        let result = store_metadata_table
             .query()
             .execute_stream()
             .await?
             .try_collect::<Vec<_>>()
             .await
-            .unwrap();
-        let source_data = SourceInputData::IndexMetadata(result);
-        let indices =
-            convert_record_batch_to_sources(source_data, self.get_store_model_dimensions())
-                .get_index_metadata()
-                .unwrap();
-        Ok(indices)
-    }
-
-    async fn index_create(&self, name: &str) -> TuoResult<Self::IndexType> {
-        let index = IndexMetadata::builder().name(name.to_string()).build();
+            .map_err(|_| {
+                TuoPartsError::StoreError("Error collecting store metadata results".to_string())
+            })?;
+        let rows = convert_record_batch_to_sources(
+            SourceInputData::StoreMetadata(result),
+            embedder_model.dimensions,
+        )?
+        .get_store_metadata()
+        .unwrap_or_default();
+        let own_row = rows.iter().find(|row| row.id == self.store_metadata.id);
 
-        let dimensions = self.get_store_model_dimensions();
-        let index_source_data = SourceData::IndexMetadata(vec![index]);
-        let converted = convert_sources_to_table_data(index_source_data, dimensions);
+        if rows.len() != 1 || own_row.is_none() {
+            let issue = StoreHealthIssue::StoreMetadataCount { count: rows.len() };
+            if repair {
+                match own_row {
+                    Some(_) => {
+                        store_metadata_table
+                            .delete(
+                                Predicate::ne(StoreMetadataFieldName::Id, self.store_metadata.id)
+                                    .to_string()
+                                    .as_str(),
+                            )
+                            .await?;
+                    }
+                    None => {
+                        store_metadata_table
+                            .delete(
+                                Predicate::is_not_null(StoreMetadataFieldName::Id)
+                                    .to_string()
+                                    .as_str(),
+                            )
+                            .await?;
+                        self.set_store_metadata(
+                            self.store_metadata.clone(),
+                            embedder_model.dimensions,
+                        )
+                        .await?;
+                    }
+                }
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
 
-        let connection = self.connect().await?;
-        let index_storage_table = connection
-            .open_table(D_TABLE_NAME_INDEX_METADATA)
-            .execute()
-            .await?;
-        let _result = index_storage_table.add(converted).execute().await?;
-        self.index_open(name).await
+        // prefer the persisted model, fall back to the model the store was created with
+        let model_id = own_row
+            .and_then(|row| row.model_id)
+            .or(self.store_metadata.model_id);
+        let persisted_model = match model_id {
+            Some(model_id) => {
+                read_model_metadata(connection, model_id, embedder_model.dimensions).await?
+            }
+            None => None,
+        };
+        if let Some(store_model) = persisted_model.or(self.store_metadata.model.clone()) {
+            if store_model.name != embedder_model.name
+                || store_model.dimensions != embedder_model.dimensions
+            {
+                report.issues.push(StoreHealthIssue::ModelMismatch {
+                    store_model: store_model.name,
+                    store_dimensions: store_model.dimensions,
+                    embedder_model: embedder_model.name,
+                    embedder_dimensions: embedder_model.dimensions,
+                });
+            }
+        }
+        Ok(())
     }
 
-    async fn index_count_records(
+    /// Find sections and nodes without a document, text embeddings without a source and text embeddings of registered models without a node, removing them on repair.
+    ///
+    /// Text embeddings only used by orphan nodes are orphans as well.
+    async fn check_orphans(
         &self,
-        index_name: &str,
-        source_type: &SourceType,
-    ) -> TuoResult<usize> {
-        let table = self.index_open(index_name).await?;
-        let count = table.count_records(source_type).await?;
-        Ok(count)
+        connection: &Connection,
+        report: &mut StoreHealthReport,
+        repair: bool,
+    ) -> TuoResult<()> {
+        let document_ids: HashSet<String> = read_string_rows(
+            connection,
+            &SourceType::Document.table_name(),
+            &[DocumentFieldName::Id.name()],
+        )
+        .await?
+        .into_iter()
+        .filter_map(|mut row| row.remove(0))
+        .collect();
+        let sections = read_string_rows(
+            connection,
+            &SourceType::Section.table_name(),
+            &[
+                SectionFieldName::Id.name(),
+                SectionFieldName::DocumentId.name(),
+            ],
+        )
+        .await?;
+        let mut orphan_section_ids = vec![];
+        let mut section_ids = HashSet::new();
+        for mut row in sections {
+            let Some(id) = row[0].take() else {
+                continue;
+            };
+            match row[1]
+                .as_ref()
+                .is_some_and(|document_id| document_ids.contains(document_id))
+            {
+                true => {
+                    section_ids.insert(id);
+                }
+                false => orphan_section_ids.push(id),
+            }
+        }
+        let nodes = read_string_rows(
+            connection,
+            &SourceType::Node.table_name(),
+            &[
+                NodeFieldName::Id.name(),
+                NodeFieldName::DocumentId.name(),
+                NodeFieldName::ContentEmbeddingsId.name(),
+            ],
+        )
+        .await?;
+        let mut orphan_node_ids = vec![];
+        let mut node_ids = HashSet::new();
+        // nodes with the same content share their embeddings, which they reference by id
+        let mut node_embeddings_ids = HashSet::new();
+        for mut row in nodes {
+            let Some(id) = row[0].take() else {
+                continue;
+            };
+            match row[1]
+                .as_ref()
+                .is_some_and(|document_id| document_ids.contains(document_id))
+            {
+                true => {
+                    node_ids.insert(id);
+                    node_embeddings_ids.extend(row[2].take());
+                }
+                false => orphan_node_ids.push(id),
+            }
+        }
+
+        let text_embedded_columns = [
+            TextEmbeddedFieldName::Id.name(),
+            TextEmbeddedFieldName::SourceType.name(),
+            TextEmbeddedFieldName::SourceId.name(),
+        ];
+        let texts_embedded = read_string_rows(
+            connection,
+            &SourceType::TextEmbedded.table_name(),
+            &text_embedded_columns,
+        )
+        .await?;
+        let orphan_text_embedded_ids: Vec<String> = texts_embedded
+            .into_iter()
+            .filter_map(|row| {
+                let id = row[0].clone()?;
+                let source_type = row[1]
+                    .as_deref()
+                    .and_then(|source_type| TextSourceType::from_str(source_type).ok());
+                let source_ids = match source_type {
+                    Some(TextSourceType::UserQuery) => return None,
+                    Some(TextSourceType::SummaryDocument) => &document_ids,
+                    Some(TextSourceType::SummarySection) => &section_ids,
+                    Some(TextSourceType::SummaryNode) => &node_ids,
+                    Some(TextSourceType::NodeContent) => {
+                        return match node_embeddings_ids.contains(&id) {
+                            true => None,
+                            false => Some(id),
+                        }
+                    }
+                    None => return Some(id),
+                };
+                match row[2]
+                    .as_ref()
+                    .is_some_and(|source_id| source_ids.contains(source_id))
+                {
+                    true => None,
+                    false => Some(id),
+                }
+            })
+            .collect();
+
+        let to_uuids = |ids: &[String]| {
+            ids.iter()
+                .filter_map(|id| Uuid::try_parse(id).ok())
+                .collect::<Vec<Uuid>>()
+        };
+        let mut orphans = vec![(
+            SourceType::TextEmbedded.table_name(),
+            id_column(&SourceType::TextEmbedded),
+            StoreHealthIssue::OrphanTextEmbedded {
+                text_embedded_ids: to_uuids(&orphan_text_embedded_ids),
+            },
+            orphan_text_embedded_ids,
+        )];
+        // the text embeddings of a registered model reference their node by their source
+        for table_name in connection.table_names().execute().await? {
+            let Some(model_id) = model_embeddings_table_model_id(&table_name) else {
+                continue;
+            };
+            let orphan_ids: Vec<String> =
+                read_string_rows(connection, &table_name, &text_embedded_columns)
+                    .await?
+                    .into_iter()
+                    .filter_map(|row| {
+                        match row[2]
+                            .as_ref()
+                            .is_some_and(|source_id| node_ids.contains(source_id))
+                        {
+                            true => None,
+                            false => row[0].clone(),
+                        }
+                    })
+                    .collect();
+            orphans.push((
+                table_name,
+                id_column(&SourceType::TextEmbedded),
+                StoreHealthIssue::OrphanModelEmbeddings {
+                    model_id,
+                    text_embedded_ids: to_uuids(&orphan_ids),
+                },
+                orphan_ids,
+            ));
+        }
+        orphans.push((
+            SourceType::Node.table_name(),
+            id_column(&SourceType::Node),
+            StoreHealthIssue::OrphanNodes {
+                node_ids: to_uuids(&orphan_node_ids),
+            },
+            orphan_node_ids,
+        ));
+        orphans.push((
+            SourceType::Section.table_name(),
+            id_column(&SourceType::Section),
+            StoreHealthIssue::OrphanSections {
+                section_ids: to_uuids(&orphan_section_ids),
+            },
+            orphan_section_ids,
+        ));
+
+        for (table_name, column, issue, orphan_ids) in orphans {
+            if orphan_ids.is_empty() {
+                continue;
+            }
+            if repair {
+                let table = connection.open_table(&table_name).execute().await?;
+                for chunk in orphan_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+                    let predicate = Predicate::is_in(column, chunk).to_string();
+                    table.delete(predicate.as_str()).await?;
+                }
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+        Ok(())
     }
+}
+
+/// Read the encoding of the vectors of a store from the vector column of its text embeddings.
+async fn read_vector_storage(connection: &Connection) -> TuoResult<LanceDbVectorStorage> {
+    let schema = connection
+        .open_table(D_TABLE_NAME_TEXT_EMBEDDED)
+        .execute()
+        .await?
+        .schema()
+        .await?;
+    let field = schema
+        .field_with_name(D_TABLE_COLUMN_NAME_VECTOR)
+        .map_err(|_| {
+            TuoPartsError::StoreError(format!(
+                "Table {} has no {} column",
+                D_TABLE_NAME_TEXT_EMBEDDED, D_TABLE_COLUMN_NAME_VECTOR
+            ))
+        })?;
+    LanceDbVectorStorage::from_vector_type(field.data_type())
+        .ok_or(TuoPartsError::StoreError(format!(
+            "Unsupported vector type {} of table {}",
+            field.data_type(),
+            D_TABLE_NAME_TEXT_EMBEDDED
+        )))
+        .map_err(Into::into)
+}
+
+/// Read the model recorded for a store, `None` for stores created before their model was recorded.
+async fn read_model_metadata(
+    connection: &Connection,
+    model_id: Uuid,
+    dimension: i32,
+) -> TuoResult<Option<EmbeddingModelMetadata>> {
+    let result = connection
+        .open_table(D_TABLE_NAME_MODELS_METADATA)
+        .execute()
+        .await?
+        .query()
+        .filter(Predicate::eq(EmbeddingModelMetadataFieldName::Id, model_id).to_string())
+        .limit(1)
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| {
+            TuoPartsError::StoreError("Error collecting model metadata results".to_string())
+        })?;
+    Ok(
+        convert_record_batch_to_sources(SourceInputData::ModelMetadata(result), dimension)?
+            .get_model_metadata()
+            .and_then(|models| models.into_iter().next()),
+    )
+}
 
-    async fn index_remove(&self, index_id: Uuid) -> TuoResult<()> {
-        todo!()
+/// Record a model of the store, replacing the model of the store recorded before, if any.
+///
+/// The registered models stay recorded.
+async fn write_model_metadata(
+    connection: &Connection,
+    model: &EmbeddingModelMetadata,
+    replaced_model_id: Option<Uuid>,
+) -> TuoResult<()> {
+    let table = connection
+        .open_table(D_TABLE_NAME_MODELS_METADATA)
+        .execute()
+        .await?;
+    // a model recorded again, e.g. by a resumed migration, replaces its own record
+    let mut predicate = Predicate::eq(EmbeddingModelMetadataFieldName::Id, model.id);
+    if let Some(replaced_model_id) = replaced_model_id {
+        predicate = predicate.or(Predicate::eq(
+            EmbeddingModelMetadataFieldName::Id,
+            replaced_model_id,
+        ));
     }
+    table.delete(predicate.to_string().as_str()).await?;
+    table
+        .add(convert_sources_to_table_data(
+            SourceData::ModelMetadata(vec![model.clone()]),
+            model.dimensions,
+            LanceDbVectorStorage::default(),
+        ))
+        .execute()
+        .await?;
+    Ok(())
+}
 
-    async fn check_health(&self) -> TuoResult<StoreMetadata> {
-        todo!()
+/// Text embeddings of a table that nothing references: node contents that no node uses, and summaries whose source does not exist.
+///
+/// The text embeddings of a registered model reference their node by their source. The table is read page by page and the references of each page
+/// are looked up with a filter on the referenced tables, so that the ids of the store are never all held at once; each page reads the id columns
+/// of the referenced tables once.
+async fn unreferenced_text_embedded_ids(
+    connection: &Connection,
+    table: &str,
+    registered_model: bool,
+) -> TuoResult<Vec<Uuid>> {
+    let mut unreferenced = vec![];
+    let mut pages = stream_string_rows(
+        connection,
+        table,
+        &[
+            TextEmbeddedFieldName::Id.name(),
+            TextEmbeddedFieldName::SourceType.name(),
+            TextEmbeddedFieldName::SourceId.name(),
+        ],
+    )
+    .await?;
+    while let Some(rows) = pages.try_next().await? {
+        for page in rows.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            let mut references = vec![];
+            let mut lookups: HashMap<(SourceType, &'static str), Vec<String>> = HashMap::new();
+            for row in page {
+                let Some(id) = row[0].clone() else {
+                    continue;
+                };
+                let source_type = row[1]
+                    .as_deref()
+                    .and_then(|source_type| TextSourceType::from_str(source_type).ok());
+                let lookup = match source_type {
+                    Some(TextSourceType::UserQuery) => continue,
+                    // nodes with the same content share their embeddings, which they reference by id
+                    Some(TextSourceType::NodeContent) if !registered_model => Some((
+                        SourceType::Node,
+                        NodeFieldName::ContentEmbeddingsId.name(),
+                        Some(id.clone()),
+                    )),
+                    Some(TextSourceType::NodeContent | TextSourceType::SummaryNode) => {
+                        Some((SourceType::Node, NodeFieldName::Id.name(), row[2].clone()))
+                    }
+                    Some(TextSourceType::SummaryDocument) => Some((
+                        SourceType::Document,
+                        DocumentFieldName::Id.name(),
+                        row[2].clone(),
+                    )),
+                    Some(TextSourceType::SummarySection) => Some((
+                        SourceType::Section,
+                        SectionFieldName::Id.name(),
+                        row[2].clone(),
+                    )),
+                    None => None,
+                };
+                let lookup = lookup.and_then(|(source_type, column, value)| {
+                    let value = value?;
+                    lookups
+                        .entry((source_type.clone(), column))
+                        .or_default()
+                        .push(value.clone());
+                    Some((source_type, column, value))
+                });
+                references.push((id, lookup));
+            }
+            let mut referenced = HashMap::new();
+            for ((source_type, column), values) in lookups {
+                let present =
+                    select_present_values(connection, &source_type.table_name(), column, &values)
+                        .await?;
+                referenced.insert((source_type, column), present);
+            }
+            unreferenced.extend(
+                references
+                    .into_iter()
+                    .filter(|(_, lookup)| {
+                        !lookup.as_ref().is_some_and(|(source_type, column, value)| {
+                            referenced
+                                .get(&(source_type.clone(), *column))
+                                .is_some_and(|present| present.contains(value))
+                        })
+                    })
+                    .filter_map(|(id, _)| Uuid::try_parse(&id).ok()),
+            );
+        }
     }
+    Ok(unreferenced)
 }
 
-#[cfg(test)]
-mod tests {
-    use arrow_array::RecordBatch;
-    use lancedb::Error;
-    use test_log::test;
-    use tracing::{debug, info};
+/// The values of a column among the given ones, reading only the records holding them.
+async fn select_present_values(
+    connection: &Connection,
+    table: &str,
+    column: &'static str,
+    values: &[String],
+) -> TuoResult<HashSet<String>> {
+    let result = connection
+        .open_table(table)
+        .execute()
+        .await?
+        .query()
+        .select(&[column])
+        .filter(Predicate::is_in(column, values).to_string())
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| TuoPartsError::StoreError(format!("Error collecting {} results", table)))?;
+    Ok(convert_record_batch_to_string_rows(result, &[column])
+        .into_iter()
+        .filter_map(|mut row| row.remove(0))
+        .collect())
+}
 
-    use tuo_core::core::messaging::content::{TextInput, TextSourceType};
-    use tuo_core::core::source::document::{Document, DocumentSourceType};
-    use tuo_core::core::source::node::{ContentType, Node};
-    use tuo_core::core::source::section::Section;
-    use tuo_core::model::model_metadata::EmbeddingModelMetadataTrait;
-    use tuo_core::parsing::document_parser::ParsedDocument;
-    use tuo_core::utility::token::{count_tokens, TokenUtility};
-    use tuo_utils::testing::get_random_test_temp_folder;
-    use crate::models::openai::models::OpenAIEmbeddingModels;
+/// Stream the rows of a table one record batch at a time, for tables too large to be read at once.
+async fn stream_string_rows(
+    connection: &Connection,
+    table: &str,
+    columns: &[&'static str],
+) -> TuoResult<BoxStream<'static, TuoResult<Vec<Vec<Option<String>>>>>> {
+    let table = table.to_string();
+    let columns = columns.to_vec();
+    Ok(connection
+        .open_table(&table)
+        .execute()
+        .await?
+        .query()
+        .select(&columns)
+        .execute_stream()
+        .await?
+        .map(move |batch| {
+            let batch = batch.map_err(|err| {
+                TuoPartsError::StoreError(format!("Error reading {} results: {}", table, err))
+            })?;
+            Ok(convert_record_batch_to_string_rows(vec![batch], &columns))
+        })
+        .boxed())
+}
 
+/// Read the string columns of all rows of the table.
+async fn read_string_rows(
+    connection: &Connection,
+    table: &str,
+    columns: &[&str],
+) -> TuoResult<Vec<Vec<Option<String>>>> {
+    let result = connection
+        .open_table(table)
+        .execute()
+        .await?
+        .query()
+        .select(columns)
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| TuoPartsError::StoreError(format!("Error collecting {} results", table)))?;
+    Ok(convert_record_batch_to_string_rows(result, columns))
+}
 
-    use super::*;
+/// Read all rows of a table holding the records of the source type.
+async fn read_sources(
+    connection: &Connection,
+    table: &str,
+    source_type: &SourceType,
+    dimension: i32,
+) -> TuoResult<SourceData> {
+    let result = connection
+        .open_table(table)
+        .execute()
+        .await?
+        .query()
+        .execute_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|_| TuoPartsError::StoreError(format!("Error collecting {} results", table)))?;
+    convert_record_batch_to_sources(SourceInputData::from_data(result, source_type), dimension)
+}
 
-    #[test(tokio::test)]
-    async fn test_lancedb_store() {
-        dotenv::dotenv().ok();
-        let temp_folder = get_random_test_temp_folder();
-        let model = OpenAIEmbeddingModels::TextEmbedding_3_Small.get_embedder(None);
-        let store = LanceDb::create("test_store", temp_folder.as_str(), Box::new(model))
-            .await
-            .unwrap();
-        let store_uri = store.get_store_uri();
-        let count = store.list_indices().await.unwrap();
-        assert_eq!(count.len(), 0);
-        let index_name = "test_index";
-        let result = store.index_create(index_name).await.unwrap();
-        let created_index_id = result.get_index_metadata().id;
-        let indices_count = store.list_indices().await.unwrap();
-        assert_eq!(indices_count.len(), 1);
-        let opened_index = store.index_open(index_name).await.unwrap();
-        let opened_index_id = opened_index.get_index_metadata().id;
-        assert_eq!(created_index_id, opened_index_id);
+/// Stream the records of a table, one batch of records at a time.
+async fn stream_sources(
+    connection: &Connection,
+    table: &str,
+    source_type: &SourceType,
+    dimension: i32,
+) -> TuoResult<BoxStream<'static, TuoResult<SourceData>>> {
+    let table = table.to_string();
+    let source_type = source_type.clone();
+    Ok(connection
+        .open_table(&table)
+        .execute()
+        .await?
+        .query()
+        .execute_stream()
+        .await?
+        .map(move |batch| {
+            let batch = batch.map_err(|err| {
+                TuoPartsError::StoreError(format!("Error reading {} results: {}", table, err))
+            })?;
+            convert_record_batch_to_sources(
+                SourceInputData::from_data(vec![batch], &source_type),
+                dimension,
+            )
+        })
+        .boxed())
+}
 
-        let document = Document::builder()
+fn staging_table(source_type: &SourceType) -> String {
+    format!(
+        "{}{}",
+        source_type.table_name(),
+        D_TABLE_NAME_SUFFIX_MIGRATION
+    )
+}
+
+/// The model of the [model migration](StoreTrait::migrate_model) staged in the store, if any.
+async fn read_staged_model(connection: &Connection) -> TuoResult<Option<EmbeddingModelMetadata>> {
+    let model_table = staging_table(&SourceType::ModelMetadata);
+    if !connection
+        .table_names()
+        .execute()
+        .await?
+        .contains(&model_table)
+    {
+        return Ok(None);
+    }
+    // model metadata has no vector, the dimension is not used
+    Ok(
+        read_sources(connection, &model_table, &SourceType::ModelMetadata, 0)
+            .await?
+            .get_model_metadata()
+            .and_then(|models| models.into_iter().next()),
+    )
+}
+
+/// Staging tables of a [model migration](StoreTrait::migrate_model), named after the tables they replace with the [D_TABLE_NAME_SUFFIX_MIGRATION] suffix.
+///
+/// The model migrated to is kept in a staging model metadata table, so that a migration is only resumed with the same model.
+struct LanceDbMigrationStaging {
+    /// Metadata of the store, switched to the model migrated to.
+    store_metadata: StoreMetadata,
+    model: EmbeddingModelMetadata,
+    /// The model migrated from, whose record is replaced.
+    previous_model_id: Option<Uuid>,
+    /// Encoding of the vectors of the store, kept by the migration.
+    vector_storage: LanceDbVectorStorage,
+}
+
+impl LanceDbMigrationStaging {
+    async fn connect(&self) -> TuoResult<Connection> {
+        Ok(connect(self.store_metadata.uri.as_str()).execute().await?)
+    }
+
+    /// Create the staging tables, dropping those of a migration to another model.
+    async fn prepare(&self) -> TuoResult<()> {
+        let connection = self.connect().await?;
+        let all_schema = get_all_schema(self.model.dimensions, self.vector_storage);
+        let table_names = connection.table_names().execute().await?;
+        let model_table = staging_table(&SourceType::ModelMetadata);
+        let staged_model = read_staged_model(&connection).await?;
+        if staged_model.is_some_and(|staged_model| {
+            staged_model.name == self.model.name && staged_model.dimensions == self.model.dimensions
+        }) {
+            return Ok(());
+        }
+        for source_type in [
+            SourceType::ModelMetadata,
+            SourceType::TextEmbedded,
+            SourceType::Node,
+        ] {
+            let table = staging_table(&source_type);
+            if table_names.contains(&table) {
+                connection.drop_table(&table).await?;
+            }
+            connection
+                .create_empty_table(&table, all_schema[&source_type.table_name()].clone())
+                .execute()
+                .await?;
+        }
+        // the model is staged last, marking the staging tables as ready
+        connection
+            .open_table(&model_table)
+            .execute()
+            .await?
+            .add(convert_sources_to_table_data(
+                SourceData::ModelMetadata(vec![self.model.clone()]),
+                self.model.dimensions,
+                self.vector_storage,
+            ))
+            .execute()
+            .await?;
+        Ok(())
+    }
+
+    async fn staged_sources(&self, source_type: &SourceType) -> TuoResult<SourceData> {
+        let connection = self.connect().await?;
+        read_sources(
+            &connection,
+            &staging_table(source_type),
+            source_type,
+            self.model.dimensions,
+        )
+        .await
+    }
+
+    /// Stage the store metadata switched to the new model, marking the start of the [commit](ModelMigrationStaging::commit).
+    async fn stage_store_metadata(&self, connection: &Connection) -> TuoResult<()> {
+        connection
+            .create_table(
+                staging_table(&SourceType::StoreMetadata),
+                convert_sources_to_table_data(
+                    SourceData::StoreMetadata(vec![self.store_metadata.clone()]),
+                    self.model.dimensions,
+                    self.vector_storage,
+                ),
+            )
+            .mode(CreateTableMode::Overwrite)
+            .execute()
+            .await?;
+        Ok(())
+    }
+
+    async fn stage(&self, connection: &Connection, source_data: SourceData) -> TuoResult<()> {
+        if source_data.get_ids().is_empty() {
+            return Ok(());
+        }
+        connection
+            .open_table(staging_table(&source_data.source_type()))
+            .execute()
+            .await?
+            .add(convert_sources_to_table_data(
+                source_data,
+                self.model.dimensions,
+                self.vector_storage,
+            ))
+            .execute()
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ModelMigrationStaging for LanceDbMigrationStaging {
+    async fn staged_nodes(&self) -> TuoResult<Vec<Node>> {
+        Ok(self
+            .staged_sources(&SourceType::Node)
+            .await?
+            .get_node()
+            .unwrap_or_default())
+    }
+
+    async fn staged_texts(&self) -> TuoResult<Vec<TextEmbedded>> {
+        Ok(self
+            .staged_sources(&SourceType::TextEmbedded)
+            .await?
+            .get_text_embedded()
+            .unwrap_or_default())
+    }
+
+    async fn unstage(&self, node_ids: &[Uuid], text_ids: &[Uuid]) -> TuoResult<()> {
+        let connection = self.connect().await?;
+        let node_table = connection
+            .open_table(staging_table(&SourceType::Node))
+            .execute()
+            .await?;
+        for chunk in node_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            node_table
+                .delete(
+                    Predicate::is_in(NodeFieldName::Id, chunk.iter().copied())
+                        .to_string()
+                        .as_str(),
+                )
+                .await?;
+        }
+        let content_ids: HashSet<Uuid> = self
+            .staged_nodes()
+            .await?
+            .into_iter()
+            .filter_map(|node| node.content_embeddings_id)
+            .collect();
+        let removed_ids: Vec<Uuid> = self
+            .staged_texts()
+            .await?
+            .into_iter()
+            .filter(|text_embedded| match text_embedded.source_type {
+                TextSourceType::NodeContent => !content_ids.contains(&text_embedded.id),
+                _ => text_ids.contains(&text_embedded.id),
+            })
+            .map(|text_embedded| text_embedded.id)
+            .collect();
+        let text_table = connection
+            .open_table(staging_table(&SourceType::TextEmbedded))
+            .execute()
+            .await?;
+        for chunk in removed_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            text_table
+                .delete(
+                    Predicate::is_in(TextEmbeddedFieldName::Id, chunk.iter().copied())
+                        .to_string()
+                        .as_str(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The content embeddings are staged first; those of nodes that were not staged, because the batch was interrupted, are removed beforehand.
+    async fn stage_nodes(
+        &self,
+        nodes: Vec<Node>,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        let connection = self.connect().await?;
+        let predicate = Predicate::eq(
+            TextEmbeddedFieldName::SourceType,
+            TextSourceType::NodeContent.as_ref(),
+        )
+        .and(Predicate::is_in(
+            TextEmbeddedFieldName::SourceId,
+            nodes.iter().map(|node| node.id),
+        ))
+        .to_string();
+        connection
+            .open_table(staging_table(&SourceType::TextEmbedded))
+            .execute()
+            .await?
+            .delete(predicate.as_str())
+            .await?;
+        self.stage(&connection, SourceData::TextEmbedded(texts_embedded))
+            .await?;
+        self.stage(&connection, SourceData::Node(nodes)).await
+    }
+
+    async fn stage_texts(&self, texts_embedded: Vec<TextEmbedded>) -> TuoResult<()> {
+        let connection = self.connect().await?;
+        self.stage(&connection, SourceData::TextEmbedded(texts_embedded))
+            .await
+    }
+
+    /// Replace the tables by the staging tables, switch the store metadata to the new model, and drop the staging tables.
+    ///
+    /// LanceDB cannot rename tables, so the staged records are streamed over the tables, each written as a new version of its table.
+    /// The new store metadata is staged beforehand: a commit interrupted while replacing the tables is finished when the store is opened, see [finish_migration_commit].
+    async fn commit(&self) -> TuoResult<()> {
+        let connection = self.connect().await?;
+        self.stage_store_metadata(&connection).await?;
+        for source_type in [SourceType::TextEmbedded, SourceType::Node] {
+            let staged_table = connection
+                .open_table(staging_table(&source_type))
+                .execute()
+                .await?;
+            let batches = StreamedBatches::new(
+                staged_table.query().execute_stream().await?,
+                staged_table.schema().await?,
+            );
+            connection
+                .create_table(source_type.table_name(), Box::new(batches))
+                .mode(CreateTableMode::Overwrite)
+                .execute()
+                .await?;
+        }
+
+        write_model_metadata(&connection, &self.model, self.previous_model_id).await?;
+        let store_metadata_table = connection
+            .open_table(D_TABLE_NAME_STORE_METADATA)
+            .execute()
+            .await?;
+        store_metadata_table
+            .delete(
+                Predicate::eq(StoreMetadataFieldName::Id, self.store_metadata.id)
+                    .to_string()
+                    .as_str(),
+            )
+            .await?;
+        store_metadata_table
+            .add(convert_sources_to_table_data(
+                SourceData::StoreMetadata(vec![self.store_metadata.clone()]),
+                self.model.dimensions,
+                self.vector_storage,
+            ))
+            .execute()
+            .await?;
+
+        // the staged store metadata is dropped first, the commit is not finished again on open once the tables are replaced
+        for source_type in [
+            SourceType::StoreMetadata,
+            SourceType::TextEmbedded,
+            SourceType::Node,
+            SourceType::ModelMetadata,
+        ] {
+            connection.drop_table(staging_table(&source_type)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Finish the commit of a [model migration](StoreTrait::migrate_model) interrupted while replacing the tables of the store, if any.
+///
+/// The staging tables are kept until the tables are replaced, so the commit is done again from the start.
+async fn finish_migration_commit(connection: &Connection, uri: &str) -> TuoResult<()> {
+    let table_names = connection.table_names().execute().await?;
+    let store_metadata_table = staging_table(&SourceType::StoreMetadata);
+    if !table_names.contains(&store_metadata_table) {
+        return Ok(());
+    }
+    let model = read_staged_model(connection)
+        .await?
+        .ok_or(TuoPartsError::StoreError(
+            "Cannot find the model of the interrupted model migration".to_string(),
+        ))?;
+    let store_metadata = read_sources(
+        connection,
+        &store_metadata_table,
+        &SourceType::StoreMetadata,
+        model.dimensions,
+    )
+    .await?
+    .get_store_metadata()
+    .and_then(|store_metadata| store_metadata.into_iter().next())
+    .ok_or(TuoPartsError::StoreError(
+        "Cannot find the store metadata of the interrupted model migration".to_string(),
+    ))?;
+    let previous_model_id = read_sources(
+        connection,
+        D_TABLE_NAME_STORE_METADATA,
+        &SourceType::StoreMetadata,
+        model.dimensions,
+    )
+    .await?
+    .get_store_metadata()
+    .and_then(|store_metadata| store_metadata.into_iter().next())
+    .and_then(|store_metadata| store_metadata.model_id);
+    let staging = LanceDbMigrationStaging {
+        store_metadata: StoreMetadata {
+            uri: uri.to_string(),
+            model: Some(model.clone()),
+            ..store_metadata
+        },
+        model,
+        previous_model_id,
+        vector_storage: read_vector_storage(connection).await?,
+    };
+    staging.commit().await
+}
+
+#[async_trait]
+impl StoreTrait for LanceDb {
+    type IndexSchema = Schema;
+    type IndexType = LanceDbIndex;
+    /// Create the store with full precision vectors, see [create_with_options](LanceDb::create_with_options).
+    async fn create(
+        store_name: &str,
+        store_folder: &str,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<Self> {
+        LanceDb::create_with_options(
+            store_name,
+            store_folder,
+            embedder,
+            &LanceDbCreateOptions::default(),
+        )
+        .await
+    }
+
+    async fn open_with_options(
+        uri: &str,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: &StoreOpenOptions,
+    ) -> TuoResult<Self>
+    where
+        Self: Sized,
+    {
+        let model = embedder.get_model_metadata();
+        // upgrade the tables written by an older version of the library
+        let connection = connect(uri).execute().await?;
+        migrate_schema(&connection, &schema_migrations(), LANCEDB_SCHEMA_VERSION).await?;
+        finish_migration_commit(&connection, uri).await?;
+        let vector_storage = read_vector_storage(&connection).await?;
+        let mut store_metadata = LanceDb::load_store_metadata(uri, model.dimensions).await?;
+        if store_metadata.model.is_none() {
+            // the model of a store created before models were recorded is the model of the embedder
+            let model = EmbeddingModelMetadata {
+                id: store_metadata.model_id.unwrap_or(model.id),
+                ..model.clone()
+            };
+            write_model_metadata(&connection, &model, None).await?;
+            if store_metadata.model_id.is_none() {
+                store_metadata.model_id = Some(model.id);
+                let store_metadata_table = connection
+                    .open_table(D_TABLE_NAME_STORE_METADATA)
+                    .execute()
+                    .await?;
+                store_metadata_table
+                    .delete(
+                        Predicate::eq(StoreMetadataFieldName::Id, store_metadata.id)
+                            .to_string()
+                            .as_str(),
+                    )
+                    .await?;
+                store_metadata_table
+                    .add(convert_sources_to_table_data(
+                        SourceData::StoreMetadata(vec![store_metadata.clone()]),
+                        model.dimensions,
+                        vector_storage,
+                    ))
+                    .execute()
+                    .await?;
+            }
+            store_metadata.model = Some(model);
+        }
+        if !opts.allow_model_mismatch {
+            store_metadata.check_model(&model)?;
+        }
+        Ok(LanceDb::builder()
+            .store_metadata(store_metadata)
+            .indices(HashMap::new())
+            .embedder(Arc::new(embedder))
+            .vector_storage(vector_storage)
+            .build())
+    }
+
+    fn get_store_metadata(&self) -> StoreMetadata {
+        self.store_metadata.clone()
+    }
+
+    fn get_store_model_metadata(&self) -> EmbeddingModelMetadata {
+        self.get_store_metadata()
+            .model
+            .ok_or(TuoPartsError::StoreError(
+                "Store metadata does not have ModelMetadata set.".to_string(),
+            ))
+            .unwrap()
+    }
+
+    fn get_store_model_dimensions(&self) -> i32 {
+        self.get_store_model_metadata().dimensions
+    }
+
+    fn get_store_uri(&self) -> String {
+        self.get_store_metadata().uri.clone()
+    }
+
+    async fn load_store_metadata(uri: &str, dimension: i32) -> TuoResult<StoreMetadata> {
+        let connection = connect(uri).execute().await?;
+        let store_metadata_table = connection
+            .open_table(D_TABLE_NAME_STORE_METADATA)
+            .execute()
+            .await?;
+        let result = store_metadata_table
+            .query()
+            .limit(1)
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::StoreError("Error collecting store metadata results".to_string())
+            })?;
+        let convertion =
+            convert_record_batch_to_sources(SourceInputData::StoreMetadata(result), dimension)?
+                .get_store_metadata();
+        let mut store_metadata =
+            convertion
+                .map(|x| x[0].clone())
+                .ok_or(TuoPartsError::StoreError(
+                    "Cannot find store metadata in the store".to_string(),
+                ))?;
+        if let Some(model_id) = store_metadata.model_id {
+            store_metadata.model = read_model_metadata(&connection, model_id, dimension).await?;
+        }
+        Ok(store_metadata)
+    }
+
+    async fn set_store_metadata(
+        &self,
+        store_metadata: StoreMetadata,
+        dimension: i32,
+    ) -> TuoResult<()> {
+        let conn = self.connect().await?;
+        let store_metadata_table = conn
+            .open_table(D_TABLE_NAME_STORE_METADATA)
+            .execute()
+            .await?;
+        let store_metadata_data = SourceData::StoreMetadata(vec![store_metadata.clone()]);
+        let converted =
+            convert_sources_to_table_data(store_metadata_data, dimension, self.vector_storage);
+        store_metadata_table.add(converted).execute().await?;
+        Ok(())
+    }
+
+    async fn index_open(&self, index_name: &str) -> TuoResult<Self::IndexType> {
+        // check if index exists
+        let exists = self.index_exists(index_name).await?;
+        if !exists {
+            return Err(TuoPartsError::StoreError(format!(
+                "Index {} does not exist",
+                index_name
+            )))?;
+        }
+        let model = self.get_store_model_metadata();
+
+        let connection = self.connect().await?;
+        let indices_table = connection
+            .open_table(D_TABLE_NAME_INDEX_METADATA)
+            .execute()
+            .await?;
+        let result = indices_table
+            .query()
+            .filter(Predicate::eq(IndexMetadataFieldName::Name, index_name).to_string())
+            .limit(1)
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::StoreError("Error collecting indices results".to_string())
+            })?;
+        let source_input_data = SourceInputData::IndexMetadata(result);
+        let source_data =
+            convert_record_batch_to_sources(source_input_data, self.get_store_model_dimensions())?;
+        let results = source_data.get_index_metadata().unwrap();
+        let index = results.first().ok_or(TuoPartsError::StoreError(
+            "Cannot find index metadata".to_string(),
+        ))?;
+        Ok(LanceDbIndex::builder()
+            .index_name(index_name.to_string())
+            .model(model)
+            .index_metadata(index.clone())
+            .store_metadata(self.get_store_metadata())
+            .embedder(Some(self.embedder.clone()))
+            .registered_models(self.registered_models.clone())
+            .vector_storage(self.vector_storage)
+            .build())
+    }
+
+    async fn index_exists(&self, index_name: &str) -> TuoResult<bool> {
+        let connection = self.connect().await?;
+        let indices_table = connection
+            .open_table(D_TABLE_NAME_INDEX_METADATA)
+            .execute()
+            .await?;
+        let result = indices_table
+            .query()
+            .filter(Predicate::eq(IndexMetadataFieldName::Name, index_name).to_string())
+            .limit(1)
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|_| {
+                TuoPartsError::StoreError("Error collecting indices results".to_string())
+            })?;
+        Ok(result.len() > 0)
+    }
+
+    async fn list_indices(&self) -> TuoResult<Vec<IndexMetadata>> {
+        let connection = self.connect().await?;
+        let indices_table = connection
+            .open_table(D_TABLE_NAME_INDEX_METADATA)
+            .execute()
+            .await?;
+        // select all records
+        let result = indices_table
+            .query()
+            .execute_stream()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let source_data = SourceInputData::IndexMetadata(result);
+        let indices =
+            convert_record_batch_to_sources(source_data, self.get_store_model_dimensions())?
+                .get_index_metadata()
+                .unwrap();
+        Ok(indices)
+    }
+
+    async fn index_create(&self, name: &str) -> TuoResult<Self::IndexType> {
+        let index = IndexMetadata::builder().name(name.to_string()).build();
+
+        let dimensions = self.get_store_model_dimensions();
+        let index_source_data = SourceData::IndexMetadata(vec![index]);
+        let converted =
+            convert_sources_to_table_data(index_source_data, dimensions, self.vector_storage);
+
+        let connection = self.connect().await?;
+        let index_storage_table = connection
+            .open_table(D_TABLE_NAME_INDEX_METADATA)
+            .execute()
+            .await?;
+        let _result = index_storage_table.add(converted).execute().await?;
+        self.index_open(name).await
+    }
+
+    async fn index_count_records(
+        &self,
+        index_name: &str,
+        source_type: &SourceType,
+    ) -> TuoResult<usize> {
+        let table = self.index_open(index_name).await?;
+        let count = table.count_records(source_type).await?;
+        Ok(count)
+    }
+
+    async fn index_remove(&self, index_id: Uuid) -> TuoResult<IndexRemoveResult> {
+        let connection = self.connect().await?;
+        let indices_table = connection
+            .open_table(D_TABLE_NAME_INDEX_METADATA)
+            .execute()
+            .await?;
+        let index_metadata_filter = Predicate::eq(IndexMetadataFieldName::Id, index_id).to_string();
+        if indices_table
+            .count_rows(Some(index_metadata_filter.clone()))
+            .await?
+            == 0
+        {
+            return Err(
+                TuoPartsError::StoreError(format!("Index {} does not exist", index_id)).into(),
+            );
+        }
+
+        // remove the records before the index metadata, so that a failed removal can be retried
+        let records_filter = Predicate::eq(NodeFieldName::IndexId, index_id).to_string();
+        let mut removed = HashMap::new();
+        for source_type in [
+            SourceType::TextEmbedded,
+            SourceType::Node,
+            SourceType::Section,
+            SourceType::Document,
+        ] {
+            let table = connection
+                .open_table(source_type.table_name())
+                .execute()
+                .await?;
+            let count = table.count_rows(Some(records_filter.clone())).await?;
+            if count > 0 {
+                table.delete(records_filter.as_str()).await?;
+            }
+            removed.insert(source_type, count);
+        }
+        // the text embeddings of the registered models are counted with the other text embeddings
+        for table_name in connection.table_names().execute().await? {
+            if model_embeddings_table_model_id(&table_name).is_none() {
+                continue;
+            }
+            let table = connection.open_table(table_name).execute().await?;
+            let count = table.count_rows(Some(records_filter.clone())).await?;
+            if count > 0 {
+                table.delete(records_filter.as_str()).await?;
+            }
+            *removed.entry(SourceType::TextEmbedded).or_default() += count;
+        }
+
+        let count = indices_table
+            .count_rows(Some(index_metadata_filter.clone()))
+            .await?;
+        indices_table.delete(index_metadata_filter.as_str()).await?;
+        removed.insert(SourceType::IndexMetadata, count);
+
+        Ok(IndexRemoveResult { index_id, removed })
+    }
+
+    async fn check_health(&self, repair: bool) -> TuoResult<StoreHealthReport> {
+        let mut report = StoreHealthReport::default();
+        let connection = self.connect().await?;
+        let embedder_model = self.embedder.get_model_metadata();
+
+        // tables and columns
+        let table_names = connection.table_names().execute().await?;
+        let mut all_schema = get_all_schema(embedder_model.dimensions, self.vector_storage)
+            .into_iter()
+            .collect::<Vec<_>>();
+        all_schema.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut missing_tables = HashSet::new();
+        for (name, schema) in all_schema {
+            if !table_names.contains(&name) {
+                let issue = StoreHealthIssue::MissingTable {
+                    table: name.clone(),
+                };
+                if repair {
+                    connection
+                        .create_empty_table(name.clone(), schema)
+                        .execute()
+                        .await?;
+                    report.repaired.push(issue.clone());
+                } else {
+                    missing_tables.insert(name);
+                }
+                report.issues.push(issue);
+                continue;
+            }
+            let table_schema = connection
+                .open_table(&name)
+                .execute()
+                .await?
+                .schema()
+                .await?;
+            let (missing_columns, dimension_mismatches) =
+                compare_table_schema(&schema, &table_schema);
+            report
+                .issues
+                .extend(missing_columns.into_iter().map(|column| {
+                    StoreHealthIssue::MissingColumn {
+                        table: name.clone(),
+                        column,
+                    }
+                }));
+            report.issues.extend(dimension_mismatches.into_iter().map(
+                |(column, expected, actual)| StoreHealthIssue::DimensionMismatch {
+                    table: name.clone(),
+                    column,
+                    expected,
+                    actual,
+                },
+            ));
+        }
+
+        if !missing_tables.contains(D_TABLE_NAME_STORE_METADATA) {
+            self.check_store_metadata(&connection, &mut report, repair)
+                .await?;
+        }
+        if [
+            SourceType::Document,
+            SourceType::Section,
+            SourceType::Node,
+            SourceType::TextEmbedded,
+        ]
+        .iter()
+        .all(|source_type| !missing_tables.contains(&source_type.table_name()))
+        {
+            self.check_orphans(&connection, &mut report, repair).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn migrate_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: ModelMigrationOptions,
+    ) -> TuoResult<ModelMigrationReport> {
+        let model = embedder.get_model_metadata();
+        let connection = self.connect().await?;
+        let dimension = self.get_store_model_dimensions();
+        let recorded = read_sources(
+            &connection,
+            D_TABLE_NAME_MODELS_METADATA,
+            &SourceType::ModelMetadata,
+            dimension,
+        )
+        .await?
+        .get_model_metadata()
+        .unwrap_or_default();
+        check_migration_model(&self.get_store_model_metadata(), &recorded, &model)?;
+        let mut store_metadata = self.store_metadata.clone();
+        store_metadata.model_id = Some(model.id);
+        store_metadata.model = Some(model.clone());
+        let staging = LanceDbMigrationStaging {
+            store_metadata,
+            model,
+            previous_model_id: self.store_metadata.model_id,
+            vector_storage: self.vector_storage,
+        };
+        staging.prepare().await?;
+
+        let nodes = stream_sources(
+            &connection,
+            &SourceType::Node.table_name(),
+            &SourceType::Node,
+            dimension,
+        )
+        .await?
+        .map_ok(|source_data| source_data.get_node().unwrap_or_default())
+        .boxed();
+        let texts_embedded = stream_sources(
+            &connection,
+            D_TABLE_NAME_TEXT_EMBEDDED,
+            &SourceType::TextEmbedded,
+            dimension,
+        )
+        .await?
+        .map_ok(|source_data| source_data.get_text_embedded().unwrap_or_default())
+        .boxed();
+
+        let mut report =
+            run_model_migration(&staging, embedder.as_ref(), nodes, texts_embedded, &opts).await?;
+        report.previous_model = self.store_metadata.model.clone();
+        if report.committed {
+            // the snapshots refer to versions of the tables replaced by the migration
+            remove_snapshots(&connection).await?;
+            self.store_metadata = staging.store_metadata;
+            self.embedder = Arc::new(embedder);
+        }
+        Ok(report)
+    }
+
+    async fn register_embedding_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<EmbeddingModelMetadata> {
+        let mut registered_model = RegisteredModel::new(embedder);
+        let connection = self.connect().await?;
+        let recorded = read_sources(
+            &connection,
+            D_TABLE_NAME_MODELS_METADATA,
+            &SourceType::ModelMetadata,
+            self.get_store_model_dimensions(),
+        )
+        .await?
+        .get_model_metadata()
+        .unwrap_or_default();
+        let (model, is_new) = model_to_register(
+            &self.get_store_model_metadata(),
+            &recorded,
+            registered_model.model,
+        )?;
+        let table_name = model_embeddings_table_name(model.id);
+        if !connection
+            .table_names()
+            .execute()
+            .await?
+            .contains(&table_name)
+        {
+            let schema = get_all_schema(model.dimensions, self.vector_storage)
+                [D_TABLE_NAME_TEXT_EMBEDDED]
+                .clone();
+            connection
+                .create_empty_table(&table_name, schema)
+                .execute()
+                .await?;
+        }
+        // the model is recorded once its table exists
+        if is_new {
+            write_model_metadata(&connection, &model, None).await?;
+        }
+        registered_model.model = model.clone();
+        self.registered_models.insert(model.id, registered_model);
+        Ok(model)
+    }
+
+    async fn list_embedding_models(&self) -> TuoResult<Vec<EmbeddingModelMetadata>> {
+        let store_model = self.get_store_model_metadata();
+        let connection = self.connect().await?;
+        let registered_models: Vec<EmbeddingModelMetadata> = read_sources(
+            &connection,
+            D_TABLE_NAME_MODELS_METADATA,
+            &SourceType::ModelMetadata,
+            store_model.dimensions,
+        )
+        .await?
+        .get_model_metadata()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|model| model.id != store_model.id)
+        .collect();
+        Ok([vec![store_model], registered_models].concat())
+    }
+
+    fn get_registered_models(&self) -> Vec<RegisteredModel> {
+        self.registered_models.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::RecordBatch;
+    use lancedb::Error;
+    use test_log::test;
+    use tracing::{debug, info};
+
+    use crate::models::openai::models::OpenAIEmbeddingModels;
+    use crate::testing::{parsed_document, CharHashEmbedder};
+    use arrow_array::StringArray;
+    use arrow_schema::{DataType, Field};
+    use tuo_core::core::messaging::content::{
+        QueryEmbeddingCache, TextEmbeddingOptions, TextInput, TextSourceType,
+    };
+    use tuo_core::core::source::document::{Document, DocumentSourceType};
+    use tuo_core::core::source::node::{ContentType, Node};
+    use tuo_core::core::source::section::Section;
+    use tuo_core::embedding::embedder::EmbedResultStats;
+    use tuo_core::model::model::ModelTrait;
+    use tuo_core::model::model_metadata::EmbeddingModelMetadataTrait;
+    use tuo_core::parsing::document_parser::ParsedDocument;
+    use tuo_core::retrieval::hybrid::{HybridFusion, HybridSearchOptions};
+    use tuo_core::retrieval::keyword::Bm25Options;
+    use tuo_core::utility::token::{count_tokens, TokenUtility};
+    use tuo_shared::consts::defaults::{
+        D_TABLE_COLUMN_NAME_SCHEMA_VERSION, D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_DOCUMENTS,
+    };
+    use tuo_utils::datetime::timestamp::utc_from_epoch;
+    use tuo_utils::hash::hash_str::hash_str;
+
+    use crate::stores::lancedb::index::{LanceDbIndexSearchOptions, SOURCE_IDS_CHUNK_SIZE};
+    use crate::stores::lancedb::schema_migration::{
+        read_schema_version, SchemaChange, SchemaMigration,
+    };
+    use crate::stores::lancedb::search_filter::LanceDbSearchFilter;
+    use tuo_core::core::indexing::index_insert::DocumentInsertOptions;
+    use tuo_core::core::indexing::index_scan::ScanOptions;
+    use tuo_core::core::source::document_tree::DocumentTreeOptions;
+    use tuo_utils::testing::get_random_test_temp_folder;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_lancedb_store() {
+        dotenv::dotenv().ok();
+        let temp_folder = get_random_test_temp_folder();
+        let model = OpenAIEmbeddingModels::TextEmbedding_3_Small.get_embedder(None);
+        let store = LanceDb::create("test_store", temp_folder.as_str(), Box::new(model))
+            .await
+            .unwrap();
+        let store_uri = store.get_store_uri();
+        let count = store.list_indices().await.unwrap();
+        assert_eq!(count.len(), 0);
+        let index_name = "test_index";
+        let result = store.index_create(index_name).await.unwrap();
+        let created_index_id = result.get_index_metadata().id;
+        let indices_count = store.list_indices().await.unwrap();
+        assert_eq!(indices_count.len(), 1);
+        let opened_index = store.index_open(index_name).await.unwrap();
+        let opened_index_id = opened_index.get_index_metadata().id;
+        assert_eq!(created_index_id, opened_index_id);
+
+        let document = Document::builder()
             .name("test_doc".to_string())
             .index_id(created_index_id)
             .source_uri(store_uri)
             .source_type(DocumentSourceType::File)
             .build();
 
-        let document_id = document.id;
+        let document_id = document.id;
+
+        let section = Section::builder()
+            .index_id(created_index_id)
+            .document_id(document_id)
+            .name("test_section".to_string())
+            .section_order(0)
+            .section_level(0)
+            .content(Some("test content".to_string()))
+            .start_char_index(Some(0))
+            .end_char_index(Some(10))
+            .build();
+
+        let section_id = section.id;
+
+        let node_content = "test content".to_string();
+
+        let node = Node::builder()
+            .index_id(created_index_id)
+            .document_id(document_id)
+            .section_id(section_id)
+            .tokens(count_tokens(&node_content) as i32)
+            .content(node_content)
+            .content_type(ContentType::Text)
+            .content_embeddings(None)
+            .content_embeddings_id(None)
+            .content_embedded_at(None)
+            .index(0)
+            .start_char_index(0)
+            .end_char_index(10)
+            .build();
+
+        let node_content_2 = "中文例子".to_string();
+        let node_2 = Node::builder()
+            .index_id(created_index_id)
+            .document_id(document_id)
+            .section_id(section_id)
+            .tokens(count_tokens(&node_content_2) as i32)
+            .content(node_content_2)
+            .content_type(ContentType::Text)
+            .content_embeddings(None)
+            .content_embeddings_id(None)
+            .content_embedded_at(None)
+            .index(1)
+            .start_char_index(0)
+            .end_char_index(10)
+            .build();
+
+        let node_1_id = node.id;
+        let node_2_id = node_2.id;
+
+        let nodes = vec![node.clone(), node_2.clone()];
+        let parsed_document = ParsedDocument {
+            document,
+            sections: vec![section],
+            total_tokens: nodes.count_tokens() as i32,
+            nodes,
+            section_count: 0,
+            node_count: 0,
+            input_uri: "".to_string(),
+        };
+
+        opened_index
+            .add_document(vec![parsed_document], None)
+            .await
+            .unwrap();
+
+        let node_count = opened_index.count_records(&SourceType::Node).await.unwrap();
+        assert_eq!(node_count, 2);
+        let section_count = opened_index
+            .count_records(&SourceType::Section)
+            .await
+            .unwrap();
+        assert_eq!(section_count, 1);
+        let document_count = opened_index
+            .count_records(&SourceType::Document)
+            .await
+            .unwrap();
+        assert_eq!(document_count, 1);
+
+        let source_data = opened_index
+            .get_source_data_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap();
+
+        let retrieved_nodes = source_data.get_node().unwrap();
+        let first_node = retrieved_nodes.first().unwrap();
+        assert_eq!(first_node.id, node_1_id);
+        assert!(first_node.content_embeddings.is_none());
+
+        let unembedded_nodes = opened_index.get_unembedded_nodes().await.unwrap();
+        assert_eq!(unembedded_nodes.len(), 2);
+
+        opened_index
+            .embed_nodes(unembedded_nodes.iter().map(|x| x.id).collect())
+            .await
+            .unwrap();
+        let unembedded_nodes = opened_index.get_unembedded_nodes().await.unwrap();
+        assert_eq!(unembedded_nodes.len(), 0);
+
+        let node_after = opened_index
+            .get_source_data_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap();
+        let node_after = node_after.get_node().unwrap();
+        let node_after = node_after.first().unwrap();
+
+        let node_after_embeddings_id = node_after.content_embeddings_id.clone().unwrap();
+
+        let text_embeddeds = opened_index
+            .get_source_data_by_id(&SourceType::TextEmbedded, node_after_embeddings_id)
+            .await
+            .unwrap();
+
+        let text_embedded = text_embeddeds.get_text_embedded().unwrap();
+        let text_embedded = text_embedded.first().unwrap();
+
+        assert_eq!(
+            node_after.content_embeddings_id.clone().unwrap(),
+            text_embedded.id
+        );
+
+        // after update there should still be only two node
+        let node_count = opened_index.count_records(&SourceType::Node).await.unwrap();
+        assert_eq!(node_count, 2);
+
+        let retrieved_nodes_after_embedding = opened_index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+
+        let first_node_after_embedding = retrieved_nodes_after_embedding.first().unwrap();
+        assert_eq!(first_node_after_embedding.id, node_1_id);
+        assert!(first_node_after_embedding.content_embeddings_id.is_some());
+        assert!(first_node_after_embedding.content_embeddings.is_some());
+
+        let text_input = TextInput::from_user_str("test");
+        let results = opened_index
+            .similar_sources(&text_input, &SourceType::Node, None)
+            .await
+            .unwrap();
+        info!("results for English similarity: {:?}", results);
+        let first_similar_result_to_english = results.get_node().unwrap();
+        let first_similar_result_to_english = first_similar_result_to_english.first().unwrap();
+        assert_eq!(first_similar_result_to_english.id, node_1_id);
+
+        let text_input = TextInput::from_user_str("中文");
+        let results = opened_index
+            .similar_sources(&text_input, &SourceType::Node, None)
+            .await
+            .unwrap();
+        info!("results for Chinese similarity: {:?}", results);
+        let first_similar_result_to_chinese = results.get_node().unwrap();
+        let first_similar_result_to_chinese = first_similar_result_to_chinese.first().unwrap();
+        assert_eq!(first_similar_result_to_chinese.id, node_2_id);
+
+        let result_text_embedded = opened_index
+            .similar_embedded_text(&text_input, &TextSourceType::NodeContent, None)
+            .await
+            .unwrap();
+
+        for similar_result in result_text_embedded {
+            info!(
+                "Distance: {:?}: Text {:?}",
+                similar_result.distance, similar_result.data.text
+            );
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_indices_are_isolated() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index_a = store.index_create("index_a").await.unwrap();
+        let index_b = store.index_create("index_b").await.unwrap();
+        let index_a_id = index_a.get_index_metadata().id;
+        let index_b_id = index_b.get_index_metadata().id;
+
+        let document_a = parsed_document(index_a_id, "doc_a", &["apple pie", "apple tart"]);
+        let document_b = parsed_document(index_b_id, "doc_b", &["中文例子"]);
+        let node_a_id = document_a.nodes[0].id;
+        let node_b_id = document_b.nodes[0].id;
+        index_a.add_document(vec![document_a], None).await.unwrap();
+        index_b.add_document(vec![document_b], None).await.unwrap();
+
+        // counts only include the records of the index
+        assert_eq!(index_a.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(index_b.count_records(&SourceType::Node).await.unwrap(), 1);
+        assert_eq!(
+            index_a.count_records(&SourceType::Section).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            index_b.count_records(&SourceType::Document).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            store
+                .index_count_records("index_b", &SourceType::Node)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // records of another index cannot be read
+        let foreign_node = index_a
+            .get_source_data_by_id(&SourceType::Node, node_b_id)
+            .await
+            .unwrap();
+        assert!(foreign_node.get_node().unwrap().is_empty());
+        let foreign_nodes = index_a
+            .get_source_data_by_ids(&SourceType::Node, vec![node_a_id, node_b_id], false)
+            .await
+            .unwrap();
+        assert_eq!(foreign_nodes.get_ids(), vec![node_a_id]);
+
+        // embedding an index leaves the other index unembedded
+        assert_eq!(index_a.get_unembedded_nodes().await.unwrap().len(), 2);
+        assert_eq!(index_b.get_unembedded_nodes().await.unwrap().len(), 1);
+        index_a.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(index_a.get_unembedded_nodes().await.unwrap().len(), 0);
+        assert_eq!(index_b.get_unembedded_nodes().await.unwrap().len(), 1);
+        assert_eq!(
+            index_a
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            index_b
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // only the requested nodes are embedded
+        let document_b_2 = parsed_document(index_b_id, "doc_b_2", &["日本語", "中文句子"]);
+        let node_b_2_id = document_b_2.nodes[0].id;
+        index_b
+            .add_document(vec![document_b_2], None)
+            .await
+            .unwrap();
+        index_b.embed_nodes(vec![node_b_id]).await.unwrap();
+        let unembedded_b = index_b.get_unembedded_nodes().await.unwrap();
+        assert_eq!(unembedded_b.len(), 2);
+        assert!(unembedded_b.iter().any(|node| node.id == node_b_2_id));
+        index_b.embed_nodes(vec![]).await.unwrap();
+
+        // similarity search never returns nodes from another index
+        let query = TextInput::from_user_str("中文例子");
+        let results_a = index_a
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(results_a.len(), 2);
+        assert!(results_a.iter().all(|node| node.index_id == index_a_id));
+        let results_b = index_b
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(results_b.len(), 3);
+        assert!(results_b.iter().all(|node| node.index_id == index_b_id));
+        assert_eq!(results_b.first().unwrap().id, node_b_id);
+
+        // deleting through an index does not touch the other index
+        index_a
+            .delete(&vec![node_b_id], &SourceType::Node)
+            .await
+            .unwrap();
+        assert_eq!(index_b.count_records(&SourceType::Node).await.unwrap(), 3);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_index_remove() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index_a = store.index_create("index_a").await.unwrap();
+        let index_b = store.index_create("index_b").await.unwrap();
+        let index_a_id = index_a.get_index_metadata().id;
+        let index_b_id = index_b.get_index_metadata().id;
+        index_a
+            .add_document(
+                vec![
+                    parsed_document(index_a_id, "doc_a", &["apple pie", "apple tart"]),
+                    parsed_document(index_a_id, "doc_a_2", &["apple juice"]),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        index_b
+            .add_document(
+                vec![parsed_document(index_b_id, "doc_b", &["中文例子"])],
+                None,
+            )
+            .await
+            .unwrap();
+        index_a.embed_nodes(vec![]).await.unwrap();
+        index_b.embed_nodes(vec![]).await.unwrap();
+        // persists a query embedding for index a
+        index_a
+            .similar_sources(&TextInput::from_user_str("apple"), &SourceType::Node, None)
+            .await
+            .unwrap();
+
+        let result = store.index_remove(index_a_id).await.unwrap();
+        assert_eq!(result.index_id, index_a_id);
+        assert_eq!(result.removed_count(&SourceType::IndexMetadata), 1);
+        assert_eq!(result.removed_count(&SourceType::Document), 2);
+        assert_eq!(result.removed_count(&SourceType::Section), 2);
+        assert_eq!(result.removed_count(&SourceType::Node), 3);
+        assert_eq!(result.removed_count(&SourceType::TextEmbedded), 4);
+
+        assert!(!store.index_exists("index_a").await.unwrap());
+        let indices = store.list_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices.first().unwrap().id, index_b_id);
+        assert_eq!(index_a.count_records(&SourceType::Node).await.unwrap(), 0);
+        assert_eq!(
+            index_a
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // the other index is untouched
+        assert_eq!(
+            index_b.count_records(&SourceType::Document).await.unwrap(),
+            1
+        );
+        assert_eq!(index_b.count_records(&SourceType::Node).await.unwrap(), 1);
+        assert_eq!(
+            index_b
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // removing an unknown index fails
+        assert!(store.index_remove(index_a_id).await.is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_check_health_shared_content_embeddings() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["apple pie", "apple pie"]);
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        // both nodes use the embedding of the first one
+        let text_embedded = index
+            .get_all_source_data(&SourceType::TextEmbedded)
+            .await
+            .unwrap()
+            .get_text_embedded()
+            .unwrap();
+        assert_eq!(text_embedded.len(), 1);
+        let nodes = index
+            .get_all_source_data(&SourceType::Node)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        let (first, surviving): (Vec<Node>, Vec<Node>) = nodes
+            .into_iter()
+            .partition(|node| Some(node.id) == text_embedded[0].source_id);
+        assert_eq!(
+            surviving[0].content_embeddings_id,
+            Some(text_embedded[0].id)
+        );
+
+        index
+            .delete(&vec![first[0].id], &SourceType::Node)
+            .await
+            .unwrap();
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        let kept = index
+            .get_all_source_data(&SourceType::TextEmbedded)
+            .await
+            .unwrap()
+            .get_text_embedded()
+            .unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(surviving[0].content_embeddings_id, Some(kept[0].id));
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_check_health_orphans() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["apple", "pear"]);
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+
+        // a section whose document is gone
+        let orphan_section = parsed_document(index_id, "gone", &["plum"]).sections[0].clone();
+        index
+            .add_source_data(
+                SourceData::Section(vec![orphan_section.clone()]),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        // a text embedding of the registered model whose node is gone
+        let mut orphan_model_embeddings = index
+            .find_model_embeddings(model.id, &[hash_str("pear")])
+            .await
+            .unwrap()
+            .remove(0);
+        orphan_model_embeddings.id = Uuid::new_v4();
+        orphan_model_embeddings.source_id = Some(Uuid::new_v4());
+        index
+            .add_model_embeddings(model.id, vec![orphan_model_embeddings.clone()])
+            .await
+            .unwrap();
+
+        let report = store.check_health(false).await.unwrap();
+        assert_eq!(
+            report.issues,
+            vec![
+                StoreHealthIssue::OrphanModelEmbeddings {
+                    model_id: model.id,
+                    text_embedded_ids: vec![orphan_model_embeddings.id],
+                },
+                StoreHealthIssue::OrphanSections {
+                    section_ids: vec![orphan_section.id],
+                },
+            ]
+        );
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.unresolved());
+        assert_eq!(report.repaired.len(), 2);
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 1);
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("pear")])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_check_health() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let healthy_document = parsed_document(index_id, "healthy", &["apple pie"]);
+        let orphaned_document = parsed_document(index_id, "orphaned", &["中文例子", "日本語"]);
+        let orphaned_document_id = orphaned_document.document.id;
+        let orphaned_node_ids: Vec<Uuid> =
+            orphaned_document.nodes.iter().map(|node| node.id).collect();
+        index
+            .add_document(vec![healthy_document, orphaned_document], None)
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .similar_sources(&TextInput::from_user_str("apple"), &SourceType::Node, None)
+            .await
+            .unwrap();
+
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report);
+        assert!(report.issues.is_empty());
+
+        // break the store
+        index
+            .delete(&vec![orphaned_document_id], &SourceType::Document)
+            .await
+            .unwrap();
+        let connection = store.connect().await.unwrap();
+        connection
+            .drop_table(D_TABLE_NAME_INDEX_METADATA)
+            .await
+            .unwrap();
+        let other_store_metadata = StoreMetadata::builder()
+            .name("other_store".to_string())
+            .uri("".to_string())
+            .model(None)
+            .model_id(None)
+            .build();
+        store
+            .set_store_metadata(other_store_metadata, store.get_store_model_dimensions())
+            .await
+            .unwrap();
+
+        let report = store.check_health(false).await.unwrap();
+        assert!(!report.is_healthy());
+        assert!(report.repaired.is_empty());
+        assert!(report.issues.contains(&StoreHealthIssue::MissingTable {
+            table: D_TABLE_NAME_INDEX_METADATA.to_string()
+        }));
+        assert!(report
+            .issues
+            .contains(&StoreHealthIssue::StoreMetadataCount { count: 2 }));
+        let orphan_nodes = report
+            .issues
+            .iter()
+            .find_map(|issue| match issue {
+                StoreHealthIssue::OrphanNodes { node_ids } => Some(node_ids.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(orphan_nodes.len(), 2);
+        assert!(orphaned_node_ids.iter().all(|id| orphan_nodes.contains(id)));
+        // the text embeddings of the orphan nodes, but not the user query
+        let orphan_texts = report
+            .issues
+            .iter()
+            .find_map(|issue| match issue {
+                StoreHealthIssue::OrphanTextEmbedded { text_embedded_ids } => {
+                    Some(text_embedded_ids.clone())
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(orphan_texts.len(), 2);
+        // the section of the removed document
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, StoreHealthIssue::OrphanSections { section_ids } if section_ids.len() == 1)));
+
+        // repair
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.unresolved());
+        assert_eq!(report.repaired.len(), 5);
+        assert!(connection
+            .table_names()
+            .execute()
+            .await
+            .unwrap()
+            .contains(&D_TABLE_NAME_INDEX_METADATA.to_string()));
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 1);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+        // an embedder with another dimension does not match the vector column
+        let reopened = LanceDb::open_with_options(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32)),
+            &StoreOpenOptions::builder()
+                .allow_model_mismatch(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+        let report = reopened.check_health(false).await.unwrap();
+        assert!(!report.is_healthy());
+        assert!(report
+            .issues
+            .contains(&StoreHealthIssue::DimensionMismatch {
+                table: SourceType::TextEmbedded.table_name(),
+                column: D_TABLE_COLUMN_NAME_VECTOR.to_string(),
+                expected: 32,
+                actual: 64,
+            }));
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_migrate_model() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let previous_dimensions = store.get_store_model_dimensions();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子", "more text"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        let query = TextInput::from_user_str("中文");
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // stop after the first batch, the store still uses the previous model
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder()
+                    .batch_size(2)
+                    .max_batches(Some(1))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.nodes_embedded, 2);
+        assert_eq!(store.get_store_model_dimensions(), previous_dimensions);
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // resume
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().batch_size(2).build(),
+            )
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!(report.nodes_resumed, 2);
+        assert_eq!(report.nodes_embedded, 1);
+        // the cached query, reused by the second search
+        assert_eq!(report.texts_dropped, 1);
+        assert_eq!(
+            report.previous_model.map(|model| model.dimensions),
+            Some(previous_dimensions)
+        );
+        assert_eq!(store.get_store_model_dimensions(), 32);
+
+        let index = store.index_open("test_index").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_ids[0])
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(
+            node[0]
+                .content_embeddings
+                .as_ref()
+                .unwrap()
+                .embeddings
+                .len(),
+            32
+        );
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+
+        // the store is opened with the model migrated to, not with the previous model
+        let uri = store.get_store_uri();
+        let reopened = LanceDb::open(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        let error = LanceDb::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
+            .await
+            .err()
+            .expect("Opening with the previous model should fail");
+        assert!(error.to_string().contains("uses model"), "{}", error);
+        let reopened = LanceDb::open_with_options(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::new()),
+            &StoreOpenOptions::builder()
+                .allow_model_mismatch(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        assert_eq!(
+            reopened
+                .index_open("test_index")
+                .await
+                .unwrap()
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_migrate_model_resume_after_changes() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let first = parsed_document(index_id, "first", &["alpha content", "beta content"]);
+        let second = parsed_document(index_id, "second", &["gamma content", "delta content"]);
+        let first_nodes = first.nodes.clone();
+        index.add_document(vec![first, second], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        // the nodes of the first document are staged
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder()
+                    .batch_size(2)
+                    .max_batches(Some(1))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.nodes_embedded, 2);
+
+        // then one is updated and the other deleted in the store
+        let mut updated = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, first_nodes[0].id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap()
+            .remove(0);
+        updated.content = "alpha updated".to_string();
+        updated.content_embeddings = None;
+        index
+            .update(SourceData::Node(vec![updated]), None)
+            .await
+            .unwrap();
+        index
+            .delete(&vec![first_nodes[1].id], &SourceType::Node)
+            .await
+            .unwrap();
+
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().batch_size(2).build(),
+            )
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!(report.records_unstaged, 2);
+        assert_eq!(report.nodes_resumed, 0);
+        assert_eq!(report.nodes_embedded, 3);
+
+        // the deleted node is not brought back
+        let index = store.index_open("test_index").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, first_nodes[0].id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap()
+            .remove(0);
+        assert_eq!(node.content, "alpha updated");
+        assert_eq!(
+            node.content_embeddings.unwrap().hash,
+            hash_str("alpha updated")
+        );
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+    }
+
+    /// Staging whose commit is interrupted right after it started.
+    struct InterruptedCommit(LanceDbMigrationStaging);
+
+    #[async_trait]
+    impl ModelMigrationStaging for InterruptedCommit {
+        async fn staged_nodes(&self) -> TuoResult<Vec<Node>> {
+            self.0.staged_nodes().await
+        }
+
+        async fn staged_texts(&self) -> TuoResult<Vec<TextEmbedded>> {
+            self.0.staged_texts().await
+        }
+
+        async fn unstage(&self, node_ids: &[Uuid], text_ids: &[Uuid]) -> TuoResult<()> {
+            self.0.unstage(node_ids, text_ids).await
+        }
+
+        async fn stage_nodes(
+            &self,
+            nodes: Vec<Node>,
+            texts_embedded: Vec<TextEmbedded>,
+        ) -> TuoResult<()> {
+            self.0.stage_nodes(nodes, texts_embedded).await
+        }
+
+        async fn stage_texts(&self, texts_embedded: Vec<TextEmbedded>) -> TuoResult<()> {
+            self.0.stage_texts(texts_embedded).await
+        }
+
+        async fn commit(&self) -> TuoResult<()> {
+            let connection = self.0.connect().await?;
+            self.0.stage_store_metadata(&connection).await?;
+            Err(TuoPartsError::StoreError("Commit interrupted".to_string()).into())
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_migrate_model_interrupted_commit() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let previous_dimensions = store.get_store_model_dimensions();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子", "more text"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let embedder = CharHashEmbedder::with_dimensions(32);
+        let model = embedder.get_model_metadata();
+        let staging = LanceDbMigrationStaging {
+            store_metadata: StoreMetadata {
+                model_id: Some(model.id),
+                model: Some(model.clone()),
+                ..store.get_store_metadata()
+            },
+            model,
+            previous_model_id: store.store_metadata.model_id,
+            vector_storage: store.vector_storage,
+        };
+        staging.prepare().await.unwrap();
+        let connection = store.connect().await.unwrap();
+        let nodes = stream_sources(
+            &connection,
+            &SourceType::Node.table_name(),
+            &SourceType::Node,
+            previous_dimensions,
+        )
+        .await
+        .unwrap()
+        .map_ok(|source_data| source_data.get_node().unwrap_or_default())
+        .boxed();
+        let texts_embedded = stream_sources(
+            &connection,
+            D_TABLE_NAME_TEXT_EMBEDDED,
+            &SourceType::TextEmbedded,
+            previous_dimensions,
+        )
+        .await
+        .unwrap()
+        .map_ok(|source_data| source_data.get_text_embedded().unwrap_or_default())
+        .boxed();
+        let result = run_model_migration(
+            &InterruptedCommit(staging),
+            &embedder,
+            nodes,
+            texts_embedded,
+            &ModelMigrationOptions::builder().build(),
+        )
+        .await;
+        assert!(result.is_err());
+
+        // the store is still usable with the previous model
+        let query = TextInput::from_user_str("中文");
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // opening the store finishes the commit
+        let reopened = LanceDb::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        let table_names = connection.table_names().execute().await.unwrap();
+        assert!(!table_names
+            .iter()
+            .any(|table| table.ends_with(D_TABLE_NAME_SUFFIX_MIGRATION)));
+        let index = reopened.index_open("test_index").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+        let report = reopened.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_schema_migration() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let uri = store.get_store_uri();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["apple pie", "中文例子"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        let connection = store.connect().await.unwrap();
+        assert_eq!(
+            read_schema_version(&connection).await.unwrap(),
+            LANCEDB_SCHEMA_VERSION
+        );
+
+        // a store written before the schema version was recorded, with the columns added since
+        for (table, column) in [
+            (
+                D_TABLE_NAME_STORE_METADATA,
+                D_TABLE_COLUMN_NAME_SCHEMA_VERSION,
+            ),
+            (
+                D_TABLE_NAME_DOCUMENTS,
+                DocumentFieldName::ContentHash.name(),
+            ),
+            (
+                D_TABLE_NAME_TEXT_EMBEDDED,
+                TextEmbeddedFieldName::IndexId.name(),
+            ),
+        ] {
+            connection
+                .open_table(table)
+                .execute()
+                .await
+                .unwrap()
+                .drop_columns(&[column])
+                .await
+                .unwrap();
+        }
+        assert_eq!(read_schema_version(&connection).await.unwrap(), 0);
+        let reopened = LanceDb::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
+            .await
+            .unwrap();
+        assert_eq!(
+            reopened.get_store_metadata().id,
+            store.get_store_metadata().id
+        );
+        assert_eq!(store.list_indices().await.unwrap().len(), 1);
+        assert_eq!(
+            read_schema_version(&connection).await.unwrap(),
+            LANCEDB_SCHEMA_VERSION
+        );
+        // the text embeddings are scoped to the index of their nodes again
+        let index = reopened.index_open("test_index").await.unwrap();
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        let results = index
+            .similar_sources(&TextInput::from_user_str("中文"), &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+        let report = reopened.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+
+        // a migration adding a column with a default, then renaming it
+        let migrations = vec![SchemaMigration {
+            version: LANCEDB_SCHEMA_VERSION + 1,
+            changes: vec![
+                SchemaChange::AddColumn {
+                    table: D_TABLE_NAME_DOCUMENTS,
+                    field: Field::new("language", DataType::Utf8, true),
+                    default: Some(|rows| Arc::new(StringArray::from(vec!["en"; rows]))),
+                },
+                SchemaChange::RenameColumn {
+                    table: D_TABLE_NAME_DOCUMENTS,
+                    from: "language",
+                    to: "lang",
+                },
+            ],
+        }];
+        let previous_version = migrate_schema(&connection, &migrations, LANCEDB_SCHEMA_VERSION + 1)
+            .await
+            .unwrap();
+        assert_eq!(previous_version, LANCEDB_SCHEMA_VERSION);
+        let documents = connection
+            .open_table(D_TABLE_NAME_DOCUMENTS)
+            .execute()
+            .await
+            .unwrap();
+        let schema = documents.schema().await.unwrap();
+        assert!(schema.field_with_name("language").is_err());
+        assert!(schema.field_with_name("lang").is_ok());
+        let rows = read_string_rows(&connection, D_TABLE_NAME_DOCUMENTS, &["lang"])
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![vec![Some("en".to_string())]]);
+
+        // the store is now newer than the library
+        let result = LanceDb::open(uri.as_str(), Box::new(CharHashEmbedder::new())).await;
+        let error = result.err().expect("Opening a newer store should fail");
+        assert!(error.to_string().contains("newer"), "{}", error);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_vector_index() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        // product quantization needs at least 256 vectors to train
+        // texts with distinct proportions of letters, so that their embeddings differ
+        let contents: Vec<String> = (0..300)
+            .map(|i| {
+                ['a', 'b', 'c', 'd', 'e']
+                    .iter()
+                    .enumerate()
+                    .map(|(digit, c)| c.to_string().repeat(i / 4usize.pow(digit as u32) % 4 + 1))
+                    .collect()
+            })
+            .collect();
+        let contents: Vec<&str> = contents.iter().map(|c| c.as_str()).collect();
+        let document = parsed_document(index_id, "doc", &contents);
+        let target_node_id = document.nodes[42].id;
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        let query = TextInput::from_user_str(contents[42]);
+        let opts = || {
+            Some(
+                LanceDbIndexSearchOptions::builder()
+                    .top_k(3)
+                    .nprobes(Some(4))
+                    .refine_factor(Some(10))
+                    .build(),
+            )
+        };
+
+        // without a vector index, searches are flat and ignore the index options
+        assert!(store.vector_index().await.unwrap().is_none());
+        let results = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, opts())
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.source_id, Some(target_node_id));
+
+        let info = store
+            .build_vector_index(
+                &LanceDbVectorIndexOptions::builder()
+                    .num_partitions(Some(4))
+                    .num_sub_vectors(Some(8))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        // the nodes and the user query of the previous search
+        assert_eq!(info.indexed_rows, 301);
+        assert_eq!(info.unindexed_rows, 0);
+        assert_eq!(info.column, D_TABLE_COLUMN_NAME_VECTOR);
+
+        let results = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, opts())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].data.source_id, Some(target_node_id));
+        assert!(results
+            .iter()
+            .all(|result| result.data.index_id == Some(index_id)));
+        // reusing the cached query rewrites it to update its used_at, outside the index
+        let info = index.vector_index().await.unwrap().unwrap();
+        assert_eq!(info.unindexed_rows, 1);
+
+        // rebuilding covers the new rows
+        let info = store
+            .build_vector_index(
+                &LanceDbVectorIndexOptions::builder()
+                    .num_partitions(Some(2))
+                    .num_sub_vectors(Some(8))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(info.indexed_rows, 301);
+        assert_eq!(info.unindexed_rows, 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_hybrid_search() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(
+            index_id,
+            "doc",
+            &["apple pie recipe", "中文例子", "日本語の例文", "pear tart"],
+        );
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        // CJK text is matched without word delimiters
+        let query = TextInput::from_user_str("例子");
+        let keyword_matches = index
+            .keyword_search(&query, 10, &Bm25Options::default())
+            .await
+            .unwrap();
+        assert_eq!(keyword_matches.len(), 2);
+        assert_eq!(keyword_matches[0].data_id, node_ids[1]);
+        assert_eq!(keyword_matches[1].data_id, node_ids[2]);
+
+        let results = index
+            .hybrid_search(
+                &query,
+                &HybridSearchOptions::builder().top_k(3).build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].data_id, node_ids[1]);
+        let scores = results[0].hybrid_scores.clone().unwrap();
+        assert!(scores.vector_distance.is_some());
+        assert!(scores.keyword_score.is_some());
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].distance <= pair[1].distance));
+
+        // keyword scores only
+        let results = index
+            .hybrid_search(
+                &TextInput::from_user_str("Apple"),
+                &HybridSearchOptions::builder()
+                    .fusion(HybridFusion::Weighted { vector_weight: 0.0 })
+                    .build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data_id, node_ids[0]);
+        assert_eq!(results[0].distance, 0.0);
+        assert_eq!(
+            results[1].hybrid_scores.as_ref().unwrap().keyword_score,
+            None
+        );
+        // the term statistics are computed again once the nodes changed
+        let document = parsed_document(index_id, "doc2", &["更多例子"]);
+        let new_node_id = document.nodes[0].id;
+        index.add_document(vec![document], None).await.unwrap();
+        let keyword_matches = index
+            .keyword_search(&query, 10, &Bm25Options::default())
+            .await
+            .unwrap();
+        assert_eq!(keyword_matches.len(), 3);
+        assert!(keyword_matches
+            .iter()
+            .any(|keyword_match| keyword_match.data_id == new_node_id));
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_search_filter() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document_a = parsed_document(
+            index_id,
+            "folder_a/doc",
+            &["apple", "apple pie with cream and sugar"],
+        );
+        let document_b = parsed_document(index_id, "folder_b/doc", &["apple tart", "pear"]);
+        let document_a_id = document_a.document.id;
+        let section_b_id = document_b.sections[0].id;
+        let node_ids: Vec<Uuid> = document_a
+            .nodes
+            .iter()
+            .chain(document_b.nodes.iter())
+            .map(|node| node.id)
+            .collect();
+        index
+            .add_document(vec![document_a, document_b], None)
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let query = TextInput::from_user_str("apple");
+        let search = |filter: LanceDbSearchFilter| {
+            let index = &index;
+            let query = &query;
+            async move {
+                let mut ids: Vec<Uuid> = index
+                    .similar_embedded_text(
+                        query,
+                        &TextSourceType::NodeContent,
+                        Some(LanceDbIndexSearchOptions::builder().filter(filter).build()),
+                    )
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|result| result.data.source_id.unwrap())
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+        let sorted = |mut ids: Vec<Uuid>| {
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            search(LanceDbSearchFilter::default()).await,
+            sorted(node_ids.clone())
+        );
+        assert_eq!(
+            search(
+                LanceDbSearchFilter::builder()
+                    .document_ids(Some(vec![document_a_id]))
+                    .build()
+            )
+            .await,
+            sorted(node_ids[..2].to_vec())
+        );
+        assert_eq!(
+            search(
+                LanceDbSearchFilter::builder()
+                    .section_ids(Some(vec![section_b_id]))
+                    .build()
+            )
+            .await,
+            sorted(node_ids[2..].to_vec())
+        );
+        assert_eq!(
+            search(
+                LanceDbSearchFilter::builder()
+                    .source_uri_prefix(Some("/test/folder_b/".to_string()))
+                    .build()
+            )
+            .await,
+            sorted(node_ids[2..].to_vec())
+        );
+        assert_eq!(
+            search(
+                LanceDbSearchFilter::builder()
+                    .source_uri_prefix(Some("/test/folder_a/".to_string()))
+                    .max_tokens(Some(2))
+                    .build()
+            )
+            .await,
+            vec![node_ids[0]]
+        );
+        assert_eq!(
+            search(LanceDbSearchFilter::builder().min_tokens(Some(3)).build()).await,
+            vec![node_ids[1]]
+        );
+        assert!(search(
+            LanceDbSearchFilter::builder()
+                .content_types(Some(vec![ContentType::Image]))
+                .build()
+        )
+        .await
+        .is_empty());
+        assert!(search(
+            LanceDbSearchFilter::builder()
+                .document_ids(Some(vec![]))
+                .build()
+        )
+        .await
+        .is_empty());
+
+        // every node was embedded within the hour around the first one
+        let embedded_at = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, None)
+            .await
+            .unwrap()[0]
+            .data
+            .embedded_at;
+        let hour = std::time::Duration::from_secs(3600);
+        assert_eq!(
+            search(
+                LanceDbSearchFilter::builder()
+                    .embedded_after(Some(embedded_at - hour))
+                    .embedded_before(Some(embedded_at + hour))
+                    .build()
+            )
+            .await,
+            sorted(node_ids.clone())
+        );
+        assert!(search(
+            LanceDbSearchFilter::builder()
+                .embedded_after(Some(embedded_at + hour))
+                .build()
+        )
+        .await
+        .is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_similar_documents_and_sections() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let embedder = index.get_index_embedder().await.unwrap();
+        let embedding_opt = TextEmbeddingOptions::builder().build();
+        let summary = |text: &str, source_type: TextSourceType, source_id: Uuid| {
+            let embedder = embedder.clone();
+            let input = TextInput {
+                text: text.to_string(),
+                source_type,
+                source_id: Some(source_id),
+            };
+            let embedding_opt = embedding_opt.clone();
+            async move { embedder.embed_input(&input, &embedding_opt).await.unwrap() }
+        };
+
+        // a document summarized as the query, whose nodes do not match it
+        let mut document_a = parsed_document(index_id, "a", &["kiwi"]);
+        let summary_a = summary(
+            "apple",
+            TextSourceType::SummaryDocument,
+            document_a.document.id,
+        )
+        .await;
+        document_a.document.summary_text_id = Some(summary_a.id);
+        // a document without summary, ranked by its closest node
+        let document_b = parsed_document(index_id, "b", &["apple pie", "plum"]);
+        // a document whose summary does not match the query, although its node does
+        let mut document_c = parsed_document(index_id, "c", &["apple"]);
+        let summary_c = summary(
+            "zzzz",
+            TextSourceType::SummaryDocument,
+            document_c.document.id,
+        )
+        .await;
+        document_c.document.summary_text_id = Some(summary_c.id);
+        let document_ids = [
+            document_a.document.id,
+            document_b.document.id,
+            document_c.document.id,
+        ];
+        let section_ids = [
+            document_a.sections[0].id,
+            document_b.sections[0].id,
+            document_c.sections[0].id,
+        ];
+        index
+            .add_document(vec![document_a, document_b, document_c], None)
+            .await
+            .unwrap();
+        index
+            .add_text_embeddings(&vec![summary_a, summary_c])
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let query = TextInput::from_user_str("apple");
+        let documents = index
+            .similar_sources(&query, &SourceType::Document, None)
+            .await
+            .unwrap()
+            .get_document()
+            .unwrap();
+        assert_eq!(
+            documents.iter().map(|d| d.id).collect::<Vec<_>>(),
+            document_ids.to_vec()
+        );
+        let documents = index
+            .similar_sources(
+                &query,
+                &SourceType::Document,
+                Some(LanceDbIndexSearchOptions::builder().top_k(2).build()),
+            )
+            .await
+            .unwrap()
+            .get_document()
+            .unwrap();
+        assert_eq!(
+            documents.iter().map(|d| d.id).collect::<Vec<_>>(),
+            document_ids[..2].to_vec()
+        );
+
+        // sections have no summary, so all are ranked by their nodes
+        let sections = index
+            .similar_sources(
+                &query,
+                &SourceType::Section,
+                Some(LanceDbIndexSearchOptions::builder().top_k(2).build()),
+            )
+            .await
+            .unwrap()
+            .get_section()
+            .unwrap();
+        assert_eq!(
+            sections.iter().map(|s| s.id).collect::<Vec<_>>(),
+            vec![section_ids[2], section_ids[1]]
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_query_embedding_cache() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let count_queries = || index.count_records(&SourceType::TextEmbedded);
+
+        // a cached query embedding, last used long ago
+        let query = TextInput::from_user_str("apple");
+        let embedder = index.get_index_embedder().await.unwrap();
+        let mut cached = embedder
+            .embed_input(
+                &query,
+                &TextEmbeddingOptions::builder().save_text(true).build(),
+            )
+            .await
+            .unwrap();
+        cached.used_at = utc_from_epoch(0);
+        index
+            .add_text_embeddings(&vec![cached.clone()])
+            .await
+            .unwrap();
+
+        index
+            .similar_embedded_text(&query, &TextSourceType::UserQuery, None)
+            .await
+            .unwrap();
+        assert_eq!(count_queries().await.unwrap(), 1);
+        let reused = index
+            .find_text_embedded(
+                &cached.hash,
+                &cached.embedding_model,
+                &TextSourceType::UserQuery,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reused.id, cached.id);
+        assert!(reused.used_at > cached.used_at);
+
+        // new queries are only persisted by a read-write cache
+        for (text, query_cache, count) in [
+            ("pear", QueryEmbeddingCache::ReadOnly, 1),
+            ("pear", QueryEmbeddingCache::Disabled, 1),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("apple", QueryEmbeddingCache::Disabled, 2),
+        ] {
+            index
+                .similar_embedded_text(
+                    &TextInput::from_user_str(text),
+                    &TextSourceType::UserQuery,
+                    Some(
+                        LanceDbIndexSearchOptions::builder()
+                            .query_cache(query_cache)
+                            .build(),
+                    ),
+                )
+                .await
+                .unwrap();
+            assert_eq!(count_queries().await.unwrap(), count);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_embedding_dedup() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let content_embeddings_ids = |nodes: SourceData| {
+            nodes
+                .get_node()
+                .unwrap()
+                .into_iter()
+                .map(|node| node.content_embeddings_id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // repeated contents of a batch are embedded once
+        let document = parsed_document(index_id, "a", &["disclaimer", "apple", "disclaimer"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        let ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(ids[0], ids[2]);
+        assert_ne!(ids[0], ids[1]);
+
+        // contents embedded before are reused across documents
+        let document = parsed_document(index_id, "b", &["disclaimer", "pear"]);
+        let other_node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 1,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let other_ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, other_node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(other_ids[0], ids[0]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_lookup_source_data_by_ids() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["a", "b", "c", "d"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+
+        // more ids than a chunk, with the nodes spread across chunks
+        let unknown_ids: Vec<Uuid> = (0..SOURCE_IDS_CHUNK_SIZE + 10)
+            .map(|_| Uuid::new_v4())
+            .collect();
+        let mut ids = unknown_ids.clone();
+        ids.insert(0, node_ids[2]);
+        ids.insert(SOURCE_IDS_CHUNK_SIZE / 2, node_ids[0]);
+        ids.insert(SOURCE_IDS_CHUNK_SIZE + 5, node_ids[3]);
+        ids.push(node_ids[1]);
+
+        let lookup = index
+            .lookup_source_data_by_ids(&SourceType::Node, ids, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            lookup.source_data.get_ids(),
+            vec![node_ids[2], node_ids[0], node_ids[3], node_ids[1]]
+        );
+        assert_eq!(lookup.missing_ids, unknown_ids);
+
+        let lookup = index
+            .lookup_source_data_by_ids(&SourceType::Node, vec![], true)
+            .await
+            .unwrap();
+        assert!(lookup.source_data.get_ids().is_empty());
+        assert!(lookup.missing_ids.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_quoted_values() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("o'brien").await.unwrap();
+        assert_eq!(index.get_index_metadata().name, "o'brien");
+        assert!(store.index_exists("o'brien").await.unwrap());
+        assert!(!store.index_exists("x' OR name != 'x").await.unwrap());
+        let index = store.index_open("o'brien").await.unwrap();
+
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "it's", &["don't panic"]);
+        let node_id = document.nodes[0].id;
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let results = index
+            .similar_embedded_text(
+                &TextInput::from_user_str("don't panic"),
+                &TextSourceType::NodeContent,
+                Some(
+                    LanceDbIndexSearchOptions::builder()
+                        .filter(
+                            LanceDbSearchFilter::builder()
+                                .source_uri_prefix(Some("/test/it's".to_string()))
+                                .build(),
+                        )
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data.source_id, Some(node_id));
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_records_by_document() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear"]);
+        let document_id = document.document.id;
+        let section_id = document.sections[0].id;
+        index
+            .add_document(
+                vec![document, parsed_document(index_id, "doc_b", &["plum"])],
+                None,
+            )
+            .await
+            .unwrap();
+
+        // each table is looked up by its own columns
+        for (source_type, count) in [
+            (SourceType::Node, 2),
+            (SourceType::Section, 1),
+            (SourceType::Document, 1),
+        ] {
+            let records = index
+                .get_source_data_by_document_ids(&source_type, &[document_id])
+                .await
+                .unwrap();
+            assert_eq!(records.get_ids().len(), count);
+        }
+        assert!(index
+            .get_source_data_by_document_ids(&SourceType::TextEmbedded, &[document_id])
+            .await
+            .is_err());
+        let section = index
+            .get_source_data_by_id(&SourceType::Section, section_id)
+            .await
+            .unwrap();
+        assert_eq!(section.get_ids(), vec![section_id]);
+        index
+            .delete(&vec![document_id], &SourceType::Document)
+            .await
+            .unwrap();
+        assert_eq!(
+            index
+                .get_source_data_by_ids(&SourceType::Document, vec![document_id], false)
+                .await
+                .unwrap()
+                .get_ids(),
+            vec![]
+        );
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_add_document_rollback() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["plum"]),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 2);
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 2);
+
+        // the documents are written last, so their write fails after the nodes and sections are added
+        let connection = store.connect().await.unwrap();
+        connection.drop_table(D_TABLE_NAME_DOCUMENTS).await.unwrap();
+        let result = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_c", &["cherry"]),
+                    parsed_document(index_id, "doc_d", &["grape", "melon"]),
+                ],
+                None,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 2);
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_snapshots() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        index
+            .add_document(vec![parsed_document(index_id, "doc_a", &["apple"])], None)
+            .await
+            .unwrap();
+        let snapshot = store.snapshot_create("s1").await.unwrap();
+        assert!(store.snapshot_create("s1").await.is_err());
+        index
+            .add_document(
+                vec![parsed_document(index_id, "doc_b", &["plum", "pear"])],
+                None,
+            )
+            .await
+            .unwrap();
+        store.snapshot_create("s2").await.unwrap();
+
+        let snapshots = store.snapshot_list().await.unwrap();
+        assert_eq!(
+            snapshots
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["s1", "s2"]
+        );
+        assert_eq!(snapshots[0], snapshot);
+        assert!(store.snapshot_index_open("s3", "test_index").await.is_err());
+
+        // the index at the snapshot only has the first document, and rejects writes
+        let snapshot_index = store.snapshot_index_open("s1", "test_index").await.unwrap();
+        assert!(snapshot_index.read_only);
+        assert_eq!(
+            snapshot_index
+                .count_records(&SourceType::Document)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            snapshot_index
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(snapshot_index
+            .add_document(vec![parsed_document(index_id, "doc_c", &["fig"])], None)
+            .await
+            .is_err());
+        assert!(snapshot_index
+            .delete(&vec![Uuid::new_v4()], &SourceType::Node)
+            .await
+            .is_err());
+        // each opening has its own copy, removed along with the index
+        let other_snapshot_index = store.snapshot_index_open("s1", "test_index").await.unwrap();
+        assert_eq!(
+            other_snapshot_index
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            1
+        );
+        let copy_uri = other_snapshot_index.get_store_metadata().uri;
+        assert_ne!(copy_uri, snapshot_index.get_store_metadata().uri);
+        assert!(Path::new(&copy_uri).exists());
+        drop(other_snapshot_index);
+        assert!(!Path::new(&copy_uri).exists());
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+
+        // rolled back to the first snapshot, then forward to the second one
+        store.snapshot_restore("s1").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 1);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 1);
+        assert_eq!(
+            store.get_store_metadata().id,
+            snapshot_index.store_metadata.id
+        );
+        store.snapshot_restore("s2").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 2);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_collect_garbage() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let registered_model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear"]);
+        let mut node = document.nodes[0].clone();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .embed_nodes_with_model(registered_model.id, vec![])
+            .await
+            .unwrap();
+        // the text embeddings of a registered model are referenced by their node
+        let mut orphan_model_embeddings = index
+            .find_model_embeddings(registered_model.id, &[hash_str("pear")])
+            .await
+            .unwrap()
+            .remove(0);
+        orphan_model_embeddings.id = Uuid::new_v4();
+        orphan_model_embeddings.source_id = Some(Uuid::new_v4());
+        index
+            .add_model_embeddings(registered_model.id, vec![orphan_model_embeddings.clone()])
+            .await
+            .unwrap();
+        index
+            .similar_sources(&TextInput::from_user_str("plum"), &SourceType::Node, None)
+            .await
+            .unwrap();
+        let model = index.get_model().name;
+        let mut old_query = index
+            .find_text_embeddings(
+                &[hash_str("plum")],
+                model.as_str(),
+                &TextSourceType::UserQuery,
+            )
+            .await
+            .unwrap()
+            .remove(0);
+        old_query.id = Uuid::new_v4();
+        old_query.hash = hash_str("cherry");
+        old_query.used_at = utc_from_epoch(0);
+        index
+            .add_text_embeddings(&vec![old_query.clone()])
+            .await
+            .unwrap();
+        // re-embedding an updated node leaves its previous embeddings unreferenced
+        let old_embeddings_id = index
+            .get_source_data_by_id(&SourceType::Node, node.id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap()[0]
+            .content_embeddings_id
+            .unwrap();
+        node.content = "banana".to_string();
+        index
+            .update(SourceData::Node(vec![node]), None)
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            5
+        );
 
-        let section = Section::builder()
-            .index_id(created_index_id)
-            .document_id(document_id)
-            .name("test_section".to_string())
-            .section_order(0)
-            .section_level(0)
-            .content(Some("test content".to_string()))
-            .start_char_index(Some(0))
-            .end_char_index(Some(10))
+        let opts = LanceDbGcOptions::builder()
+            .user_query_max_age(Some(Duration::from_secs(24 * 60 * 60)))
+            .prune_versions_older_than(Some(Duration::ZERO))
             .build();
+        let report = store
+            .collect_garbage(&LanceDbGcOptions {
+                dry_run: true,
+                ..opts.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            report.unreferenced_text_embedded_ids,
+            vec![old_embeddings_id]
+        );
+        assert_eq!(report.expired_user_query_ids, vec![old_query.id]);
+        assert_eq!(
+            report.unreferenced_model_embedding_ids,
+            HashMap::from([(registered_model.id, vec![orphan_model_embeddings.id])])
+        );
+        assert_eq!(report.bytes_reclaimed(), 0);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            5
+        );
 
-        let section_id = section.id;
+        let report = store.collect_garbage(&opts).await.unwrap();
+        assert_eq!(
+            report.unreferenced_text_embedded_ids,
+            vec![old_embeddings_id]
+        );
+        assert_eq!(report.expired_user_query_ids, vec![old_query.id]);
+        assert_eq!(
+            report.unreferenced_model_embedding_ids,
+            HashMap::from([(registered_model.id, vec![orphan_model_embeddings.id])])
+        );
+        assert_eq!(
+            index
+                .find_model_embeddings(registered_model.id, &[hash_str("pear")])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(report.versions_removed[D_TABLE_NAME_TEXT_EMBEDDED] > 0);
+        assert!(report.bytes_reclaimed() > 0, "{:?}", report);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+        let results = index
+            .similar_sources(&TextInput::from_user_str("banana"), &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_node().unwrap().len(), 2);
 
-        let node_content = "test content".to_string();
+        // the versions of snapshots are kept
+        store.snapshot_create("s1").await.unwrap();
+        index
+            .add_document(vec![parsed_document(index_id, "doc_b", &["fig"])], None)
+            .await
+            .unwrap();
+        store.collect_garbage(&opts).await.unwrap();
+        let snapshot_index = store.snapshot_index_open("s1", "test_index").await.unwrap();
+        assert_eq!(
+            snapshot_index
+                .count_records(&SourceType::Document)
+                .await
+                .unwrap(),
+            1
+        );
+    }
 
-        let node = Node::builder()
-            .index_id(created_index_id)
-            .document_id(document_id)
-            .section_id(section_id)
-            .tokens(count_tokens(&node_content) as i32)
-            .content(node_content)
-            .content_type(ContentType::Text)
-            .content_embeddings(None)
-            .content_embeddings_id(None)
-            .content_embedded_at(None)
-            .index(0)
-            .start_char_index(0)
-            .end_char_index(10)
-            .build();
+    #[test(tokio::test)]
+    async fn test_lancedb_scan() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let documents = vec![
+            parsed_document(index_id, "doc_a", &["apple", "pear", "plum"]),
+            parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+            parsed_document(index_id, "doc_c", &["lime"]),
+        ];
+        let mut nodes: Vec<Node> = documents
+            .iter()
+            .flat_map(|document| document.nodes.clone())
+            .collect();
+        nodes.sort_by_key(|node| (node.document_id, node.index));
+        let node_ids: Vec<Uuid> = nodes.iter().map(|node| node.id).collect();
+        let mut document_ids: Vec<Uuid> = documents
+            .iter()
+            .map(|document| document.document.id)
+            .collect();
+        document_ids.sort();
+        index.add_document(documents, None).await.unwrap();
+        // the records of other indices are not scanned
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_d",
+                    &["date"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
 
-        let node_content_2 = "中文例子".to_string();
-        let node_2 = Node::builder()
-            .index_id(created_index_id)
-            .document_id(document_id)
-            .section_id(section_id)
-            .tokens(count_tokens(&node_content_2) as i32)
-            .content(node_content_2)
-            .content_type(ContentType::Text)
-            .content_embeddings(None)
-            .content_embeddings_id(None)
-            .content_embedded_at(None)
-            .index(1)
-            .start_char_index(0)
-            .end_char_index(10)
-            .build();
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder().page_size(4).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(
+            pages
+                .iter()
+                .flat_map(|page| page.source_data.get_ids())
+                .collect::<Vec<_>>(),
+            node_ids
+        );
 
-        let node_1_id = node.id;
-        let node_2_id = node_2.id;
+        // resumed after the first page
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder()
+                    .page_size(4)
+                    .cursor(pages[0].cursor.clone())
+                    .build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), node_ids[4..]);
 
-        let nodes = vec![node.clone(), node_2.clone()];
-        let parsed_document = ParsedDocument {
-            document,
-            sections: vec![section],
-            total_tokens: nodes.count_tokens() as i32,
-            nodes,
-            section_count: 0,
-            node_count: 0,
-            input_uri: "".to_string(),
+        // a full page is followed by an empty one, which ends the scan
+        let pages = index
+            .scan(
+                &SourceType::Document,
+                ScanOptions::builder().page_size(3).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), document_ids);
+    }
+    #[test(tokio::test)]
+    async fn test_lancedb_add_document_upsert() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_b",
+                    &["fig"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+        let upsert = |remove_missing_under: Option<&str>| {
+            Some(
+                DocumentInsertOptions::builder()
+                    .upsert(true)
+                    .remove_missing_under(remove_missing_under.map(str::to_string))
+                    .build(),
+            )
         };
 
-        opened_index
-            .add_document(vec![parsed_document], None)
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig"]),
+                ],
+                upsert(None),
+            )
             .await
             .unwrap();
+        assert_eq!(report.added, vec!["/test/doc_a", "/test/doc_b"]);
+        index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
 
-        let node_count = opened_index.count_records(&SourceType::Node).await.unwrap();
-        assert_eq!(node_count, 2);
-        let section_count = opened_index
-            .count_records(&SourceType::Section)
+        // the same content is skipped, changed content replaces the document and its embeddings
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+                    parsed_document(index_id, "doc_c", &["lime"]),
+                ],
+                upsert(Some("/test/")),
+            )
             .await
             .unwrap();
-        assert_eq!(section_count, 1);
-        let document_count = opened_index
-            .count_records(&SourceType::Document)
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.updated, vec!["/test/doc_b"]);
+        assert_eq!(report.added, vec!["/test/doc_c"]);
+        assert!(report.removed.is_empty());
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 5);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 3);
+
+        // documents of missing sources are removed, but not those of other indices
+        let report = index
+            .add_document(
+                vec![parsed_document(index_id, "doc_a", &["apple", "pear"])],
+                upsert(Some("/test/")),
+            )
             .await
             .unwrap();
-        assert_eq!(document_count, 1);
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.removed, vec!["/test/doc_b", "/test/doc_c"]);
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 1);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            other_index
+                .count_records(&SourceType::Document)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+    #[test(tokio::test)]
+    async fn test_lancedb_document_tree() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let embedder = index.get_index_embedder().await.unwrap();
 
-        let source_data = opened_index
-            .get_source_data_by_id(&SourceType::Node, node_1_id)
+        // a document of two sections, whose sections and nodes are added out of order
+        let mut document = parsed_document(index_id, "doc", &["apple", "pear", "plum"]);
+        let mut second_section = document.sections[0].clone();
+        second_section.id = Uuid::new_v4();
+        second_section.section_order = 1;
+        document.nodes[2].section_id = second_section.id;
+        document.sections.insert(0, second_section);
+        document.nodes.reverse();
+        let summary = embedder
+            .embed_input(
+                &TextInput {
+                    text: "fruits".to_string(),
+                    source_type: TextSourceType::SummaryDocument,
+                    source_id: Some(document.document.id),
+                },
+                &TextEmbeddingOptions::builder().build(),
+            )
             .await
             .unwrap();
+        document.document.summary_text_id = Some(summary.id);
+        let document_id = document.document.id;
+        index
+            .add_document(
+                vec![document, parsed_document(index_id, "other", &["fig"])],
+                None,
+            )
+            .await
+            .unwrap();
+        index
+            .add_text_embeddings(&vec![summary.clone()])
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
 
-        let retrieved_nodes = source_data.get_node().unwrap();
-        let first_node = retrieved_nodes.first().unwrap();
-        assert_eq!(first_node.id, node_1_id);
-        assert!(first_node.content_embeddings.is_none());
-
-        let unembedded_nodes = opened_index.get_unembedded_nodes().await.unwrap();
-        assert_eq!(unembedded_nodes.len(), 2);
-
-        opened_index
-            .embed_nodes(unembedded_nodes.iter().map(|x| x.id).collect())
+        let tree = index
+            .get_document_tree(document_id, &DocumentTreeOptions::default())
             .await
+            .unwrap()
             .unwrap();
-        let unembedded_nodes = opened_index.get_unembedded_nodes().await.unwrap();
-        assert_eq!(unembedded_nodes.len(), 0);
+        assert_eq!(tree.document.id, document_id);
+        let section_orders: Vec<i32> = tree
+            .sections
+            .iter()
+            .map(|section| section.section.section_order)
+            .collect();
+        assert_eq!(section_orders, vec![0, 1]);
+        let contents: Vec<&str> = tree
+            .nodes()
+            .iter()
+            .map(|node| node.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["apple", "pear", "plum"]);
+        assert!(tree.document.summary.is_none());
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_none()));
 
-        let node_after = opened_index
-            .get_source_data_by_id(&SourceType::Node, node_1_id)
+        let tree = index
+            .get_document_tree(
+                document_id,
+                &DocumentTreeOptions::builder()
+                    .with_embeddings(true)
+                    .with_summaries(true)
+                    .build(),
+            )
             .await
+            .unwrap()
             .unwrap();
-        let node_after = node_after.get_node().unwrap();
-        let node_after = node_after.first().unwrap();
+        assert_eq!(
+            tree.document.summary.as_ref().map(|summary| summary.id),
+            Some(summary.id)
+        );
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_some()));
+        assert!(index
+            .get_document_tree(Uuid::new_v4(), &DocumentTreeOptions::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
 
-        let node_after_embeddings_id = node_after.content_embeddings_id.clone().unwrap();
+    #[test(tokio::test)]
+    async fn test_lancedb_open_store_without_model() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let uri = store.get_store_uri();
+        let model = store.get_store_model_metadata();
+        let connection = store.connect().await.unwrap();
+        let reopened = LanceDb::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
+            .await
+            .unwrap();
+        assert_eq!(
+            reopened.get_store_metadata().model.map(|model| model.id),
+            Some(model.id)
+        );
 
-        let text_embeddeds = opened_index
-            .get_source_data_by_id(&SourceType::TextEmbedded, node_after_embeddings_id)
+        // a store created before its model was recorded takes the model of the embedder
+        connection
+            .open_table(D_TABLE_NAME_MODELS_METADATA)
+            .execute()
+            .await
+            .unwrap()
+            .delete(
+                Predicate::is_not_null(EmbeddingModelMetadataFieldName::Id)
+                    .to_string()
+                    .as_str(),
+            )
+            .await
+            .unwrap();
+        let reopened = LanceDb::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
             .await
             .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), model.dimensions);
+        assert_eq!(reopened.get_store_metadata().model_id, Some(model.id));
+        let models = read_string_rows(
+            &connection,
+            D_TABLE_NAME_MODELS_METADATA,
+            &[EmbeddingModelMetadataFieldName::Id.name()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(models, vec![vec![Some(model.id.to_string())]]);
+        assert!(LanceDb::open(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32))
+        )
+        .await
+        .is_err());
+    }
 
-        let text_embedded = text_embeddeds.get_text_embedded().unwrap();
-        let text_embedded = text_embedded.first().unwrap();
+    #[test(tokio::test)]
+    async fn test_lancedb_multiple_models() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = LanceDb::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let store_model = store.get_store_model_metadata();
+        // the model of the store cannot be registered
+        assert!(store
+            .register_embedding_model(Box::new(CharHashEmbedder::new()))
+            .await
+            .is_err());
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear", "apple"]);
+        let plum = parsed_document(index_id, "doc_b", &["plum"]);
+        let plum_id = plum.nodes[0].id;
+        let plum_document_id = plum.document.id;
+        index
+            .add_document(vec![document, plum], None)
+            .await
+            .unwrap();
 
+        // the nodes embedded by each model are tracked separately
+        index.embed_nodes(vec![]).await.unwrap();
+        assert!(index
+            .get_unembedded_nodes_of_model(store_model.id)
+            .await
+            .unwrap()
+            .is_empty());
         assert_eq!(
-            node_after.content_embeddings_id.clone().unwrap(),
-            text_embedded.id
+            index
+                .get_unembedded_nodes_of_model(model.id)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![plum_id])
+            .await
+            .unwrap();
+        assert_eq!(stats.embedded, 1);
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
         );
+        assert!(index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(index
+            .embed_nodes_with_model(Uuid::new_v4(), vec![])
+            .await
+            .is_err());
 
-        // after update there should still be only two node
-        let node_count = opened_index.count_records(&SourceType::Node).await.unwrap();
-        assert_eq!(node_count, 2);
+        // the query is embedded and searched with the model chosen in the search options
+        let query = TextInput::from_user_str("plum");
+        let results = index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    LanceDbIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.source_id, Some(plum_id));
+        assert_eq!(results[0].data.embeddings.len(), 32);
+        let nodes = index
+            .similar_sources(
+                &query,
+                &SourceType::Node,
+                Some(
+                    LanceDbIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(nodes.get_ids(), vec![plum_id]);
+        let results = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.embeddings.len(), 64);
+        assert!(index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    LanceDbIndexSearchOptions::builder()
+                        .model_id(Some(Uuid::new_v4()))
+                        .build()
+                ),
+            )
+            .await
+            .is_err());
 
-        let retrieved_nodes_after_embedding = opened_index
-            .get_source_data_with_relations_by_id(&SourceType::Node, node_1_id)
+        // a model registered again keeps its id and its embeddings
+        let mut reopened = LanceDb::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let registered = reopened
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
             .await
-            .unwrap()
-            .get_node()
             .unwrap();
+        assert_eq!(registered.id, model.id);
+        let model_ids: Vec<Uuid> = reopened
+            .list_embedding_models()
+            .await
+            .unwrap()
+            .iter()
+            .map(|model| model.id)
+            .collect();
+        assert_eq!(model_ids, vec![store_model.id, model.id]);
+        let reopened_index = reopened.index_open("test_index").await.unwrap();
+        assert!(reopened_index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
 
-        let first_node_after_embedding = retrieved_nodes_after_embedding.first().unwrap();
-        assert_eq!(first_node_after_embedding.id, node_1_id);
-        assert!(first_node_after_embedding.content_embeddings_id.is_some());
-        assert!(first_node_after_embedding.content_embeddings.is_some());
+        // the embeddings of the registered models are removed with their nodes
+        index.delete_documents(&[plum_document_id]).await.unwrap();
+        assert!(index
+            .find_model_embeddings(model.id, &[hash_str("plum")])
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("apple")])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
 
-        let text_input = TextInput::from_user_str("test");
-        let results = opened_index
-            .similar_sources(&text_input, &SourceType::Node, None)
+        // the store cannot be migrated to one of its registered models
+        assert!(store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().build(),
+            )
+            .await
+            .is_err());
+        // migrating the store to another model keeps the registered models
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(48)),
+                ModelMigrationOptions::builder().build(),
+            )
             .await
             .unwrap();
-        info!("results for English similarity: {:?}", results);
-        let first_similar_result_to_english = results.get_node().unwrap();
-        let first_similar_result_to_english = first_similar_result_to_english.first().unwrap();
-        assert_eq!(first_similar_result_to_english.id, node_1_id);
+        assert!(report.committed);
+        let models = store.list_embedding_models().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].dimensions, 48);
+        assert_eq!(models[1].id, model.id);
+        let index = store.index_open("test_index").await.unwrap();
+        assert!(index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
 
-        let text_input = TextInput::from_user_str("中文");
-        let results = opened_index
-            .similar_sources(&text_input, &SourceType::Node, None)
+        let result = store.index_remove(index_id).await.unwrap();
+        assert_eq!(result.removed_count(&SourceType::TextEmbedded), 5);
+    }
+
+    /// A store of the vector storage, with an index of one node per content embedded by the signed char hash embedder.
+    async fn vector_storage_index(
+        vector_storage: LanceDbVectorStorage,
+        contents: &[&str],
+    ) -> (LanceDb, LanceDbIndex) {
+        let temp_folder = get_random_test_temp_folder();
+        let store = LanceDb::create_with_options(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::signed()),
+            &LanceDbCreateOptions::builder()
+                .vector_storage(vector_storage)
+                .build(),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        index
+            .add_document(vec![parsed_document(index_id, "doc", contents)], None)
             .await
             .unwrap();
-        info!("results for Chinese similarity: {:?}", results);
-        let first_similar_result_to_chinese = results.get_node().unwrap();
-        let first_similar_result_to_chinese = first_similar_result_to_chinese.first().unwrap();
-        assert_eq!(first_similar_result_to_chinese.id, node_2_id);
+        index.embed_nodes(vec![]).await.unwrap();
+        (store, index)
+    }
 
-        let result_text_embedded = opened_index
-            .similar_embedded_text(&text_input, &TextSourceType::NodeContent, None)
+    /// The contents of the nodes closest to each query.
+    async fn closest_contents(
+        index: &LanceDbIndex,
+        queries: &[String],
+        rescore_factor: Option<usize>,
+    ) -> Vec<Vec<String>> {
+        let mut closest = vec![];
+        for query in queries {
+            let results = index
+                .similar_embedded_text(
+                    &TextInput::from_user_str(query),
+                    &TextSourceType::NodeContent,
+                    Some(
+                        LanceDbIndexSearchOptions::builder()
+                            .top_k(10)
+                            .rescore_factor(rescore_factor)
+                            .query_cache(QueryEmbeddingCache::Disabled)
+                            .build(),
+                    ),
+                )
+                .await
+                .unwrap();
+            closest.push(
+                results
+                    .into_iter()
+                    .map(|result| result.data.text.unwrap())
+                    .collect(),
+            );
+        }
+        closest
+    }
+
+    /// Share of the expected closest contents that were found.
+    fn recall(expected: &[Vec<String>], found: &[Vec<String>]) -> f32 {
+        let (hits, total) =
+            expected
+                .iter()
+                .zip(found)
+                .fold((0, 0), |(hits, total), (expected, found)| {
+                    (
+                        hits + found.iter().filter(|text| expected.contains(text)).count(),
+                        total + expected.len(),
+                    )
+                });
+        hits as f32 / total as f32
+    }
+
+    #[test(tokio::test)]
+    async fn test_lancedb_vector_storage() {
+        // pseudo-random texts over many characters, so that most dimensions are used
+        let alphabet: Vec<char> = ('a'..='z')
+            .chain('0'..='9')
+            .chain('α'..='ω')
+            .chain('а'..='я')
+            .collect();
+        let mut seed = 42u64;
+        let mut random_text = |length: usize| -> String {
+            (0..length)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    alphabet[(seed >> 33) as usize % alphabet.len()]
+                })
+                .collect()
+        };
+        // topics of 10 variants each, the queries being other variants of the topics
+        let topics: Vec<String> = (0..30).map(|_| random_text(150)).collect();
+        let contents: Vec<String> = topics
+            .iter()
+            .flat_map(|topic| {
+                (0..10)
+                    .map(|_| format!("{}{}", topic, random_text(50)))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let queries: Vec<String> = topics
+            .iter()
+            .take(20)
+            .map(|topic| format!("{}{}", topic, random_text(50)))
+            .collect();
+        let contents: Vec<&str> = contents.iter().map(|c| c.as_str()).collect();
+
+        let (_, index) = vector_storage_index(LanceDbVectorStorage::Float32, &contents).await;
+        let expected = closest_contents(&index, &queries, None).await;
+        // re-scoring does not apply to full precision vectors
+        assert_eq!(closest_contents(&index, &queries, Some(4)).await, expected);
+
+        let mut recalls = HashMap::new();
+        for vector_storage in [
+            LanceDbVectorStorage::Float16,
+            LanceDbVectorStorage::Int8,
+            LanceDbVectorStorage::Binary,
+        ] {
+            let (store, index) = vector_storage_index(vector_storage, &contents).await;
+            let found = closest_contents(&index, &queries, None).await;
+            let rescored = closest_contents(&index, &queries, Some(4)).await;
+            recalls.insert(
+                vector_storage,
+                (recall(&expected, &found), recall(&expected, &rescored)),
+            );
+
+            // the vector storage is read back from the table when the store is opened
+            let reopened = LanceDb::open(
+                store.get_store_uri().as_str(),
+                Box::new(CharHashEmbedder::signed()),
+            )
             .await
             .unwrap();
-
-        for similar_result in result_text_embedded {
-            info!(
-                "Distance: {:?}: Text {:?}",
-                similar_result.distance, similar_result.data.text
+            assert_eq!(reopened.vector_storage, vector_storage);
+            assert_eq!(reopened.check_health(false).await.unwrap().issues.len(), 0);
+            assert_eq!(
+                store
+                    .build_vector_index(&LanceDbVectorIndexOptions::builder().build())
+                    .await
+                    .is_err(),
+                !vector_storage.is_searchable()
             );
         }
+        info!(
+            "Recall@10 of the vector storages, without and with re-scoring: {:?}",
+            recalls
+        );
+        let (float16, float16_rescored) = recalls[&LanceDbVectorStorage::Float16];
+        assert_eq!(float16, float16_rescored);
+        assert!(float16 >= 0.99);
+        let (int8, int8_rescored) = recalls[&LanceDbVectorStorage::Int8];
+        assert!(int8 >= 0.9);
+        assert!(int8_rescored >= int8);
+        let (binary, binary_rescored) = recalls[&LanceDbVectorStorage::Binary];
+        assert!(binary < int8);
+        assert!(binary_rescored > binary);
     }
 }
@@ -0,0 +1,108 @@
+use lancedb::index::MetricType;
+use lancedb::Table;
+use typed_builder::TypedBuilder;
+
+use tuo_shared::consts::defaults::D_TABLE_COLUMN_NAME_VECTOR;
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+/// Options of building an IVF-PQ vector index on the text embeddings of a [LanceDb](crate::stores::lancedb::store::LanceDb) store.
+///
+/// Unset options are chosen by LanceDB from the number of rows and the dimensions of the embeddings.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct LanceDbVectorIndexOptions {
+    /// Number of IVF partitions, defaults to the square root of the number of rows.
+    #[builder(default)]
+    pub num_partitions: Option<u32>,
+    /// Number of PQ sub-vectors, which must divide the dimensions of the embeddings.
+    #[builder(default)]
+    pub num_sub_vectors: Option<u32>,
+    /// Number of k-means iterations when training the partitions.
+    #[builder(default)]
+    pub max_iterations: Option<u32>,
+    /// Number of rows sampled per partition when training.
+    #[builder(default)]
+    pub sample_rate: Option<u32>,
+}
+
+/// The vector index of the text embeddings of a [LanceDb](crate::stores::lancedb::store::LanceDb) store.
+#[derive(Debug, Clone)]
+pub struct LanceDbVectorIndexInfo {
+    pub name: String,
+    pub uuid: String,
+    pub column: String,
+    /// Rows covered by the index.
+    pub indexed_rows: usize,
+    /// Rows added since the index was built, which searches scan without the index until it is rebuilt.
+    pub unindexed_rows: usize,
+}
+
+/// Build the vector index of the table, replacing the existing one.
+///
+/// Training the product quantizer requires at least 256 rows.
+pub(crate) async fn build_vector_index(
+    table: &Table,
+    opts: &LanceDbVectorIndexOptions,
+) -> TuoResult<LanceDbVectorIndexInfo> {
+    let mut builder = table
+        .create_index(&[D_TABLE_COLUMN_NAME_VECTOR])
+        .ivf_pq()
+        .metric_type(MetricType::Cosine)
+        .replace(true);
+    if let Some(num_partitions) = opts.num_partitions {
+        builder = builder.num_partitions(num_partitions);
+    }
+    if let Some(num_sub_vectors) = opts.num_sub_vectors {
+        builder = builder.num_sub_vectors(num_sub_vectors);
+    }
+    if let Some(max_iterations) = opts.max_iterations {
+        builder = builder.max_iterations(max_iterations);
+    }
+    if let Some(sample_rate) = opts.sample_rate {
+        builder = builder.sample_rate(sample_rate);
+    }
+    builder.build().await?;
+    load_vector_index(table)
+        .await?
+        .ok_or(TuoPartsError::StoreError(format!(
+            "Vector index of table {} is missing after building it",
+            table.name()
+        )))
+        .map_err(Into::into)
+}
+
+/// Load the vector index of the table, if any.
+pub(crate) async fn load_vector_index(table: &Table) -> TuoResult<Option<LanceDbVectorIndexInfo>> {
+    let native_table = table.as_native().ok_or(TuoPartsError::StoreError(format!(
+        "Table {} is not a local table",
+        table.name()
+    )))?;
+    let Some(index) = native_table
+        .load_indices()
+        .await?
+        .into_iter()
+        .find(|index| {
+            index
+                .columns
+                .iter()
+                .any(|c| c == D_TABLE_COLUMN_NAME_VECTOR)
+        })
+    else {
+        return Ok(None);
+    };
+    let indexed_rows = native_table
+        .count_indexed_rows(&index.index_uuid)
+        .await?
+        .unwrap_or_default();
+    let unindexed_rows = native_table
+        .count_unindexed_rows(&index.index_uuid)
+        .await?
+        .unwrap_or_default();
+    Ok(Some(LanceDbVectorIndexInfo {
+        name: index.index_name,
+        uuid: index.index_uuid,
+        column: D_TABLE_COLUMN_NAME_VECTOR.to_string(),
+        indexed_rows,
+        unindexed_rows,
+    }))
+}
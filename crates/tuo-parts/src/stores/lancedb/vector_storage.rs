@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    Array, ArrayRef, FixedSizeListArray, Float16Array, Float32Array, Int8Array, UInt8Array,
+};
+use arrow_schema::{DataType, Field};
+use half::f16;
+
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+/// Encoding of the embedding vectors persisted by a [LanceDb](crate::stores::lancedb::store::LanceDb) store, chosen when the store is [created](crate::stores::lancedb::store::LanceDb::create_with_options).
+///
+/// The encoding is read back from the vector column of the text embeddings when the store is opened, and kept when the store is migrated to another model.
+/// Compressed vectors keep the direction of the embeddings, which is all cosine distances compare, but not their norm.
+///
+/// LanceDB only searches float vectors. Int8 and binary vectors are searched by a full scan of the vector column of the text embeddings, each vector decoded and compared in Rust,
+/// and cannot have a [vector index](crate::stores::lancedb::store::LanceDb::build_vector_index). No full precision copy of the vectors is kept,
+/// the closest ones are [re-scored](crate::stores::lancedb::index::LanceDbIndexSearchOptions::rescore_factor) by embedding their texts again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LanceDbVectorStorage {
+    /// Full precision, 4 bytes per dimension.
+    #[default]
+    Float32,
+    /// Half precision, 2 bytes per dimension, searched by LanceDB like full precision vectors.
+    Float16,
+    /// Scalar-quantised, 1 byte per dimension, each vector scaled so that its largest component is 127.
+    Int8,
+    /// The sign of each component, 1 bit per dimension, packed 8 dimensions per byte.
+    Binary,
+}
+
+impl LanceDbVectorStorage {
+    /// Type of the vector column of embeddings of the dimension.
+    pub(crate) fn vector_type(&self, dimension: i32) -> DataType {
+        let (item_type, size) = match self {
+            LanceDbVectorStorage::Float32 => (DataType::Float32, dimension),
+            LanceDbVectorStorage::Float16 => (DataType::Float16, dimension),
+            LanceDbVectorStorage::Int8 => (DataType::Int8, dimension),
+            LanceDbVectorStorage::Binary => (DataType::UInt8, (dimension + 7) / 8),
+        };
+        DataType::FixedSizeList(Arc::new(Field::new("item", item_type, true)), size)
+    }
+
+    /// The encoding of a vector column, `None` for columns of other types.
+    pub(crate) fn from_vector_type(data_type: &DataType) -> Option<Self> {
+        let DataType::FixedSizeList(item, _) = data_type else {
+            return None;
+        };
+        match item.data_type() {
+            DataType::Float32 => Some(LanceDbVectorStorage::Float32),
+            DataType::Float16 => Some(LanceDbVectorStorage::Float16),
+            DataType::Int8 => Some(LanceDbVectorStorage::Int8),
+            DataType::UInt8 => Some(LanceDbVectorStorage::Binary),
+            _ => None,
+        }
+    }
+
+    /// Whether LanceDB searches the vectors itself, which it only does for float vectors.
+    ///
+    /// The other vectors are scanned, see [rescore_factor](crate::stores::lancedb::index::LanceDbIndexSearchOptions::rescore_factor).
+    pub fn is_searchable(&self) -> bool {
+        matches!(
+            self,
+            LanceDbVectorStorage::Float32 | LanceDbVectorStorage::Float16
+        )
+    }
+
+    /// Encode vectors of the dimension into a vector column.
+    pub(crate) fn encode<'a>(
+        &self,
+        vectors: impl Iterator<Item = &'a [f32]>,
+        dimension: i32,
+    ) -> FixedSizeListArray {
+        let values: ArrayRef = match self {
+            LanceDbVectorStorage::Float32 => Arc::new(Float32Array::from_iter_values(
+                vectors.flat_map(|vector| vector.iter().copied()),
+            )),
+            LanceDbVectorStorage::Float16 => {
+                Arc::new(Float16Array::from_iter_values(vectors.flat_map(|vector| {
+                    vector.iter().map(|value| f16::from_f32(*value))
+                })))
+            }
+            LanceDbVectorStorage::Int8 => {
+                Arc::new(Int8Array::from_iter_values(vectors.flat_map(quantize_int8)))
+            }
+            LanceDbVectorStorage::Binary => {
+                Arc::new(UInt8Array::from_iter_values(vectors.flat_map(|vector| {
+                    vector.chunks(8).map(|chunk| {
+                        chunk
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, value)| **value > 0.0)
+                            .fold(0u8, |byte, (bit, _)| byte | 1 << bit)
+                    })
+                })))
+            }
+        };
+        let DataType::FixedSizeList(item, size) = self.vector_type(dimension) else {
+            unreachable!("Vector columns are fixed size lists");
+        };
+        FixedSizeListArray::new(item, size, values, None)
+    }
+
+    /// The vector as it is read back once stored, e.g. to compare a query with the stored vectors.
+    pub fn compress(&self, vector: &[f32]) -> TuoResult<Vec<f32>> {
+        let dimension = vector.len() as i32;
+        let encoded = self.encode(std::iter::once(vector), dimension);
+        decode_vector(&encoded.value(0), dimension)
+    }
+}
+
+/// Decode a vector of embeddings of the dimension from a vector column.
+///
+/// Int8 components are scaled down to `[-1, 1]`, binary components are `1` or `-1`.
+/// Components of other types, or whose number does not match the dimension, are an error.
+pub(crate) fn decode_vector(array: &ArrayRef, dimension: i32) -> TuoResult<Vec<f32>> {
+    let dimension = dimension as usize;
+    let components = array.as_any();
+    let vector: Vec<f32> = if let Some(values) = components.downcast_ref::<Float32Array>() {
+        values.values().to_vec()
+    } else if let Some(values) = components.downcast_ref::<Float16Array>() {
+        values.values().iter().map(|value| value.to_f32()).collect()
+    } else if let Some(values) = components.downcast_ref::<Int8Array>() {
+        values
+            .values()
+            .iter()
+            .map(|value| *value as f32 / i8::MAX as f32)
+            .collect()
+    } else if let Some(values) = components.downcast_ref::<UInt8Array>() {
+        if values.len() != dimension.div_ceil(8) {
+            return Err(TuoPartsError::IndexError(format!(
+                "Binary vector of {} bytes does not have {} dimensions",
+                values.len(),
+                dimension
+            ))
+            .into());
+        }
+        (0..dimension)
+            .map(|index| match values.value(index / 8) >> (index % 8) & 1 {
+                1 => 1.0,
+                _ => -1.0,
+            })
+            .collect()
+    } else {
+        return Err(TuoPartsError::IndexError(format!(
+            "Vector components of type {} cannot be decoded",
+            array.data_type()
+        ))
+        .into());
+    };
+    if vector.len() != dimension {
+        return Err(TuoPartsError::IndexError(format!(
+            "Vector of {} components does not have {} dimensions",
+            vector.len(),
+            dimension
+        ))
+        .into());
+    }
+    Ok(vector)
+}
+
+/// Scale the vector so that its largest component is 127, and round its components.
+fn quantize_int8(vector: &[f32]) -> impl Iterator<Item = i8> + '_ {
+    let max = vector
+        .iter()
+        .fold(0.0f32, |max, value| max.max(value.abs()));
+    let scale = match max > 0.0 {
+        true => i8::MAX as f32 / max,
+        false => 0.0,
+    };
+    vector
+        .iter()
+        .map(move |value| (value * scale).round() as i8)
+}
+
+#[cfg(test)]
+mod tests {
+    use tuo_core::retrieval::similarity::cosine_distance;
+
+    use super::*;
+
+    #[test]
+    fn test_vector_storage_round_trip() {
+        let vector = vec![0.5, -0.25, 0.0, 0.125, -1.0];
+        for storage in [
+            LanceDbVectorStorage::Float32,
+            LanceDbVectorStorage::Float16,
+            LanceDbVectorStorage::Int8,
+            LanceDbVectorStorage::Binary,
+        ] {
+            let encoded = storage.encode(std::iter::once(vector.as_slice()), 5);
+            assert_eq!(
+                LanceDbVectorStorage::from_vector_type(encoded.data_type()),
+                Some(storage)
+            );
+            let decoded = decode_vector(&encoded.value(0), 5).unwrap();
+            assert_eq!(decoded.len(), 5);
+            assert_eq!(storage.compress(&decoded).unwrap(), decoded);
+            // vectors of another dimension are rejected
+            assert!(decode_vector(&encoded.value(0), 9).is_err());
+            assert!(decode_vector(&encoded.value(0), 17).is_err());
+            match storage {
+                LanceDbVectorStorage::Float32 | LanceDbVectorStorage::Float16 => {
+                    assert_eq!(decoded, vector)
+                }
+                LanceDbVectorStorage::Int8 => {
+                    assert_eq!(decoded[4], -1.0);
+                    assert!(cosine_distance(&vector, &decoded) < 1e-4);
+                }
+                LanceDbVectorStorage::Binary => {
+                    assert_eq!(decoded, vec![1.0, -1.0, -1.0, 1.0, -1.0])
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::debug;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index::IndexTrait;
+use tuo_core::core::indexing::index_insert::{
+    insert_documents, DocumentInsertOptions, DocumentInsertReport,
+};
+use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::indexing::index_scan::{ScanCursor, ScanPage};
+use tuo_core::core::messaging::content::{
+    QueryEmbeddingCache, TextEmbedded, TextInput, TextSourceType,
+};
+use tuo_core::core::source::node::Node;
+use tuo_core::core::source::sources::{SourceData, SourceType, SourceTypeTrait};
+use tuo_core::embedding::embedder::EmbedderTrait;
+use tuo_core::model::model_metadata::EmbeddingModelMetadata;
+use tuo_core::parsing::document_parser::ParsedDocument;
+use tuo_core::retrieval::keyword::{keyword_search_nodes, Bm25Options, KeywordMatch};
+use tuo_core::retrieval::search_result::SimilarResult;
+use tuo_core::retrieval::similarity::cosine_distance;
+use tuo_core::storage::store_metadata::StoreMetadata;
+use tuo_core::storage::store_models::{find_registered_model, RegisteredModel};
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::now;
+
+use crate::stores::memory::tables::{read_tables, write_tables, SharedMemoryTables};
+
+/// An index of a [MemoryStore](crate::stores::memory::store::MemoryStore).
+///
+/// Searches are brute-force over all text embeddings of the index, using cosine distance like `LanceDbIndex`.
+#[derive(TypedBuilder)]
+pub struct MemoryIndex {
+    pub index_metadata: IndexMetadata,
+    pub store_metadata: StoreMetadata,
+    pub index_name: String,
+    /// The embedder to use for the index
+    pub embedder: Option<Arc<Box<dyn EmbedderTrait>>>,
+    pub model: EmbeddingModelMetadata,
+    /// The tables of the store
+    pub tables: SharedMemoryTables,
+    /// The models registered on the store when the index was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+}
+
+#[async_trait]
+impl IndexTrait for MemoryIndex {
+    type StoreDataType = SourceData;
+    type QueryOptions = Option<MemoryIndexSearchOptions>;
+
+    type InsertOptions = Option<DocumentInsertOptions>;
+    /// A snapshot of the records of the index in the table.
+    type TableType = SourceData;
+
+    async fn add_document(
+        &self,
+        data: Vec<ParsedDocument>,
+        opt: Self::InsertOptions,
+    ) -> TuoResult<DocumentInsertReport> {
+        insert_documents(self, data, &opt.unwrap_or_default()).await
+    }
+
+    async fn add_source_data(
+        &self,
+        source_data: SourceData,
+        _opt: Self::InsertOptions,
+    ) -> TuoResult<()> {
+        self.add_to_table(source_data);
+        Ok(())
+    }
+
+    async fn add_text_embeddings(&self, text_embedded: &Vec<TextEmbedded>) -> TuoResult<()> {
+        let source_data = SourceData::TextEmbedded(text_embedded.clone());
+        self.add_source_data(source_data, None).await?;
+        Ok(())
+    }
+
+    async fn find_text_embeddings(
+        &self,
+        hashes: &[String],
+        embedding_model: &str,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        Ok(read_tables(&self.tables)
+            .text_embedded
+            .iter()
+            .filter(|text_embedded| {
+                text_embedded.index_id == Some(self.index_metadata.id)
+                    && hashes.contains(&text_embedded.hash)
+                    && text_embedded.embedding_model == embedding_model
+                    && text_embedded.source_type.as_ref() == text_source_type.as_ref()
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn touch_text_embeddings(&self, ids: &[Uuid]) -> TuoResult<()> {
+        let used_at = now();
+        for text_embedded in write_tables(&self.tables).text_embedded.iter_mut() {
+            if text_embedded.index_id == Some(self.index_metadata.id)
+                && ids.contains(&text_embedded.id)
+            {
+                text_embedded.used_at = used_at;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, source_ids: &Vec<Uuid>, source_type: &SourceType) -> TuoResult<()> {
+        let source_ids: HashSet<&Uuid> = source_ids.iter().collect();
+        write_tables(&self.tables).remove(source_type, |id, index_id| {
+            self.in_index(source_type, index_id) && source_ids.contains(&id)
+        });
+        Ok(())
+    }
+
+    async fn delete_documents(&self, document_ids: &[Uuid]) -> TuoResult<()> {
+        let index_id = self.index_metadata.id;
+        let document_ids: HashSet<Uuid> = document_ids.iter().cloned().collect();
+        let mut tables = write_tables(&self.tables);
+        let section_ids: HashSet<Uuid> = tables
+            .sections
+            .iter()
+            .filter(|section| {
+                section.index_id == index_id && document_ids.contains(&section.document_id)
+            })
+            .map(|section| section.id)
+            .collect();
+        let nodes: Vec<&Node> = tables
+            .nodes
+            .iter()
+            .filter(|node| node.index_id == index_id && document_ids.contains(&node.document_id))
+            .collect();
+        let node_ids: HashSet<Uuid> = nodes.iter().map(|node| node.id).collect();
+        // the content embeddings the nodes reuse, unless other nodes of the index use them too
+        let mut text_embedded_ids: HashSet<Uuid> = nodes
+            .iter()
+            .filter_map(|node| node.content_embeddings_id)
+            .collect();
+        let used_ids: HashSet<Uuid> = tables
+            .nodes
+            .iter()
+            .filter(|node| node.index_id == index_id && !node_ids.contains(&node.id))
+            .filter_map(|node| node.content_embeddings_id)
+            .collect();
+        text_embedded_ids.extend(
+            tables
+                .text_embedded
+                .iter()
+                .filter(|text_embedded| {
+                    text_embedded.source_id.is_some_and(|source_id| {
+                        document_ids.contains(&source_id)
+                            || section_ids.contains(&source_id)
+                            || node_ids.contains(&source_id)
+                    })
+                })
+                .map(|text_embedded| text_embedded.id),
+        );
+
+        let in_index = |index_id| self.in_index(&SourceType::Node, index_id);
+        tables.remove(&SourceType::Node, |id, index_id| {
+            in_index(index_id) && node_ids.contains(&id)
+        });
+        tables.remove(&SourceType::Section, |id, index_id| {
+            in_index(index_id) && section_ids.contains(&id)
+        });
+        tables.remove(&SourceType::Document, |id, index_id| {
+            in_index(index_id) && document_ids.contains(&id)
+        });
+        tables.remove(&SourceType::TextEmbedded, |id, index_id| {
+            in_index(index_id) && text_embedded_ids.contains(&id) && !used_ids.contains(&id)
+        });
+        for texts_embedded in tables.model_embeddings.values_mut() {
+            texts_embedded.retain(|text_embedded| {
+                text_embedded.index_id != Some(index_id)
+                    || !text_embedded
+                        .source_id
+                        .is_some_and(|source_id| node_ids.contains(&source_id))
+            });
+        }
+        Ok(())
+    }
+
+    async fn similar_embedded_text(
+        &self,
+        text: &TextInput,
+        text_source_type: &TextSourceType,
+        opts: Self::QueryOptions,
+    ) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
+        let opts = opts.unwrap_or(MemoryIndexSearchOptions::builder().build());
+        let embedded_text = self
+            .embed_query_with_model(text, opts.model_id, &opts.query_cache)
+            .await?;
+        let tables = read_tables(&self.tables);
+        let candidates = match opts.model_id.filter(|model_id| *model_id != self.model.id) {
+            Some(model_id) => tables
+                .model_embeddings
+                .get(&model_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            None => tables.text_embedded.as_slice(),
+        };
+        let mut results: Vec<SimilarResult<TextEmbedded>> = candidates
+            .iter()
+            .filter(|candidate| {
+                candidate.index_id == Some(self.index_metadata.id)
+                    && candidate.source_type.as_ref() == text_source_type.as_ref()
+            })
+            .map(|candidate| SimilarResult {
+                distance: cosine_distance(&embedded_text.embeddings, &candidate.embeddings),
+                data_id: candidate.id,
+                data: candidate.clone(),
+                hybrid_scores: None,
+            })
+            .collect();
+        results.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        results.truncate(opts.top_k);
+        Ok(results)
+    }
+
+    async fn keyword_search(
+        &self,
+        text: &TextInput,
+        top_k: usize,
+        opts: &Bm25Options,
+    ) -> TuoResult<Vec<KeywordMatch<Node>>> {
+        let nodes: Vec<Node> = read_tables(&self.tables)
+            .nodes
+            .iter()
+            .filter(|node| node.index_id == self.index_metadata.id)
+            .cloned()
+            .collect();
+        Ok(keyword_search_nodes(&text.text, nodes, top_k, opts))
+    }
+
+    async fn get_index_embedder(&self) -> TuoResult<Arc<Box<dyn EmbedderTrait>>> {
+        let embedder = self
+            .embedder
+            .as_ref()
+            .ok_or(TuoPartsError::IndexError(format!(
+                "No embedder found for the index {}",
+                self.index_name
+            )))?;
+        Ok(embedder.clone())
+    }
+
+    fn get_index_metadata(&self) -> IndexMetadata {
+        self.index_metadata.clone()
+    }
+
+    fn get_store_metadata(&self) -> StoreMetadata {
+        self.store_metadata.clone()
+    }
+
+    async fn count_records(&self, source_type: &SourceType) -> TuoResult<usize> {
+        Ok(self.open_source_table(source_type).await?.get_ids().len())
+    }
+
+    async fn get_source_data_by_id(
+        &self,
+        source_type: &SourceType,
+        id: Uuid,
+    ) -> TuoResult<SourceData> {
+        Ok(
+            read_tables(&self.tables).select(source_type, |record_id, index_id| {
+                self.in_index(source_type, index_id) && record_id == id
+            }),
+        )
+    }
+
+    /// Get source data by ids
+    ///
+    /// Ids not found in the index are skipped. With `preserve_order`, the records follow the order of the ids.
+    async fn get_source_data_by_ids(
+        &self,
+        source_type: &SourceType,
+        ids: Vec<Uuid>,
+        preserve_order: bool,
+    ) -> TuoResult<SourceData> {
+        let id_set: HashSet<&Uuid> = ids.iter().collect();
+        let source_data = read_tables(&self.tables).select(source_type, |id, index_id| {
+            self.in_index(source_type, index_id) && id_set.contains(&id)
+        });
+        Ok(match preserve_order {
+            true => source_data.order_by_ids(&ids),
+            false => source_data,
+        })
+    }
+
+    async fn get_source_data_by_document_ids(
+        &self,
+        source_type: &SourceType,
+        document_ids: &[Uuid],
+    ) -> TuoResult<SourceData> {
+        let index_id = self.index_metadata.id;
+        let document_ids: HashSet<&Uuid> = document_ids.iter().collect();
+        let tables = read_tables(&self.tables);
+        match source_type {
+            SourceType::Section => Ok(SourceData::Section(
+                tables
+                    .sections
+                    .iter()
+                    .filter(|section| {
+                        section.index_id == index_id && document_ids.contains(&section.document_id)
+                    })
+                    .cloned()
+                    .collect(),
+            )),
+            SourceType::Node => Ok(SourceData::Node(
+                tables
+                    .nodes
+                    .iter()
+                    .filter(|node| {
+                        node.index_id == index_id && document_ids.contains(&node.document_id)
+                    })
+                    .cloned()
+                    .collect(),
+            )),
+            _ => Err(TuoPartsError::IndexError(format!(
+                "{:?} records do not belong to a document",
+                source_type
+            ))
+            .into()),
+        }
+    }
+
+    async fn get_all_source_data(&self, source_type: &SourceType) -> TuoResult<SourceData> {
+        Ok(
+            read_tables(&self.tables).select(source_type, |_, index_id| {
+                self.in_index(source_type, index_id)
+            }),
+        )
+    }
+
+    async fn scan_page(
+        &self,
+        source_type: &SourceType,
+        page_size: usize,
+        cursor: Option<&ScanCursor>,
+    ) -> TuoResult<ScanPage> {
+        let tables = read_tables(&self.tables);
+        let source_data = tables.select(source_type, |_, index_id| {
+            self.in_index(source_type, index_id)
+        });
+        let mut keys: Vec<ScanCursor> = ScanCursor::of(&source_data)
+            .into_iter()
+            .filter(|key| !cursor.is_some_and(|cursor| key <= cursor))
+            .collect();
+        keys.sort();
+        let ids: Vec<Uuid> = keys.iter().take(page_size).map(|key| key.id).collect();
+        let page_ids: HashSet<&Uuid> = ids.iter().collect();
+        let page = tables
+            .select(source_type, |id, index_id| {
+                page_ids.contains(&id) && self.in_index(source_type, index_id)
+            })
+            .order_by_ids(&ids);
+        Ok(ScanPage::new(page))
+    }
+
+    async fn open_source_table(&self, source_type: &SourceType) -> TuoResult<Self::TableType> {
+        Ok(
+            read_tables(&self.tables).select(source_type, |_, index_id| {
+                self.in_index(source_type, index_id)
+            }),
+        )
+    }
+
+    async fn get_unembedded_nodes(&self) -> TuoResult<Vec<Node>> {
+        Ok(read_tables(&self.tables)
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.index_id == self.index_metadata.id && node.content_embeddings_id.is_none()
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn get_registered_model(&self, model_id: Uuid) -> TuoResult<RegisteredModel> {
+        find_registered_model(&self.registered_models, model_id, &self.index_name)
+    }
+
+    async fn add_model_embeddings(
+        &self,
+        model_id: Uuid,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        self.get_registered_model(model_id)?;
+        let index_id = self.index_metadata.id;
+        write_tables(&self.tables)
+            .model_embeddings
+            .entry(model_id)
+            .or_default()
+            .extend(texts_embedded.into_iter().map(|mut text_embedded| {
+                text_embedded.index_id = Some(index_id);
+                text_embedded
+            }));
+        Ok(())
+    }
+
+    async fn find_model_embeddings(
+        &self,
+        model_id: Uuid,
+        hashes: &[String],
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        Ok(self
+            .model_embeddings(model_id)?
+            .into_iter()
+            .filter(|text_embedded| hashes.contains(&text_embedded.hash))
+            .collect())
+    }
+
+    async fn get_unembedded_nodes_of_model(&self, model_id: Uuid) -> TuoResult<Vec<Node>> {
+        if model_id == self.model.id {
+            return self.get_unembedded_nodes().await;
+        }
+        let embedded_ids: HashSet<Uuid> = self
+            .model_embeddings(model_id)?
+            .into_iter()
+            .filter_map(|text_embedded| text_embedded.source_id)
+            .collect();
+        Ok(read_tables(&self.tables)
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.index_id == self.index_metadata.id && !embedded_ids.contains(&node.id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn get_dimension(&self) -> i32 {
+        self.model.dimensions
+    }
+
+    fn get_index_name(&self) -> String {
+        self.index_name.clone()
+    }
+
+    fn get_model(&self) -> EmbeddingModelMetadata {
+        self.model.clone()
+    }
+}
+
+#[derive(TypedBuilder)]
+pub struct MemoryIndexSearchOptions {
+    #[builder(default = 10)]
+    pub top_k: usize,
+    #[builder(default)]
+    pub query_cache: QueryEmbeddingCache,
+    /// Search the node content embeddings of a [registered model](IndexTrait::get_registered_model), instead of the text embeddings of the model of the store.
+    #[builder(default)]
+    pub model_id: Option<Uuid>,
+}
+
+impl MemoryIndex {
+    /// Add records to their table, assigning them to this index.
+    fn add_to_table(&self, mut source_data: SourceData) {
+        source_data.assign_index_id(self.index_metadata.id);
+        debug!(
+            "Adding {} records to {:?}",
+            source_data.get_ids().len(),
+            source_data.source_type()
+        );
+        write_tables(&self.tables).insert(source_data);
+    }
+
+    /// The text embeddings of this index in the table of a registered model.
+    fn model_embeddings(&self, model_id: Uuid) -> TuoResult<Vec<TextEmbedded>> {
+        self.get_registered_model(model_id)?;
+        Ok(read_tables(&self.tables)
+            .model_embeddings
+            .get(&model_id)
+            .map(|texts_embedded| {
+                texts_embedded
+                    .iter()
+                    .filter(|text_embedded| text_embedded.index_id == Some(self.index_metadata.id))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Whether a record with the index id belongs to this index.
+    ///
+    /// Records of types that are not index-scoped belong to every index.
+    fn in_index(&self, source_type: &SourceType, index_id: Option<Uuid>) -> bool {
+        !source_type.is_index_scoped() || index_id == Some(self.index_metadata.id)
+    }
+}
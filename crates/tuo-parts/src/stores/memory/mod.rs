@@ -0,0 +1,3 @@
+pub mod index;
+pub mod store;
+pub mod tables;
@@ -0,0 +1,1584 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index::IndexTrait;
+use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::messaging::content::{TextEmbedded, TextSourceType};
+use tuo_core::core::source::node::Node;
+use tuo_core::core::source::sources::{SourceData, SourceType};
+use tuo_core::embedding::embedder::EmbedderTrait;
+use tuo_core::model::model_metadata::EmbeddingModelMetadata;
+use tuo_core::storage::store::{IndexRemoveResult, StoreOpenOptions, StoreTrait};
+use tuo_core::storage::store_health::{StoreHealthIssue, StoreHealthReport};
+use tuo_core::storage::store_metadata::StoreMetadata;
+use tuo_core::storage::store_migration::{
+    run_model_migration, ModelMigrationOptions, ModelMigrationReport, ModelMigrationStaging,
+};
+use tuo_core::storage::store_models::{check_migration_model, model_to_register, RegisteredModel};
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::stores::memory::index::MemoryIndex;
+use crate::stores::memory::tables::{
+    find_tables, read_tables, register_tables, unregister_tables, write_tables, MemoryMigration,
+    SharedMemoryTables,
+};
+
+/// A store keeping all its tables in memory.
+///
+/// Stores live as long as the process, or until [removed](MemoryStore::remove), and can be opened again by their uri in the meantime.
+/// Nothing is written to the filesystem.
+#[derive(TypedBuilder)]
+pub struct MemoryStore {
+    pub store_metadata: StoreMetadata,
+    pub embedder: Arc<Box<dyn EmbedderTrait>>,
+    pub tables: SharedMemoryTables,
+    /// The models registered since the store was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+}
+
+impl MemoryStore {
+    /// Remove the store at the uri from memory, returning whether it existed.
+    ///
+    /// Instances of the store and its indices keep working on the removed tables until dropped.
+    pub fn remove(uri: &str) -> bool {
+        unregister_tables(uri).is_some()
+    }
+
+    fn find_index(&self, index_name: &str) -> Option<IndexMetadata> {
+        read_tables(&self.tables)
+            .index_metadata
+            .iter()
+            .find(|index| index.name == index_name)
+            .cloned()
+    }
+}
+
+/// Stages the migration in [MemoryMigration], which must be set before running it.
+#[async_trait]
+impl ModelMigrationStaging for MemoryStore {
+    async fn staged_nodes(&self) -> TuoResult<Vec<Node>> {
+        Ok(read_tables(&self.tables)
+            .migration
+            .iter()
+            .flat_map(|migration| migration.nodes.clone())
+            .collect())
+    }
+
+    async fn staged_texts(&self) -> TuoResult<Vec<TextEmbedded>> {
+        Ok(read_tables(&self.tables)
+            .migration
+            .iter()
+            .flat_map(|migration| migration.text_embedded.clone())
+            .collect())
+    }
+
+    async fn unstage(&self, node_ids: &[Uuid], text_ids: &[Uuid]) -> TuoResult<()> {
+        let mut tables = write_tables(&self.tables);
+        let migration = tables.migration.as_mut().ok_or(no_migration_error())?;
+        migration.nodes.retain(|node| !node_ids.contains(&node.id));
+        let content_ids: HashSet<Uuid> = migration
+            .nodes
+            .iter()
+            .filter_map(|node| node.content_embeddings_id)
+            .collect();
+        migration
+            .text_embedded
+            .retain(|text_embedded| match text_embedded.source_type {
+                TextSourceType::NodeContent => content_ids.contains(&text_embedded.id),
+                _ => !text_ids.contains(&text_embedded.id),
+            });
+        Ok(())
+    }
+
+    async fn stage_nodes(
+        &self,
+        nodes: Vec<Node>,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        let mut tables = write_tables(&self.tables);
+        let migration = tables.migration.as_mut().ok_or(no_migration_error())?;
+        migration.text_embedded.extend(texts_embedded);
+        migration.nodes.extend(nodes);
+        Ok(())
+    }
+
+    async fn stage_texts(&self, texts_embedded: Vec<TextEmbedded>) -> TuoResult<()> {
+        let mut tables = write_tables(&self.tables);
+        let migration = tables.migration.as_mut().ok_or(no_migration_error())?;
+        migration.text_embedded.extend(texts_embedded);
+        Ok(())
+    }
+
+    async fn commit(&self) -> TuoResult<()> {
+        let mut tables = write_tables(&self.tables);
+        let migration = tables.migration.take().ok_or(no_migration_error())?;
+        tables.text_embedded = migration.text_embedded;
+        tables.nodes = migration.nodes;
+        Ok(())
+    }
+}
+
+fn no_migration_error() -> TuoPartsError {
+    TuoPartsError::StoreError("No model migration in progress".to_string())
+}
+
+#[async_trait]
+impl StoreTrait for MemoryStore {
+    type IndexSchema = ();
+    type IndexType = MemoryIndex;
+
+    async fn create(
+        store_name: &str,
+        store_folder: &str,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<Self> {
+        let model_metadata = embedder.get_model_metadata();
+        let uri_path = PathBuf::from(store_folder).join(store_name);
+        let store_metadata = StoreMetadata::builder()
+            .name(store_name.to_string())
+            .model_id(Some(model_metadata.id))
+            .model(Some(model_metadata))
+            .uri(uri_path.to_str().unwrap().to_string())
+            .build();
+        let tables = register_tables(&store_metadata.uri).ok_or(TuoPartsError::StoreError(
+            format!("Store {} already exists", store_metadata.uri),
+        ))?;
+        let instance = MemoryStore::builder()
+            .store_metadata(store_metadata.clone())
+            .embedder(Arc::new(embedder))
+            .tables(tables)
+            .build();
+        instance
+            .set_store_metadata(store_metadata, instance.get_store_model_dimensions())
+            .await?;
+        Ok(instance)
+    }
+
+    async fn open_with_options(
+        uri: &str,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: &StoreOpenOptions,
+    ) -> TuoResult<Self>
+    where
+        Self: Sized,
+    {
+        let model = embedder.get_model_metadata();
+        let store_metadata = MemoryStore::load_store_metadata(uri, model.dimensions).await?;
+        if !opts.allow_model_mismatch {
+            store_metadata.check_model(&model)?;
+        }
+        let tables = find_tables(uri).ok_or(TuoPartsError::StoreError(format!(
+            "Store {} does not exist",
+            uri
+        )))?;
+        Ok(MemoryStore::builder()
+            .store_metadata(store_metadata)
+            .embedder(Arc::new(embedder))
+            .tables(tables)
+            .build())
+    }
+
+    fn get_store_metadata(&self) -> StoreMetadata {
+        self.store_metadata.clone()
+    }
+
+    fn get_store_model_metadata(&self) -> EmbeddingModelMetadata {
+        self.get_store_metadata()
+            .model
+            .ok_or(TuoPartsError::StoreError(
+                "Store metadata does not have ModelMetadata set.".to_string(),
+            ))
+            .unwrap()
+    }
+
+    fn get_store_model_dimensions(&self) -> i32 {
+        self.get_store_model_metadata().dimensions
+    }
+
+    fn get_store_uri(&self) -> String {
+        self.get_store_metadata().uri.clone()
+    }
+
+    async fn load_store_metadata(uri: &str, _dimension: i32) -> TuoResult<StoreMetadata> {
+        let tables = find_tables(uri).ok_or(TuoPartsError::StoreError(format!(
+            "Store {} does not exist",
+            uri
+        )))?;
+        let store_metadata = read_tables(&tables).store_metadata.first().cloned();
+        Ok(store_metadata.ok_or(TuoPartsError::StoreError(
+            "Cannot find store metadata in the store".to_string(),
+        ))?)
+    }
+
+    async fn set_store_metadata(
+        &self,
+        store_metadata: StoreMetadata,
+        _dimension: i32,
+    ) -> TuoResult<()> {
+        write_tables(&self.tables).insert(SourceData::StoreMetadata(vec![store_metadata]));
+        Ok(())
+    }
+
+    async fn index_open(&self, index_name: &str) -> TuoResult<Self::IndexType> {
+        let index = self
+            .find_index(index_name)
+            .ok_or(TuoPartsError::StoreError(format!(
+                "Index {} does not exist",
+                index_name
+            )))?;
+        Ok(MemoryIndex::builder()
+            .index_name(index_name.to_string())
+            .model(self.get_store_model_metadata())
+            .index_metadata(index)
+            .store_metadata(self.get_store_metadata())
+            .embedder(Some(self.embedder.clone()))
+            .tables(self.tables.clone())
+            .registered_models(self.registered_models.clone())
+            .build())
+    }
+
+    async fn index_exists(&self, index_name: &str) -> TuoResult<bool> {
+        Ok(self.find_index(index_name).is_some())
+    }
+
+    async fn list_indices(&self) -> TuoResult<Vec<IndexMetadata>> {
+        Ok(read_tables(&self.tables).index_metadata.clone())
+    }
+
+    async fn index_create(&self, name: &str) -> TuoResult<Self::IndexType> {
+        let index = IndexMetadata::builder().name(name.to_string()).build();
+        write_tables(&self.tables).insert(SourceData::IndexMetadata(vec![index]));
+        self.index_open(name).await
+    }
+
+    async fn index_count_records(
+        &self,
+        index_name: &str,
+        source_type: &SourceType,
+    ) -> TuoResult<usize> {
+        let index = self.index_open(index_name).await?;
+        let count = index.count_records(source_type).await?;
+        Ok(count)
+    }
+
+    async fn index_remove(&self, index_id: Uuid) -> TuoResult<IndexRemoveResult> {
+        let mut tables = write_tables(&self.tables);
+        if !tables
+            .index_metadata
+            .iter()
+            .any(|index| index.id == index_id)
+        {
+            return Err(
+                TuoPartsError::StoreError(format!("Index {} does not exist", index_id)).into(),
+            );
+        }
+        let mut removed = HashMap::new();
+        for source_type in [
+            SourceType::TextEmbedded,
+            SourceType::Node,
+            SourceType::Section,
+            SourceType::Document,
+        ] {
+            let count = tables.remove(&source_type, |_, record_index_id| {
+                record_index_id == Some(index_id)
+            });
+            removed.insert(source_type, count);
+        }
+        // the text embeddings of the registered models are counted with the other text embeddings
+        for texts_embedded in tables.model_embeddings.values_mut() {
+            let count = texts_embedded.len();
+            texts_embedded.retain(|text_embedded| text_embedded.index_id != Some(index_id));
+            *removed.entry(SourceType::TextEmbedded).or_default() += count - texts_embedded.len();
+        }
+        let count = tables.remove(&SourceType::IndexMetadata, |id, _| id == index_id);
+        removed.insert(SourceType::IndexMetadata, count);
+        Ok(IndexRemoveResult { index_id, removed })
+    }
+
+    /// Check health
+    ///
+    /// The tables of a memory store always exist with the schema of the entities, so only the store metadata and orphans are checked.
+    async fn check_health(&self, repair: bool) -> TuoResult<StoreHealthReport> {
+        let mut report = StoreHealthReport::default();
+        let embedder_model = self.embedder.get_model_metadata();
+        let mut tables = write_tables(&self.tables);
+
+        let count = tables.store_metadata.len();
+        let own_row = tables
+            .store_metadata
+            .iter()
+            .any(|row| row.id == self.store_metadata.id);
+        if count != 1 || !own_row {
+            let issue = StoreHealthIssue::StoreMetadataCount { count };
+            if repair {
+                tables.store_metadata = vec![self.store_metadata.clone()];
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+        if let Some(store_model) = &self.store_metadata.model {
+            if store_model.name != embedder_model.name
+                || store_model.dimensions != embedder_model.dimensions
+            {
+                report.issues.push(StoreHealthIssue::ModelMismatch {
+                    store_model: store_model.name.clone(),
+                    store_dimensions: store_model.dimensions,
+                    embedder_model: embedder_model.name,
+                    embedder_dimensions: embedder_model.dimensions,
+                });
+            }
+        }
+
+        let document_ids: HashSet<Uuid> = tables.documents.iter().map(|x| x.id).collect();
+        let orphan_section_ids: Vec<Uuid> = tables
+            .sections
+            .iter()
+            .filter(|section| !document_ids.contains(&section.document_id))
+            .map(|section| section.id)
+            .collect();
+        let section_ids: HashSet<Uuid> = tables
+            .sections
+            .iter()
+            .filter(|section| document_ids.contains(&section.document_id))
+            .map(|section| section.id)
+            .collect();
+        let orphan_node_ids: Vec<Uuid> = tables
+            .nodes
+            .iter()
+            .filter(|node| !document_ids.contains(&node.document_id))
+            .map(|node| node.id)
+            .collect();
+        let node_ids: HashSet<Uuid> = tables
+            .nodes
+            .iter()
+            .filter(|node| document_ids.contains(&node.document_id))
+            .map(|node| node.id)
+            .collect();
+        let orphan_text_embedded_ids: Vec<Uuid> = tables
+            .text_embedded
+            .iter()
+            .filter(|text_embedded| {
+                let source_ids = match text_embedded.source_type {
+                    TextSourceType::UserQuery => return false,
+                    TextSourceType::SummaryDocument => &document_ids,
+                    TextSourceType::SummarySection => &section_ids,
+                    TextSourceType::SummaryNode | TextSourceType::NodeContent => &node_ids,
+                };
+                !text_embedded
+                    .source_id
+                    .is_some_and(|source_id| source_ids.contains(&source_id))
+            })
+            .map(|text_embedded| text_embedded.id)
+            .collect();
+
+        // the text embeddings of a registered model reference their node by their source
+        for (model_id, texts_embedded) in tables.model_embeddings.iter_mut() {
+            let is_orphan = |text_embedded: &TextEmbedded| {
+                !text_embedded
+                    .source_id
+                    .is_some_and(|source_id| node_ids.contains(&source_id))
+            };
+            let orphan_ids: Vec<Uuid> = texts_embedded
+                .iter()
+                .filter(|text_embedded| is_orphan(text_embedded))
+                .map(|text_embedded| text_embedded.id)
+                .collect();
+            if orphan_ids.is_empty() {
+                continue;
+            }
+            let issue = StoreHealthIssue::OrphanModelEmbeddings {
+                model_id: *model_id,
+                text_embedded_ids: orphan_ids,
+            };
+            if repair {
+                texts_embedded.retain(|text_embedded| !is_orphan(text_embedded));
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+
+        for (source_type, orphan_ids) in [
+            (SourceType::TextEmbedded, orphan_text_embedded_ids),
+            (SourceType::Node, orphan_node_ids),
+            (SourceType::Section, orphan_section_ids),
+        ] {
+            if orphan_ids.is_empty() {
+                continue;
+            }
+            if repair {
+                let orphan_id_set: HashSet<&Uuid> = orphan_ids.iter().collect();
+                tables.remove(&source_type, |id, _| orphan_id_set.contains(&id));
+            }
+            let issue = match source_type {
+                SourceType::Section => StoreHealthIssue::OrphanSections {
+                    section_ids: orphan_ids,
+                },
+                SourceType::Node => StoreHealthIssue::OrphanNodes {
+                    node_ids: orphan_ids,
+                },
+                _ => StoreHealthIssue::OrphanTextEmbedded {
+                    text_embedded_ids: orphan_ids,
+                },
+            };
+            if repair {
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+        Ok(report)
+    }
+
+    /// Migrate the store to another embedding model
+    ///
+    /// Batches are applied under the lock of the tables, so a migration is never half-staged.
+    async fn migrate_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: ModelMigrationOptions,
+    ) -> TuoResult<ModelMigrationReport> {
+        let model = embedder.get_model_metadata();
+        let (nodes, texts_embedded) = {
+            let mut tables = write_tables(&self.tables);
+            check_migration_model(
+                &self.get_store_model_metadata(),
+                &tables.model_metadata,
+                &model,
+            )?;
+            // a migration to another model is started over
+            if !tables.migration.as_ref().is_some_and(|migration| {
+                migration.model_name == model.name && migration.dimensions == model.dimensions
+            }) {
+                tables.migration = Some(MemoryMigration {
+                    model_name: model.name.clone(),
+                    dimensions: model.dimensions,
+                    ..Default::default()
+                });
+            }
+            (tables.nodes.clone(), tables.text_embedded.clone())
+        };
+        let mut report = run_model_migration(
+            &*self,
+            embedder.as_ref(),
+            stream::iter([Ok(nodes)]).boxed(),
+            stream::iter([Ok(texts_embedded)]).boxed(),
+            &opts,
+        )
+        .await?;
+        report.previous_model = self.store_metadata.model.clone();
+        if report.committed {
+            self.store_metadata.model_id = Some(model.id);
+            self.store_metadata.model = Some(model);
+            let mut tables = write_tables(&self.tables);
+            for row in tables.store_metadata.iter_mut() {
+                if row.id == self.store_metadata.id {
+                    *row = self.store_metadata.clone();
+                }
+            }
+            self.embedder = Arc::new(embedder);
+        }
+        Ok(report)
+    }
+
+    async fn register_embedding_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<EmbeddingModelMetadata> {
+        let mut registered_model = RegisteredModel::new(embedder);
+        {
+            let mut tables = write_tables(&self.tables);
+            let (model, is_new) = model_to_register(
+                &self.get_store_model_metadata(),
+                &tables.model_metadata,
+                registered_model.model,
+            )?;
+            if is_new {
+                tables.insert(SourceData::ModelMetadata(vec![model.clone()]));
+            }
+            tables.model_embeddings.entry(model.id).or_default();
+            registered_model.model = model;
+        }
+        let model = registered_model.model.clone();
+        self.registered_models.insert(model.id, registered_model);
+        Ok(model)
+    }
+
+    async fn list_embedding_models(&self) -> TuoResult<Vec<EmbeddingModelMetadata>> {
+        let store_model = self.get_store_model_metadata();
+        let registered_models: Vec<EmbeddingModelMetadata> = read_tables(&self.tables)
+            .model_metadata
+            .iter()
+            .filter(|model| model.id != store_model.id)
+            .cloned()
+            .collect();
+        Ok([vec![store_model], registered_models].concat())
+    }
+
+    fn get_registered_models(&self) -> Vec<RegisteredModel> {
+        self.registered_models.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use crate::stores::memory::index::MemoryIndexSearchOptions;
+    use crate::testing::{parsed_document, CharHashEmbedder};
+    use futures::TryStreamExt;
+    use tuo_core::core::indexing::index_insert::DocumentInsertOptions;
+    use tuo_core::core::indexing::index_scan::ScanOptions;
+    use tuo_core::core::messaging::content::{
+        QueryEmbeddingCache, TextEmbeddingOptions, TextInput, TextSourceType,
+    };
+    use tuo_core::core::source::document_tree::DocumentTreeOptions;
+    use tuo_core::embedding::embedder::EmbedResultStats;
+    use tuo_core::retrieval::hybrid::{HybridFusion, HybridSearchOptions};
+    use tuo_core::retrieval::keyword::Bm25Options;
+    use tuo_utils::datetime::timestamp::utc_from_epoch;
+    use tuo_utils::hash::hash_str::hash_str;
+    use tuo_utils::testing::get_random_test_temp_folder;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_memory_store() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        assert!(store.list_indices().await.unwrap().is_empty());
+        // a store cannot be created twice
+        assert!(MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .is_err());
+
+        let index = store.index_create("test_index").await.unwrap();
+        let other_index = store.index_create("other_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let other_index_id = other_index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子"]);
+        let node_1_id = document.nodes[0].id;
+        let node_2_id = document.nodes[1].id;
+        index.add_document(vec![document], None).await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(other_index_id, "other", &["中文"])],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 1);
+        assert_eq!(
+            store
+                .index_count_records("other_index", &SourceType::Node)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // embed
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 2);
+        index.embed_nodes(vec![node_1_id]).await.unwrap();
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 1);
+        index.embed_nodes(vec![]).await.unwrap();
+        assert!(index.get_unembedded_nodes().await.unwrap().is_empty());
+        assert_eq!(other_index.get_unembedded_nodes().await.unwrap().len(), 1);
+        other_index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+
+        // nodes are stored without their embeddings, which are fetched as relations
+        let node = index
+            .get_source_data_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert!(node[0].content_embeddings_id.is_some());
+        assert!(node[0].content_embeddings.is_none());
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert!(node[0].content_embeddings.is_some());
+
+        // search within the index, most similar first
+        let results = index
+            .similar_sources(&TextInput::from_user_str("中文"), &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids(), vec![node_2_id, node_1_id]);
+        // only nodes are searched by similarity
+        assert!(index
+            .similar_sources(
+                &TextInput::from_user_str("中文"),
+                &SourceType::Document,
+                None
+            )
+            .await
+            .is_err());
+        let results = index
+            .similar_embedded_text(
+                &TextInput::from_user_str("test"),
+                &TextSourceType::NodeContent,
+                Some(MemoryIndexSearchOptions::builder().top_k(1).build()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data.source_id, Some(node_1_id));
+        assert!(results[0].distance < 1.0);
+
+        // ordered lookup
+        let nodes = index
+            .get_source_data_by_ids(&SourceType::Node, vec![node_2_id, node_1_id], true)
+            .await
+            .unwrap();
+        assert_eq!(nodes.get_ids(), vec![node_2_id, node_1_id]);
+
+        // the store can be opened again by its uri
+        let reopened = MemoryStore::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let reopened_index = reopened.index_open("test_index").await.unwrap();
+        assert_eq!(
+            reopened_index
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            2
+        );
+        assert!(reopened.check_health(false).await.unwrap().is_healthy());
+
+        // delete and remove
+        index
+            .delete(&vec![node_1_id], &SourceType::Node)
+            .await
+            .unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 1);
+        let report = store.check_health(true).await.unwrap();
+        assert_eq!(report.repaired.len(), 1);
+        let result = store.index_remove(index_id).await.unwrap();
+        assert_eq!(result.removed_count(&SourceType::Node), 1);
+        assert_eq!(result.removed_count(&SourceType::Document), 1);
+        assert_eq!(result.removed_count(&SourceType::IndexMetadata), 1);
+        assert!(!store.index_exists("test_index").await.unwrap());
+        assert_eq!(
+            other_index.count_records(&SourceType::Node).await.unwrap(),
+            1
+        );
+
+        assert!(MemoryStore::remove(store.get_store_uri().as_str()));
+        assert!(MemoryStore::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new())
+        )
+        .await
+        .is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_migrate_model() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let previous_dimensions = store.get_store_model_dimensions();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子", "more text"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        let query = TextInput::from_user_str("中文");
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // stop after the first batch, the store still uses the previous model
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder()
+                    .batch_size(2)
+                    .max_batches(Some(1))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.nodes_embedded, 2);
+        assert_eq!(store.get_store_model_dimensions(), previous_dimensions);
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // resume
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().batch_size(2).build(),
+            )
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!(report.nodes_resumed, 2);
+        assert_eq!(report.nodes_embedded, 1);
+        // the cached query, reused by the second search
+        assert_eq!(report.texts_dropped, 1);
+        assert_eq!(
+            report.previous_model.map(|model| model.dimensions),
+            Some(previous_dimensions)
+        );
+        assert_eq!(store.get_store_model_dimensions(), 32);
+
+        let index = store.index_open("test_index").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_ids[0])
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(
+            node[0]
+                .content_embeddings
+                .as_ref()
+                .unwrap()
+                .embeddings
+                .len(),
+            32
+        );
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+
+        // the store is opened with the model migrated to, not with the previous model
+        let uri = store.get_store_uri();
+        let reopened = MemoryStore::open(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        let error = MemoryStore::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
+            .await
+            .err()
+            .expect("Opening with the previous model should fail");
+        assert!(error.to_string().contains("uses model"), "{}", error);
+        let reopened = MemoryStore::open_with_options(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::new()),
+            &StoreOpenOptions::builder()
+                .allow_model_mismatch(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        assert_eq!(
+            reopened
+                .index_open("test_index")
+                .await
+                .unwrap()
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_hybrid_search() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(
+            index_id,
+            "doc",
+            &["apple pie recipe", "中文例子", "日本語の例文", "pear tart"],
+        );
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        // CJK text is matched without word delimiters
+        let query = TextInput::from_user_str("例子");
+        let keyword_matches = index
+            .keyword_search(&query, 10, &Bm25Options::default())
+            .await
+            .unwrap();
+        assert_eq!(keyword_matches.len(), 2);
+        assert_eq!(keyword_matches[0].data_id, node_ids[1]);
+        assert_eq!(keyword_matches[1].data_id, node_ids[2]);
+
+        let results = index
+            .hybrid_search(
+                &query,
+                &HybridSearchOptions::builder().top_k(3).build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].data_id, node_ids[1]);
+        let scores = results[0].hybrid_scores.clone().unwrap();
+        assert!(scores.vector_distance.is_some());
+        assert!(scores.keyword_score.is_some());
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].distance <= pair[1].distance));
+
+        // keyword scores only
+        let results = index
+            .hybrid_search(
+                &TextInput::from_user_str("Apple"),
+                &HybridSearchOptions::builder()
+                    .fusion(HybridFusion::Weighted { vector_weight: 0.0 })
+                    .build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data_id, node_ids[0]);
+        assert_eq!(results[0].distance, 0.0);
+        assert_eq!(
+            results[1].hybrid_scores.as_ref().unwrap().keyword_score,
+            None
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_query_embedding_cache() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let count_queries = || index.count_records(&SourceType::TextEmbedded);
+
+        // a cached query embedding, last used long ago
+        let query = TextInput::from_user_str("apple");
+        let embedder = index.get_index_embedder().await.unwrap();
+        let mut cached = embedder
+            .embed_input(
+                &query,
+                &TextEmbeddingOptions::builder().save_text(true).build(),
+            )
+            .await
+            .unwrap();
+        cached.used_at = utc_from_epoch(0);
+        index
+            .add_text_embeddings(&vec![cached.clone()])
+            .await
+            .unwrap();
+
+        index
+            .similar_embedded_text(&query, &TextSourceType::UserQuery, None)
+            .await
+            .unwrap();
+        assert_eq!(count_queries().await.unwrap(), 1);
+        let reused = index
+            .find_text_embedded(
+                &cached.hash,
+                &cached.embedding_model,
+                &TextSourceType::UserQuery,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reused.id, cached.id);
+        assert!(reused.used_at > cached.used_at);
+
+        // new queries are only persisted by a read-write cache
+        for (text, query_cache, count) in [
+            ("pear", QueryEmbeddingCache::ReadOnly, 1),
+            ("pear", QueryEmbeddingCache::Disabled, 1),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("apple", QueryEmbeddingCache::Disabled, 2),
+        ] {
+            index
+                .similar_embedded_text(
+                    &TextInput::from_user_str(text),
+                    &TextSourceType::UserQuery,
+                    Some(
+                        MemoryIndexSearchOptions::builder()
+                            .query_cache(query_cache)
+                            .build(),
+                    ),
+                )
+                .await
+                .unwrap();
+            assert_eq!(count_queries().await.unwrap(), count);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_embedding_dedup() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let content_embeddings_ids = |nodes: SourceData| {
+            nodes
+                .get_node()
+                .unwrap()
+                .into_iter()
+                .map(|node| node.content_embeddings_id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // repeated contents of a batch are embedded once
+        let document = parsed_document(index_id, "a", &["disclaimer", "apple", "disclaimer"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        let ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(ids[0], ids[2]);
+        assert_ne!(ids[0], ids[1]);
+
+        // contents embedded before are reused across documents
+        let document = parsed_document(index_id, "b", &["disclaimer", "pear"]);
+        let other_node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 1,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let other_ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, other_node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(other_ids[0], ids[0]);
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_scan() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let documents = vec![
+            parsed_document(index_id, "doc_a", &["apple", "pear", "plum"]),
+            parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+            parsed_document(index_id, "doc_c", &["lime"]),
+        ];
+        let mut nodes: Vec<Node> = documents
+            .iter()
+            .flat_map(|document| document.nodes.clone())
+            .collect();
+        nodes.sort_by_key(|node| (node.document_id, node.index));
+        let node_ids: Vec<Uuid> = nodes.iter().map(|node| node.id).collect();
+        let mut document_ids: Vec<Uuid> = documents
+            .iter()
+            .map(|document| document.document.id)
+            .collect();
+        document_ids.sort();
+        index.add_document(documents, None).await.unwrap();
+        // the records of other indices are not scanned
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_d",
+                    &["date"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder().page_size(4).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(
+            pages
+                .iter()
+                .flat_map(|page| page.source_data.get_ids())
+                .collect::<Vec<_>>(),
+            node_ids
+        );
+
+        // resumed after the first page
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder()
+                    .page_size(4)
+                    .cursor(pages[0].cursor.clone())
+                    .build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), node_ids[4..]);
+
+        // a full page is followed by an empty one, which ends the scan
+        let pages = index
+            .scan(
+                &SourceType::Document,
+                ScanOptions::builder().page_size(3).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), document_ids);
+    }
+    #[test(tokio::test)]
+    async fn test_memory_add_document_upsert() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_b",
+                    &["fig"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+        let upsert = |remove_missing_under: Option<&str>| {
+            Some(
+                DocumentInsertOptions::builder()
+                    .upsert(true)
+                    .remove_missing_under(remove_missing_under.map(str::to_string))
+                    .build(),
+            )
+        };
+
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig"]),
+                ],
+                upsert(None),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.added, vec!["/test/doc_a", "/test/doc_b"]);
+        index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+
+        // the same content is skipped, changed content replaces the document and its embeddings
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+                    parsed_document(index_id, "doc_c", &["lime"]),
+                ],
+                upsert(Some("/test/")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.updated, vec!["/test/doc_b"]);
+        assert_eq!(report.added, vec!["/test/doc_c"]);
+        assert!(report.removed.is_empty());
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 5);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 3);
+
+        // documents of missing sources are removed, but not those of other indices
+        let report = index
+            .add_document(
+                vec![parsed_document(index_id, "doc_a", &["apple", "pear"])],
+                upsert(Some("/test/")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.removed, vec!["/test/doc_b", "/test/doc_c"]);
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 1);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            other_index
+                .count_records(&SourceType::Document)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+    #[test(tokio::test)]
+    async fn test_memory_document_tree() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let embedder = index.get_index_embedder().await.unwrap();
+
+        // a document of two sections, whose sections and nodes are added out of order
+        let mut document = parsed_document(index_id, "doc", &["apple", "pear", "plum"]);
+        let mut second_section = document.sections[0].clone();
+        second_section.id = Uuid::new_v4();
+        second_section.section_order = 1;
+        document.nodes[2].section_id = second_section.id;
+        document.sections.insert(0, second_section);
+        document.nodes.reverse();
+        let summary = embedder
+            .embed_input(
+                &TextInput {
+                    text: "fruits".to_string(),
+                    source_type: TextSourceType::SummaryDocument,
+                    source_id: Some(document.document.id),
+                },
+                &TextEmbeddingOptions::builder().build(),
+            )
+            .await
+            .unwrap();
+        document.document.summary_text_id = Some(summary.id);
+        let document_id = document.document.id;
+        index
+            .add_document(
+                vec![document, parsed_document(index_id, "other", &["fig"])],
+                None,
+            )
+            .await
+            .unwrap();
+        index
+            .add_text_embeddings(&vec![summary.clone()])
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let tree = index
+            .get_document_tree(document_id, &DocumentTreeOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tree.document.id, document_id);
+        let section_orders: Vec<i32> = tree
+            .sections
+            .iter()
+            .map(|section| section.section.section_order)
+            .collect();
+        assert_eq!(section_orders, vec![0, 1]);
+        let contents: Vec<&str> = tree
+            .nodes()
+            .iter()
+            .map(|node| node.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["apple", "pear", "plum"]);
+        assert!(tree.document.summary.is_none());
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_none()));
+
+        let tree = index
+            .get_document_tree(
+                document_id,
+                &DocumentTreeOptions::builder()
+                    .with_embeddings(true)
+                    .with_summaries(true)
+                    .build(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tree.document.summary.as_ref().map(|summary| summary.id),
+            Some(summary.id)
+        );
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_some()));
+        assert!(index
+            .get_document_tree(Uuid::new_v4(), &DocumentTreeOptions::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_check_health_orphans() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["apple", "pear"]);
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+
+        // a section whose document is gone
+        let orphan_section = parsed_document(index_id, "gone", &["plum"]).sections[0].clone();
+        index
+            .add_source_data(
+                SourceData::Section(vec![orphan_section.clone()]),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        // a text embedding of the registered model whose node is gone
+        let mut orphan_model_embeddings = index
+            .find_model_embeddings(model.id, &[hash_str("pear")])
+            .await
+            .unwrap()
+            .remove(0);
+        orphan_model_embeddings.id = Uuid::new_v4();
+        orphan_model_embeddings.source_id = Some(Uuid::new_v4());
+        index
+            .add_model_embeddings(model.id, vec![orphan_model_embeddings.clone()])
+            .await
+            .unwrap();
+
+        let report = store.check_health(false).await.unwrap();
+        assert_eq!(
+            report.issues,
+            vec![
+                StoreHealthIssue::OrphanModelEmbeddings {
+                    model_id: model.id,
+                    text_embedded_ids: vec![orphan_model_embeddings.id],
+                },
+                StoreHealthIssue::OrphanSections {
+                    section_ids: vec![orphan_section.id],
+                },
+            ]
+        );
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.unresolved());
+        assert_eq!(report.repaired.len(), 2);
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 1);
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("pear")])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_memory_multiple_models() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = MemoryStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let store_model = store.get_store_model_metadata();
+        // the model of the store cannot be registered
+        assert!(store
+            .register_embedding_model(Box::new(CharHashEmbedder::new()))
+            .await
+            .is_err());
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear", "apple"]);
+        let plum = parsed_document(index_id, "doc_b", &["plum"]);
+        let plum_id = plum.nodes[0].id;
+        let plum_document_id = plum.document.id;
+        index
+            .add_document(vec![document, plum], None)
+            .await
+            .unwrap();
+
+        // the nodes embedded by each model are tracked separately
+        index.embed_nodes(vec![]).await.unwrap();
+        assert!(index
+            .get_unembedded_nodes_of_model(store_model.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .get_unembedded_nodes_of_model(model.id)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![plum_id])
+            .await
+            .unwrap();
+        assert_eq!(stats.embedded, 1);
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
+        );
+        assert!(index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(index
+            .embed_nodes_with_model(Uuid::new_v4(), vec![])
+            .await
+            .is_err());
+
+        // the query is embedded and searched with the model chosen in the search options
+        let query = TextInput::from_user_str("plum");
+        let results = index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    MemoryIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.source_id, Some(plum_id));
+        assert_eq!(results[0].data.embeddings.len(), 32);
+        let nodes = index
+            .similar_sources(
+                &query,
+                &SourceType::Node,
+                Some(
+                    MemoryIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(nodes.get_ids(), vec![plum_id]);
+        let results = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.embeddings.len(), 64);
+        assert!(index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    MemoryIndexSearchOptions::builder()
+                        .model_id(Some(Uuid::new_v4()))
+                        .build()
+                ),
+            )
+            .await
+            .is_err());
+
+        // a model registered again keeps its id and its embeddings
+        let mut reopened = MemoryStore::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let registered = reopened
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        assert_eq!(registered.id, model.id);
+        let model_ids: Vec<Uuid> = reopened
+            .list_embedding_models()
+            .await
+            .unwrap()
+            .iter()
+            .map(|model| model.id)
+            .collect();
+        assert_eq!(model_ids, vec![store_model.id, model.id]);
+        let reopened_index = reopened.index_open("test_index").await.unwrap();
+        assert!(reopened_index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the embeddings of the registered models are removed with their nodes
+        index.delete_documents(&[plum_document_id]).await.unwrap();
+        assert!(index
+            .find_model_embeddings(model.id, &[hash_str("plum")])
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("apple")])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        let result = store.index_remove(index_id).await.unwrap();
+        // the embeddings of the store model are the apple, pear and cached plum query ones
+        assert_eq!(result.removed_count(&SourceType::TextEmbedded), 6);
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::messaging::content::TextEmbedded;
+use tuo_core::core::source::document::Document;
+use tuo_core::core::source::node::Node;
+use tuo_core::core::source::section::Section;
+use tuo_core::core::source::sources::{SourceData, SourceType};
+use tuo_core::model::model_metadata::EmbeddingModelMetadata;
+use tuo_core::storage::store_metadata::StoreMetadata;
+
+/// The tables of an in-memory store, one vector of records per [source type](SourceType).
+///
+/// Like the tables of a database, they are shared by the store and all its indices.
+#[derive(Debug, Default)]
+pub struct MemoryTables {
+    pub store_metadata: Vec<StoreMetadata>,
+    pub model_metadata: Vec<EmbeddingModelMetadata>,
+    pub index_metadata: Vec<IndexMetadata>,
+    pub text_embedded: Vec<TextEmbedded>,
+    pub documents: Vec<Document>,
+    pub sections: Vec<Section>,
+    pub nodes: Vec<Node>,
+    /// Node content embeddings of the [registered models](tuo_core::storage::store_models::RegisteredModel), keyed by model id.
+    pub model_embeddings: HashMap<Uuid, Vec<TextEmbedded>>,
+    /// Records staged by an ongoing migration to another model, see [MemoryMigration].
+    pub migration: Option<MemoryMigration>,
+}
+
+/// Text embeddings and nodes staged by a [model migration](tuo_core::storage::store::StoreTrait::migrate_model), replacing the tables once committed.
+#[derive(Debug, Default)]
+pub struct MemoryMigration {
+    /// Name of the model migrated to.
+    pub model_name: String,
+    /// Dimensions of the model migrated to.
+    pub dimensions: i32,
+    pub text_embedded: Vec<TextEmbedded>,
+    pub nodes: Vec<Node>,
+}
+
+/// Handle to the tables of an in-memory store.
+pub type SharedMemoryTables = Arc<RwLock<MemoryTables>>;
+
+/// Access to the id and index id of a record, used to filter records.
+trait MemoryRecord {
+    fn record_id(&self) -> Uuid;
+    fn record_index_id(&self) -> Option<Uuid>;
+}
+
+macro_rules! impl_memory_record {
+    ($type:ty, |$record:ident| $index_id:expr) => {
+        impl MemoryRecord for $type {
+            fn record_id(&self) -> Uuid {
+                self.id
+            }
+
+            fn record_index_id(&self) -> Option<Uuid> {
+                let $record = self;
+                $index_id
+            }
+        }
+    };
+}
+
+impl_memory_record!(StoreMetadata, |_record| None);
+impl_memory_record!(EmbeddingModelMetadata, |_record| None);
+impl_memory_record!(IndexMetadata, |_record| None);
+impl_memory_record!(TextEmbedded, |record| record.index_id);
+impl_memory_record!(Document, |record| Some(record.index_id));
+impl_memory_record!(Section, |record| Some(record.index_id));
+impl_memory_record!(Node, |record| Some(record.index_id));
+
+fn select_records<T: MemoryRecord + Clone>(
+    records: &[T],
+    predicate: impl Fn(Uuid, Option<Uuid>) -> bool,
+) -> Vec<T> {
+    records
+        .iter()
+        .filter(|record| predicate(record.record_id(), record.record_index_id()))
+        .cloned()
+        .collect()
+}
+
+fn remove_records<T: MemoryRecord>(
+    records: &mut Vec<T>,
+    predicate: impl Fn(Uuid, Option<Uuid>) -> bool,
+) -> usize {
+    let count = records.len();
+    records.retain(|record| !predicate(record.record_id(), record.record_index_id()));
+    count - records.len()
+}
+
+impl MemoryTables {
+    /// Append the records to their table.
+    ///
+    /// As in a database, the node table only keeps the id of the content embeddings, not the embeddings themselves.
+    pub fn insert(&mut self, source_data: SourceData) {
+        match source_data {
+            SourceData::StoreMetadata(data) => self.store_metadata.extend(data),
+            SourceData::ModelMetadata(data) => self.model_metadata.extend(data),
+            SourceData::IndexMetadata(data) => self.index_metadata.extend(data),
+            SourceData::TextEmbedded(data) => self.text_embedded.extend(data),
+            SourceData::Document(data) => self.documents.extend(data),
+            SourceData::Section(data) => self.sections.extend(data),
+            SourceData::Node(data) => self.nodes.extend(data.into_iter().map(|mut node| {
+                node.content_embeddings = None;
+                node
+            })),
+        }
+    }
+
+    /// Records of the source type for which the predicate on `(id, index_id)` holds, in insertion order.
+    pub fn select(
+        &self,
+        source_type: &SourceType,
+        predicate: impl Fn(Uuid, Option<Uuid>) -> bool,
+    ) -> SourceData {
+        match source_type {
+            SourceType::StoreMetadata => {
+                SourceData::StoreMetadata(select_records(&self.store_metadata, predicate))
+            }
+            SourceType::ModelMetadata => {
+                SourceData::ModelMetadata(select_records(&self.model_metadata, predicate))
+            }
+            SourceType::IndexMetadata => {
+                SourceData::IndexMetadata(select_records(&self.index_metadata, predicate))
+            }
+            SourceType::TextEmbedded => {
+                SourceData::TextEmbedded(select_records(&self.text_embedded, predicate))
+            }
+            SourceType::Document => {
+                SourceData::Document(select_records(&self.documents, predicate))
+            }
+            SourceType::Section => SourceData::Section(select_records(&self.sections, predicate)),
+            SourceType::Node => SourceData::Node(select_records(&self.nodes, predicate)),
+        }
+    }
+
+    /// Remove the records of the source type for which the predicate on `(id, index_id)` holds.
+    ///
+    /// Returns the number of removed records.
+    pub fn remove(
+        &mut self,
+        source_type: &SourceType,
+        predicate: impl Fn(Uuid, Option<Uuid>) -> bool,
+    ) -> usize {
+        match source_type {
+            SourceType::StoreMetadata => remove_records(&mut self.store_metadata, predicate),
+            SourceType::ModelMetadata => remove_records(&mut self.model_metadata, predicate),
+            SourceType::IndexMetadata => remove_records(&mut self.index_metadata, predicate),
+            SourceType::TextEmbedded => remove_records(&mut self.text_embedded, predicate),
+            SourceType::Document => remove_records(&mut self.documents, predicate),
+            SourceType::Section => remove_records(&mut self.sections, predicate),
+            SourceType::Node => remove_records(&mut self.nodes, predicate),
+        }
+    }
+}
+
+/// Lock the tables for reading.
+///
+/// Every write to the tables is a single, non-panicking step, so a poisoned lock still guards consistent data.
+pub(crate) fn read_tables(tables: &SharedMemoryTables) -> RwLockReadGuard<'_, MemoryTables> {
+    tables.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Lock the tables for writing, see [read_tables].
+pub(crate) fn write_tables(tables: &SharedMemoryTables) -> RwLockWriteGuard<'_, MemoryTables> {
+    tables.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// In-memory stores of the process, keyed by uri, so that a store can be opened again after it was created.
+fn registry() -> &'static Mutex<HashMap<String, SharedMemoryTables>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, SharedMemoryTables>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Register new tables for the uri, or `None` if a store already exists at the uri.
+pub(crate) fn register_tables(uri: &str) -> Option<SharedMemoryTables> {
+    let mut registry = registry().lock().unwrap_or_else(PoisonError::into_inner);
+    if registry.contains_key(uri) {
+        return None;
+    }
+    let tables = SharedMemoryTables::default();
+    registry.insert(uri.to_string(), tables.clone());
+    Some(tables)
+}
+
+pub(crate) fn find_tables(uri: &str) -> Option<SharedMemoryTables> {
+    registry()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(uri)
+        .cloned()
+}
+
+pub(crate) fn unregister_tables(uri: &str) -> Option<SharedMemoryTables> {
+    registry()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(uri)
+}
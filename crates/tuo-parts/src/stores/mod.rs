@@ -1,2 +1,8 @@
-pub mod query_engine;
+#[cfg(feature = "lancedb")]
 pub mod lancedb;
+
+#[cfg(feature = "db_memory")]
+pub mod memory;
+
+#[cfg(feature = "db_sqlite")]
+pub mod sqlite;
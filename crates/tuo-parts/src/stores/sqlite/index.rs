@@ -0,0 +1,756 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use async_trait::async_trait;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, ParamsFromIter};
+use tracing::debug;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index::IndexTrait;
+use tuo_core::core::indexing::index_insert::{
+    insert_documents, DocumentInsertOptions, DocumentInsertReport,
+};
+use tuo_core::core::indexing::index_metadata::IndexMetadata;
+use tuo_core::core::indexing::index_scan::{ScanCursor, ScanPage};
+use tuo_core::core::messaging::content::{
+    QueryEmbeddingCache, TextEmbedded, TextEmbeddedFieldName, TextInput, TextSourceType,
+};
+use tuo_core::core::source::node::{Node, NodeFieldName};
+use tuo_core::core::source::section::SectionFieldName;
+use tuo_core::core::source::sources::{SourceData, SourceTableName, SourceType, SourceTypeTrait};
+use tuo_core::embedding::embedder::EmbedderTrait;
+use tuo_core::model::model_metadata::EmbeddingModelMetadata;
+use tuo_core::parsing::document_parser::ParsedDocument;
+use tuo_core::retrieval::keyword::{Bm25Corpus, Bm25Options, KeywordMatch};
+use tuo_core::retrieval::search_result::SimilarResult;
+use tuo_core::storage::store_metadata::StoreMetadata;
+use tuo_core::storage::store_models::{
+    find_registered_model, model_embeddings_table_name, RegisteredModel,
+};
+use tuo_shared::consts::defaults::D_TABLE_COLUMN_NAME_VECTOR;
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+use tuo_utils::datetime::timestamp::now;
+
+use crate::stores::sqlite::schema::{
+    connect, insert_sources, insert_sources_into, model_embeddings_tables, node_version_table,
+    quote, row_to_text_embedded, select_columns, select_sources, select_sources_from,
+    vector_signature, vector_to_blob, SQLITE_COLUMN_NAME_VECTOR_SIGNATURE,
+    SQLITE_FUNCTION_COSINE_DISTANCE, SQLITE_FUNCTION_HAMMING_DISTANCE,
+};
+
+#[derive(TypedBuilder)]
+pub struct SqliteIndex {
+    pub index_metadata: IndexMetadata,
+    pub store_metadata: StoreMetadata,
+    pub index_name: String,
+    /// The embedder to use for the index
+    pub embedder: Option<Arc<Box<dyn EmbedderTrait>>>,
+    pub model: EmbeddingModelMetadata,
+    /// The models registered on the store when the index was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+    /// Term statistics of the node contents for keyword search, with the [version of the nodes](node_version_table) they were computed at.
+    #[builder(default)]
+    keyword_corpus: Mutex<Option<(i64, Arc<Bm25Corpus>)>>,
+}
+
+#[async_trait]
+impl IndexTrait for SqliteIndex {
+    type StoreDataType = SourceData;
+    type QueryOptions = Option<SqliteIndexSearchOptions>;
+
+    type InsertOptions = Option<DocumentInsertOptions>;
+    type TableType = Connection;
+
+    async fn add_document(
+        &self,
+        data: Vec<ParsedDocument>,
+        opt: Self::InsertOptions,
+    ) -> TuoResult<DocumentInsertReport> {
+        insert_documents(self, data, &opt.unwrap_or_default()).await
+    }
+
+    async fn add_source_data(
+        &self,
+        source_data: SourceData,
+        _opt: Self::InsertOptions,
+    ) -> TuoResult<()> {
+        self.add_to_table(source_data)
+    }
+
+    async fn add_text_embeddings(&self, text_embedded: &Vec<TextEmbedded>) -> TuoResult<()> {
+        let source_data = SourceData::TextEmbedded(text_embedded.clone());
+        self.add_source_data(source_data, None).await?;
+        Ok(())
+    }
+
+    async fn find_text_embeddings(
+        &self,
+        hashes: &[String],
+        embedding_model: &str,
+        text_source_type: &TextSourceType,
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        let source_type = SourceType::TextEmbedded;
+        let connection = self.open_source_table(&source_type).await?;
+        let mut text_embedded = vec![];
+        for hashes in hashes.chunks(SQLITE_IN_CHUNK_SIZE) {
+            let filter = self.scoped_filter(
+                &source_type,
+                format!(
+                    "{} AND {} = ?{} AND {} = ?{}",
+                    hash_in_filter(hashes.len()),
+                    quote(TextEmbeddedFieldName::EmbeddingModel.name()),
+                    hashes.len() + 1,
+                    quote(TextEmbeddedFieldName::SourceType.name()),
+                    hashes.len() + 2
+                ),
+            );
+            let params = in_params(
+                hashes,
+                vec![
+                    Value::Text(embedding_model.to_string()),
+                    Value::Text(text_source_type.as_ref().to_string()),
+                ],
+            );
+            text_embedded.extend(
+                select_sources(&connection, &source_type, &filter, "", params)?
+                    .get_text_embedded()
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(text_embedded)
+    }
+
+    async fn touch_text_embeddings(&self, ids: &[Uuid]) -> TuoResult<()> {
+        let source_type = SourceType::TextEmbedded;
+        let connection = self.open_source_table(&source_type).await?;
+        let used_at = now().timestamp();
+        for ids in ids.chunks(SQLITE_IN_CHUNK_SIZE) {
+            let predicate = self.scoped_filter(&source_type, id_in_filter(ids.len()));
+            connection.execute(
+                format!(
+                    "UPDATE {} SET {} = ?{} WHERE {}",
+                    source_type.table_name(),
+                    quote(TextEmbeddedFieldName::UsedAt.name()),
+                    ids.len() + 1,
+                    predicate
+                )
+                .as_str(),
+                in_params(ids, vec![Value::Integer(used_at)]),
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, source_ids: &Vec<Uuid>, source_type: &SourceType) -> TuoResult<()> {
+        let connection = self.open_source_table(source_type).await?;
+        for ids in source_ids.chunks(SQLITE_IN_CHUNK_SIZE) {
+            let predicate = self.scoped_filter(source_type, id_in_filter(ids.len()));
+            connection.execute(
+                format!(
+                    "DELETE FROM {} WHERE {}",
+                    source_type.table_name(),
+                    predicate
+                )
+                .as_str(),
+                in_params(ids, vec![]),
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn delete_documents(&self, document_ids: &[Uuid]) -> TuoResult<()> {
+        if document_ids.is_empty() {
+            return Ok(());
+        }
+        let connection = self.open_source_table(&SourceType::Document).await?;
+        let transaction = connection.unchecked_transaction()?;
+        let model_embeddings_tables = model_embeddings_tables(&transaction)?;
+        // the documents are deleted a chunk at a time, the content embeddings shared with the nodes of the next chunks are kept until those are deleted
+        for document_ids in document_ids.chunks(SQLITE_IN_CHUNK_SIZE) {
+            for sql in
+                self.delete_documents_statements(document_ids.len(), &model_embeddings_tables)
+            {
+                transaction.execute(sql.as_str(), in_params(document_ids, vec![]))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn similar_embedded_text(
+        &self,
+        text: &TextInput,
+        text_source_type: &TextSourceType,
+        opts: Self::QueryOptions,
+    ) -> TuoResult<Vec<SimilarResult<TextEmbedded>>> {
+        let opts = opts.unwrap_or(SqliteIndexSearchOptions::builder().build());
+        let embedded_text = self
+            .embed_query_with_model(text, opts.model_id, &opts.query_cache)
+            .await?;
+        let source_type = SourceType::TextEmbedded;
+        let table = match opts.model_id.filter(|model_id| *model_id != self.model.id) {
+            Some(model_id) => model_embeddings_table_name(model_id),
+            None => source_type.table_name(),
+        };
+        let filter = self.scoped_filter(
+            &source_type,
+            format!(
+                "{} = '{}'",
+                quote(TextEmbeddedFieldName::SourceType.name()),
+                text_source_type.as_ref()
+            ),
+        );
+        let filter = match opts.mode {
+            SqliteSearchMode::Exact => filter,
+            // shortlist the candidates by their signatures (?2), then rank them exactly
+            SqliteSearchMode::SignatureRescoring { refine_factor } => format!(
+                "{id} IN (SELECT {id} FROM {table} WHERE {filter} ORDER BY {hamming}({signature}, ?2) LIMIT {candidates})",
+                id = quote(NodeFieldName::Id.name()),
+                hamming = SQLITE_FUNCTION_HAMMING_DISTANCE,
+                signature = quote(SQLITE_COLUMN_NAME_VECTOR_SIGNATURE),
+                candidates = opts.top_k * refine_factor.max(1),
+            ),
+        };
+        let sql = format!(
+            "SELECT {columns}, {cosine}({vector}, ?1) AS distance FROM {table} WHERE {filter} ORDER BY distance LIMIT {top_k}",
+            columns = select_columns(&source_type),
+            cosine = SQLITE_FUNCTION_COSINE_DISTANCE,
+            vector = quote(D_TABLE_COLUMN_NAME_VECTOR),
+            top_k = opts.top_k,
+        );
+        let connection = self.open_source_table(&source_type).await?;
+        let mut statement = connection.prepare(&sql)?;
+        let query_vector = vector_to_blob(&embedded_text.embeddings);
+        let mut rows = match opts.mode {
+            SqliteSearchMode::Exact => statement.query(params![query_vector])?,
+            SqliteSearchMode::SignatureRescoring { .. } => statement.query(params![
+                query_vector,
+                vector_signature(&embedded_text.embeddings)
+            ])?,
+        };
+        let mut results = vec![];
+        while let Some(row) = rows.next()? {
+            let data = row_to_text_embedded(row)?;
+            // the distance is null for a text embedding without a vector
+            let distance: f64 =
+                row.get::<_, Option<f64>>("distance")?
+                    .ok_or(TuoPartsError::IndexError(format!(
+                        "Text embedding {} cannot be compared with the query",
+                        data.id
+                    )))?;
+            results.push(SimilarResult {
+                distance: distance as f32,
+                data_id: data.id,
+                data,
+                hybrid_scores: None,
+            });
+        }
+        Ok(results)
+    }
+
+    async fn keyword_search(
+        &self,
+        text: &TextInput,
+        top_k: usize,
+        opts: &Bm25Options,
+    ) -> TuoResult<Vec<KeywordMatch<Node>>> {
+        let connection = self.open_source_table(&SourceType::Node).await?;
+        let ranked = self.keyword_corpus(&connection)?.rank(&text.text, opts);
+        self.fetch_keyword_matches(ranked, top_k).await
+    }
+
+    async fn get_index_embedder(&self) -> TuoResult<Arc<Box<dyn EmbedderTrait>>> {
+        let embedder = self
+            .embedder
+            .as_ref()
+            .ok_or(TuoPartsError::IndexError(format!(
+                "No embedder found for the index {}",
+                self.index_name
+            )))?;
+        Ok(embedder.clone())
+    }
+
+    fn get_index_metadata(&self) -> IndexMetadata {
+        self.index_metadata.clone()
+    }
+
+    fn get_store_metadata(&self) -> StoreMetadata {
+        self.store_metadata.clone()
+    }
+
+    async fn count_records(&self, source_type: &SourceType) -> TuoResult<usize> {
+        let connection = self.open_source_table(source_type).await?;
+        let filter = self
+            .index_filter(source_type)
+            .unwrap_or("1 = 1".to_string());
+        let count: i64 = connection.query_row(
+            format!(
+                "SELECT COUNT(*) FROM {} WHERE {}",
+                source_type.table_name(),
+                filter
+            )
+            .as_str(),
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    async fn get_source_data_by_id(
+        &self,
+        source_type: &SourceType,
+        id: Uuid,
+    ) -> TuoResult<SourceData> {
+        let connection = self.open_source_table(source_type).await?;
+        let filter = self.scoped_filter(source_type, id_in_filter(1));
+        Ok(select_sources(
+            &connection,
+            source_type,
+            &filter,
+            "LIMIT 1",
+            in_params(&[id], vec![]),
+        )?)
+    }
+
+    /// Get source data by ids
+    ///
+    /// Ids not found in the index are skipped. With `preserve_order`, the records follow the order of the ids.
+    async fn get_source_data_by_ids(
+        &self,
+        source_type: &SourceType,
+        ids: Vec<Uuid>,
+        preserve_order: bool,
+    ) -> TuoResult<SourceData> {
+        let connection = self.open_source_table(source_type).await?;
+        let source_data = select_sources_in(
+            &connection,
+            &source_type.table_name(),
+            source_type,
+            NodeFieldName::Id.name(),
+            &ids,
+            |filter| self.scoped_filter(source_type, filter),
+        )?;
+        Ok(match preserve_order {
+            true => source_data.order_by_ids(&ids),
+            false => source_data,
+        })
+    }
+
+    async fn get_source_data_by_document_ids(
+        &self,
+        source_type: &SourceType,
+        document_ids: &[Uuid],
+    ) -> TuoResult<SourceData> {
+        let connection = self.open_source_table(source_type).await?;
+        select_sources_in(
+            &connection,
+            &source_type.table_name(),
+            source_type,
+            NodeFieldName::DocumentId.name(),
+            document_ids,
+            |filter| self.scoped_filter(source_type, filter),
+        )
+    }
+
+    async fn get_all_source_data(&self, source_type: &SourceType) -> TuoResult<SourceData> {
+        let connection = self.open_source_table(source_type).await?;
+        let filter = self.scoped_filter(source_type, "TRUE".to_string());
+        Ok(select_sources(&connection, source_type, &filter, "", [])?)
+    }
+
+    async fn scan_page(
+        &self,
+        source_type: &SourceType,
+        page_size: usize,
+        cursor: Option<&ScanCursor>,
+    ) -> TuoResult<ScanPage> {
+        let connection = self.open_source_table(source_type).await?;
+        // the scan key is compared as a row value, in the order of its columns
+        let (key_columns, after_cursor) = match source_type {
+            SourceType::Node => (
+                [
+                    NodeFieldName::DocumentId.name(),
+                    NodeFieldName::Index.name(),
+                    NodeFieldName::Id.name(),
+                ]
+                .map(quote)
+                .join(", "),
+                cursor.map(|cursor| {
+                    format!(
+                        "'{}', {}, '{}'",
+                        cursor.document_id.unwrap_or_default(),
+                        cursor.index.unwrap_or_default(),
+                        cursor.id
+                    )
+                }),
+            ),
+            _ => (
+                quote(NodeFieldName::Id.name()),
+                cursor.map(|cursor| format!("'{}'", cursor.id)),
+            ),
+        };
+        let filter = match after_cursor {
+            Some(after_cursor) => format!("({}) > ({})", key_columns, after_cursor),
+            None => "TRUE".to_string(),
+        };
+        let source_data = select_sources(
+            &connection,
+            source_type,
+            &self.scoped_filter(source_type, filter),
+            &format!("ORDER BY {} LIMIT {}", key_columns, page_size),
+            [],
+        )?;
+        Ok(ScanPage::new(source_data))
+    }
+
+    /// Open a connection to the database holding the table.
+    async fn open_source_table(&self, _source_type: &SourceType) -> TuoResult<Self::TableType> {
+        Ok(connect(&self.store_metadata.uri, false)?)
+    }
+
+    async fn get_unembedded_nodes(&self) -> TuoResult<Vec<Node>> {
+        let connection = self.open_source_table(&SourceType::Node).await?;
+        let filter = self.scoped_filter(
+            &SourceType::Node,
+            format!(
+                "{} IS NULL",
+                quote(NodeFieldName::ContentEmbeddingsId.name())
+            ),
+        );
+        let nodes = select_sources(&connection, &SourceType::Node, &filter, "", [])?
+            .get_node()
+            .unwrap_or_default();
+        Ok(nodes)
+    }
+
+    fn get_registered_model(&self, model_id: Uuid) -> TuoResult<RegisteredModel> {
+        find_registered_model(&self.registered_models, model_id, &self.index_name)
+    }
+
+    async fn add_model_embeddings(
+        &self,
+        model_id: Uuid,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        self.get_registered_model(model_id)?;
+        let mut source_data = SourceData::TextEmbedded(texts_embedded);
+        source_data.assign_index_id(self.index_metadata.id);
+        let connection = self.open_source_table(&SourceType::TextEmbedded).await?;
+        let transaction = connection.unchecked_transaction()?;
+        insert_sources_into(
+            &transaction,
+            &model_embeddings_table_name(model_id),
+            source_data,
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn find_model_embeddings(
+        &self,
+        model_id: Uuid,
+        hashes: &[String],
+    ) -> TuoResult<Vec<TextEmbedded>> {
+        self.get_registered_model(model_id)?;
+        let source_type = SourceType::TextEmbedded;
+        let connection = self.open_source_table(&source_type).await?;
+        let text_embedded = select_sources_in(
+            &connection,
+            &model_embeddings_table_name(model_id),
+            &source_type,
+            TextEmbeddedFieldName::Hash.name(),
+            hashes,
+            |filter| self.scoped_filter(&source_type, filter),
+        )?
+        .get_text_embedded()
+        .unwrap_or_default();
+        Ok(text_embedded)
+    }
+
+    async fn get_unembedded_nodes_of_model(&self, model_id: Uuid) -> TuoResult<Vec<Node>> {
+        if model_id == self.model.id {
+            return self.get_unembedded_nodes().await;
+        }
+        self.get_registered_model(model_id)?;
+        let connection = self.open_source_table(&SourceType::Node).await?;
+        let source_id = quote(TextEmbeddedFieldName::SourceId.name());
+        let filter = self.scoped_filter(
+            &SourceType::Node,
+            format!(
+                "{} NOT IN (SELECT {source_id} FROM {} WHERE {})",
+                quote(NodeFieldName::Id.name()),
+                model_embeddings_table_name(model_id),
+                self.scoped_filter(
+                    &SourceType::TextEmbedded,
+                    format!("{source_id} IS NOT NULL")
+                ),
+            ),
+        );
+        let nodes = select_sources(&connection, &SourceType::Node, &filter, "", [])?
+            .get_node()
+            .unwrap_or_default();
+        Ok(nodes)
+    }
+
+    fn get_dimension(&self) -> i32 {
+        self.model.dimensions
+    }
+
+    fn get_index_name(&self) -> String {
+        self.index_name.clone()
+    }
+
+    fn get_model(&self) -> EmbeddingModelMetadata {
+        self.model.clone()
+    }
+}
+
+#[derive(TypedBuilder)]
+pub struct SqliteIndexSearchOptions {
+    #[builder(default = 10)]
+    pub top_k: usize,
+    #[builder(default)]
+    pub mode: SqliteSearchMode,
+    #[builder(default)]
+    pub query_cache: QueryEmbeddingCache,
+    /// Search the node content embeddings of a [registered model](IndexTrait::get_registered_model), instead of the text embeddings of the model of the store.
+    #[builder(default)]
+    pub model_id: Option<Uuid>,
+}
+
+/// How the nearest neighbours are searched.
+#[derive(Debug, Clone, Default)]
+pub enum SqliteSearchMode {
+    /// Rank all text embeddings of the index by cosine distance.
+    #[default]
+    Exact,
+    /// Shortlist `top_k * refine_factor` candidates by the hamming distance between the sign bits of their vectors and of the query, then re-score the candidates by cosine distance.
+    ///
+    /// This is not an index: every text embedding of the index is still scanned, the hamming distance being computed for each signature.
+    /// It only saves reading the vectors and computing the cosine distances of the other text embeddings,
+    /// at the cost of possibly missing some neighbours; a larger `refine_factor` improves recall.
+    SignatureRescoring { refine_factor: usize },
+}
+
+impl SqliteIndex {
+    /// Add records to their table, assigning them to this index.
+    fn add_to_table(&self, mut source_data: SourceData) -> TuoResult<()> {
+        source_data.assign_index_id(self.index_metadata.id);
+        debug!(
+            "Adding {} records to {}",
+            source_data.get_ids().len(),
+            source_data.source_type().table_name()
+        );
+        let connection = connect(&self.store_metadata.uri, false)?;
+        insert_sources(&connection, source_data)?;
+        Ok(())
+    }
+
+    /// Statements deleting `count` documents, bound as [their parameters](column_in_filter), along with their records and text embeddings.
+    ///
+    /// The text embeddings go first, as they are found through the nodes and sections of the documents.
+    fn delete_documents_statements(
+        &self,
+        count: usize,
+        model_embeddings_tables: &[String],
+    ) -> Vec<String> {
+        let nodes_filter = self.scoped_filter(
+            &SourceType::Node,
+            column_in_filter(NodeFieldName::DocumentId.name(), count),
+        );
+        let sections_filter = self.scoped_filter(
+            &SourceType::Section,
+            column_in_filter(SectionFieldName::DocumentId.name(), count),
+        );
+        let other_nodes_filter = self.scoped_filter(
+            &SourceType::Node,
+            format!(
+                "NOT ({}) AND {} IS NOT NULL",
+                column_in_filter(NodeFieldName::DocumentId.name(), count),
+                quote(NodeFieldName::ContentEmbeddingsId.name())
+            ),
+        );
+        let id = quote(NodeFieldName::Id.name());
+        let source_id = quote(TextEmbeddedFieldName::SourceId.name());
+        let content_embeddings_id = quote(NodeFieldName::ContentEmbeddingsId.name());
+        let nodes = SourceType::Node.table_name();
+        let sections = SourceType::Section.table_name();
+        // the summaries and content embeddings of the records, and the content embeddings their nodes reuse,
+        // unless other nodes of the index use them too
+        let text_embedded_filter = self.scoped_filter(
+            &SourceType::TextEmbedded,
+            format!(
+                "({documents} OR {source_id} IN (SELECT {id} FROM {sections} WHERE {sections_filter}) \
+                OR {source_id} IN (SELECT {id} FROM {nodes} WHERE {nodes_filter}) \
+                OR {id} IN (SELECT {content_embeddings_id} FROM {nodes} WHERE {nodes_filter})) \
+                AND {id} NOT IN (SELECT {content_embeddings_id} FROM {nodes} WHERE {other_nodes_filter})",
+                documents = column_in_filter(TextEmbeddedFieldName::SourceId.name(), count),
+            ),
+        );
+        // the content embeddings of the registered models, including those not registered on this store instance
+        let model_embeddings_filter = self.scoped_filter(
+            &SourceType::TextEmbedded,
+            format!("{source_id} IN (SELECT {id} FROM {nodes} WHERE {nodes_filter})"),
+        );
+        let mut statements: Vec<String> = model_embeddings_tables
+            .iter()
+            .map(|table| format!("DELETE FROM {} WHERE {}", table, model_embeddings_filter))
+            .collect();
+        for (source_type, filter) in [
+            (SourceType::TextEmbedded, text_embedded_filter),
+            (SourceType::Node, nodes_filter),
+            (SourceType::Section, sections_filter),
+            (
+                SourceType::Document,
+                self.scoped_filter(&SourceType::Document, id_in_filter(count)),
+            ),
+        ] {
+            statements.push(format!(
+                "DELETE FROM {} WHERE {}",
+                source_type.table_name(),
+                filter
+            ));
+        }
+        statements
+    }
+
+    /// Filter matching the records of this index, if the source type is index-scoped.
+    ///
+    /// All indices share the same tables, so every query on index-scoped records must be scoped by `index_id`.
+    fn index_filter(&self, source_type: &SourceType) -> Option<String> {
+        source_type.is_index_scoped().then(|| {
+            format!(
+                "{} = '{}'",
+                quote(NodeFieldName::IndexId.name()),
+                self.index_metadata.id
+            )
+        })
+    }
+
+    /// Term statistics of the node contents of this index, computed again only once the version of the nodes changed.
+    fn keyword_corpus(&self, connection: &Connection) -> TuoResult<Arc<Bm25Corpus>> {
+        let version: i64 = connection.query_row(
+            format!("SELECT version FROM {}", node_version_table()).as_str(),
+            [],
+            |row| row.get(0),
+        )?;
+        if let Some((cached_version, corpus)) = self
+            .keyword_corpus
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            if *cached_version == version {
+                return Ok(corpus.clone());
+            }
+        }
+
+        // the contents only
+        let sql = format!(
+            "SELECT {id}, {content} FROM {table} WHERE {filter}",
+            id = quote(NodeFieldName::Id.name()),
+            content = quote(NodeFieldName::Content.name()),
+            table = quote(&SourceType::Node.table_name()),
+            filter = self
+                .index_filter(&SourceType::Node)
+                .unwrap_or_else(|| "1 = 1".to_string()),
+        );
+        let mut statement = connection.prepare(&sql)?;
+        let contents = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let corpus = Arc::new(Bm25Corpus::new(contents.iter().filter_map(
+            |(id, content)| Some((Uuid::try_parse(id).ok()?, content.as_str())),
+        )));
+        *self
+            .keyword_corpus
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some((version, corpus.clone()));
+        Ok(corpus)
+    }
+
+    /// Restrict the filter to the records of this index, see [index_filter](SqliteIndex::index_filter).
+    fn scoped_filter(&self, source_type: &SourceType, filter: String) -> String {
+        match self.index_filter(source_type) {
+            Some(index_filter) => format!("{} AND ({})", index_filter, filter),
+            None => filter,
+        }
+    }
+}
+
+/// Maximum number of values bound to one query by an `IN` filter, well below the limit of parameters of SQLite.
+pub(crate) const SQLITE_IN_CHUNK_SIZE: usize = 500;
+
+/// Filter matching the records with one of `count` ids, see [column_in_filter].
+pub(crate) fn id_in_filter(count: usize) -> String {
+    column_in_filter(NodeFieldName::Id.name(), count)
+}
+
+/// Filter matching the text embeddings with one of `count` hashes, see [column_in_filter].
+fn hash_in_filter(count: usize) -> String {
+    column_in_filter(TextEmbeddedFieldName::Hash.name(), count)
+}
+
+/// Filter matching the records whose column is one of `count` values, bound as the first parameters of the query, `?1` to `?count`.
+///
+/// The parameters of the rest of the query are numbered after them, see [in_params].
+pub(crate) fn column_in_filter(column: &str, count: usize) -> String {
+    format!(
+        "{} IN ({})",
+        quote(column),
+        (1..=count)
+            .map(|number| format!("?{}", number))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+/// Parameters of a query filtering on [one of the values](column_in_filter), followed by the other parameters of the query.
+pub(crate) fn in_params(
+    values: &[impl ToString],
+    others: Vec<Value>,
+) -> ParamsFromIter<Vec<Value>> {
+    params_from_iter(
+        values
+            .iter()
+            .map(|value| Value::Text(value.to_string()))
+            .chain(others)
+            .collect(),
+    )
+}
+
+/// Select the records of a table whose column is one of the values, [SQLITE_IN_CHUNK_SIZE] values at a time.
+///
+/// `filter` makes the filter of each query from the [filter on the column](column_in_filter).
+pub(crate) fn select_sources_in(
+    connection: &Connection,
+    table: &str,
+    source_type: &SourceType,
+    column: &str,
+    values: &[impl ToString],
+    filter: impl Fn(String) -> String,
+) -> TuoResult<SourceData> {
+    let select = |values: &[_]| {
+        select_sources_from(
+            connection,
+            table,
+            source_type,
+            &filter(column_in_filter(column, values.len())),
+            "",
+            in_params(values, vec![]),
+        )
+    };
+    let mut chunks = values.chunks(SQLITE_IN_CHUNK_SIZE);
+    // the first query also gives the empty records of the source type if there are no values
+    let mut source_data = select(chunks.next().unwrap_or_default())?;
+    for chunk in chunks {
+        source_data.extend(select(chunk)?);
+    }
+    Ok(source_data)
+}
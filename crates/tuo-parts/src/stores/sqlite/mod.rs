@@ -0,0 +1,3 @@
+pub mod index;
+mod schema;
+pub mod store;
@@ -0,0 +1,776 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{Type, Value};
+use rusqlite::{params_from_iter, Connection, OpenFlags, Params, Row};
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index_metadata::{IndexMetadata, IndexMetadataFieldName};
+use tuo_core::core::messaging::content::{TextEmbedded, TextEmbeddedFieldName};
+use tuo_core::core::source::document::{Document, DocumentFieldName};
+use tuo_core::core::source::node::{Node, NodeFieldName};
+use tuo_core::core::source::section::{Section, SectionFieldName};
+use tuo_core::core::source::sources::{SourceData, SourceTableName, SourceType, SourceTypeTrait};
+use tuo_core::model::model_metadata::{EmbeddingModelMetadata, EmbeddingModelMetadataFieldName};
+use tuo_core::retrieval::similarity::cosine_distance;
+use tuo_core::storage::store_metadata::{StoreMetadata, StoreMetadataFieldName};
+use tuo_core::storage::store_models::{
+    model_embeddings_table_model_id, model_embeddings_table_name,
+};
+use tuo_core::types::date_time::TuoDateTime;
+use tuo_shared::consts::defaults::D_TABLE_COLUMN_NAME_VECTOR;
+use tuo_utils::datetime::timestamp::utc_from_epoch;
+
+/// Column of the text embeddings table holding the sign bits of the vector, used by the [signature rescoring](crate::stores::sqlite::index::SqliteSearchMode::SignatureRescoring) search.
+pub(crate) const SQLITE_COLUMN_NAME_VECTOR_SIGNATURE: &str = "vector_signature";
+
+/// SQL function computing the cosine distance between two vector blobs.
+pub(crate) const SQLITE_FUNCTION_COSINE_DISTANCE: &str = "cosine_distance";
+
+/// SQL function computing the hamming distance between two vector signatures.
+pub(crate) const SQLITE_FUNCTION_HAMMING_DISTANCE: &str = "hamming_distance";
+
+/// Open a connection to the database file, registering the vector functions.
+///
+/// The file is only created if `create` is set, so that opening a missing store fails.
+pub(crate) fn connect(uri: &str, create: bool) -> rusqlite::Result<Connection> {
+    let mut flags = OpenFlags::SQLITE_OPEN_READ_WRITE
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if create {
+        flags |= OpenFlags::SQLITE_OPEN_CREATE;
+    }
+    let connection = Connection::open_with_flags(uri, flags)?;
+    register_functions(&connection)?;
+    Ok(connection)
+}
+
+fn register_functions(connection: &Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    connection.create_scalar_function(SQLITE_FUNCTION_COSINE_DISTANCE, 2, flags, |ctx| {
+        let a = ctx.get::<Option<Vec<u8>>>(0)?;
+        let b = ctx.get::<Option<Vec<u8>>>(1)?;
+        Ok(match (a, b) {
+            (Some(a), Some(b)) => {
+                Some(cosine_distance(&blob_to_vector(&a), &blob_to_vector(&b)) as f64)
+            }
+            _ => None,
+        })
+    })?;
+    connection.create_scalar_function(SQLITE_FUNCTION_HAMMING_DISTANCE, 2, flags, |ctx| {
+        let a = ctx.get::<Option<Vec<u8>>>(0)?;
+        let b = ctx.get::<Option<Vec<u8>>>(1)?;
+        Ok(match (a, b) {
+            (Some(a), Some(b)) => Some(
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(x, y)| (x ^ y).count_ones() as i64)
+                    .sum::<i64>(),
+            ),
+            _ => None,
+        })
+    })?;
+    Ok(())
+}
+
+/// Encode a vector as a blob of little-endian f32.
+pub(crate) fn vector_to_blob(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+pub(crate) fn blob_to_vector(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+/// Sign bits of the vector, one bit per dimension.
+///
+/// The hamming distance between signatures approximates the angle between vectors.
+pub(crate) fn vector_signature(vector: &[f32]) -> Vec<u8> {
+    vector
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, value)| match *value > 0.0 {
+                    true => byte | (1 << bit),
+                    false => byte,
+                })
+        })
+        .collect()
+}
+
+/// Quote an identifier, since some column names such as `index` are SQL keywords.
+pub(crate) fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier)
+}
+
+/// Comma-separated, quoted column names of the table of the source type.
+pub(crate) fn select_columns(source_type: &SourceType) -> String {
+    columns(source_type)
+        .iter()
+        .map(|(name, _)| quote(name))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Columns of the table of each source type, as `(name, SQL type)`.
+pub(crate) fn columns(source_type: &SourceType) -> Vec<(&'static str, &'static str)> {
+    match source_type {
+        SourceType::StoreMetadata => vec![
+            (StoreMetadataFieldName::Id.name(), "TEXT NOT NULL"),
+            (StoreMetadataFieldName::Name.name(), "TEXT NOT NULL"),
+            (StoreMetadataFieldName::CreatedAt.name(), "INTEGER NOT NULL"),
+            (StoreMetadataFieldName::ModelId.name(), "TEXT"),
+            (StoreMetadataFieldName::Uri.name(), "TEXT NOT NULL"),
+        ],
+        SourceType::ModelMetadata => vec![
+            (EmbeddingModelMetadataFieldName::Id.name(), "TEXT NOT NULL"),
+            (
+                EmbeddingModelMetadataFieldName::Name.name(),
+                "TEXT NOT NULL",
+            ),
+            (
+                EmbeddingModelMetadataFieldName::Author.name(),
+                "TEXT NOT NULL",
+            ),
+            (EmbeddingModelMetadataFieldName::Description.name(), "TEXT"),
+            (EmbeddingModelMetadataFieldName::Url.name(), "TEXT NOT NULL"),
+            (
+                EmbeddingModelMetadataFieldName::AccessedAt.name(),
+                "INTEGER NOT NULL",
+            ),
+            (
+                EmbeddingModelMetadataFieldName::Dimensions.name(),
+                "INTEGER NOT NULL",
+            ),
+            (
+                EmbeddingModelMetadataFieldName::MaxInput.name(),
+                "INTEGER NOT NULL",
+            ),
+            (
+                EmbeddingModelMetadataFieldName::PricingPer1kTokens.name(),
+                "REAL NOT NULL",
+            ),
+            (
+                EmbeddingModelMetadataFieldName::PricingUpdateAt.name(),
+                "INTEGER NOT NULL",
+            ),
+        ],
+        SourceType::IndexMetadata => vec![
+            (IndexMetadataFieldName::Id.name(), "TEXT NOT NULL"),
+            (IndexMetadataFieldName::Name.name(), "TEXT NOT NULL"),
+            (IndexMetadataFieldName::Description.name(), "TEXT"),
+            (
+                IndexMetadataFieldName::DocumentCount.name(),
+                "INTEGER NOT NULL",
+            ),
+            (IndexMetadataFieldName::CreatedAt.name(), "INTEGER NOT NULL"),
+            (IndexMetadataFieldName::UpdatedAt.name(), "INTEGER NOT NULL"),
+        ],
+        SourceType::TextEmbedded => vec![
+            (TextEmbeddedFieldName::Id.name(), "TEXT NOT NULL"),
+            (TextEmbeddedFieldName::Text.name(), "TEXT"),
+            (TextEmbeddedFieldName::Hash.name(), "TEXT NOT NULL"),
+            (
+                TextEmbeddedFieldName::EmbeddingModel.name(),
+                "TEXT NOT NULL",
+            ),
+            (TextEmbeddedFieldName::CreatedAt.name(), "INTEGER NOT NULL"),
+            (D_TABLE_COLUMN_NAME_VECTOR, "BLOB"),
+            (SQLITE_COLUMN_NAME_VECTOR_SIGNATURE, "BLOB"),
+            (TextEmbeddedFieldName::EmbeddedAt.name(), "INTEGER NOT NULL"),
+            (TextEmbeddedFieldName::UsedAt.name(), "INTEGER NOT NULL"),
+            (TextEmbeddedFieldName::SourceType.name(), "TEXT NOT NULL"),
+            (TextEmbeddedFieldName::SourceId.name(), "TEXT"),
+            (TextEmbeddedFieldName::IndexId.name(), "TEXT"),
+        ],
+        SourceType::Document => vec![
+            (DocumentFieldName::Id.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::IndexId.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::Name.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::DocumentType.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::RawContent.name(), "TEXT"),
+            (DocumentFieldName::SourceType.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::SourceUri.name(), "TEXT NOT NULL"),
+            (DocumentFieldName::SummaryTextId.name(), "TEXT"),
+            (DocumentFieldName::ContentHash.name(), "TEXT"),
+        ],
+        SourceType::Section => vec![
+            (SectionFieldName::Id.name(), "TEXT NOT NULL"),
+            (SectionFieldName::IndexId.name(), "TEXT NOT NULL"),
+            (SectionFieldName::DocumentId.name(), "TEXT NOT NULL"),
+            (SectionFieldName::Name.name(), "TEXT NOT NULL"),
+            (SectionFieldName::SectionOrder.name(), "INTEGER NOT NULL"),
+            (SectionFieldName::SectionLevel.name(), "INTEGER NOT NULL"),
+            (SectionFieldName::Content.name(), "TEXT"),
+            (SectionFieldName::StartCharIndex.name(), "INTEGER"),
+            (SectionFieldName::EndCharIndex.name(), "INTEGER"),
+            (SectionFieldName::SummaryTextId.name(), "TEXT"),
+        ],
+        SourceType::Node => vec![
+            (NodeFieldName::Id.name(), "TEXT NOT NULL"),
+            (NodeFieldName::IndexId.name(), "TEXT NOT NULL"),
+            (NodeFieldName::DocumentId.name(), "TEXT NOT NULL"),
+            (NodeFieldName::SectionId.name(), "TEXT NOT NULL"),
+            (NodeFieldName::Content.name(), "TEXT NOT NULL"),
+            (NodeFieldName::ContentEmbeddingsId.name(), "TEXT"),
+            (NodeFieldName::ContentEmbeddedAt.name(), "INTEGER"),
+            (NodeFieldName::ContentType.name(), "TEXT NOT NULL"),
+            (NodeFieldName::Tokens.name(), "INTEGER NOT NULL"),
+            (NodeFieldName::Index.name(), "INTEGER NOT NULL"),
+            (NodeFieldName::StartCharIndex.name(), "INTEGER NOT NULL"),
+            (NodeFieldName::EndCharIndex.name(), "INTEGER NOT NULL"),
+        ],
+    }
+}
+
+/// All source types, one per table.
+pub(crate) fn all_source_types() -> Vec<SourceType> {
+    vec![
+        SourceType::StoreMetadata,
+        SourceType::ModelMetadata,
+        SourceType::IndexMetadata,
+        SourceType::TextEmbedded,
+        SourceType::Document,
+        SourceType::Section,
+        SourceType::Node,
+    ]
+}
+
+/// `CREATE TABLE` statements of all tables, keyed by table name, along with their lookup indices.
+pub(crate) fn get_all_schema() -> HashMap<String, String> {
+    all_source_types()
+        .iter()
+        .map(|source_type| (source_type.table_name(), table_schema(source_type)))
+        .collect()
+}
+
+/// `CREATE TABLE` statement of the table of the source type, along with its lookup indices.
+pub(crate) fn table_schema(source_type: &SourceType) -> String {
+    let table = source_type.table_name();
+    let mut statements = vec![
+        create_table_statement(source_type, &table),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {table}_id ON {table} ({});",
+            quote(NodeFieldName::Id.name())
+        ),
+    ];
+    if source_type.is_index_scoped() {
+        statements.push(format!(
+            "CREATE INDEX IF NOT EXISTS {table}_index_id ON {table} ({});",
+            quote(NodeFieldName::IndexId.name())
+        ));
+    }
+    if *source_type == SourceType::Node {
+        statements.push(node_version_schema());
+    }
+    statements.join("\n")
+}
+
+/// Table holding the version of the nodes, increased by every write to the node table.
+pub(crate) fn node_version_table() -> String {
+    format!("{}_version", SourceType::Node.table_name())
+}
+
+/// Statements creating the [node version table](node_version_table) and the triggers increasing the version on every write to the nodes.
+///
+/// The triggers are dropped with the node table, so the statements are applied again whenever the table is created, which increases the version as well.
+pub(crate) fn node_version_schema() -> String {
+    let table = SourceType::Node.table_name();
+    let version_table = node_version_table();
+    let bump = format!("UPDATE {version_table} SET version = version + 1;");
+    let mut statements = vec![
+        format!("CREATE TABLE IF NOT EXISTS {version_table} (version INTEGER NOT NULL);"),
+        format!(
+            "INSERT INTO {version_table} SELECT 0 WHERE NOT EXISTS (SELECT 1 FROM {version_table});"
+        ),
+        bump.clone(),
+    ];
+    for event in ["INSERT", "UPDATE", "DELETE"] {
+        statements.push(format!(
+            "CREATE TRIGGER IF NOT EXISTS {table}_{}_version AFTER {event} ON {table} BEGIN {bump} END;",
+            event.to_lowercase()
+        ));
+    }
+    statements.join("\n")
+}
+
+/// `CREATE TABLE` statement of a table with the columns of the source type, without lookup indices.
+pub(crate) fn create_table_statement(source_type: &SourceType, table: &str) -> String {
+    let column_definitions = columns(source_type)
+        .iter()
+        .map(|(name, sql_type)| format!("{} {}", quote(name), sql_type))
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} ({});",
+        table, column_definitions
+    )
+}
+
+/// `CREATE TABLE` statement of the table of the node content embeddings of a registered model, along with its lookup indices.
+pub(crate) fn model_embeddings_table_schema(model_id: Uuid) -> String {
+    let table = model_embeddings_table_name(model_id);
+    [
+        create_table_statement(&SourceType::TextEmbedded, &table),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {table}_index_id ON {table} ({});",
+            quote(NodeFieldName::IndexId.name())
+        ),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {table}_source_id ON {table} ({});",
+            quote(TextEmbeddedFieldName::SourceId.name())
+        ),
+    ]
+    .join("\n")
+}
+
+/// Names of the tables of the node content embeddings of the registered models.
+pub(crate) fn model_embeddings_tables(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let table_names: Vec<String> = connection
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(table_names
+        .into_iter()
+        .filter(|table_name| model_embeddings_table_model_id(table_name).is_some())
+        .collect())
+}
+
+/// Add the nullable columns missing from the tables, and the [node version](node_version_schema), e.g. of a store created by an older version of the library.
+///
+/// Columns that cannot be null have no value for the existing rows, so they are left to the [health check](tuo_core::storage::store::StoreTrait::check_health) to report.
+pub(crate) fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+    for source_type in all_source_types() {
+        let table = source_type.table_name();
+        let table_columns: HashSet<String> = connection
+            .prepare(format!("PRAGMA table_info({})", table).as_str())?
+            .query_map([], |row| row.get("name"))?
+            .collect::<rusqlite::Result<_>>()?;
+        // missing tables are not created either
+        if table_columns.is_empty() {
+            continue;
+        }
+        for (column, sql_type) in columns(&source_type) {
+            if !table_columns.contains(column) && !sql_type.contains("NOT NULL") {
+                connection.execute_batch(
+                    format!(
+                        "ALTER TABLE {} ADD COLUMN {} {};",
+                        table,
+                        quote(column),
+                        sql_type
+                    )
+                    .as_str(),
+                )?;
+            }
+        }
+        if source_type == SourceType::Node {
+            connection.execute_batch(&node_version_schema())?;
+        }
+    }
+    Ok(())
+}
+
+/// Insert the records into their table in a single transaction.
+pub(crate) fn insert_sources(
+    connection: &Connection,
+    source_data: SourceData,
+) -> rusqlite::Result<()> {
+    let table = source_data.source_type().table_name();
+    let transaction = connection.unchecked_transaction()?;
+    insert_sources_into(&transaction, &table, source_data)?;
+    transaction.commit()
+}
+
+/// Insert the records into a table with the columns of their source type, e.g. a staging table.
+///
+/// Unlike [insert_sources], no transaction is started, so that the caller can group several inserts.
+pub(crate) fn insert_sources_into(
+    connection: &Connection,
+    table: &str,
+    source_data: SourceData,
+) -> rusqlite::Result<()> {
+    let source_type = match &source_data {
+        SourceData::StoreMetadata(_) => SourceType::StoreMetadata,
+        SourceData::ModelMetadata(_) => SourceType::ModelMetadata,
+        SourceData::IndexMetadata(_) => SourceType::IndexMetadata,
+        SourceData::TextEmbedded(_) => SourceType::TextEmbedded,
+        SourceData::Document(_) => SourceType::Document,
+        SourceData::Section(_) => SourceType::Section,
+        SourceData::Node(_) => SourceType::Node,
+    };
+    let rows: Vec<Vec<Value>> = match source_data {
+        SourceData::StoreMetadata(data) => data.iter().map(store_metadata_values).collect(),
+        SourceData::ModelMetadata(data) => data.iter().map(model_metadata_values).collect(),
+        SourceData::IndexMetadata(data) => data.iter().map(index_metadata_values).collect(),
+        SourceData::TextEmbedded(data) => data.iter().map(text_embedded_values).collect(),
+        SourceData::Document(data) => data.iter().map(document_values).collect(),
+        SourceData::Section(data) => data.iter().map(section_values).collect(),
+        SourceData::Node(data) => data.iter().map(node_values).collect(),
+    };
+    let columns = columns(&source_type);
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        select_columns(&source_type),
+        (1..=columns.len())
+            .map(|position| format!("?{}", position))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let mut statement = connection.prepare_cached(&sql)?;
+    for row in rows {
+        statement.execute(params_from_iter(row))?;
+    }
+    Ok(())
+}
+
+/// Select the records of the source type matching the `WHERE` clause, followed by an optional `ORDER BY`/`LIMIT` suffix.
+pub(crate) fn select_sources<P: Params>(
+    connection: &Connection,
+    source_type: &SourceType,
+    filter: &str,
+    suffix: &str,
+    params: P,
+) -> rusqlite::Result<SourceData> {
+    select_sources_from(
+        connection,
+        &source_type.table_name(),
+        source_type,
+        filter,
+        suffix,
+        params,
+    )
+}
+
+/// Select the records of a table with the columns of the source type, e.g. a staging table, see [select_sources].
+pub(crate) fn select_sources_from<P: Params>(
+    connection: &Connection,
+    table: &str,
+    source_type: &SourceType,
+    filter: &str,
+    suffix: &str,
+    params: P,
+) -> rusqlite::Result<SourceData> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} {}",
+        select_columns(source_type),
+        table,
+        filter,
+        suffix
+    );
+    let mut statement = connection.prepare(&sql)?;
+    let rows = statement.query(params)?;
+    convert_rows_to_sources(rows, source_type)
+}
+
+/// Convert the rows of a query selecting the [columns](select_columns) of the source type.
+pub(crate) fn convert_rows_to_sources(
+    rows: rusqlite::Rows,
+    source_type: &SourceType,
+) -> rusqlite::Result<SourceData> {
+    Ok(match source_type {
+        SourceType::StoreMetadata => {
+            SourceData::StoreMetadata(rows.mapped(row_to_store_metadata).try_collect()?)
+        }
+        SourceType::ModelMetadata => {
+            SourceData::ModelMetadata(rows.mapped(row_to_model_metadata).try_collect()?)
+        }
+        SourceType::IndexMetadata => {
+            SourceData::IndexMetadata(rows.mapped(row_to_index_metadata).try_collect()?)
+        }
+        SourceType::TextEmbedded => {
+            SourceData::TextEmbedded(rows.mapped(row_to_text_embedded).try_collect()?)
+        }
+        SourceType::Document => SourceData::Document(rows.mapped(row_to_document).try_collect()?),
+        SourceType::Section => SourceData::Section(rows.mapped(row_to_section).try_collect()?),
+        SourceType::Node => SourceData::Node(rows.mapped(row_to_node).try_collect()?),
+    })
+}
+
+fn uuid_value(id: &Uuid) -> Value {
+    Value::Text(id.to_string())
+}
+
+fn optional_uuid_value(id: Option<Uuid>) -> Value {
+    id.map(|id| uuid_value(&id)).unwrap_or(Value::Null)
+}
+
+fn text_value(text: &str) -> Value {
+    Value::Text(text.to_string())
+}
+
+fn optional_text_value(text: &Option<String>) -> Value {
+    text.clone().map(Value::Text).unwrap_or(Value::Null)
+}
+
+fn date_time_value(date_time: &TuoDateTime) -> Value {
+    Value::Integer(date_time.timestamp())
+}
+
+fn get_uuid(row: &Row, column: &str) -> rusqlite::Result<Uuid> {
+    let index = row.as_ref().column_index(column)?;
+    let text: String = row.get(index)?;
+    Uuid::try_parse(&text)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+fn get_optional_uuid(row: &Row, column: &str) -> rusqlite::Result<Option<Uuid>> {
+    let index = row.as_ref().column_index(column)?;
+    let text: Option<String> = row.get(index)?;
+    text.map(|text| {
+        Uuid::try_parse(&text).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err))
+        })
+    })
+    .transpose()
+}
+
+fn get_date_time(row: &Row, column: &str) -> rusqlite::Result<TuoDateTime> {
+    Ok(utc_from_epoch(row.get(column)?))
+}
+
+fn get_enum<T: FromStr>(row: &Row, column: &str) -> rusqlite::Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let index = row.as_ref().column_index(column)?;
+    let text: String = row.get(index)?;
+    T::from_str(&text)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+fn store_metadata_values(data: &StoreMetadata) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        text_value(&data.name),
+        date_time_value(&data.created_at),
+        optional_uuid_value(data.model_id),
+        text_value(&data.uri),
+    ]
+}
+
+/// The model is not part of the row, only its id, see [StoreMetadata].
+fn row_to_store_metadata(row: &Row) -> rusqlite::Result<StoreMetadata> {
+    Ok(StoreMetadata {
+        id: get_uuid(row, StoreMetadataFieldName::Id.name())?,
+        name: row.get(StoreMetadataFieldName::Name.name())?,
+        created_at: get_date_time(row, StoreMetadataFieldName::CreatedAt.name())?,
+        model: None,
+        model_id: get_optional_uuid(row, StoreMetadataFieldName::ModelId.name())?,
+        uri: row.get(StoreMetadataFieldName::Uri.name())?,
+    })
+}
+
+fn model_metadata_values(data: &EmbeddingModelMetadata) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        text_value(&data.name),
+        text_value(&data.author),
+        optional_text_value(&data.description),
+        text_value(&data.url),
+        date_time_value(&data.accessed_at),
+        Value::Integer(data.dimensions as i64),
+        Value::Integer(data.max_input as i64),
+        Value::Real(data.pricing_per_1k_tokens as f64),
+        date_time_value(&data.pricing_update_at),
+    ]
+}
+
+fn row_to_model_metadata(row: &Row) -> rusqlite::Result<EmbeddingModelMetadata> {
+    Ok(EmbeddingModelMetadata {
+        id: get_uuid(row, EmbeddingModelMetadataFieldName::Id.name())?,
+        name: row.get(EmbeddingModelMetadataFieldName::Name.name())?,
+        author: row.get(EmbeddingModelMetadataFieldName::Author.name())?,
+        description: row.get(EmbeddingModelMetadataFieldName::Description.name())?,
+        url: row.get(EmbeddingModelMetadataFieldName::Url.name())?,
+        accessed_at: get_date_time(row, EmbeddingModelMetadataFieldName::AccessedAt.name())?,
+        dimensions: row.get(EmbeddingModelMetadataFieldName::Dimensions.name())?,
+        max_input: row.get(EmbeddingModelMetadataFieldName::MaxInput.name())?,
+        pricing_per_1k_tokens: row
+            .get::<_, f64>(EmbeddingModelMetadataFieldName::PricingPer1kTokens.name())?
+            as f32,
+        pricing_update_at: get_date_time(
+            row,
+            EmbeddingModelMetadataFieldName::PricingUpdateAt.name(),
+        )?,
+    })
+}
+
+fn index_metadata_values(data: &IndexMetadata) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        text_value(&data.name),
+        optional_text_value(&data.description),
+        Value::Integer(data.document_count as i64),
+        date_time_value(&data.created_at),
+        date_time_value(&data.updated_at),
+    ]
+}
+
+fn row_to_index_metadata(row: &Row) -> rusqlite::Result<IndexMetadata> {
+    Ok(IndexMetadata {
+        id: get_uuid(row, IndexMetadataFieldName::Id.name())?,
+        name: row.get(IndexMetadataFieldName::Name.name())?,
+        description: row.get(IndexMetadataFieldName::Description.name())?,
+        document_count: row.get(IndexMetadataFieldName::DocumentCount.name())?,
+        created_at: get_date_time(row, IndexMetadataFieldName::CreatedAt.name())?,
+        updated_at: get_date_time(row, IndexMetadataFieldName::UpdatedAt.name())?,
+    })
+}
+
+fn text_embedded_values(data: &TextEmbedded) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        optional_text_value(&data.text),
+        text_value(&data.hash),
+        text_value(&data.embedding_model),
+        date_time_value(&data.created_at),
+        Value::Blob(vector_to_blob(&data.embeddings)),
+        Value::Blob(vector_signature(&data.embeddings)),
+        date_time_value(&data.embedded_at),
+        date_time_value(&data.used_at),
+        text_value(data.source_type.as_ref()),
+        optional_uuid_value(data.source_id),
+        optional_uuid_value(data.index_id),
+    ]
+}
+
+pub(crate) fn row_to_text_embedded(row: &Row) -> rusqlite::Result<TextEmbedded> {
+    let vector: Option<Vec<u8>> = row.get(D_TABLE_COLUMN_NAME_VECTOR)?;
+    Ok(TextEmbedded {
+        id: get_uuid(row, TextEmbeddedFieldName::Id.name())?,
+        text: row.get(TextEmbeddedFieldName::Text.name())?,
+        hash: row.get(TextEmbeddedFieldName::Hash.name())?,
+        embedding_model: row.get(TextEmbeddedFieldName::EmbeddingModel.name())?,
+        created_at: get_date_time(row, TextEmbeddedFieldName::CreatedAt.name())?,
+        embeddings: vector.map(|blob| blob_to_vector(&blob)).unwrap_or_default(),
+        embedded_at: get_date_time(row, TextEmbeddedFieldName::EmbeddedAt.name())?,
+        used_at: get_date_time(row, TextEmbeddedFieldName::UsedAt.name())?,
+        source_type: get_enum(row, TextEmbeddedFieldName::SourceType.name())?,
+        source_id: get_optional_uuid(row, TextEmbeddedFieldName::SourceId.name())?,
+        index_id: get_optional_uuid(row, TextEmbeddedFieldName::IndexId.name())?,
+    })
+}
+
+fn document_values(data: &Document) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        uuid_value(&data.index_id),
+        text_value(&data.name),
+        text_value(data.document_type.as_ref()),
+        optional_text_value(&data.raw_content),
+        text_value(data.source_type.as_ref()),
+        text_value(&data.source_uri),
+        optional_uuid_value(
+            data.summary
+                .as_ref()
+                .map(|summary| summary.id)
+                .or(data.summary_text_id),
+        ),
+        optional_text_value(&data.content_hash),
+    ]
+}
+
+fn row_to_document(row: &Row) -> rusqlite::Result<Document> {
+    Ok(Document {
+        id: get_uuid(row, DocumentFieldName::Id.name())?,
+        index_id: get_uuid(row, DocumentFieldName::IndexId.name())?,
+        name: row.get(DocumentFieldName::Name.name())?,
+        document_type: get_enum(row, DocumentFieldName::DocumentType.name())?,
+        raw_content: row.get(DocumentFieldName::RawContent.name())?,
+        source_type: get_enum(row, DocumentFieldName::SourceType.name())?,
+        source_uri: row.get(DocumentFieldName::SourceUri.name())?,
+        content_hash: row.get(DocumentFieldName::ContentHash.name())?,
+        summary: None,
+        summary_text_id: get_optional_uuid(row, DocumentFieldName::SummaryTextId.name())?,
+    })
+}
+
+fn section_values(data: &Section) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        uuid_value(&data.index_id),
+        uuid_value(&data.document_id),
+        text_value(&data.name),
+        Value::Integer(data.section_order as i64),
+        Value::Integer(data.section_level as i64),
+        optional_text_value(&data.content),
+        data.start_char_index
+            .map(|index| Value::Integer(index as i64))
+            .unwrap_or(Value::Null),
+        data.end_char_index
+            .map(|index| Value::Integer(index as i64))
+            .unwrap_or(Value::Null),
+        optional_uuid_value(
+            data.summary
+                .as_ref()
+                .map(|summary| summary.id)
+                .or(data.summary_text_id),
+        ),
+    ]
+}
+
+fn row_to_section(row: &Row) -> rusqlite::Result<Section> {
+    Ok(Section {
+        id: get_uuid(row, SectionFieldName::Id.name())?,
+        index_id: get_uuid(row, SectionFieldName::IndexId.name())?,
+        document_id: get_uuid(row, SectionFieldName::DocumentId.name())?,
+        name: row.get(SectionFieldName::Name.name())?,
+        section_order: row.get(SectionFieldName::SectionOrder.name())?,
+        section_level: row.get(SectionFieldName::SectionLevel.name())?,
+        content: row.get(SectionFieldName::Content.name())?,
+        start_char_index: row.get(SectionFieldName::StartCharIndex.name())?,
+        end_char_index: row.get(SectionFieldName::EndCharIndex.name())?,
+        summary: None,
+        summary_text_id: get_optional_uuid(row, SectionFieldName::SummaryTextId.name())?,
+    })
+}
+
+/// The content embeddings are not part of the row, only their id, see [Node].
+fn node_values(data: &Node) -> Vec<Value> {
+    vec![
+        uuid_value(&data.id),
+        uuid_value(&data.index_id),
+        uuid_value(&data.document_id),
+        uuid_value(&data.section_id),
+        text_value(&data.content),
+        optional_uuid_value(data.content_embeddings_id),
+        data.content_embedded_at
+            .as_ref()
+            .map(date_time_value)
+            .unwrap_or(Value::Null),
+        text_value(data.content_type.as_ref()),
+        Value::Integer(data.tokens as i64),
+        Value::Integer(data.index as i64),
+        Value::Integer(data.start_char_index as i64),
+        Value::Integer(data.end_char_index as i64),
+    ]
+}
+
+fn row_to_node(row: &Row) -> rusqlite::Result<Node> {
+    Ok(Node {
+        id: get_uuid(row, NodeFieldName::Id.name())?,
+        index_id: get_uuid(row, NodeFieldName::IndexId.name())?,
+        document_id: get_uuid(row, NodeFieldName::DocumentId.name())?,
+        section_id: get_uuid(row, NodeFieldName::SectionId.name())?,
+        content: row.get(NodeFieldName::Content.name())?,
+        content_type: get_enum(row, NodeFieldName::ContentType.name())?,
+        content_embeddings_id: get_optional_uuid(row, NodeFieldName::ContentEmbeddingsId.name())?,
+        content_embeddings: None,
+        content_embedded_at: row
+            .get::<_, Option<i64>>(NodeFieldName::ContentEmbeddedAt.name())?
+            .map(utc_from_epoch),
+        tokens: row.get(NodeFieldName::Tokens.name())?,
+        index: row.get(NodeFieldName::Index.name())?,
+        start_char_index: row.get(NodeFieldName::StartCharIndex.name())?,
+        end_char_index: row.get(NodeFieldName::EndCharIndex.name())?,
+    })
+}
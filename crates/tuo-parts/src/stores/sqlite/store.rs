@@ -0,0 +1,2111 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use rusqlite::{params, Connection, OptionalExtension};
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use tuo_core::core::indexing::index::IndexTrait;
+use tuo_core::core::indexing::index_metadata::{IndexMetadata, IndexMetadataFieldName};
+use tuo_core::core::messaging::content::{TextEmbedded, TextEmbeddedFieldName, TextSourceType};
+use tuo_core::core::source::document::DocumentFieldName;
+use tuo_core::core::source::node::{Node, NodeFieldName};
+use tuo_core::core::source::section::SectionFieldName;
+use tuo_core::core::source::sources::{SourceData, SourceTableName, SourceType};
+use tuo_core::embedding::embedder::EmbedderTrait;
+use tuo_core::model::model_metadata::{EmbeddingModelMetadata, EmbeddingModelMetadataFieldName};
+use tuo_core::storage::store::{IndexRemoveResult, StoreOpenOptions, StoreTrait};
+use tuo_core::storage::store_health::{StoreHealthIssue, StoreHealthReport};
+use tuo_core::storage::store_metadata::{StoreMetadata, StoreMetadataFieldName};
+use tuo_core::storage::store_migration::{
+    run_model_migration, ModelMigrationOptions, ModelMigrationReport, ModelMigrationStaging,
+};
+use tuo_core::storage::store_models::{
+    check_migration_model, model_embeddings_table_model_id, model_to_register, RegisteredModel,
+};
+use tuo_shared::consts::defaults::{D_TABLE_COLUMN_NAME_VECTOR, D_TABLE_NAME_SUFFIX_MIGRATION};
+use tuo_shared::errors::parts::TuoPartsError;
+use tuo_shared::types::return_type::TuoResult;
+
+use crate::stores::sqlite::index::{id_in_filter, in_params, SqliteIndex, SQLITE_IN_CHUNK_SIZE};
+use crate::stores::sqlite::schema::{
+    add_missing_columns, all_source_types, columns, connect, create_table_statement,
+    get_all_schema, insert_sources, insert_sources_into, model_embeddings_table_schema,
+    model_embeddings_tables, quote, select_sources, select_sources_from, table_schema,
+};
+
+/// A store keeping all its tables in a single SQLite database file.
+///
+/// Vectors are stored as blobs of little-endian f32, and searched with SQL functions registered on each connection,
+/// see [SqliteIndexSearchOptions](crate::stores::sqlite::index::SqliteIndexSearchOptions).
+#[derive(TypedBuilder)]
+pub struct SqliteStore {
+    pub store_metadata: StoreMetadata,
+    pub embedder: Arc<Box<dyn EmbedderTrait>>,
+    /// The models registered since the store was opened, keyed by model id.
+    #[builder(default)]
+    pub registered_models: HashMap<Uuid, RegisteredModel>,
+}
+
+impl SqliteStore {
+    pub fn connect(&self) -> TuoResult<Connection> {
+        Ok(connect(&self.get_store_uri(), false)?)
+    }
+
+    fn find_index(&self, column: &str, value: String) -> TuoResult<Option<IndexMetadata>> {
+        let connection = self.connect()?;
+        let index = select_sources(
+            &connection,
+            &SourceType::IndexMetadata,
+            &format!("{} = ?1", quote(column)),
+            "LIMIT 1",
+            params![value],
+        )?
+        .get_index_metadata()
+        .and_then(|indices| indices.into_iter().next());
+        Ok(index)
+    }
+
+    /// Check that the store metadata table holds exactly the row of this store, and that its model is the model of the embedder.
+    ///
+    /// On repair, the row of this store is re-inserted if missing, and rows of other stores are removed.
+    fn check_store_metadata(
+        &self,
+        connection: &Connection,
+        report: &mut StoreHealthReport,
+        repair: bool,
+    ) -> TuoResult<()> {
+        let embedder_model = self.embedder.get_model_metadata();
+        let rows = select_sources(connection, &SourceType::StoreMetadata, "1 = 1", "", [])?
+            .get_store_metadata()
+            .unwrap_or_default();
+        let own_row = rows.iter().find(|row| row.id == self.store_metadata.id);
+
+        if rows.len() != 1 || own_row.is_none() {
+            let issue = StoreHealthIssue::StoreMetadataCount { count: rows.len() };
+            if repair {
+                connection.execute(
+                    format!(
+                        "DELETE FROM {} WHERE {} != ?1",
+                        SourceType::StoreMetadata.table_name(),
+                        quote(StoreMetadataFieldName::Id.name())
+                    )
+                    .as_str(),
+                    params![self.store_metadata.id.to_string()],
+                )?;
+                if own_row.is_none() {
+                    insert_sources(
+                        connection,
+                        SourceData::StoreMetadata(vec![self.store_metadata.clone()]),
+                    )?;
+                }
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+
+        // prefer the persisted model, fall back to the model the store was created with
+        let model_id = own_row
+            .and_then(|row| row.model_id)
+            .or(self.store_metadata.model_id);
+        let persisted_model = match model_id {
+            Some(model_id) => select_model_metadata(connection, model_id)?,
+            None => None,
+        };
+        if let Some(store_model) = persisted_model.or(self.store_metadata.model.clone()) {
+            if store_model.name != embedder_model.name
+                || store_model.dimensions != embedder_model.dimensions
+            {
+                report.issues.push(StoreHealthIssue::ModelMismatch {
+                    store_model: store_model.name,
+                    store_dimensions: store_model.dimensions,
+                    embedder_model: embedder_model.name,
+                    embedder_dimensions: embedder_model.dimensions,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Find sections and nodes without a document, text embeddings without a source and text embeddings of registered models without a node, removing them on repair.
+    ///
+    /// Text embeddings only used by orphan nodes are orphans as well.
+    fn check_orphans(
+        &self,
+        connection: &Connection,
+        report: &mut StoreHealthReport,
+        repair: bool,
+    ) -> TuoResult<()> {
+        let id = quote(NodeFieldName::Id.name());
+        let document_ids = format!(
+            "SELECT {} FROM {}",
+            quote(DocumentFieldName::Id.name()),
+            SourceType::Document.table_name()
+        );
+        let orphan_node_filter = format!(
+            "{} NOT IN ({})",
+            quote(NodeFieldName::DocumentId.name()),
+            document_ids
+        );
+        let node_ids = format!(
+            "SELECT {} FROM {} WHERE NOT ({})",
+            id,
+            SourceType::Node.table_name(),
+            orphan_node_filter
+        );
+        // nodes with the same content share their embeddings, which they reference by id
+        let content_embeddings_id = quote(NodeFieldName::ContentEmbeddingsId.name());
+        let node_embeddings_ids = format!(
+            "SELECT {} FROM {} WHERE {} IS NOT NULL AND NOT ({})",
+            content_embeddings_id,
+            SourceType::Node.table_name(),
+            content_embeddings_id,
+            orphan_node_filter
+        );
+        let orphan_section_filter = format!(
+            "{} NOT IN ({})",
+            quote(SectionFieldName::DocumentId.name()),
+            document_ids
+        );
+        let section_ids = format!(
+            "SELECT {} FROM {} WHERE NOT ({})",
+            id,
+            SourceType::Section.table_name(),
+            orphan_section_filter
+        );
+        let source_type = quote(TextEmbeddedFieldName::SourceType.name());
+        let source_id = quote(TextEmbeddedFieldName::SourceId.name());
+        let has_source = |text_source_types: &[TextSourceType], source_ids: &str| {
+            format!(
+                "({} IN ({}) AND {} IS NOT NULL AND {} IN ({}))",
+                source_type,
+                text_source_types
+                    .iter()
+                    .map(|text_source_type| format!("'{}'", text_source_type.as_ref()))
+                    .collect::<Vec<String>>()
+                    .join(","),
+                source_id,
+                source_id,
+                source_ids
+            )
+        };
+        let orphan_text_embedded_filter = format!(
+            "{} != '{}' AND NOT ({} OR {} OR {} OR ({} = '{}' AND {} IN ({})))",
+            source_type,
+            TextSourceType::UserQuery.as_ref(),
+            has_source(&[TextSourceType::SummaryDocument], &document_ids),
+            has_source(&[TextSourceType::SummarySection], &section_ids),
+            has_source(&[TextSourceType::SummaryNode], &node_ids),
+            source_type,
+            TextSourceType::NodeContent.as_ref(),
+            id,
+            node_embeddings_ids
+        );
+
+        // the text embeddings of a registered model reference their node by their source
+        let orphan_model_embeddings_filter = format!(
+            "{} IS NULL OR {} NOT IN ({})",
+            source_id, source_id, node_ids
+        );
+
+        // text embeddings first, as their orphan filters depend on the nodes and sections
+        let mut orphans = vec![(
+            SourceType::TextEmbedded,
+            None,
+            SourceType::TextEmbedded.table_name(),
+            orphan_text_embedded_filter,
+        )];
+        for table in model_embeddings_tables(connection)? {
+            orphans.push((
+                SourceType::TextEmbedded,
+                model_embeddings_table_model_id(&table),
+                table,
+                orphan_model_embeddings_filter.clone(),
+            ));
+        }
+        orphans.push((
+            SourceType::Node,
+            None,
+            SourceType::Node.table_name(),
+            orphan_node_filter,
+        ));
+        orphans.push((
+            SourceType::Section,
+            None,
+            SourceType::Section.table_name(),
+            orphan_section_filter,
+        ));
+        for (source_type, model_id, table, filter) in orphans {
+            let orphan_ids: Vec<Uuid> = connection
+                .prepare(format!("SELECT {} FROM {} WHERE {}", id, table, filter).as_str())?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .filter_map(|id| id.ok().and_then(|id| Uuid::try_parse(&id).ok()))
+                .collect();
+            if orphan_ids.is_empty() {
+                continue;
+            }
+            let issue = match (source_type, model_id) {
+                (SourceType::Section, _) => StoreHealthIssue::OrphanSections {
+                    section_ids: orphan_ids,
+                },
+                (SourceType::Node, _) => StoreHealthIssue::OrphanNodes {
+                    node_ids: orphan_ids,
+                },
+                (_, Some(model_id)) => StoreHealthIssue::OrphanModelEmbeddings {
+                    model_id,
+                    text_embedded_ids: orphan_ids,
+                },
+                _ => StoreHealthIssue::OrphanTextEmbedded {
+                    text_embedded_ids: orphan_ids,
+                },
+            };
+            if repair {
+                connection.execute(
+                    format!("DELETE FROM {} WHERE {}", table, filter).as_str(),
+                    [],
+                )?;
+                report.repaired.push(issue.clone());
+            }
+            report.issues.push(issue);
+        }
+        Ok(())
+    }
+}
+
+/// Staging tables of a [model migration](StoreTrait::migrate_model), named after the tables they replace with the [D_TABLE_NAME_SUFFIX_MIGRATION] suffix.
+///
+/// The model migrated to is kept in a staging model metadata table, so that a migration is only resumed with the same model.
+struct SqliteMigrationStaging {
+    /// Metadata of the store, switched to the model migrated to.
+    store_metadata: StoreMetadata,
+    model: EmbeddingModelMetadata,
+    /// The model migrated from, whose record is replaced.
+    previous_model_id: Option<Uuid>,
+}
+
+fn staging_table(source_type: &SourceType) -> String {
+    format!(
+        "{}{}",
+        source_type.table_name(),
+        D_TABLE_NAME_SUFFIX_MIGRATION
+    )
+}
+
+impl SqliteMigrationStaging {
+    fn connect(&self) -> TuoResult<Connection> {
+        Ok(connect(&self.store_metadata.uri, false)?)
+    }
+
+    /// Create the staging tables, dropping those of a migration to another model.
+    fn prepare(&self) -> TuoResult<()> {
+        let connection = self.connect()?;
+        let model_table = staging_table(&SourceType::ModelMetadata);
+        connection.execute_batch(&create_table_statement(
+            &SourceType::ModelMetadata,
+            &model_table,
+        ))?;
+        let staged_model = select_sources_from(
+            &connection,
+            &model_table,
+            &SourceType::ModelMetadata,
+            "1 = 1",
+            "LIMIT 1",
+            [],
+        )?
+        .get_model_metadata()
+        .and_then(|models| models.into_iter().next());
+        let transaction = connection.unchecked_transaction()?;
+        if !staged_model.as_ref().is_some_and(|staged_model| {
+            staged_model.name == self.model.name && staged_model.dimensions == self.model.dimensions
+        }) {
+            for source_type in [SourceType::TextEmbedded, SourceType::Node] {
+                transaction.execute_batch(&format!(
+                    "DROP TABLE IF EXISTS {};",
+                    staging_table(&source_type)
+                ))?;
+            }
+            transaction.execute(format!("DELETE FROM {}", model_table).as_str(), [])?;
+            insert_sources_into(
+                &transaction,
+                &model_table,
+                SourceData::ModelMetadata(vec![self.model.clone()]),
+            )?;
+        }
+        for source_type in [SourceType::TextEmbedded, SourceType::Node] {
+            transaction.execute_batch(&create_table_statement(
+                &source_type,
+                &staging_table(&source_type),
+            ))?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn staged_sources(&self, source_type: &SourceType) -> TuoResult<SourceData> {
+        let connection = self.connect()?;
+        Ok(select_sources_from(
+            &connection,
+            &staging_table(source_type),
+            source_type,
+            "1 = 1",
+            "",
+            [],
+        )?)
+    }
+}
+
+#[async_trait]
+impl ModelMigrationStaging for SqliteMigrationStaging {
+    async fn staged_nodes(&self) -> TuoResult<Vec<Node>> {
+        Ok(self
+            .staged_sources(&SourceType::Node)?
+            .get_node()
+            .unwrap_or_default())
+    }
+
+    async fn staged_texts(&self) -> TuoResult<Vec<TextEmbedded>> {
+        Ok(self
+            .staged_sources(&SourceType::TextEmbedded)?
+            .get_text_embedded()
+            .unwrap_or_default())
+    }
+
+    async fn unstage(&self, node_ids: &[Uuid], text_ids: &[Uuid]) -> TuoResult<()> {
+        let connection = self.connect()?;
+        let transaction = connection.unchecked_transaction()?;
+        let node_table = staging_table(&SourceType::Node);
+        let text_table = staging_table(&SourceType::TextEmbedded);
+        for (table, ids) in [(&node_table, node_ids), (&text_table, text_ids)] {
+            for ids in ids.chunks(SQLITE_IN_CHUNK_SIZE) {
+                transaction.execute(
+                    format!("DELETE FROM {} WHERE {}", table, id_in_filter(ids.len())).as_str(),
+                    in_params(ids, vec![]),
+                )?;
+            }
+        }
+        // the content embeddings no staged node points to anymore
+        transaction.execute(
+            format!(
+                "DELETE FROM {} WHERE {} = ?1 AND {} NOT IN (SELECT {} FROM {} WHERE {} IS NOT NULL)",
+                text_table,
+                quote(TextEmbeddedFieldName::SourceType.name()),
+                quote(TextEmbeddedFieldName::Id.name()),
+                quote(NodeFieldName::ContentEmbeddingsId.name()),
+                node_table,
+                quote(NodeFieldName::ContentEmbeddingsId.name()),
+            )
+            .as_str(),
+            params![TextSourceType::NodeContent.as_ref()],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Nodes and their content embeddings are staged in a single transaction, so no content embeddings are staged without their nodes.
+    async fn stage_nodes(
+        &self,
+        nodes: Vec<Node>,
+        texts_embedded: Vec<TextEmbedded>,
+    ) -> TuoResult<()> {
+        let connection = self.connect()?;
+        let transaction = connection.unchecked_transaction()?;
+        insert_sources_into(
+            &transaction,
+            &staging_table(&SourceType::TextEmbedded),
+            SourceData::TextEmbedded(texts_embedded),
+        )?;
+        insert_sources_into(
+            &transaction,
+            &staging_table(&SourceType::Node),
+            SourceData::Node(nodes),
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    async fn stage_texts(&self, texts_embedded: Vec<TextEmbedded>) -> TuoResult<()> {
+        let connection = self.connect()?;
+        insert_sources_into(
+            &connection,
+            &staging_table(&SourceType::TextEmbedded),
+            SourceData::TextEmbedded(texts_embedded),
+        )?;
+        Ok(())
+    }
+
+    /// Swap the tables and switch the store metadata to the new model in a single transaction.
+    async fn commit(&self) -> TuoResult<()> {
+        let connection = self.connect()?;
+        let transaction = connection.unchecked_transaction()?;
+        for source_type in [SourceType::TextEmbedded, SourceType::Node] {
+            let table = source_type.table_name();
+            transaction.execute_batch(&format!(
+                "DROP TABLE {table};\nALTER TABLE {} RENAME TO {table};\n{}",
+                staging_table(&source_type),
+                table_schema(&source_type),
+            ))?;
+        }
+        insert_model_metadata(&transaction, &self.model, self.previous_model_id)?;
+        transaction.execute_batch(&format!(
+            "DROP TABLE {};",
+            staging_table(&SourceType::ModelMetadata)
+        ))?;
+        transaction.execute(
+            format!(
+                "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+                SourceType::StoreMetadata.table_name(),
+                quote(StoreMetadataFieldName::ModelId.name()),
+                quote(StoreMetadataFieldName::Id.name())
+            )
+            .as_str(),
+            params![
+                self.store_metadata.model_id.map(|id| id.to_string()),
+                self.store_metadata.id.to_string()
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Read the model recorded for a store, `None` for stores created before their model was recorded.
+fn select_model_metadata(
+    connection: &Connection,
+    model_id: Uuid,
+) -> TuoResult<Option<EmbeddingModelMetadata>> {
+    Ok(select_sources(
+        connection,
+        &SourceType::ModelMetadata,
+        &format!("{} = ?1", quote(EmbeddingModelMetadataFieldName::Id.name())),
+        "LIMIT 1",
+        params![model_id.to_string()],
+    )?
+    .get_model_metadata()
+    .and_then(|models| models.into_iter().next()))
+}
+
+/// Record a model of the store, replacing the model of the store recorded before, if any.
+///
+/// The registered models stay recorded.
+/// No transaction is started, so that the model can be switched within the transaction of a [model migration](StoreTrait::migrate_model).
+fn insert_model_metadata(
+    connection: &Connection,
+    model: &EmbeddingModelMetadata,
+    replaced_model_id: Option<Uuid>,
+) -> TuoResult<()> {
+    let table = SourceType::ModelMetadata.table_name();
+    let replaced_ids: Vec<Uuid> = [Some(model.id), replaced_model_id]
+        .into_iter()
+        .flatten()
+        .collect();
+    connection.execute(
+        format!(
+            "DELETE FROM {} WHERE {}",
+            table,
+            id_in_filter(replaced_ids.len())
+        )
+        .as_str(),
+        in_params(&replaced_ids, vec![]),
+    )?;
+    insert_sources_into(
+        connection,
+        &table,
+        SourceData::ModelMetadata(vec![model.clone()]),
+    )?;
+    Ok(())
+}
+
+#[async_trait]
+impl StoreTrait for SqliteStore {
+    type IndexSchema = ();
+    type IndexType = SqliteIndex;
+
+    async fn create(
+        store_name: &str,
+        store_folder: &str,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<Self> {
+        let model_metadata = embedder.get_model_metadata();
+        let uri_path = PathBuf::from(store_folder).join(format!("{}.sqlite", store_name));
+        if uri_path.exists() {
+            return Err(TuoPartsError::StoreError(format!(
+                "Store {} already exists",
+                uri_path.display()
+            ))
+            .into());
+        }
+        let store_metadata = StoreMetadata::builder()
+            .name(store_name.to_string())
+            .model_id(Some(model_metadata.id))
+            .model(Some(model_metadata.clone()))
+            .uri(uri_path.to_str().unwrap().to_string())
+            .build();
+        std::fs::create_dir_all(store_folder)?;
+        // create the database file and its tables
+        let connection = connect(&store_metadata.uri, true)?;
+        let mut all_schema = get_all_schema().into_iter().collect::<Vec<_>>();
+        all_schema.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, schema) in all_schema {
+            connection.execute_batch(&schema)?;
+        }
+        insert_model_metadata(&connection, &model_metadata, None)?;
+        let instance = SqliteStore::builder()
+            .store_metadata(store_metadata.clone())
+            .embedder(Arc::new(embedder))
+            .build();
+        instance
+            .set_store_metadata(store_metadata, instance.get_store_model_dimensions())
+            .await?;
+        Ok(instance)
+    }
+
+    async fn open_with_options(
+        uri: &str,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: &StoreOpenOptions,
+    ) -> TuoResult<Self>
+    where
+        Self: Sized,
+    {
+        let model = embedder.get_model_metadata();
+        // upgrade the tables written by an older version of the library
+        let connection = connect(uri, false)?;
+        add_missing_columns(&connection)?;
+        let mut store_metadata = SqliteStore::load_store_metadata(uri, model.dimensions).await?;
+        if store_metadata.model.is_none() {
+            // the model of a store created before models were recorded is the model of the embedder
+            let model = EmbeddingModelMetadata {
+                id: store_metadata.model_id.unwrap_or(model.id),
+                ..model.clone()
+            };
+            insert_model_metadata(&connection, &model, None)?;
+            connection.execute(
+                format!(
+                    "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+                    SourceType::StoreMetadata.table_name(),
+                    quote(StoreMetadataFieldName::ModelId.name()),
+                    quote(StoreMetadataFieldName::Id.name())
+                )
+                .as_str(),
+                params![model.id.to_string(), store_metadata.id.to_string()],
+            )?;
+            store_metadata.model_id = Some(model.id);
+            store_metadata.model = Some(model);
+        }
+        if !opts.allow_model_mismatch {
+            store_metadata.check_model(&model)?;
+        }
+        Ok(SqliteStore::builder()
+            .store_metadata(store_metadata)
+            .embedder(Arc::new(embedder))
+            .build())
+    }
+
+    fn get_store_metadata(&self) -> StoreMetadata {
+        self.store_metadata.clone()
+    }
+
+    fn get_store_model_metadata(&self) -> EmbeddingModelMetadata {
+        self.get_store_metadata()
+            .model
+            .ok_or(TuoPartsError::StoreError(
+                "Store metadata does not have ModelMetadata set.".to_string(),
+            ))
+            .unwrap()
+    }
+
+    fn get_store_model_dimensions(&self) -> i32 {
+        self.get_store_model_metadata().dimensions
+    }
+
+    fn get_store_uri(&self) -> String {
+        self.get_store_metadata().uri.clone()
+    }
+
+    async fn load_store_metadata(uri: &str, _dimension: i32) -> TuoResult<StoreMetadata> {
+        let connection = connect(uri, false)?;
+        let store_metadata = select_sources(
+            &connection,
+            &SourceType::StoreMetadata,
+            "1 = 1",
+            "LIMIT 1",
+            [],
+        )?
+        .get_store_metadata()
+        .and_then(|rows| rows.into_iter().next());
+        let mut store_metadata = store_metadata.ok_or(TuoPartsError::StoreError(
+            "Cannot find store metadata in the store".to_string(),
+        ))?;
+        if let Some(model_id) = store_metadata.model_id {
+            store_metadata.model = select_model_metadata(&connection, model_id)?;
+        }
+        Ok(store_metadata)
+    }
+
+    async fn set_store_metadata(
+        &self,
+        store_metadata: StoreMetadata,
+        _dimension: i32,
+    ) -> TuoResult<()> {
+        let connection = self.connect()?;
+        insert_sources(&connection, SourceData::StoreMetadata(vec![store_metadata]))?;
+        Ok(())
+    }
+
+    async fn index_open(&self, index_name: &str) -> TuoResult<Self::IndexType> {
+        let index = self
+            .find_index(IndexMetadataFieldName::Name.name(), index_name.to_string())?
+            .ok_or(TuoPartsError::StoreError(format!(
+                "Index {} does not exist",
+                index_name
+            )))?;
+        Ok(SqliteIndex::builder()
+            .index_name(index_name.to_string())
+            .model(self.get_store_model_metadata())
+            .index_metadata(index)
+            .store_metadata(self.get_store_metadata())
+            .embedder(Some(self.embedder.clone()))
+            .registered_models(self.registered_models.clone())
+            .build())
+    }
+
+    async fn index_exists(&self, index_name: &str) -> TuoResult<bool> {
+        let connection = self.connect()?;
+        let exists = connection
+            .query_row(
+                format!(
+                    "SELECT 1 FROM {} WHERE {} = ?1 LIMIT 1",
+                    SourceType::IndexMetadata.table_name(),
+                    quote(IndexMetadataFieldName::Name.name())
+                )
+                .as_str(),
+                params![index_name],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(exists.is_some())
+    }
+
+    async fn list_indices(&self) -> TuoResult<Vec<IndexMetadata>> {
+        let connection = self.connect()?;
+        let indices = select_sources(&connection, &SourceType::IndexMetadata, "1 = 1", "", [])?
+            .get_index_metadata()
+            .unwrap_or_default();
+        Ok(indices)
+    }
+
+    async fn index_create(&self, name: &str) -> TuoResult<Self::IndexType> {
+        let index = IndexMetadata::builder().name(name.to_string()).build();
+        let connection = self.connect()?;
+        insert_sources(&connection, SourceData::IndexMetadata(vec![index]))?;
+        self.index_open(name).await
+    }
+
+    async fn index_count_records(
+        &self,
+        index_name: &str,
+        source_type: &SourceType,
+    ) -> TuoResult<usize> {
+        let index = self.index_open(index_name).await?;
+        let count = index.count_records(source_type).await?;
+        Ok(count)
+    }
+
+    /// Remove an index
+    ///
+    /// The records and the index metadata are removed in a single transaction.
+    async fn index_remove(&self, index_id: Uuid) -> TuoResult<IndexRemoveResult> {
+        if self
+            .find_index(IndexMetadataFieldName::Id.name(), index_id.to_string())?
+            .is_none()
+        {
+            return Err(
+                TuoPartsError::StoreError(format!("Index {} does not exist", index_id)).into(),
+            );
+        }
+        let connection = self.connect()?;
+        let transaction = connection.unchecked_transaction()?;
+        let records_filter = format!("{} = ?1", quote(NodeFieldName::IndexId.name()));
+        let mut removed = HashMap::new();
+        for source_type in [
+            SourceType::TextEmbedded,
+            SourceType::Node,
+            SourceType::Section,
+            SourceType::Document,
+        ] {
+            let count = transaction.execute(
+                format!(
+                    "DELETE FROM {} WHERE {}",
+                    source_type.table_name(),
+                    records_filter
+                )
+                .as_str(),
+                params![index_id.to_string()],
+            )?;
+            removed.insert(source_type, count);
+        }
+        // the text embeddings of the registered models are counted with the other text embeddings
+        for table in model_embeddings_tables(&transaction)? {
+            let count = transaction.execute(
+                format!("DELETE FROM {} WHERE {}", table, records_filter).as_str(),
+                params![index_id.to_string()],
+            )?;
+            *removed.entry(SourceType::TextEmbedded).or_default() += count;
+        }
+        let count = transaction.execute(
+            format!(
+                "DELETE FROM {} WHERE {}",
+                SourceType::IndexMetadata.table_name(),
+                id_in_filter(1)
+            )
+            .as_str(),
+            in_params(&[index_id], vec![]),
+        )?;
+        removed.insert(SourceType::IndexMetadata, count);
+        transaction.commit()?;
+        Ok(IndexRemoveResult { index_id, removed })
+    }
+
+    async fn check_health(&self, repair: bool) -> TuoResult<StoreHealthReport> {
+        let mut report = StoreHealthReport::default();
+        let connection = self.connect()?;
+        let embedder_model = self.embedder.get_model_metadata();
+
+        // tables and columns
+        let table_names: HashSet<String> = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+            .query_map([], |row| row.get(0))?
+            .try_collect()?;
+        let mut missing_tables = HashSet::new();
+        let mut source_types = all_source_types();
+        source_types.sort_by_key(|source_type| source_type.table_name());
+        for source_type in source_types {
+            let name = source_type.table_name();
+            if !table_names.contains(&name) {
+                let issue = StoreHealthIssue::MissingTable {
+                    table: name.clone(),
+                };
+                if repair {
+                    connection.execute_batch(&get_all_schema()[&name])?;
+                    report.repaired.push(issue.clone());
+                } else {
+                    missing_tables.insert(name);
+                }
+                report.issues.push(issue);
+                continue;
+            }
+            let table_columns: HashSet<String> = connection
+                .prepare(format!("PRAGMA table_info({})", name).as_str())?
+                .query_map([], |row| row.get("name"))?
+                .try_collect()?;
+            report.issues.extend(
+                columns(&source_type)
+                    .into_iter()
+                    .filter(|(column, _)| !table_columns.contains(*column))
+                    .map(|(column, _)| StoreHealthIssue::MissingColumn {
+                        table: name.clone(),
+                        column: column.to_string(),
+                    }),
+            );
+            if source_type == SourceType::TextEmbedded
+                && table_columns.contains(D_TABLE_COLUMN_NAME_VECTOR)
+            {
+                // vectors are blobs of 4 bytes per dimension
+                let actual: Option<i32> = connection
+                    .query_row(
+                        format!(
+                            "SELECT length({vector}) / 4 FROM {name} WHERE length({vector}) != ?1 LIMIT 1",
+                            vector = quote(D_TABLE_COLUMN_NAME_VECTOR)
+                        )
+                        .as_str(),
+                        params![embedder_model.dimensions * 4],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(actual) = actual {
+                    report.issues.push(StoreHealthIssue::DimensionMismatch {
+                        table: name.clone(),
+                        column: D_TABLE_COLUMN_NAME_VECTOR.to_string(),
+                        expected: embedder_model.dimensions,
+                        actual,
+                    });
+                }
+            }
+        }
+
+        if !missing_tables.contains(&SourceType::StoreMetadata.table_name()) {
+            self.check_store_metadata(&connection, &mut report, repair)?;
+        }
+        if [
+            SourceType::Document,
+            SourceType::Section,
+            SourceType::Node,
+            SourceType::TextEmbedded,
+        ]
+        .iter()
+        .all(|source_type| !missing_tables.contains(&source_type.table_name()))
+        {
+            self.check_orphans(&connection, &mut report, repair)?;
+        }
+
+        Ok(report)
+    }
+
+    async fn migrate_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+        opts: ModelMigrationOptions,
+    ) -> TuoResult<ModelMigrationReport> {
+        let model = embedder.get_model_metadata();
+        let connection = self.connect()?;
+        let recorded = select_sources(&connection, &SourceType::ModelMetadata, "1 = 1", "", [])?
+            .get_model_metadata()
+            .unwrap_or_default();
+        check_migration_model(&self.get_store_model_metadata(), &recorded, &model)?;
+        let mut store_metadata = self.store_metadata.clone();
+        store_metadata.model_id = Some(model.id);
+        store_metadata.model = Some(model.clone());
+        let staging = SqliteMigrationStaging {
+            store_metadata,
+            model,
+            previous_model_id: self.store_metadata.model_id,
+        };
+        staging.prepare()?;
+        let nodes = select_sources(&connection, &SourceType::Node, "1 = 1", "", [])?
+            .get_node()
+            .unwrap_or_default();
+        let texts_embedded =
+            select_sources(&connection, &SourceType::TextEmbedded, "1 = 1", "", [])?
+                .get_text_embedded()
+                .unwrap_or_default();
+        let mut report = run_model_migration(
+            &staging,
+            embedder.as_ref(),
+            stream::iter([Ok(nodes)]).boxed(),
+            stream::iter([Ok(texts_embedded)]).boxed(),
+            &opts,
+        )
+        .await?;
+        report.previous_model = self.store_metadata.model.clone();
+        if report.committed {
+            self.store_metadata = staging.store_metadata;
+            self.embedder = Arc::new(embedder);
+        }
+        Ok(report)
+    }
+
+    async fn register_embedding_model(
+        &mut self,
+        embedder: Box<dyn EmbedderTrait>,
+    ) -> TuoResult<EmbeddingModelMetadata> {
+        let mut registered_model = RegisteredModel::new(embedder);
+        let connection = self.connect()?;
+        let recorded = select_sources(&connection, &SourceType::ModelMetadata, "1 = 1", "", [])?
+            .get_model_metadata()
+            .unwrap_or_default();
+        let (model, is_new) = model_to_register(
+            &self.get_store_model_metadata(),
+            &recorded,
+            registered_model.model,
+        )?;
+        let transaction = connection.unchecked_transaction()?;
+        transaction.execute_batch(&model_embeddings_table_schema(model.id))?;
+        if is_new {
+            insert_model_metadata(&transaction, &model, None)?;
+        }
+        transaction.commit()?;
+        registered_model.model = model.clone();
+        self.registered_models.insert(model.id, registered_model);
+        Ok(model)
+    }
+
+    async fn list_embedding_models(&self) -> TuoResult<Vec<EmbeddingModelMetadata>> {
+        let store_model = self.get_store_model_metadata();
+        let connection = self.connect()?;
+        let registered_models: Vec<EmbeddingModelMetadata> =
+            select_sources(&connection, &SourceType::ModelMetadata, "1 = 1", "", [])?
+                .get_model_metadata()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|model| model.id != store_model.id)
+                .collect();
+        Ok([vec![store_model], registered_models].concat())
+    }
+
+    fn get_registered_models(&self) -> Vec<RegisteredModel> {
+        self.registered_models.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_log::test;
+
+    use crate::stores::sqlite::index::{SqliteIndexSearchOptions, SqliteSearchMode};
+    use crate::testing::{parsed_document, CharHashEmbedder};
+    use futures::TryStreamExt;
+    use tuo_core::core::indexing::index_insert::DocumentInsertOptions;
+    use tuo_core::core::indexing::index_scan::ScanOptions;
+    use tuo_core::core::messaging::content::{
+        QueryEmbeddingCache, TextEmbeddingOptions, TextInput, TextSourceType,
+    };
+    use tuo_core::core::source::document_tree::DocumentTreeOptions;
+    use tuo_core::embedding::embedder::EmbedResultStats;
+    use tuo_core::retrieval::hybrid::{HybridFusion, HybridSearchOptions};
+    use tuo_core::retrieval::keyword::Bm25Options;
+    use tuo_utils::datetime::timestamp::utc_from_epoch;
+    use tuo_utils::hash::hash_str::hash_str;
+    use tuo_utils::testing::get_random_test_temp_folder;
+
+    use super::*;
+
+    #[test(tokio::test)]
+    async fn test_sqlite_store() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        assert!(store.list_indices().await.unwrap().is_empty());
+        assert!(store.check_health(false).await.unwrap().is_healthy());
+        // a store cannot be created twice
+        assert!(SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .is_err());
+
+        let index = store.index_create("test_index").await.unwrap();
+        let other_index = store.index_create("other_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let other_index_id = other_index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子"]);
+        let node_1_id = document.nodes[0].id;
+        let node_2_id = document.nodes[1].id;
+        index.add_document(vec![document], None).await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(other_index_id, "other", &["中文"])],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 1);
+        assert_eq!(
+            store
+                .index_count_records("other_index", &SourceType::Node)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // embed
+        index.embed_nodes(vec![node_1_id]).await.unwrap();
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 1);
+        index.embed_nodes(vec![]).await.unwrap();
+        assert!(index.get_unembedded_nodes().await.unwrap().is_empty());
+        other_index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_1_id)
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(
+            node[0]
+                .content_embeddings
+                .as_ref()
+                .unwrap()
+                .embeddings
+                .len(),
+            store.get_store_model_dimensions() as usize
+        );
+
+        // exact and signature rescoring search within the index, most similar first
+        let results = index
+            .similar_sources(&TextInput::from_user_str("中文"), &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids(), vec![node_2_id, node_1_id]);
+        // only nodes are searched by similarity
+        assert!(index
+            .similar_sources(
+                &TextInput::from_user_str("中文"),
+                &SourceType::Document,
+                None
+            )
+            .await
+            .is_err());
+        let rescoring = SqliteIndexSearchOptions::builder()
+            .mode(SqliteSearchMode::SignatureRescoring { refine_factor: 2 })
+            .build();
+        let results = index
+            .similar_sources(
+                &TextInput::from_user_str("中文"),
+                &SourceType::Node,
+                Some(rescoring),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids(), vec![node_2_id, node_1_id]);
+        let results = index
+            .similar_embedded_text(
+                &TextInput::from_user_str("test"),
+                &TextSourceType::NodeContent,
+                Some(SqliteIndexSearchOptions::builder().top_k(1).build()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data.source_id, Some(node_1_id));
+        assert!(results[0].distance < 1.0);
+
+        // ordered lookup
+        let nodes = index
+            .get_source_data_by_ids(&SourceType::Node, vec![node_2_id, node_1_id], true)
+            .await
+            .unwrap();
+        assert_eq!(nodes.get_ids(), vec![node_2_id, node_1_id]);
+
+        // the store can be opened again by its uri
+        let reopened = SqliteStore::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.list_indices().await.unwrap().len(), 2);
+        assert!(reopened.check_health(false).await.unwrap().is_healthy());
+
+        // delete and remove
+        index
+            .delete(&vec![node_1_id], &SourceType::Node)
+            .await
+            .unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 1);
+        let report = store.check_health(true).await.unwrap();
+        assert_eq!(report.repaired.len(), 1);
+        assert!(store.check_health(false).await.unwrap().is_healthy());
+        let result = store.index_remove(index_id).await.unwrap();
+        assert_eq!(result.removed_count(&SourceType::Node), 1);
+        assert_eq!(result.removed_count(&SourceType::Document), 1);
+        assert_eq!(result.removed_count(&SourceType::IndexMetadata), 1);
+        assert!(!store.index_exists("test_index").await.unwrap());
+        assert_eq!(
+            other_index.count_records(&SourceType::Node).await.unwrap(),
+            1
+        );
+
+        assert!(SqliteStore::open(
+            format!("{}/missing.sqlite", temp_folder).as_str(),
+            Box::new(CharHashEmbedder::new())
+        )
+        .await
+        .is_err());
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_migrate_model() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let previous_dimensions = store.get_store_model_dimensions();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["test content", "中文例子", "more text"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        let query = TextInput::from_user_str("中文");
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // stop after the first batch, the store still uses the previous model
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder()
+                    .batch_size(2)
+                    .max_batches(Some(1))
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(!report.committed);
+        assert_eq!(report.nodes_embedded, 2);
+        assert_eq!(store.get_store_model_dimensions(), previous_dimensions);
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+
+        // resume
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().batch_size(2).build(),
+            )
+            .await
+            .unwrap();
+        assert!(report.committed);
+        assert_eq!(report.nodes_resumed, 2);
+        assert_eq!(report.nodes_embedded, 1);
+        // the cached query, reused by the second search
+        assert_eq!(report.texts_dropped, 1);
+        assert_eq!(
+            report.previous_model.map(|model| model.dimensions),
+            Some(previous_dimensions)
+        );
+        assert_eq!(store.get_store_model_dimensions(), 32);
+
+        let index = store.index_open("test_index").await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 3);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let node = index
+            .get_source_data_with_relations_by_id(&SourceType::Node, node_ids[0])
+            .await
+            .unwrap()
+            .get_node()
+            .unwrap();
+        assert_eq!(
+            node[0]
+                .content_embeddings
+                .as_ref()
+                .unwrap()
+                .embeddings
+                .len(),
+            32
+        );
+        let results = index
+            .similar_sources(&query, &SourceType::Node, None)
+            .await
+            .unwrap();
+        assert_eq!(results.get_ids()[0], node_ids[1]);
+        let report = store.check_health(false).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.issues);
+
+        // the store is opened with the model migrated to, not with the previous model
+        let uri = store.get_store_uri();
+        let reopened = SqliteStore::open(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::with_dimensions(32)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        let error = SqliteStore::open(uri.as_str(), Box::new(CharHashEmbedder::new()))
+            .await
+            .err()
+            .expect("Opening with the previous model should fail");
+        assert!(error.to_string().contains("uses model"), "{}", error);
+        let reopened = SqliteStore::open_with_options(
+            uri.as_str(),
+            Box::new(CharHashEmbedder::new()),
+            &StoreOpenOptions::builder()
+                .allow_model_mismatch(true)
+                .build(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reopened.get_store_model_dimensions(), 32);
+        assert_eq!(
+            reopened
+                .index_open("test_index")
+                .await
+                .unwrap()
+                .count_records(&SourceType::Node)
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_hybrid_search() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(
+            index_id,
+            "doc",
+            &["apple pie recipe", "中文例子", "日本語の例文", "pear tart"],
+        );
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        // CJK text is matched without word delimiters
+        let query = TextInput::from_user_str("例子");
+        let keyword_matches = index
+            .keyword_search(&query, 10, &Bm25Options::default())
+            .await
+            .unwrap();
+        assert_eq!(keyword_matches.len(), 2);
+        assert_eq!(keyword_matches[0].data_id, node_ids[1]);
+        assert_eq!(keyword_matches[1].data_id, node_ids[2]);
+
+        let results = index
+            .hybrid_search(
+                &query,
+                &HybridSearchOptions::builder().top_k(3).build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].data_id, node_ids[1]);
+        let scores = results[0].hybrid_scores.clone().unwrap();
+        assert!(scores.vector_distance.is_some());
+        assert!(scores.keyword_score.is_some());
+        assert!(results
+            .windows(2)
+            .all(|pair| pair[0].distance <= pair[1].distance));
+
+        // keyword scores only
+        let results = index
+            .hybrid_search(
+                &TextInput::from_user_str("Apple"),
+                &HybridSearchOptions::builder()
+                    .fusion(HybridFusion::Weighted { vector_weight: 0.0 })
+                    .build(),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data_id, node_ids[0]);
+        assert_eq!(results[0].distance, 0.0);
+        assert_eq!(
+            results[1].hybrid_scores.as_ref().unwrap().keyword_score,
+            None
+        );
+
+        // the term statistics are computed again once the nodes were written, also by another index
+        let other_index = store.index_open("test_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(index_id, "other", &["plum cake"])],
+                None,
+            )
+            .await
+            .unwrap();
+        let keyword_matches = index
+            .keyword_search(
+                &TextInput::from_user_str("plum"),
+                10,
+                &Bm25Options::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(keyword_matches.len(), 1);
+        assert_eq!(keyword_matches[0].data.content, "plum cake");
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_query_embedding_cache() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let count_queries = || index.count_records(&SourceType::TextEmbedded);
+
+        // a cached query embedding, last used long ago
+        let query = TextInput::from_user_str("apple");
+        let embedder = index.get_index_embedder().await.unwrap();
+        let mut cached = embedder
+            .embed_input(
+                &query,
+                &TextEmbeddingOptions::builder().save_text(true).build(),
+            )
+            .await
+            .unwrap();
+        cached.used_at = utc_from_epoch(0);
+        index
+            .add_text_embeddings(&vec![cached.clone()])
+            .await
+            .unwrap();
+
+        index
+            .similar_embedded_text(&query, &TextSourceType::UserQuery, None)
+            .await
+            .unwrap();
+        assert_eq!(count_queries().await.unwrap(), 1);
+        let reused = index
+            .find_text_embedded(
+                &cached.hash,
+                &cached.embedding_model,
+                &TextSourceType::UserQuery,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reused.id, cached.id);
+        assert!(reused.used_at > cached.used_at);
+
+        // new queries are only persisted by a read-write cache
+        for (text, query_cache, count) in [
+            ("pear", QueryEmbeddingCache::ReadOnly, 1),
+            ("pear", QueryEmbeddingCache::Disabled, 1),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("pear", QueryEmbeddingCache::ReadWrite, 2),
+            ("apple", QueryEmbeddingCache::Disabled, 2),
+        ] {
+            index
+                .similar_embedded_text(
+                    &TextInput::from_user_str(text),
+                    &TextSourceType::UserQuery,
+                    Some(
+                        SqliteIndexSearchOptions::builder()
+                            .query_cache(query_cache)
+                            .build(),
+                    ),
+                )
+                .await
+                .unwrap();
+            assert_eq!(count_queries().await.unwrap(), count);
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_embedding_dedup() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let content_embeddings_ids = |nodes: SourceData| {
+            nodes
+                .get_node()
+                .unwrap()
+                .into_iter()
+                .map(|node| node.content_embeddings_id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // repeated contents of a batch are embedded once
+        let document = parsed_document(index_id, "a", &["disclaimer", "apple", "disclaimer"]);
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        let ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(ids[0], ids[2]);
+        assert_ne!(ids[0], ids[1]);
+
+        // contents embedded before are reused across documents
+        let document = parsed_document(index_id, "b", &["disclaimer", "pear"]);
+        let other_node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+        let stats = index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 1,
+                reused: 1
+            }
+        );
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+        let other_ids = content_embeddings_ids(
+            index
+                .get_source_data_by_ids(&SourceType::Node, other_node_ids, true)
+                .await
+                .unwrap(),
+        );
+        assert_eq!(other_ids[0], ids[0]);
+
+        // the shared embedding is kept once the node it was embedded for is removed
+        let shared = index
+            .get_source_data_by_ids(&SourceType::TextEmbedded, vec![ids[0]], false)
+            .await
+            .unwrap()
+            .get_text_embedded()
+            .unwrap();
+        index
+            .delete(&vec![shared[0].source_id.unwrap()], &SourceType::Node)
+            .await
+            .unwrap();
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_scan() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let documents = vec![
+            parsed_document(index_id, "doc_a", &["apple", "pear", "plum"]),
+            parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+            parsed_document(index_id, "doc_c", &["lime"]),
+        ];
+        let mut nodes: Vec<Node> = documents
+            .iter()
+            .flat_map(|document| document.nodes.clone())
+            .collect();
+        nodes.sort_by_key(|node| (node.document_id, node.index));
+        let node_ids: Vec<Uuid> = nodes.iter().map(|node| node.id).collect();
+        let mut document_ids: Vec<Uuid> = documents
+            .iter()
+            .map(|document| document.document.id)
+            .collect();
+        document_ids.sort();
+        index.add_document(documents, None).await.unwrap();
+        // the records of other indices are not scanned
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_d",
+                    &["date"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder().page_size(4).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(
+            pages
+                .iter()
+                .flat_map(|page| page.source_data.get_ids())
+                .collect::<Vec<_>>(),
+            node_ids
+        );
+
+        // resumed after the first page
+        let pages = index
+            .scan(
+                &SourceType::Node,
+                ScanOptions::builder()
+                    .page_size(4)
+                    .cursor(pages[0].cursor.clone())
+                    .build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), node_ids[4..]);
+
+        // a full page is followed by an empty one, which ends the scan
+        let pages = index
+            .scan(
+                &SourceType::Document,
+                ScanOptions::builder().page_size(3).build(),
+            )
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].source_data.get_ids(), document_ids);
+    }
+    #[test(tokio::test)]
+    async fn test_sqlite_lookup_in_chunks() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        // names are bound as parameters
+        let index = store.index_create("test's index").await.unwrap();
+        assert!(store.index_open("test's index").await.is_ok());
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear", "plum"]);
+        let document_id = document.document.id;
+        let node_ids: Vec<Uuid> = document.nodes.iter().map(|node| node.id).collect();
+        index.add_document(vec![document], None).await.unwrap();
+
+        // more ids than bound by one query, the nodes in the last chunks
+        let mut ids: Vec<Uuid> = (0..SQLITE_IN_CHUNK_SIZE * 2)
+            .map(|_| Uuid::new_v4())
+            .collect();
+        ids.extend(node_ids.iter().rev());
+        let nodes = index
+            .get_source_data_by_ids(&SourceType::Node, ids.clone(), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            nodes.get_ids(),
+            node_ids.iter().rev().copied().collect::<Vec<_>>()
+        );
+        ids.truncate(SQLITE_IN_CHUNK_SIZE * 2);
+        ids.push(node_ids[0]);
+        index.delete(&ids, &SourceType::Node).await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        ids.push(document_id);
+        index.delete_documents(&ids).await.unwrap();
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 0);
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 0);
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_add_document_upsert() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let other_index = store.index_create("other_index").await.unwrap();
+        other_index
+            .add_document(
+                vec![parsed_document(
+                    other_index.get_index_metadata().id,
+                    "doc_b",
+                    &["fig"],
+                )],
+                None,
+            )
+            .await
+            .unwrap();
+        let upsert = |remove_missing_under: Option<&str>| {
+            Some(
+                DocumentInsertOptions::builder()
+                    .upsert(true)
+                    .remove_missing_under(remove_missing_under.map(str::to_string))
+                    .build(),
+            )
+        };
+
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig"]),
+                ],
+                upsert(None),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.added, vec!["/test/doc_a", "/test/doc_b"]);
+        index.embed_nodes(vec![]).await.unwrap();
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            3
+        );
+
+        // the same content is skipped, changed content replaces the document and its embeddings
+        let report = index
+            .add_document(
+                vec![
+                    parsed_document(index_id, "doc_a", &["apple", "pear"]),
+                    parsed_document(index_id, "doc_b", &["fig", "kiwi"]),
+                    parsed_document(index_id, "doc_c", &["lime"]),
+                ],
+                upsert(Some("/test/")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.updated, vec!["/test/doc_b"]);
+        assert_eq!(report.added, vec!["/test/doc_c"]);
+        assert!(report.removed.is_empty());
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 3);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 5);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(index.get_unembedded_nodes().await.unwrap().len(), 3);
+
+        // documents of missing sources are removed, but not those of other indices
+        let report = index
+            .add_document(
+                vec![parsed_document(index_id, "doc_a", &["apple", "pear"])],
+                upsert(Some("/test/")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, vec!["/test/doc_a"]);
+        assert_eq!(report.removed, vec!["/test/doc_b", "/test/doc_c"]);
+        assert_eq!(index.count_records(&SourceType::Document).await.unwrap(), 1);
+        assert_eq!(index.count_records(&SourceType::Node).await.unwrap(), 2);
+        assert_eq!(
+            index
+                .count_records(&SourceType::TextEmbedded)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            other_index
+                .count_records(&SourceType::Document)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+    #[test(tokio::test)]
+    async fn test_sqlite_document_tree() {
+        let temp_folder = get_random_test_temp_folder();
+        let store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let embedder = index.get_index_embedder().await.unwrap();
+
+        // a document of two sections, whose sections and nodes are added out of order
+        let mut document = parsed_document(index_id, "doc", &["apple", "pear", "plum"]);
+        let mut second_section = document.sections[0].clone();
+        second_section.id = Uuid::new_v4();
+        second_section.section_order = 1;
+        document.nodes[2].section_id = second_section.id;
+        document.sections.insert(0, second_section);
+        document.nodes.reverse();
+        let summary = embedder
+            .embed_input(
+                &TextInput {
+                    text: "fruits".to_string(),
+                    source_type: TextSourceType::SummaryDocument,
+                    source_id: Some(document.document.id),
+                },
+                &TextEmbeddingOptions::builder().build(),
+            )
+            .await
+            .unwrap();
+        document.document.summary_text_id = Some(summary.id);
+        let document_id = document.document.id;
+        index
+            .add_document(
+                vec![document, parsed_document(index_id, "other", &["fig"])],
+                None,
+            )
+            .await
+            .unwrap();
+        index
+            .add_text_embeddings(&vec![summary.clone()])
+            .await
+            .unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+
+        let tree = index
+            .get_document_tree(document_id, &DocumentTreeOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tree.document.id, document_id);
+        let section_orders: Vec<i32> = tree
+            .sections
+            .iter()
+            .map(|section| section.section.section_order)
+            .collect();
+        assert_eq!(section_orders, vec![0, 1]);
+        let contents: Vec<&str> = tree
+            .nodes()
+            .iter()
+            .map(|node| node.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["apple", "pear", "plum"]);
+        assert!(tree.document.summary.is_none());
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_none()));
+
+        let tree = index
+            .get_document_tree(
+                document_id,
+                &DocumentTreeOptions::builder()
+                    .with_embeddings(true)
+                    .with_summaries(true)
+                    .build(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tree.document.summary.as_ref().map(|summary| summary.id),
+            Some(summary.id)
+        );
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.content_embeddings.is_some()));
+        assert!(index
+            .get_document_tree(Uuid::new_v4(), &DocumentTreeOptions::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_check_health_orphans() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc", &["apple", "pear"]);
+        index.add_document(vec![document], None).await.unwrap();
+        index.embed_nodes(vec![]).await.unwrap();
+        index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+
+        // a section whose document is gone
+        let orphan_section = parsed_document(index_id, "gone", &["plum"]).sections[0].clone();
+        index
+            .add_source_data(
+                SourceData::Section(vec![orphan_section.clone()]),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        // a text embedding of the registered model whose node is gone
+        let mut orphan_model_embeddings = index
+            .find_model_embeddings(model.id, &[hash_str("pear")])
+            .await
+            .unwrap()
+            .remove(0);
+        orphan_model_embeddings.id = Uuid::new_v4();
+        orphan_model_embeddings.source_id = Some(Uuid::new_v4());
+        index
+            .add_model_embeddings(model.id, vec![orphan_model_embeddings.clone()])
+            .await
+            .unwrap();
+
+        let report = store.check_health(false).await.unwrap();
+        assert_eq!(
+            report.issues,
+            vec![
+                StoreHealthIssue::OrphanModelEmbeddings {
+                    model_id: model.id,
+                    text_embedded_ids: vec![orphan_model_embeddings.id],
+                },
+                StoreHealthIssue::OrphanSections {
+                    section_ids: vec![orphan_section.id],
+                },
+            ]
+        );
+        let report = store.check_health(true).await.unwrap();
+        assert!(report.is_healthy(), "{:?}", report.unresolved());
+        assert_eq!(report.repaired.len(), 2);
+        assert!(store.check_health(false).await.unwrap().issues.is_empty());
+        assert_eq!(index.count_records(&SourceType::Section).await.unwrap(), 1);
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("pear")])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn test_sqlite_multiple_models() {
+        let temp_folder = get_random_test_temp_folder();
+        let mut store = SqliteStore::create(
+            "test_store",
+            temp_folder.as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let store_model = store.get_store_model_metadata();
+        // the model of the store cannot be registered
+        assert!(store
+            .register_embedding_model(Box::new(CharHashEmbedder::new()))
+            .await
+            .is_err());
+        let model = store
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        let index = store.index_create("test_index").await.unwrap();
+        let index_id = index.get_index_metadata().id;
+        let document = parsed_document(index_id, "doc_a", &["apple", "pear", "apple"]);
+        let plum = parsed_document(index_id, "doc_b", &["plum"]);
+        let plum_id = plum.nodes[0].id;
+        let plum_document_id = plum.document.id;
+        index
+            .add_document(vec![document, plum], None)
+            .await
+            .unwrap();
+
+        // the nodes embedded by each model are tracked separately
+        index.embed_nodes(vec![]).await.unwrap();
+        assert!(index
+            .get_unembedded_nodes_of_model(store_model.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .get_unembedded_nodes_of_model(model.id)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![plum_id])
+            .await
+            .unwrap();
+        assert_eq!(stats.embedded, 1);
+        let stats = index
+            .embed_nodes_with_model(model.id, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            stats,
+            EmbedResultStats {
+                embedded: 2,
+                reused: 1
+            }
+        );
+        assert!(index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(index
+            .embed_nodes_with_model(Uuid::new_v4(), vec![])
+            .await
+            .is_err());
+
+        // the query is embedded and searched with the model chosen in the search options
+        let query = TextInput::from_user_str("plum");
+        let results = index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    SqliteIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.source_id, Some(plum_id));
+        assert_eq!(results[0].data.embeddings.len(), 32);
+        let nodes = index
+            .similar_sources(
+                &query,
+                &SourceType::Node,
+                Some(
+                    SqliteIndexSearchOptions::builder()
+                        .top_k(1)
+                        .model_id(Some(model.id))
+                        .build(),
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(nodes.get_ids(), vec![plum_id]);
+        let results = index
+            .similar_embedded_text(&query, &TextSourceType::NodeContent, None)
+            .await
+            .unwrap();
+        assert_eq!(results[0].data.embeddings.len(), 64);
+        assert!(index
+            .similar_embedded_text(
+                &query,
+                &TextSourceType::NodeContent,
+                Some(
+                    SqliteIndexSearchOptions::builder()
+                        .model_id(Some(Uuid::new_v4()))
+                        .build()
+                ),
+            )
+            .await
+            .is_err());
+
+        // a model registered again keeps its id and its embeddings
+        let mut reopened = SqliteStore::open(
+            store.get_store_uri().as_str(),
+            Box::new(CharHashEmbedder::new()),
+        )
+        .await
+        .unwrap();
+        let registered = reopened
+            .register_embedding_model(Box::new(CharHashEmbedder::with_dimensions(32)))
+            .await
+            .unwrap();
+        assert_eq!(registered.id, model.id);
+        let model_ids: Vec<Uuid> = reopened
+            .list_embedding_models()
+            .await
+            .unwrap()
+            .iter()
+            .map(|model| model.id)
+            .collect();
+        assert_eq!(model_ids, vec![store_model.id, model.id]);
+        let reopened_index = reopened.index_open("test_index").await.unwrap();
+        assert!(reopened_index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the embeddings of the registered models are removed with their nodes
+        index.delete_documents(&[plum_document_id]).await.unwrap();
+        assert!(index
+            .find_model_embeddings(model.id, &[hash_str("plum")])
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .find_model_embeddings(model.id, &[hash_str("apple")])
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // the store cannot be migrated to one of its registered models
+        assert!(store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(32)),
+                ModelMigrationOptions::builder().build(),
+            )
+            .await
+            .is_err());
+        // migrating the store to another model keeps the registered models
+        let report = store
+            .migrate_model(
+                Box::new(CharHashEmbedder::with_dimensions(48)),
+                ModelMigrationOptions::builder().build(),
+            )
+            .await
+            .unwrap();
+        assert!(report.committed);
+        let models = store.list_embedding_models().await.unwrap();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].dimensions, 48);
+        assert_eq!(models[1].id, model.id);
+        let index = store.index_open("test_index").await.unwrap();
+        assert!(index
+            .get_unembedded_nodes_of_model(model.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let result = store.index_remove(index_id).await.unwrap();
+        assert_eq!(result.removed_count(&SourceType::TextEmbedded), 5);
+    }
+}
@@ -0,0 +1,145 @@
+//! Test helpers shared by the unit tests of tuo-parts.
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use tuo_core::core::source::document::{Document, DocumentSourceType};
+use tuo_core::core::source::node::{ContentType, Node};
+use tuo_core::core::source::section::Section;
+use tuo_core::embedding::embedder::EmbedderTrait;
+use tuo_core::embedding::embeddings::Embeddings;
+use tuo_core::model::model::ModelTrait;
+use tuo_core::model::model_metadata::EmbeddingModelMetadata;
+use tuo_core::parsing::document_parser::ParsedDocument;
+use tuo_core::utility::token::{count_tokens, TokenUtility};
+use tuo_shared::types::return_type::TuoResult;
+
+pub(crate) const CHAR_HASH_MODEL_NAME: &str = "char-hash";
+pub(crate) const CHAR_HASH_SIGNED_MODEL_NAME: &str = "char-hash-signed";
+pub(crate) const CHAR_HASH_DIMENSIONS: i32 = 64;
+
+/// A deterministic, offline embedder for tests.
+///
+/// Each character of the text is hashed into one of the dimensions, so texts sharing characters are close to each other in cosine distance.
+pub(crate) struct CharHashEmbedder {
+    metadata: EmbeddingModelMetadata,
+    /// Hash each character to a sign as well, see [signed](CharHashEmbedder::signed).
+    signed: bool,
+}
+
+impl CharHashEmbedder {
+    pub(crate) fn new() -> Self {
+        Self::with_dimensions(CHAR_HASH_DIMENSIONS)
+    }
+
+    /// An embedder whose characters add 1 or -1 to their dimension, so that the components are centred around zero like those of real embedding models.
+    pub(crate) fn signed() -> Self {
+        let mut embedder = Self::new();
+        embedder.metadata.name = CHAR_HASH_SIGNED_MODEL_NAME.to_string();
+        embedder.signed = true;
+        embedder
+    }
+
+    pub(crate) fn with_dimensions(dimensions: i32) -> Self {
+        let metadata = EmbeddingModelMetadata::builder()
+            .name(CHAR_HASH_MODEL_NAME.to_string())
+            .author("tuo".to_string())
+            .url("".to_string())
+            .dimensions(dimensions)
+            .max_input(8191)
+            .pricing_per_1k_tokens(0.0)
+            .build();
+        CharHashEmbedder {
+            metadata,
+            signed: false,
+        }
+    }
+}
+
+#[async_trait]
+impl ModelTrait for CharHashEmbedder {
+    async fn is_healthy(&self) -> bool {
+        true
+    }
+
+    fn get_model_name(&self) -> String {
+        self.metadata.name.clone()
+    }
+
+    fn get_model_metadata(&self) -> EmbeddingModelMetadata {
+        self.metadata.clone()
+    }
+}
+
+#[async_trait]
+impl EmbedderTrait for CharHashEmbedder {
+    async fn embed_string(&self, text: &str) -> TuoResult<Embeddings> {
+        let dimensions = self.metadata.dimensions as usize;
+        let mut vector = vec![0.0f32; dimensions];
+        for c in text.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+            // FNV-1a over the code point
+            let hash = (c as u32)
+                .to_le_bytes()
+                .iter()
+                .fold(0xcbf29ce484222325u64, |hash, byte| {
+                    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+                });
+            let sign = match self.signed && (hash >> 32) % 2 == 1 {
+                true => -1.0,
+                false => 1.0,
+            };
+            vector[(hash % dimensions as u64) as usize] += sign;
+        }
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        match norm > 0.0 {
+            true => vector.iter_mut().for_each(|v| *v /= norm),
+            // avoid zero vectors, which have no cosine distance
+            false => vector[0] = 1.0,
+        }
+        Ok(Embeddings::builder()
+            .model(self.get_model_name())
+            .vector(vector)
+            .build())
+    }
+}
+
+/// Build a parsed document of the index with one section and one node per content.
+pub(crate) fn parsed_document(index_id: Uuid, name: &str, contents: &[&str]) -> ParsedDocument {
+    let document = Document::builder()
+        .name(name.to_string())
+        .index_id(index_id)
+        .source_uri(format!("/test/{}", name))
+        .source_type(DocumentSourceType::File)
+        .build();
+    let section = Section::builder()
+        .index_id(index_id)
+        .document_id(document.id)
+        .name(format!("{}_section", name))
+        .content(Some(contents.join("\n")))
+        .start_char_index(Some(0))
+        .end_char_index(Some(0))
+        .build();
+    let nodes: Vec<Node> = contents
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            Node::builder()
+                .index_id(index_id)
+                .document_id(document.id)
+                .section_id(section.id)
+                .tokens(count_tokens(content) as i32)
+                .content(content.to_string())
+                .content_type(ContentType::Text)
+                .index(index as i32)
+                .build()
+        })
+        .collect();
+    ParsedDocument {
+        document,
+        sections: vec![section],
+        total_tokens: nodes.count_tokens() as i32,
+        section_count: 1,
+        node_count: nodes.len() as i32,
+        nodes,
+        input_uri: format!("/test/{}", name),
+    }
+}
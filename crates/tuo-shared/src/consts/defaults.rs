@@ -14,4 +14,12 @@ pub static D_TABLE_NAME_NODES: &str = "nodes";
 
 pub static D_TABLE_NAME_TEXT_EMBEDDED: &str = "embedded_texts";
 
+pub static D_TABLE_NAME_SNAPSHOTS: &str = "snapshots";
+
+pub static D_FOLDER_NAME_SNAPSHOTS: &str = "tuo_snapshots";
+
 pub static D_TABLE_COLUMN_NAME_VECTOR: &str = "vector";
+
+pub static D_TABLE_NAME_SUFFIX_MIGRATION: &str = "_migration";
+
+pub static D_TABLE_COLUMN_NAME_SCHEMA_VERSION: &str = "schema_version";
@@ -1 +1,5 @@
-pub mod lancedb;
\ No newline at end of file
+#[cfg(feature = "lancedb")]
+pub mod lancedb;
+
+#[cfg(feature = "db_sqlite")]
+pub mod sqlite;
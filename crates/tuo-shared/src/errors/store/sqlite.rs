@@ -0,0 +1,14 @@
+use crate::errors::parts::TuoPartsError;
+use crate::errors::tuo::TuoError;
+
+impl From<rusqlite::Error> for TuoPartsError {
+    fn from(err: rusqlite::Error) -> Self {
+        TuoPartsError::StoreError(err.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for TuoError {
+    fn from(err: rusqlite::Error) -> Self {
+        TuoError::from(TuoPartsError::StoreError(err.to_string()))
+    }
+}